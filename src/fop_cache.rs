@@ -0,0 +1,103 @@
+//! File cache for `--cache=FILE`
+//!
+//! Remembers each list's mtime, size and content hash after a run, so the next run can
+//! skip lists that haven't changed since. A list whose mtime moved but whose size didn't
+//! is re-hashed, so touching a file or checking it out again doesn't force a re-sort. The cache is tied to a fingerprint of the
+//! FOP version and options; a run with different options starts from an empty cache.
+
+use ahash::AHashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// What a list looked like when it was last recorded
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CacheEntry {
+    /// Modification time in nanoseconds since the Unix epoch
+    mtime: u128,
+    size: u64,
+    /// MD5 of the file as written (hex)
+    hash: String,
+}
+
+#[derive(Debug, Default)]
+pub struct FileCache {
+    fingerprint: String,
+    entries: AHashMap<PathBuf, CacheEntry>,
+}
+
+/// (mtime in nanoseconds, size) of `path`
+fn stat(path: &Path) -> Option<(u128, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    let mtime = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?.as_nanos();
+    Some((mtime, metadata.len()))
+}
+
+impl FileCache {
+    /// Read the cache at `path`. A missing or malformed file, or one written with a
+    /// different `fingerprint`, gives an empty cache.
+    pub fn load(path: &Path, fingerprint: &str) -> Self {
+        let mut cache = FileCache { fingerprint: fingerprint.to_string(), entries: AHashMap::new() };
+        let Ok(content) = fs::read_to_string(path) else {
+            return cache;
+        };
+        let mut lines = content.lines();
+        if lines.next().and_then(|header| header.strip_prefix("# fop cache ")) != Some(fingerprint) {
+            return cache;
+        }
+        for line in lines {
+            let mut fields = line.splitn(4, '\t');
+            let (Some(mtime), Some(size), Some(hash), Some(file)) =
+                (fields.next(), fields.next(), fields.next(), fields.next())
+            else {
+                continue;
+            };
+            if let (Ok(mtime), Ok(size)) = (mtime.parse(), size.parse()) {
+                cache.entries.insert(PathBuf::from(file), CacheEntry { mtime, size, hash: hash.to_string() });
+            }
+        }
+        cache
+    }
+
+    /// Whether `path` is unchanged since it was recorded: same mtime and size, or same
+    /// size and content hash when only the mtime moved
+    pub fn is_fresh(&self, path: &Path) -> bool {
+        let (Some(entry), Some((mtime, size))) = (self.entries.get(path), stat(path)) else {
+            return false;
+        };
+        size == entry.size
+            && (mtime == entry.mtime
+                || fs::read(path).is_ok_and(|content| format!("{:x}", md5::compute(&content)) == entry.hash))
+    }
+
+    /// Record `path` as it is on disk now. An existing entry is replaced when the file's
+    /// content hash differs; otherwise only its mtime and size are refreshed.
+    pub fn record(&mut self, path: &Path) {
+        let (Some((mtime, size)), Ok(content)) = (stat(path), fs::read(path)) else {
+            self.entries.remove(path);
+            return;
+        };
+        let hash = format!("{:x}", md5::compute(&content));
+        match self.entries.get_mut(path) {
+            Some(entry) if entry.hash == hash => {
+                entry.mtime = mtime;
+                entry.size = size;
+            }
+            _ => {
+                self.entries.insert(path.to_path_buf(), CacheEntry { mtime, size, hash });
+            }
+        }
+    }
+
+    /// Write the cache to `path`, entries sorted by file
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut files: Vec<_> = self.entries.iter().collect();
+        files.sort_by(|a, b| a.0.cmp(b.0));
+        let mut out = format!("# fop cache {}\n", self.fingerprint);
+        for (file, entry) in files {
+            out.push_str(&format!("{}\t{}\t{}\t{}\n", entry.mtime, entry.size, entry.hash, file.display()));
+        }
+        fs::write(path, out)
+    }
+}
@@ -2,11 +2,12 @@
 //!
 //! Calculates and inserts/updates `! Checksum: <base64-md5>` in filter list headers.
 //! Uses the standard ABP format: MD5 of normalized content, Base64 without padding.
+//! Lists in another `--input-encoding` are hashed as their decoded (UTF-8) text.
 
-use std::fs;
-use std::io;
+use crate::fop_error::FopError;
+use crate::fop_datestamp::header_change_line;
+use crate::fop_sort::{read_list_text, write_list_text, SortConfig};
 use std::path::Path;
-use owo_colors::OwoColorize;
 
 use base64::{engine::general_purpose::STANDARD, Engine};
 use md5::Context;
@@ -27,7 +28,7 @@ fn is_checksum_line(line: &str) -> bool {
 /// Normalization: remove \r, collapse consecutive \n.
 /// Matches Perl: `$data =~ s/\r//g; $data =~ s/\n+/\n/g; md5_base64(encode_utf8($data))`
 #[inline]
-fn calculate_checksum(data: &str) -> String {
+pub fn calculate_checksum(data: &str) -> String {
     let mut hasher = Context::new();
     let mut prev_newline = false;
 
@@ -68,8 +69,8 @@ pub enum ChecksumResult {
 
 /// Verify checksum in a filter list file.
 /// Returns the verification result without modifying the file.
-pub fn verify_checksum(filename: &Path) -> io::Result<ChecksumResult> {
-    let content = fs::read_to_string(filename)?;
+pub fn verify_checksum(filename: &Path, config: &SortConfig) -> Result<ChecksumResult, FopError> {
+    let content = read_list_text(filename, config)?;
     if content.is_empty() {
         return Ok(ChecksumResult::Missing);
     }
@@ -87,13 +88,7 @@ pub fn verify_checksum(filename: &Path) -> io::Result<ChecksumResult> {
     };
 
     // Calculate expected checksum
-    let data_for_hash: String = lines.iter()
-        .filter(|line| !is_checksum_line(line))
-        .copied()
-        .collect::<Vec<_>>()
-        .join("\n") + "\n";
-
-    let expected = calculate_checksum(&data_for_hash);
+    let expected = calculate_checksum(&hashed_data(&lines));
 
     if expected == found {
         Ok(ChecksumResult::Valid)
@@ -102,13 +97,81 @@ pub fn verify_checksum(filename: &Path) -> io::Result<ChecksumResult> {
     }
 }
 
+/// Lines covered by the checksum (every line but the checksum itself), joined for hashing
+fn hashed_data(lines: &[&str]) -> String {
+    lines.iter()
+        .filter(|line| !is_checksum_line(line))
+        .copied()
+        .collect::<Vec<_>>()
+        .join("\n") + "\n"
+}
+
+/// What a checksum was computed over, for bisecting a mismatch (`--verify-verbose`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChecksumDetails {
+    /// Bytes hashed after normalization
+    pub normalized_len: usize,
+    /// Lines hashed after normalization
+    pub lines: usize,
+    /// Stray `\r` bytes dropped by normalization (CRLF line endings aren't counted)
+    pub carriage_returns: usize,
+    /// Blank lines dropped by normalization
+    pub blank_lines: usize,
+    /// (line, short checksum of the list up to that line) at each quarter of the list;
+    /// compare against another copy to find the first region that differs
+    pub prefix_checksums: Vec<(usize, String)>,
+}
+
+/// Describe the normalized content `verify_checksum` hashes
+pub fn checksum_details(filename: &Path, config: &SortConfig) -> Result<ChecksumDetails, FopError> {
+    let content = read_list_text(filename, config)?;
+    let lines: Vec<&str> = content.lines().collect();
+    let data = hashed_data(&lines);
+    let carriage_returns = data.bytes().filter(|&b| b == b'\r').count();
+
+    // Normalize the way calculate_checksum does: no \r, no empty lines
+    let normalized: Vec<String> = data
+        .lines()
+        .map(|line| line.replace('\r', ""))
+        .filter(|line| !line.is_empty())
+        .collect();
+    let blank_lines = data.lines().count() - normalized.len();
+    let normalized_len = normalized.iter().map(|line| line.len() + 1).sum();
+
+    let mut prefix_checksums = Vec::new();
+    for quarter in 1..=4 {
+        let end = normalized.len() * quarter / 4;
+        if end == 0 || prefix_checksums.last().is_some_and(|(line, _)| *line == end) {
+            continue;
+        }
+        let prefix = normalized[..end].join("\n") + "\n";
+        let mut checksum = calculate_checksum(&prefix);
+        checksum.truncate(8);
+        prefix_checksums.push((end, checksum));
+    }
+
+    Ok(ChecksumDetails {
+        normalized_len,
+        lines: normalized.len(),
+        carriage_returns,
+        blank_lines,
+        prefix_checksums,
+    })
+}
+
 /// Add or update checksum in a filter list file.
 /// - `use_hash`: if true, use `#` prefix (for localhost/hosts files), otherwise `!`
 ///
 /// Returns `Ok(None)` if the file was unchanged (checksum already correct),
 /// or `Ok(Some(checksum))` with the written checksum if the file was modified.
-pub fn add_checksum(filename: &Path, use_hash: bool, quiet: bool, no_color: bool) -> io::Result<Option<String>> {
-    let content = fs::read_to_string(filename)?;
+pub fn add_checksum(
+    filename: &Path,
+    use_hash: bool,
+    quiet: bool,
+    no_color: bool,
+    config: &SortConfig,
+) -> Result<Option<String>, FopError> {
+    let content = read_list_text(filename, config)?;
     if content.is_empty() {
         return Ok(None);
     }
@@ -139,37 +202,15 @@ pub fn add_checksum(filename: &Path, use_hash: bool, quiet: bool, no_color: bool
     let checksum = calculate_checksum(&data_for_hash);
     let checksum_line = format!("{} Checksum: {}", prefix, checksum);
 
-    // Check if checksum would be unchanged
-    if let Some(idx) = checksum_idx {
-        if lines[idx].ends_with(&checksum) {
-            return Ok(None);
-        }
+    // Replace the existing checksum in place, or insert after line 1
+    // (matches Perl: $data =~ s/(\r?\n)/$1! Checksum: $checksum$1/).
+    // Either way it never goes above the [Adblock Plus 2.0] header.
+    let insert_at = crate::fop_datestamp::header_line_position(&without_checksum, checksum_idx);
+    if checksum_idx == Some(insert_at) && lines[insert_at].ends_with(&checksum) {
+        return Ok(None);
     }
-
-    // Build result - either replace existing or insert new
-    let result_lines: Vec<String> = if let Some(idx) = checksum_idx {
-        // Replace existing checksum line
-        lines.iter()
-            .enumerate()
-            .map(|(i, line)| {
-                if i == idx {
-                    checksum_line.clone()
-                } else {
-                    line.to_string()
-                }
-            })
-            .collect()
-    } else {
-        // Insert checksum after line 1 (matches Perl: $data =~ s/(\r?\n)/$1! Checksum: $checksum$1/)
-        let mut result: Vec<String> = Vec::with_capacity(without_checksum.len() + 1);
-        for (i, line) in without_checksum.iter().enumerate() {
-            result.push(line.to_string());
-            if i == 0 {
-                result.push(checksum_line.clone());
-            }
-        }
-        result
-    };
+    let mut result_lines: Vec<&str> = without_checksum.clone();
+    result_lines.insert(insert_at, &checksum_line);
 
     let mut result = result_lines.join(line_ending);
     if content.ends_with('\n') || content.ends_with("\r\n") {
@@ -181,20 +222,10 @@ pub fn add_checksum(filename: &Path, use_hash: bool, quiet: bool, no_color: bool
         return Ok(None);
     }
 
-    fs::write(filename, &result)?;
+    write_list_text(filename, &result, config)?;
 
     if !quiet {
-        if no_color {
-            if let Some(ref old) = old_checksum {
-                println!("Checksum: {} -> {} {}", old, checksum, filename.display());
-            } else {
-                println!("Checksum: {} {}", checksum, filename.display());
-            }
-        } else if let Some(ref old) = old_checksum {
-            println!("{} {} -> {} {}", "Checksum:".bold(), old.red(), checksum.green(), filename.display());
-        } else {
-            println!("{} {} {}", "Checksum:".bold(), checksum.green(), filename.display());
-        }
+        println!("{}", header_change_line("Checksum:", old_checksum.as_deref(), &checksum, filename, no_color));
     }
 
     Ok(Some(checksum))
@@ -1,9 +1,9 @@
 //! Datestamp/timestamp support for filter list headers
 //!
-//! Handles `! Last modified:` and `! Version:` lines in filter lists.
+//! Handles `! Last modified:`, `! Version:` and `! Rules:` lines in filter lists.
 
-use std::fs;
-use std::io;
+use crate::fop_error::FopError;
+use crate::fop_sort::{read_list_text, write_list_text, SortConfig};
 use std::path::Path;
 use std::time::{SystemTime, UNIX_EPOCH};
 use owo_colors::OwoColorize;
@@ -28,15 +28,31 @@ pub fn is_version_line(line: &str) -> bool {
     trimmed.len() >= 8 && trimmed[..8].eq_ignore_ascii_case("version:")
 }
 
+/// Check if line is a rule count line (`! Rules: N`)
+#[inline]
+pub fn is_rule_count_line(line: &str) -> bool {
+    let trimmed = line.trim_start().trim_start_matches(['!', '#']).trim_start();
+    trimmed.len() >= 6 && trimmed[..6].eq_ignore_ascii_case("rules:")
+}
+
 // =============================================================================
 // Timestamp Formatting
 // =============================================================================
 
+const MONTHS: [&str; 12] = ["Jan","Feb","Mar","Apr","May","Jun",
+                             "Jul","Aug","Sep","Oct","Nov","Dec"];
+const MONTH_NAMES: [&str; 12] = ["January", "February", "March", "April", "May", "June", "July",
+                                 "August", "September", "October", "November", "December"];
+
+/// `--timestamp-format=iso8601` as a pattern: "2026-01-30T08:31Z"
+pub const ISO8601_FORMAT: &str = "%Y-%m-%dT%H:%MZ";
+
+/// Tokens accepted in a `--timestamp-format` pattern (after `%`)
+const FORMAT_TOKENS: &str = "YymdeHMSbBZ%";
+
 /// Format Unix timestamp as "30 Jan 2026 08:31 UTC"
 #[inline]
 pub fn format_timestamp_utc(secs: u64) -> String {
-    const MONTHS: [&str; 12] = ["Jan","Feb","Mar","Apr","May","Jun",
-                                 "Jul","Aug","Sep","Oct","Nov","Dec"];
     let mut result = String::with_capacity(24);
     let (year, month, day, hours, minutes) = decompose_utc(secs);
     use std::fmt::Write;
@@ -44,6 +60,59 @@ pub fn format_timestamp_utc(secs: u64) -> String {
     result
 }
 
+/// The first `%` token in `format` that `format_timestamp` doesn't know, as written
+/// (`%q`, or `%` alone at the end)
+pub fn unknown_timestamp_token(format: &str) -> Option<String> {
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c == '%' {
+            match chars.next() {
+                Some(token) if FORMAT_TOKENS.contains(token) => {}
+                Some(token) => return Some(format!("%{}", token)),
+                None => return Some("%".to_string()),
+            }
+        }
+    }
+    None
+}
+
+/// Format Unix timestamp with a strftime-like `format` (UTC), or as `format_timestamp_utc`
+/// when there is none. Tokens: `%Y` `%y` year, `%m` month, `%d` `%e` day, `%H` `%M` `%S`
+/// time, `%b` `%B` month name, `%Z` "UTC", `%%` a percent sign.
+pub fn format_timestamp(secs: u64, format: Option<&str>) -> String {
+    let Some(format) = format else {
+        return format_timestamp_utc(secs);
+    };
+    let (year, month, day, hours, minutes) = decompose_utc(secs);
+    let mut result = String::with_capacity(format.len() + 16);
+    use std::fmt::Write;
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            result.push(c);
+            continue;
+        }
+        let _ = match chars.next() {
+            Some('Y') => write!(result, "{}", year),
+            Some('y') => write!(result, "{:02}", year % 100),
+            Some('m') => write!(result, "{:02}", month + 1),
+            Some('d') => write!(result, "{:02}", day),
+            Some('e') => write!(result, "{}", day),
+            Some('H') => write!(result, "{:02}", hours),
+            Some('M') => write!(result, "{:02}", minutes),
+            Some('S') => write!(result, "{:02}", secs % 60),
+            Some('b') => write!(result, "{}", MONTHS[month]),
+            Some('B') => write!(result, "{}", MONTH_NAMES[month]),
+            Some('Z') => write!(result, "UTC"),
+            Some('%') => write!(result, "%"),
+            // Unknown tokens are rejected up front (`unknown_timestamp_token`); keep them as written
+            Some(other) => write!(result, "%{}", other),
+            None => write!(result, "%"),
+        };
+    }
+    result
+}
+
 /// Format Unix timestamp as version "YYYYMMDDHHMM"
 #[inline]
 pub fn format_version_utc(secs: u64) -> String {
@@ -84,9 +153,10 @@ fn is_leap_year(year: u64) -> bool {
 // Line Update Functions (for use during sorting)
 // =============================================================================
 
-/// Update timestamp in header line (returns updated line or None if not a timestamp line)
+/// Update timestamp in header line (returns updated line or None if not a timestamp line).
+/// `format` is a `--timestamp-format` pattern; None keeps the default layout.
 #[inline]
-pub fn update_timestamp_line(line: &str) -> Option<String> {
+pub fn update_timestamp_line(line: &str, format: Option<&str>) -> Option<String> {
     let lower = line.to_ascii_lowercase();
     if !lower.contains("last modified:") && !lower.contains("last updated:") {
         return None;
@@ -94,7 +164,7 @@ pub fn update_timestamp_line(line: &str) -> Option<String> {
     let prefix = if line.trim_start().starts_with('#') { "#" } else { "!" };
     let keyword = if lower.contains("last modified:") { "Last modified" } else { "Last updated" };
     let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
-    Some(format!("{} {}: {}", prefix, keyword, format_timestamp_utc(now)))
+    Some(format!("{} {}: {}", prefix, keyword, format_timestamp(now, format)))
 }
 
 /// Update version line in header (returns updated line or None if not a version line)
@@ -108,17 +178,199 @@ pub fn update_version_line(line: &str) -> Option<String> {
     Some(format!("{} Version: {}", prefix, format_version_utc(now)))
 }
 
+/// Insert or update the `! Rules: N` header line in sorted content.
+/// Counts non-comment, non-blank rules (valid host entries only in localhost mode).
+/// An existing line anywhere in the leading comment block is updated in place;
+/// otherwise the line goes after the version/timestamp lines, or after the title.
+/// Returns None if the count is already current.
+pub fn update_rule_count(content: &str, comment_chars: &[String], localhost: bool, blackhole: &[String]) -> Option<String> {
+    let is_rule = |line: &str| {
+        if localhost {
+            return crate::fop_sort::is_localhost_entry(line, blackhole);
+        }
+        !(line.is_empty()
+            || line.starts_with('!')
+            || line.starts_with("%include")
+            || (line.starts_with('[') && line.ends_with(']'))
+            || crate::fop_sort::is_comment(line, comment_chars, false))
+    };
+
+    let lines: Vec<&str> = content.lines().collect();
+    let count = lines.iter().filter(|line| is_rule(line)).count();
+    let header_len = lines.iter().position(|line| is_rule(line)).unwrap_or(lines.len());
+    let header = &lines[..header_len];
+    let default_prefix = if localhost { "#" } else { "!" };
+
+    let mut result: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+    if let Some(idx) = header.iter().position(|line| is_rule_count_line(line)) {
+        let prefix = if header[idx].trim_start().starts_with('#') { "#" } else { "!" };
+        let updated = format!("{} Rules: {}", prefix, count);
+        if header[idx] == updated {
+            return None;
+        }
+        result[idx] = updated;
+    } else {
+        let insert_at = header
+            .iter()
+            .rposition(|line| is_version_line(line) || is_timestamp_line(line))
+            .map(|idx| idx + 1)
+            .unwrap_or(if header.is_empty() { 0 } else { 1 });
+        result.insert(insert_at, format!("{} Rules: {}", default_prefix, count));
+    }
+
+    let mut updated = result.join("\n");
+    if content.ends_with('\n') {
+        updated.push('\n');
+    }
+    Some(updated)
+}
+
+/// Known metadata fields in canonical header order (`Last updated` sorts with `Last modified`)
+const HEADER_FIELDS: &[(&str, usize)] = &[
+    ("Version", 0),
+    ("Title", 1),
+    ("Last modified", 2),
+    ("Last updated", 2),
+    ("Expires", 3),
+    ("Homepage", 4),
+];
+
+/// Parse a metadata comment like `!Title :EasyList` into (rank, canonical line).
+fn header_field(line: &str, prefix: char) -> Option<(usize, String)> {
+    let rest = line.trim().strip_prefix(prefix)?;
+    let (key, value) = rest.split_once(':')?;
+    let key = key.trim();
+    let &(label, rank) = HEADER_FIELDS.iter().find(|(label, _)| label.eq_ignore_ascii_case(key))?;
+    let value = value.trim();
+    let canonical = if value.is_empty() {
+        format!("{} {}:", prefix, label)
+    } else {
+        format!("{} {}: {}", prefix, label, value)
+    };
+    Some((rank, canonical))
+}
+
+/// Put the known metadata lines (`Version`, `Title`, `Last modified`, `Expires`, `Homepage`)
+/// of the leading comment block in canonical order, with one space after each colon.
+/// They are grouped where the first of them appeared; other comments and everything
+/// after the first rule are left alone. Returns None if nothing changes.
+pub fn normalize_header(content: &str, localhost: bool) -> Option<String> {
+    let prefix = if localhost { '#' } else { '!' };
+    let lines: Vec<&str> = content.lines().collect();
+    let header_len = lines
+        .iter()
+        .position(|line| {
+            let line = line.trim_start_matches('\u{feff}').trim();
+            !(line.is_empty() || line.starts_with(prefix) || (line.starts_with('[') && line.ends_with(']')))
+        })
+        .unwrap_or(lines.len());
+    reorder_header_fields(content, &lines, header_len, |line| header_field(line, prefix))
+}
+
+/// Put the known metadata lines of the leading comment block in the same order as
+/// `normalize_header`, but keep each line exactly as written (`--sort-header`). The block
+/// ends at the first rule or blank line; other comments (including `! Checksum:`) keep
+/// their order. Returns None if nothing changes.
+pub fn sort_header(content: &str, localhost: bool) -> Option<String> {
+    let prefix = if localhost { '#' } else { '!' };
+    let lines: Vec<&str> = content.lines().collect();
+    let header_len = lines
+        .iter()
+        .position(|line| {
+            let line = line.trim_start_matches('\u{feff}').trim();
+            !(line.starts_with(prefix) || (line.starts_with('[') && line.ends_with(']')))
+        })
+        .unwrap_or(lines.len());
+    reorder_header_fields(content, &lines, header_len, |line| {
+        header_field(line, prefix).map(|(rank, _)| (rank, line.to_string()))
+    })
+}
+
+/// Group the lines of `lines[..header_len]` that `field` ranks, in rank order, where the
+/// first of them appeared; `field` also gives the text each is written as
+fn reorder_header_fields(
+    content: &str,
+    lines: &[&str],
+    header_len: usize,
+    field: impl Fn(&str) -> Option<(usize, String)>,
+) -> Option<String> {
+    let mut fields: Vec<(usize, String)> = Vec::new();
+    let mut others: Vec<String> = Vec::with_capacity(header_len);
+    let mut insert_at = None;
+    for line in &lines[..header_len] {
+        match field(line) {
+            Some(field) => {
+                insert_at.get_or_insert(others.len());
+                fields.push(field);
+            }
+            None => others.push(line.to_string()),
+        }
+    }
+    let insert_at = insert_at?;
+    fields.sort_by_key(|(rank, _)| *rank);
+
+    let mut result = others;
+    result.splice(insert_at..insert_at, fields.into_iter().map(|(_, line)| line));
+    result.extend(lines[header_len..].iter().map(|line| line.to_string()));
+    let mut updated = result.join("\n");
+    if content.ends_with('\n') {
+        updated.push('\n');
+    }
+    (updated != content).then_some(updated)
+}
+
+/// Index of a `[Adblock Plus 2.0]`-style header within the leading `!` comment block.
+/// Checksum and timestamp lines belong after it: ABP expects the header on line 1.
+pub(crate) fn list_header_index<S: AsRef<str>>(lines: &[S]) -> Option<usize> {
+    let is_header = |line: &str| line.starts_with('[') && line.ends_with(']');
+    lines
+        .iter()
+        .map(|line| line.as_ref().trim_start_matches('\u{feff}').trim())
+        .take_while(|line| line.is_empty() || line.starts_with('!') || is_header(line))
+        .position(is_header)
+}
+
+/// Where a header line goes: its current slot if that's already below the `[...]` header,
+/// otherwise directly after the header (or after line 1 when there is none).
+/// `lines` excludes the line being placed; `existing` is its index in the original file.
+pub(crate) fn header_line_position<S: AsRef<str>>(lines: &[S], existing: Option<usize>) -> usize {
+    let header = list_header_index(lines);
+    let position = match existing {
+        Some(idx) if header.is_none_or(|h| idx > h) => idx,
+        _ => header.map_or(1, |h| h + 1),
+    };
+    position.min(lines.len())
+}
+
 // =============================================================================
 // File Operations
 // =============================================================================
 
+/// `Label: old -> new file` (or `Label: new file` for a new line) for a header update
+pub(crate) fn header_change_line(label: &str, old: Option<&str>, new: &str, filename: &Path, no_color: bool) -> String {
+    match (old, crate::colors_off(no_color)) {
+        (Some(old), true) => format!("{} {} -> {} {}", label, old, new, filename.display()),
+        (None, true) => format!("{} {} {}", label, new, filename.display()),
+        (Some(old), false) => format!("{} {} -> {} {}", label.bold(), old.red(), new.green(), filename.display()),
+        (None, false) => format!("{} {} {}", label.bold(), new.green(), filename.display()),
+    }
+}
+
 /// Add or update timestamp in a filter list file.
 /// - `use_hash`: if true, use `#` prefix (for localhost/hosts files), otherwise `!`
+/// - `config`: the list's encodings and `--timestamp-format` pattern (see `format_timestamp`)
 ///
-/// If timestamp exists, updates it in place. If not, inserts after line 1.
+/// If timestamp exists, updates it in place. If not, inserts after line 1
+/// (after the `[Adblock Plus 2.0]` header, which always stays first).
 /// Returns true if the file was modified.
-pub fn add_timestamp(filename: &Path, use_hash: bool, quiet: bool, no_color: bool) -> io::Result<bool> {
-    let content = fs::read_to_string(filename)?;
+pub fn add_timestamp(
+    filename: &Path,
+    use_hash: bool,
+    quiet: bool,
+    no_color: bool,
+    config: &SortConfig,
+) -> Result<bool, FopError> {
+    let content = read_list_text(filename, config)?;
     if content.is_empty() {
         return Ok(false);
     }
@@ -127,7 +379,7 @@ pub fn add_timestamp(filename: &Path, use_hash: bool, quiet: bool, no_color: boo
     let prefix = if use_hash { "#" } else { "!" };
 
     let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
-    let timestamp = format_timestamp_utc(now);
+    let timestamp = format_timestamp(now, config.timestamp_format);
 
     let lines: Vec<&str> = content.lines().collect();
     let timestamp_idx = lines.iter().position(|line| is_timestamp_line(line));
@@ -138,34 +390,19 @@ pub fn add_timestamp(filename: &Path, use_hash: bool, quiet: bool, no_color: boo
         line.find(':').map(|pos| line[pos + 1..].trim().to_string())
     });
 
-    let result_lines: Vec<String> = if let Some(idx) = timestamp_idx {
-        // Update existing timestamp, preserving label
-        lines.iter()
-            .enumerate()
-            .map(|(i, line)| {
-                if i == idx {
-                    if let Some(colon_pos) = line.find(':') {
-                        format!("{}: {}", &line[..colon_pos], timestamp)
-                    } else {
-                        format!("{} Last modified: {}", prefix, timestamp)
-                    }
-                } else {
-                    line.to_string()
-                }
-            })
-            .collect()
-    } else {
-        // Insert after line 1 (before checksum if present)
-        let timestamp_line = format!("{} Last modified: {}", prefix, timestamp);
-        let mut result: Vec<String> = Vec::with_capacity(lines.len() + 1);
-        for (i, line) in lines.iter().enumerate() {
-            result.push(line.to_string());
-            if i == 0 {
-                result.push(timestamp_line.clone());
-            }
-        }
-        result
+    // Update existing timestamp (preserving label), or add one after line 1 (before checksum if present)
+    let timestamp_line = match timestamp_idx.and_then(|idx| lines[idx].find(':').map(|pos| (idx, pos))) {
+        Some((idx, colon_pos)) => format!("{}: {}", &lines[idx][..colon_pos], timestamp),
+        None => format!("{} Last modified: {}", prefix, timestamp),
     };
+    let mut result_lines: Vec<String> = lines
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| Some(*i) != timestamp_idx)
+        .map(|(_, line)| line.to_string())
+        .collect();
+    let insert_at = header_line_position(&result_lines, timestamp_idx);
+    result_lines.insert(insert_at, timestamp_line);
 
     let mut result = result_lines.join(line_ending);
     if content.ends_with('\n') || content.ends_with("\r\n") {
@@ -176,20 +413,10 @@ pub fn add_timestamp(filename: &Path, use_hash: bool, quiet: bool, no_color: boo
         return Ok(false);
     }
 
-    fs::write(filename, &result)?;
+    write_list_text(filename, &result, config)?;
 
     if !quiet {
-        if no_color {
-            if let Some(ref old) = old_timestamp {
-                println!("Timestamp: {} -> {} {}", old, timestamp, filename.display());
-            } else {
-                println!("Timestamp: {} {}", timestamp, filename.display());
-            }
-        } else if let Some(ref old) = old_timestamp {
-            println!("{} {} -> {} {}", "Timestamp:".bold(), old.red(), timestamp.green(), filename.display());
-        } else {
-            println!("{} {} {}", "Timestamp:".bold(), timestamp.green(), filename.display());
-        }
+        println!("{}", header_change_line("Timestamp:", old_timestamp.as_deref(), &timestamp, filename, no_color));
     }
 
     Ok(true)
@@ -0,0 +1,56 @@
+//! Error type for the library API
+//!
+//! Sorting, checksum, timestamp, typo-rule and git helpers return `FopError` so callers
+//! can tell an unreadable file from a successful run. The CLI prints them at the boundary.
+
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum FopError {
+    /// Reading, writing or replacing a file failed
+    Io(io::Error),
+    /// A list couldn't be opened for sorting; nothing was written
+    Open { path: PathBuf, source: io::Error },
+    /// The sorted copy of a list couldn't be written to its temp file; the list is untouched
+    TempFile { path: PathBuf, source: io::Error },
+    /// A list isn't valid UTF-8 (`strict_utf8`); `line` is the first invalid line
+    Utf8 { path: PathBuf, line: usize },
+    /// A rules or config file couldn't be parsed
+    Parse(String),
+    /// A git command failed or couldn't be run (git's stderr when available)
+    GitFailed(String),
+}
+
+impl fmt::Display for FopError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FopError::Io(e) => write!(f, "{}", e),
+            FopError::Open { path, .. } => write!(f, "Cannot open {}", path.display()),
+            FopError::TempFile { path, source } => {
+                write!(f, "Cannot create temp file for {}: {}", path.display(), source)
+            }
+            FopError::Utf8 { path, line } => {
+                write!(f, "{} is not valid UTF-8 (line {})", path.display(), line)
+            }
+            FopError::Parse(message) => write!(f, "{}", message),
+            FopError::GitFailed(message) => write!(f, "git failed: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for FopError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FopError::Io(e) | FopError::Open { source: e, .. } | FopError::TempFile { source: e, .. } => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for FopError {
+    fn from(e: io::Error) -> Self {
+        FopError::Io(e)
+    }
+}
@@ -3,8 +3,9 @@
 use owo_colors::OwoColorize;
 use regex::Regex;
 use std::io::{self, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use crate::fop_error::FopError;
 use crate::fop_sort::SORT_CHANGES;
 use std::sync::LazyLock;
 use rustyline::DefaultEditor;
@@ -123,7 +124,7 @@ pub fn check_banned_domains(no_color: bool, auto_remove: bool, base_cmd: &[Strin
     
     println!("\n{} banned domain(s) found in new additions:", banned_found.len());
     for (domain, rule, file) in &banned_found {
-        if no_color {
+        if crate::colors_off(no_color) {
             println!("  {} in: {} ({})", domain, rule, file);
         } else {
             println!("  {} in: {} ({})", domain.red(), rule, file);
@@ -154,7 +155,7 @@ pub fn check_banned_domains(no_color: bool, auto_remove: bool, base_cmd: &[Strin
 fn remove_banned_lines(banned: &[(String, String, String)], base_cmd: &[String]) -> bool {
     use std::collections::HashMap;
     use std::collections::HashSet;
-    use std::fs;
+    use crate::fop_sort::{read_list_to_string, write_list};
     
     // Group by file
     let mut files_to_fix: HashMap<String, HashSet<String>> = HashMap::new();
@@ -170,7 +171,7 @@ fn remove_banned_lines(banned: &[(String, String, String)], base_cmd: &[String])
             continue;
         }
         
-        let content = match fs::read_to_string(path) {
+        let content = match read_list_to_string(path) {
             Ok(c) => c,
             Err(e) => {
                 eprintln!("Error reading {}: {}", file, e);
@@ -188,7 +189,7 @@ fn remove_banned_lines(banned: &[(String, String, String)], base_cmd: &[String])
         }
         
         // Write back
-        if let Err(e) = fs::write(path, new_content) {
+        if let Err(e) = write_list(path, new_content.as_bytes()) {
             eprintln!("Error writing {}: {}", file, e);
             return false;
         }
@@ -225,6 +226,8 @@ pub struct RepoDefinition {
     pub check_changes: &'static [&'static str],
     pub difference: &'static [&'static str],
     pub commit: &'static [&'static str],
+    /// Commit only the paths that follow the message (`--commit-pathspec`)
+    pub commit_paths: &'static [&'static str],
     pub pull: &'static [&'static str],
     pub push: &'static [&'static str],
 }
@@ -237,6 +240,7 @@ pub const GIT: RepoDefinition = RepoDefinition {
     check_changes: &["status", "-s", "--untracked-files=no"],
     difference: &["diff"],
     commit: &["commit", "-a", "-m"],
+    commit_paths: &["commit", "-m"],
     pull: &["pull", "--rebase"],
     push: &["push"],
 };
@@ -340,6 +344,11 @@ pub fn git_available() -> bool {
 }
 
 pub fn check_repo_changes(base_cmd: &[String], repo: &RepoDefinition) -> Option<bool> {
+    uncommitted_changes(base_cmd, repo).map(|changes| !changes.is_empty())
+}
+
+/// Staged and unstaged changes to tracked files, one short-status line each (` M list.txt`)
+pub fn uncommitted_changes(base_cmd: &[String], repo: &RepoDefinition) -> Option<Vec<String>> {
     if base_cmd.is_empty() {
         return None;
     }
@@ -349,17 +358,167 @@ pub fn check_repo_changes(base_cmd: &[String], repo: &RepoDefinition) -> Option<
         .output()
         .ok()?;
 
-    Some(!output.stdout.is_empty())
+    Some(
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(String::from)
+            .collect(),
+    )
+}
+
+/// Repository-root-relative paths as literal pathspecs, whatever the current directory
+fn top_level_pathspecs(paths: &[String]) -> impl Iterator<Item = String> + '_ {
+    paths.iter().map(|path| format!(":(top,literal){}", path))
+}
+
+/// Working-tree diff, limited to `paths` when non-empty
+pub fn get_diff(base_cmd: &[String], repo: &RepoDefinition, paths: &[String]) -> Option<String> {
+    let mut cmd = Command::new(&base_cmd[0]);
+    cmd.args(&base_cmd[1..]).args(repo.difference);
+    if !paths.is_empty() {
+        cmd.arg("--").args(top_level_pathspecs(paths));
+    }
+    let output = cmd.output().ok()?;
+
+    String::from_utf8(output.stdout).ok().map(|diff| expand_gzip_diffs(base_cmd, diff, 3))
+}
+
+/// Root of the repository's working tree
+fn work_tree(base_cmd: &[String]) -> Option<PathBuf> {
+    let output = Command::new(&base_cmd[0])
+        .args(&base_cmd[1..])
+        .args(["rev-parse", "--show-toplevel"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout).ok().map(|s| PathBuf::from(s.trim()))
+}
+
+/// Path of a gzipped list from git's `Binary files a/X.gz and b/X.gz differ` line
+fn gzip_binary_diff_path(line: &str) -> Option<&str> {
+    let (old, new) = line.strip_prefix("Binary files a/")?.strip_suffix(" differ")?.split_once(" and b/")?;
+    (old == new && crate::fop_sort::is_gzip(Path::new(old))).then_some(old)
 }
 
-pub fn get_diff(base_cmd: &[String], repo: &RepoDefinition) -> Option<String> {
+/// Decompressed text of a gzipped list in the index and in the working tree
+fn gzip_versions(base_cmd: &[String], root: &Path, path: &str) -> Option<(String, String)> {
+    use crate::fop_sort::{gunzip, read_list};
     let output = Command::new(&base_cmd[0])
         .args(&base_cmd[1..])
-        .args(repo.difference)
+        .args(["show", &format!(":{}", path)])
         .output()
         .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let old = String::from_utf8(gunzip(&output.stdout).ok()?).ok()?;
+    let new = String::from_utf8(read_list(&root.join(path)).ok()?).ok()?;
+    Some((old, new))
+}
+
+/// Replace git's binary stanza for `.gz` lists with a unified diff of the decompressed
+/// content, so gzipped lists are reviewed, counted and checked like plain ones
+fn expand_gzip_diffs(base_cmd: &[String], diff: String, context: usize) -> String {
+    if !diff.lines().any(|line| gzip_binary_diff_path(line).is_some()) {
+        return diff;
+    }
+    let Some(root) = work_tree(base_cmd) else {
+        return diff;
+    };
+
+    let mut expanded = String::with_capacity(diff.len());
+    for line in diff.lines() {
+        let versions = gzip_binary_diff_path(line)
+            .and_then(|path| Some((path, gzip_versions(base_cmd, &root, path)?)));
+        match versions {
+            Some((path, (old, new))) => expanded.push_str(
+                &similar::TextDiff::from_lines(&old, &new)
+                    .unified_diff()
+                    .context_radius(context)
+                    .header(&format!("a/{}", path), &format!("b/{}", path))
+                    .to_string(),
+            ),
+            None => {
+                expanded.push_str(line);
+                expanded.push('\n');
+            }
+        }
+    }
+    expanded
+}
+
+/// `--commit-pathspec`: the files sorted in this run that match the glob, as paths
+/// relative to the repository root (`repo_root`), in order
+pub fn sorted_files_matching(sorted: &[&Path], repo_root: &Path, pathspec: &globset::GlobMatcher) -> Vec<String> {
+    let mut matched: Vec<String> = sorted
+        .iter()
+        .filter_map(|path| path.strip_prefix(repo_root).ok())
+        .filter(|relative| pathspec.is_match(relative))
+        .map(|relative| relative.to_string_lossy().replace('\\', "/"))
+        .collect();
+    matched.sort_unstable();
+    matched
+}
+
+/// Commit everything tracked, or only `paths` when non-empty
+fn commit_command(base_cmd: &[String], repo: &RepoDefinition, message: &str, paths: &[String]) -> Command {
+    let mut cmd = Command::new(&base_cmd[0]);
+    cmd.args(&base_cmd[1..]);
+    if paths.is_empty() {
+        cmd.args(repo.commit).arg(message);
+    } else {
+        cmd.args(repo.commit_paths).arg(message).arg("--").args(top_level_pathspecs(paths));
+    }
+    cmd
+}
+
+/// Files under `location` that differ from `git_ref` (`git diff --name-only <ref>`).
+/// Paths outside `location` and deleted files are left out.
+/// Fails with `GitFailed` if git can't run or the diff fails (not a repository, unknown ref).
+pub fn files_changed_since(location: &Path, git_ref: &str, git_binary: Option<&str>) -> Result<Vec<PathBuf>, FopError> {
+    let output = Command::new(git_binary.unwrap_or("git"))
+        .args(["diff", "--name-only", "--relative", "--diff-filter=d", "-z", git_ref, "--"])
+        .current_dir(location)
+        .output()
+        .map_err(|e| FopError::GitFailed(e.to_string()))?;
+    if !output.status.success() {
+        return Err(FopError::GitFailed(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(
+        stdout
+            .split('\0')
+            .filter(|p| !p.is_empty())
+            .map(|p| location.join(p))
+            .filter(|p| p.is_file())
+            .collect(),
+    )
+}
 
-    String::from_utf8(output.stdout).ok()
+/// Raw contents of `path` at `git_ref` (`git show <ref>:./<file>`), or None if the file
+/// didn't exist there. Fails with `GitFailed` if git can't run or the ref is unknown.
+pub fn file_at_ref(path: &Path, git_ref: &str, git_binary: Option<&str>) -> Result<Option<Vec<u8>>, FopError> {
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+    let output = Command::new(git_binary.unwrap_or("git"))
+        .arg("show")
+        .arg(format!("{}:./{}", git_ref, name))
+        .current_dir(path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new(".")))
+        // The missing-file check below matches git's untranslated messages
+        .env("LC_ALL", "C")
+        .output()
+        .map_err(|e| FopError::GitFailed(e.to_string()))?;
+    if output.status.success() {
+        return Ok(Some(output.stdout));
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if stderr.contains("exists on disk, but not in") || stderr.contains("does not exist in") {
+        return Ok(None);
+    }
+    Err(FopError::GitFailed(stderr.trim().to_string()))
 }
 
 // =============================================================================
@@ -370,16 +529,63 @@ pub fn get_diff(base_cmd: &[String], repo: &RepoDefinition) -> Option<String> {
 fn is_large_change(diff: &str) -> bool {
     const LARGE_LINES_THRESHOLD: usize = 25;
 
-    let changed_lines = diff
-        .lines()
-        .filter(|line| {
-            (line.starts_with('+') || line.starts_with('-'))
-                && !line.starts_with("+++")
-                && !line.starts_with("---")
-        })
-        .count();
+    changed_lines(diff).count() > LARGE_LINES_THRESHOLD
+}
+
+/// Added/removed lines of a unified diff (excluding +++/--- file headers)
+#[inline]
+fn changed_lines(diff: &str) -> impl Iterator<Item = &str> {
+    diff.lines().filter(|line| {
+        (line.starts_with('+') || line.starts_with('-'))
+            && !line.starts_with("+++")
+            && !line.starts_with("---")
+    })
+}
 
-    changed_lines > LARGE_LINES_THRESHOLD
+/// Summary of a unified diff: lines added, removed, and whether it only reorders lines
+#[derive(Debug, PartialEq)]
+pub(crate) struct DiffStats {
+    pub added: usize,
+    pub removed: usize,
+    pub reorder_only: bool,
+}
+
+/// Count added/removed lines; a diff is a pure reorder when both sides hold the same lines
+pub(crate) fn diff_stats(diff: &str) -> DiffStats {
+    let mut added: Vec<&str> = Vec::new();
+    let mut removed: Vec<&str> = Vec::new();
+    for line in changed_lines(diff) {
+        if let Some(content) = line.strip_prefix('+') {
+            added.push(content);
+        } else {
+            removed.push(&line[1..]);
+        }
+    }
+    let reorder_only = !added.is_empty() && added.len() == removed.len() && {
+        added.sort_unstable();
+        removed.sort_unstable();
+        added == removed
+    };
+    DiffStats { added: added.len(), removed: removed.len(), reorder_only }
+}
+
+/// Print a one-line added/removed/net summary of a diff
+fn print_diff_stats(diff: &str, no_color: bool) {
+    let stats = diff_stats(diff);
+    let net = stats.added as isize - stats.removed as isize;
+    let reorder = if stats.reorder_only { " (reorder only)" } else { "" };
+    if crate::colors_off(no_color) {
+        println!("Diff stats: +{} -{} (net {:+}){}", stats.added, stats.removed, net, reorder);
+    } else {
+        println!(
+            "{} {} {} (net {:+}){}",
+            "Diff stats:".bold(),
+            format!("+{}", stats.added).green(),
+            format!("-{}", stats.removed).red(),
+            net,
+            reorder
+        );
+    }
 }
 
 /// Prompt user to restore changes
@@ -406,7 +612,7 @@ fn prompt_restore(base_cmd: &[String], no_color: bool) -> io::Result<bool> {
 
 #[inline]
 fn print_diff_line(line: &str, no_color: bool) {
-    if no_color {
+    if crate::colors_off(no_color) {
         println!("{}", line);
     } else if line.starts_with('+') && !line.starts_with("+++") {
         println!("{}", line.green());
@@ -556,7 +762,7 @@ fn get_commit_url(base_cmd: &[String]) -> Option<String> {
 /// Prompt user to select a remote
 fn prompt_for_remote(remotes: &[String], no_color: bool) -> Option<String> {
     println!("Available remotes: {}", 
-        if no_color {
+        if crate::colors_off(no_color) {
             remotes.join(", ")
         } else {
             remotes.iter().map(|s| s.yellow().to_string()).collect::<Vec<_>>().join(", ")
@@ -577,7 +783,13 @@ fn prompt_for_remote(remotes: &[String], no_color: bool) -> Option<String> {
 }
 
 /// Convert git remote URL to web URL and generate PR/MR link
-fn generate_pr_url(remote: &str, base_branch: &str, pr_branch: &str, body: Option<&str>) -> Option<String> {
+pub(crate) fn generate_pr_url(
+    remote: &str,
+    base_branch: &str,
+    pr_branch: &str,
+    body: Option<&str>,
+    labels: &[String],
+) -> Option<String> {
     let remote = remote.trim().trim_end_matches(".git");
     
     // Build base URL from SSH or HTTPS format
@@ -606,12 +818,50 @@ fn generate_pr_url(remote: &str, base_branch: &str, pr_branch: &str, body: Optio
         if let Some(b) = body {
             url.push_str(&format!("&body={}", urlencoding::encode(b)));
         }
+        if !labels.is_empty() {
+            let labels: Vec<_> = labels.iter().map(|label| urlencoding::encode(label)).collect();
+            url.push_str(&format!("&labels={}", labels.join(",")));
+        }
         Some(url)
     } else {
         None
     }
 }
 
+/// Check if the GitHub CLI is available
+fn gh_available() -> bool {
+    Command::new("gh")
+        .arg("--version")
+        .output()
+        .map(|o| o.status.success())
+        .unwrap_or(false)
+}
+
+/// Open the PR with the GitHub CLI, returning its URL
+fn gh_create_pr(
+    work_tree: &Path,
+    base_branch: &str,
+    pr_branch: &str,
+    title: &str,
+    body: &str,
+    labels: &[String],
+) -> Option<String> {
+    let mut cmd = Command::new("gh");
+    cmd.current_dir(work_tree)
+        .args(["pr", "create", "--base", base_branch, "--head", pr_branch])
+        .args(["--title", title, "--body", body]);
+    for label in labels {
+        cmd.args(["--label", label]);
+    }
+    let output = cmd.output().ok()?;
+    if !output.status.success() {
+        eprintln!("gh pr create failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+        return None;
+    }
+    let stdout = String::from_utf8(output.stdout).ok()?;
+    stdout.lines().last().map(|line| line.trim().to_string()).filter(|url| !url.is_empty())
+}
+
 /// Switch to a branch
 fn checkout_branch(base_cmd: &[String], branch: &str) -> io::Result<bool> {
     Command::new(&base_cmd[0])
@@ -631,7 +881,7 @@ pub fn get_added_lines(base_cmd: &[String]) -> Option<Vec<crate::fop_typos::Addi
         .output()
         .ok()?;
 
-    let diff = String::from_utf8(output.stdout).ok()?;
+    let diff = expand_gzip_diffs(base_cmd, String::from_utf8(output.stdout).ok()?, 0);
     let mut added = Vec::new();
     let mut current_file = String::new();
     let mut line_num: usize = 0;
@@ -713,10 +963,13 @@ pub fn create_pull_request(
     pr_branch_override: &Option<String>,
     quiet: bool,
     show_changes: bool,
+    body: Option<&str>,
+    labels: &[String],
     no_color: bool,
+    paths: &[String],
 ) -> io::Result<Option<String>> {
     // Show diff first
-    let diff = match get_diff(base_cmd, repo) {
+    let diff = match get_diff(base_cmd, repo, paths) {
         Some(d) if !d.is_empty() => d,
         _ => {
             println!("\nNo changes have been recorded by the repository.");
@@ -726,6 +979,7 @@ pub fn create_pull_request(
 
     if !quiet {
         println!("\nThe following changes will be included in the PR:");
+        print_diff_stats(&diff, no_color);
         print_diff(&diff, no_color);
     }
 
@@ -763,11 +1017,7 @@ pub fn create_pull_request(
     }
 
     // Commit changes
-    let status = Command::new(&base_cmd[0])
-        .args(&base_cmd[1..])
-        .args(repo.commit)
-        .arg(message)
-        .status()?;
+    let status = commit_command(base_cmd, repo, message, paths).status()?;
     if !status.success() {
         eprintln!("Failed to commit changes");
         let _ = checkout_branch(base_cmd, &current_branch);
@@ -795,21 +1045,46 @@ pub fn create_pull_request(
     // Switch back to original branch
     let _ = checkout_branch(base_cmd, &current_branch);
 
-    // Build PR body if show_changes enabled
-    let pr_body = if show_changes {
-        let body = format_pr_changes();
-        if body.is_empty() { None } else { Some(body) }
-    } else {
-        None
-    };
+    // Build PR body: --pr-body text first, then rule changes if show_changes enabled
+    let changes = if show_changes { format_pr_changes() } else { String::new() };
+    let pr_body = [body.unwrap_or("").trim(), changes.trim()]
+        .into_iter()
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    let pr_body = if pr_body.is_empty() { None } else { Some(pr_body) };
+
+    let remote_url = get_remote_url(base_cmd, remote);
+
+    // Open the PR directly when the GitHub CLI is available, else fall back to the URL
+    if remote_url.as_deref().is_some_and(|url| url.contains("github")) && gh_available() {
+        let opened = work_tree(base_cmd).and_then(|root| {
+            gh_create_pr(&root, &base_branch, &pr_branch, message, pr_body.as_deref().unwrap_or(""), labels)
+        });
+        if let Some(url) = opened {
+            if crate::colors_off(no_color) {
+                println!("\nPull request opened successfully!");
+                println!("\nPR:\n  {}", url);
+            } else {
+                println!("\n{}", "Pull request opened successfully!".green());
+                println!("\nPR:\n  {}", url.cyan());
+            }
+            return Ok(Some(url));
+        }
+    }
 
     // Generate PR URL
-    let pr_url = get_remote_url(base_cmd, remote)
-        .and_then(|remote| generate_pr_url(&remote, &base_branch, &pr_branch, pr_body.as_deref()));
+    let pr_url = remote_url
+        .and_then(|remote| generate_pr_url(&remote, &base_branch, &pr_branch, pr_body.as_deref(), labels));
 
     if let Some(ref url) = pr_url {
-        println!("\n{}", "Pull request branch pushed successfully!".green());
-        println!("\nCreate PR at:\n  {}", url.cyan());
+        if crate::colors_off(no_color) {
+            println!("\nPull request branch pushed successfully!");
+            println!("\nCreate PR at:\n  {}", url);
+        } else {
+            println!("\n{}", "Pull request branch pushed successfully!".green());
+            println!("\nCreate PR at:\n  {}", url.cyan());
+        }
     } else {
         println!(
             "\nBranch '{}' pushed. Create PR/MR manually in your git web interface.",
@@ -851,7 +1126,11 @@ fn pull_and_push(
                 if !out.stderr.is_empty() {
                     eprint!("{}", String::from_utf8_lossy(&out.stderr));
                 }
-                if i == 1 { push_failed = true; }
+                if i == 0 {
+                    eprintln!("Pull failed; attempting push anyway.");
+                } else {
+                    push_failed = true;
+                }
             }
             Err(e) => {
                 eprintln!("Git command failed: {}", e);
@@ -876,9 +1155,54 @@ fn current_branch_name(base_cmd: &[String]) -> Option<String> {
     if name.is_empty() { None } else { Some(name) }
 }
 
-/// Attempt rebase and retry push after initial push failure
-#[inline]
-fn rebase_and_retry_push(base_cmd: &[String], repo: &RepoDefinition, quiet: bool, comment: Option<&str>, no_color: bool) {
+/// Outcome of one rebase-and-push attempt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PushAttempt {
+    Pushed,
+    /// Rebase or push failed in a way retrying won't fix
+    Failed,
+    /// Push rejected again (likely another concurrent commit)
+    Retry,
+}
+
+/// Run `attempt` up to `retries` times with exponential backoff (`base_delay`, doubling)
+/// between attempts, until it pushes or fails outright. Returns true once a push succeeds.
+pub(crate) fn retry_push(
+    retries: usize,
+    quiet: bool,
+    base_delay: std::time::Duration,
+    mut attempt: impl FnMut() -> PushAttempt,
+) -> bool {
+    for number in 1..=retries {
+        if number > 1 {
+            let delay = base_delay * (1 << (number - 2));
+            if !quiet {
+                eprintln!("Retrying in {}s (attempt {} of {})...", delay.as_secs(), number, retries);
+            }
+            std::thread::sleep(delay);
+        }
+        match attempt() {
+            PushAttempt::Pushed => return true,
+            PushAttempt::Failed => return false,
+            PushAttempt::Retry => {}
+        }
+    }
+
+    eprintln!("\nPush still failed after {} retr{} (likely another concurrent commit). Suggested fix:",
+        retries, if retries == 1 { "y" } else { "ies" });
+    eprintln!("    git pull --rebase --autostash");
+    eprintln!("    git push");
+    false
+}
+
+/// Rebase onto the upstream and push once
+fn rebase_and_push(
+    base_cmd: &[String],
+    repo: &RepoDefinition,
+    quiet: bool,
+    comment: Option<&str>,
+    no_color: bool,
+) -> PushAttempt {
     if !quiet {
         eprintln!("Push failed. Attempting rebase...");
     }
@@ -888,7 +1212,7 @@ fn rebase_and_retry_push(base_cmd: &[String], repo: &RepoDefinition, quiet: bool
         .output() else {
         eprintln!("Rebase failed to execute. Run manually:");
         eprintln!("    git pull --rebase --autostash && git push");
-        return;
+        return PushAttempt::Failed;
     };
 
     if !output.status.success() {
@@ -926,7 +1250,7 @@ fn rebase_and_retry_push(base_cmd: &[String], repo: &RepoDefinition, quiet: bool
             eprintln!("    git pull --rebase --autostash");
             eprintln!("    git push");
         }
-        return;
+        return PushAttempt::Failed;
     }
 
     let Ok(retry) = Command::new(&base_cmd[0])
@@ -934,7 +1258,7 @@ fn rebase_and_retry_push(base_cmd: &[String], repo: &RepoDefinition, quiet: bool
             .args(repo.push)
         .output() else {
         eprintln!("Push failed to execute. Retry manually: git push");
-        return;
+        return PushAttempt::Failed;
     };
 
     if retry.status.success() {
@@ -942,7 +1266,7 @@ fn rebase_and_retry_push(base_cmd: &[String], repo: &RepoDefinition, quiet: bool
             use owo_colors::OwoColorize;
             println!("Push succeeded after rebase.");
             let commit_url = get_commit_url(base_cmd).unwrap_or_default();
-            if no_color {
+            if crate::colors_off(no_color) {
                 if let Some(c) = comment {
                     println!("Commit message:   {}", c);
                 }
@@ -964,7 +1288,7 @@ fn rebase_and_retry_push(base_cmd: &[String], repo: &RepoDefinition, quiet: bool
                 }
             }
         }
-        return;
+        return PushAttempt::Pushed;
     }
 
     let stderr_text = String::from_utf8_lossy(&retry.stderr);
@@ -984,10 +1308,66 @@ fn rebase_and_retry_push(base_cmd: &[String], repo: &RepoDefinition, quiet: bool
         eprintln!("    git branch -D {}", branch);
         eprintln!("If '{}' really is a feature branch you want to publish:", branch);
         eprintln!("    git push --set-upstream origin {}", branch);
+        return PushAttempt::Failed;
+    }
+    // Otherwise another commit likely landed concurrently; rebase again
+    PushAttempt::Retry
+}
+
+/// Attempt rebase and retry push after initial push failure.
+/// Retries up to `retries` times with exponential backoff (1s, 2s, 4s, ...) between attempts.
+/// Returns true once a push succeeds.
+fn rebase_and_retry_push(
+    base_cmd: &[String],
+    repo: &RepoDefinition,
+    quiet: bool,
+    comment: Option<&str>,
+    no_color: bool,
+    retries: usize,
+) -> bool {
+    retry_push(retries, quiet, std::time::Duration::from_secs(1), || {
+        rebase_and_push(base_cmd, repo, quiet, comment, no_color)
+    })
+}
+
+/// Handle a failed push: rebase and retry if enabled, otherwise print the manual fix.
+/// Returns an error if the commit could not be pushed.
+fn recover_failed_push(
+    base_cmd: &[String],
+    repo: &RepoDefinition,
+    quiet: bool,
+    comment: &str,
+    no_color: bool,
+    rebase_on_fail: bool,
+    push_retries: usize,
+) -> io::Result<()> {
+    if rebase_on_fail {
+        if rebase_and_retry_push(base_cmd, repo, quiet, Some(comment), no_color, push_retries) {
+            return Ok(());
+        }
     } else {
-        eprintln!("\nPush still failed (likely another concurrent commit). Suggested fix:");
-        eprintln!("    git pull --rebase --autostash");
-        eprintln!("    git push");
+        eprintln!("Push failed. Run 'git pull --rebase' then 'git push'.");
+    }
+    Err(io::Error::other("commit created locally but push failed"))
+}
+
+/// Pull latest before committing to avoid orphaned commits from post-commit rebase.
+/// A failed pull is reported but not fatal; the push retry logic handles divergence.
+fn pull_before_commit(base_cmd: &[String], repo: &RepoDefinition) {
+    match Command::new(&base_cmd[0])
+        .args(&base_cmd[1..])
+        .args(repo.pull)
+        .arg("--autostash")
+        .output()
+    {
+        Ok(out) if out.status.success() => {}
+        Ok(out) => {
+            if !out.stderr.is_empty() {
+                eprint!("{}", String::from_utf8_lossy(&out.stderr));
+            }
+            eprintln!("Warning: pull before commit failed; committing on top of local state.");
+        }
+        Err(e) => eprintln!("Warning: pull before commit failed to execute: {}", e),
     }
 }
 
@@ -1002,11 +1382,13 @@ pub fn commit_changes(
     quiet: bool,
     limited_quiet: bool,
     rebase_on_fail: bool,
+    push_retries: usize,
     git_message: &Option<String>,
     history: &[String],
+    paths: &[String],
 ) -> io::Result<()> {
     let git_quiet = quiet || limited_quiet;
-    let diff = match get_diff(base_cmd, repo) {
+    let diff = match get_diff(base_cmd, repo, paths) {
         Some(d) if !d.is_empty() => d,
         _ => {
             println!("\nNo changes have been recorded by the repository.");
@@ -1016,6 +1398,7 @@ pub fn commit_changes(
 
     if !quiet {
         println!("\nThe following changes have been recorded by the repository:");
+        print_diff_stats(&diff, no_color);
         print_diff(&diff, no_color);
     }
 
@@ -1034,28 +1417,18 @@ pub fn commit_changes(
             println!("Committing with message: {}", message);
         }
 
-        // Pull latest before committing to avoid orphaned commits from post-commit rebase
-        let _ = Command::new(&base_cmd[0])
-            .args(&base_cmd[1..])
-            .args(repo.pull)
-            .arg("--autostash")
-            .output();
+        pull_before_commit(base_cmd, repo);
 
-        Command::new(&base_cmd[0])
-            .args(&base_cmd[1..])
-            .args(repo.commit)
-            .arg(message)
-            .status()?;
+        let status = commit_command(base_cmd, repo, message, paths).status()?;
+        if !status.success() {
+            return Err(io::Error::other("git commit failed"));
+        }
 
         if pull_and_push(base_cmd, repo, git_quiet) {
-            if rebase_on_fail {
-                rebase_and_retry_push(base_cmd, repo, git_quiet, Some(message), no_color);
-            } else {
-                eprintln!("Push failed. Run 'git pull --rebase' then 'git push'.");
-            }
+            recover_failed_push(base_cmd, repo, git_quiet, message, no_color, rebase_on_fail, push_retries)?;
         } else if !quiet {
             let commit_url = get_commit_url(base_cmd).unwrap_or_default();
-            if no_color {
+            if crate::colors_off(no_color) {
                 println!("Commit successful:  {}", commit_url);
             } else {
                 print!(
@@ -1073,7 +1446,7 @@ pub fn commit_changes(
 
     // Check for large changes
     if !no_large_warning && !original_difference && is_large_change(&diff) {
-        if no_color {
+        if crate::colors_off(no_color) {
             println!("\nThis is a large change. Are you sure you want to proceed?");
         } else {
             println!(
@@ -1091,7 +1464,7 @@ pub fn commit_changes(
 
     // Get commit comment
     loop {
-        if no_color {
+        if crate::colors_off(no_color) {
             println!("Please enter a valid commit comment (or ABORT to restore):");
         } else {
             println!(
@@ -1125,7 +1498,7 @@ pub fn commit_changes(
         }
 
         if no_msg_check || check_comment(&comment, original_difference) {
-            if no_color {
+            if crate::colors_off(no_color) {
                 println!("Comment \"{}\" accepted.", comment);
             } else {
                 println!(
@@ -1136,28 +1509,25 @@ pub fn commit_changes(
                 );
             }
 
-            // Pull latest before committing to avoid orphaned commits from post-commit rebase
-            let _ = Command::new(&base_cmd[0])
-                .args(&base_cmd[1..])
-                .args(repo.pull)
-                .arg("--autostash")
-                .output();
+            pull_before_commit(base_cmd, repo);
 
             // Execute commit
-            let status = Command::new(&base_cmd[0])
-                .args(&base_cmd[1..])
-                .args(repo.commit)
-                .arg(&comment)
-                .status();
+            let status = commit_command(base_cmd, repo, &comment, paths).status();
 
-            if let Err(e) = status {
-                eprintln!("Unexpected error with commit: {}", e);
-                return Err(e);
+            match status {
+                Err(e) => {
+                    eprintln!("Unexpected error with commit: {}", e);
+                    return Err(e);
+                }
+                Ok(s) if !s.success() => {
+                    return Err(io::Error::other("git commit failed"));
+                }
+                Ok(_) => {}
             }
 
             // Pull and push
             if !quiet {
-                if no_color {
+                if crate::colors_off(no_color) {
                     print!("Connecting to server. Please enter your password if required.");
                 } else {
                     print!(
@@ -1172,15 +1542,11 @@ pub fn commit_changes(
                 if !quiet {
                     println!(); // finish the "Connecting" line
                 }
-                if rebase_on_fail {
-                    rebase_and_retry_push(base_cmd, repo, git_quiet, Some(&comment), no_color);
-                } else {
-                    eprintln!("Push failed. Run 'git pull --rebase' then 'git push'.");
-                }
+                recover_failed_push(base_cmd, repo, git_quiet, &comment, no_color, rebase_on_fail, push_retries)?;
             } else if !quiet {
                 // Overwrite "Connecting to server..." with commit message + URL
                 let commit_url = get_commit_url(base_cmd).unwrap_or_default();
-                if no_color {
+                if crate::colors_off(no_color) {
                     println!("\r\x1b[2K\nCommit message:   {}", comment);
                     print!("Commit successful:  {}", commit_url);
                 } else {
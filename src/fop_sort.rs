@@ -9,12 +9,12 @@
 #![allow(clippy::write_with_newline)]
 
 use std::borrow::Cow;
-use std::fs::{self, File};
-use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
 use std::io::Cursor;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use crate::fop_datestamp::{update_timestamp_line, update_version_line};
+use crate::fop_datestamp::{normalize_header, sort_header, update_rule_count, update_timestamp_line, update_version_line};
 
 
 use owo_colors::OwoColorize;
@@ -27,11 +27,12 @@ use crate::{
     write_warning, ADGUARD_ELEMENT_DOMAIN_PATTERN, ADGUARD_ELEMENT_PATTERN,
     ATTRIBUTE_VALUE_PATTERN, DOMAIN_EXTRACT_PATTERN, ELEMENT_DOMAIN_PATTERN,
     ELEMENT_PATTERN, FILTER_DOMAIN_PATTERN, FOPPY_ELEMENT_DOMAIN_PATTERN, FOPPY_ELEMENT_PATTERN,
-    IP_ADDRESS_PATTERN, KNOWN_OPTIONS, OPTION_PATTERN,
-    PSEUDO_PATTERN, REGEX_ELEMENT_PATTERN, REMOVAL_PATTERN, TREE_SELECTOR,
+    HTML_FILTER_PATTERN, IP_ADDRESS_PATTERN, KNOWN_OPTIONS, OPTION_PATTERN,
+    ABP_OPTIONS, ADGUARD_OPTIONS, UBO_OPTIONS, PSEUDO_PATTERN, REGEX_ELEMENT_PATTERN, REMOVAL_PATTERN, TREE_SELECTOR,
     UBO_CONVERSIONS, UNICODE_SELECTOR,
 };
 
+use crate::fop_error::FopError;
 use crate::fop_typos;
 
 /// Safe values for trusted -> non-trusted scriptlet conversion (case-insensitive)
@@ -127,6 +128,133 @@ const SKIP_SCHEMES: [&str; 6] = [
     "|javascript", "|data:", "|dddata:", "|about:", "|blob:", "|http",
 ];
 
+/// Ordering used for domain lists and section sorts (`--sort-locale`)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortLocale {
+    /// Byte-wise UTF-8 ordering (ASCII case-folded for network rules)
+    #[default]
+    Ascii,
+    /// Unicode Collation Algorithm, so accented/IDN domains sort next to their base letters
+    Unicode,
+}
+
+/// Key cosmetic sections are sorted on (`--sort-key`)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortKey {
+    /// Ignore the `domains##`/`domains#@#` prefix, so rules sort by selector; other
+    /// separators (`#?#`, `#$#`, ...) keep their domains in the key
+    #[default]
+    Domain,
+    /// The whole rule, domains first
+    Full,
+    /// Ignore the domain prefix for every separator, grouping identical selectors (`--alt-sort`)
+    Selector,
+}
+
+/// How much is reported per file (`-v`, `-vv`); `quiet` still silences everything
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    #[default]
+    Normal,
+    /// Per-file classification, rule counts and passes, and live "Sorted:" lines
+    Verbose,
+    /// `Verbose` plus the per-section detail of `print_sections`
+    VeryVerbose,
+}
+
+/// Engine a list targets (`--dialect`); `All` accepts every option FOP knows
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Dialect {
+    #[default]
+    All,
+    Abp,
+    Ubo,
+    Adguard,
+}
+
+impl Dialect {
+    /// Engine name used in warnings
+    pub fn name(self) -> &'static str {
+        match self {
+            Dialect::All => "all",
+            Dialect::Abp => "Adblock Plus",
+            Dialect::Ubo => "uBlock Origin",
+            Dialect::Adguard => "AdGuard",
+        }
+    }
+
+    /// Whether the engine accepts the option `name` (no `~` or `=value`)
+    fn allows(self, name: &str) -> bool {
+        let options = match self {
+            Dialect::All => return true,
+            Dialect::Abp => ABP_OPTIONS,
+            Dialect::Ubo => UBO_OPTIONS,
+            Dialect::Adguard => ADGUARD_OPTIONS,
+        };
+        // `$_____` is the uBO/AdGuard no-op option
+        options.contains(&name)
+            || (self != Dialect::Abp && name.bytes().all(|b| b == b'_' || b == b'-'))
+    }
+}
+
+/// Options of a tidied network rule that FOP knows but `dialect` doesn't support
+pub(crate) fn dialect_unsupported_options(filter: &str, dialect: Dialect) -> Vec<&str> {
+    if dialect == Dialect::All {
+        return Vec::new();
+    }
+    let Some(pos) = find_option_separator(filter) else {
+        return Vec::new();
+    };
+    split_filter_options(&filter[pos + 1..])
+        .into_iter()
+        .filter(|option| is_known_option(option))
+        .map(|option| {
+            let name = option.trim_start_matches('~');
+            name.split_once('=').map_or(name, |(name, _)| name)
+        })
+        .filter(|name| !dialect.allows(name))
+        .collect()
+}
+
+thread_local! {
+    /// Collator for the file currently being sorted on this thread (`None` = byte ordering).
+    /// Set for the duration of `sort_lines` so the deep domain sorts don't need a config parameter.
+    static COLLATOR: std::cell::RefCell<Option<feruca::Collator>> = const { std::cell::RefCell::new(None) };
+}
+
+/// Restores the previous collator when a `sort_lines` call ends
+struct CollatorGuard(Option<feruca::Collator>);
+
+impl CollatorGuard {
+    fn set(locale: SortLocale) -> Self {
+        let collator = (locale == SortLocale::Unicode)
+            .then(|| feruca::Collator::new(feruca::Tailoring::default(), false, true));
+        CollatorGuard(COLLATOR.with(|c| c.replace(collator)))
+    }
+}
+
+impl Drop for CollatorGuard {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        COLLATOR.with(|c| *c.borrow_mut() = previous);
+    }
+}
+
+/// Compare with the active collator, or `fallback` under byte ordering
+#[inline]
+fn cmp_collated(a: &str, b: &str, fallback: fn(&str, &str) -> Ordering) -> Ordering {
+    COLLATOR.with(|c| match c.borrow_mut().as_mut() {
+        Some(collator) => collator.collate(a, b),
+        None => fallback(a, b),
+    })
+}
+
+/// Whether `--sort-locale=unicode` is active for the file being sorted
+#[inline]
+fn collating() -> bool {
+    COLLATOR.with(|c| c.borrow().is_some())
+}
+
 /// Case-insensitive ASCII comparison without allocation
 #[inline]
 fn cmp_ascii_case_insensitive(a: &str, b: &str) -> Ordering {
@@ -148,29 +276,144 @@ fn cmp_ascii_case_insensitive(a: &str, b: &str) -> Ordering {
     }
 }
 
-/// Fast check for localhost entry without regex
+/// Blackhole addresses accepted in hosts files when `--hosts-blackhole` isn't set
+pub const DEFAULT_BLACKHOLE_ADDRESSES: &[&str] = &["0.0.0.0", "127.0.0.1", "::1", "::"];
+
+/// Fast check for localhost entry without regex.
+/// `blackhole` lists the accepted addresses (empty = `DEFAULT_BLACKHOLE_ADDRESSES`).
 #[inline]
-pub(crate) fn is_localhost_entry(line: &str) -> bool {
-    let rest = if let Some(r) = line.strip_prefix("0.0.0.0") {
-        r
-    } else if let Some(r) = line.strip_prefix("127.0.0.1") {
-        r
-    } else {
+pub(crate) fn is_localhost_entry(line: &str, blackhole: &[String]) -> bool {
+    let Some((address, rest)) = line.split_once(|c: char| c.is_ascii_whitespace()) else {
         return false;
     };
-    rest.as_bytes()
-        .first()
-        .is_some_and(|b| b.is_ascii_whitespace())
-        && !rest.trim_start().is_empty()
+    let known = if blackhole.is_empty() {
+        DEFAULT_BLACKHOLE_ADDRESSES.contains(&address)
+    } else {
+        blackhole.iter().any(|a| a == address)
+    };
+    known && !rest.trim_start().is_empty()
 }
 
 /// Extract domain from localhost entry without regex
 #[inline]
 pub(crate) fn localhost_domain(line: &str) -> &str {
-    let rest = line.strip_prefix("0.0.0.0")
-        .or_else(|| line.strip_prefix("127.0.0.1"))
-        .unwrap_or(line);
-    rest.trim_start()
+    line.split_once(|c: char| c.is_ascii_whitespace())
+        .map_or(line, |(_, rest)| rest.trim_start())
+}
+
+/// Cosmetic separators that can open a generic rule (`##.ad`, `#@#.ad`, `#$#...`)
+const GENERIC_COSMETIC_PREFIXES: &[&str] = &[
+    "##", "#@#", "#?#", "#@?#", "#$#", "#@$#", "#%#", "#@%#", "#$?#", "#@$?#",
+];
+
+/// Check if line is a comment. Detection is prefix-anchored: a line is a comment
+/// when it starts with one of `comment_chars` (e.g. `--comments=!,#`). With `#`
+/// configured, generic cosmetic rules (`##.ad`, `#@#.ad`) still count as rules,
+/// while `# note` or `#div` are comments. In localhost mode every `#` line is a comment.
+#[inline]
+pub(crate) fn is_comment(line: &str, comment_chars: &[String], localhost: bool) -> bool {
+    if localhost && line.starts_with('#') {
+        return true;
+    }
+    comment_chars.iter().any(|c| line.starts_with(c.as_str()))
+        && !(line.starts_with('#') && GENERIC_COSMETIC_PREFIXES.iter().any(|p| line.starts_with(p)))
+}
+
+/// Check if line is a uBO preprocessor directive (`!#if`, `!#else`, `!#endif`, `!#include`).
+/// These are hard section boundaries: rules are never sorted across them.
+#[inline]
+pub(crate) fn is_preprocessor_directive(line: &str) -> bool {
+    line.strip_prefix("!#").is_some_and(|rest| {
+        rest.starts_with("if") || rest.starts_with("else")
+            || rest.starts_with("endif") || rest.starts_with("include")
+    })
+}
+
+/// Network options that take a `=value`
+const VALUE_OPTIONS: &[&str] = &[
+    "domain", "csp", "redirect", "redirect-rule", "denyallow", "from", "to",
+    "removeparam", "header", "permissions", "method", "rewrite", "replace",
+    "jsonprune", "xmlprune", "ipaddress", "sitekey", "urlskip", "urltransform", "uritransform",
+];
+
+/// Whether an option-list fragment starts a new option (`image`, `~script`, `domain=...`)
+/// rather than continuing a value that contained a comma
+#[inline]
+fn is_option_start(part: &str) -> bool {
+    let name = part.trim_start_matches('~');
+    let name = name.split_once('=').map_or(name, |(n, _)| n);
+    KNOWN_OPTIONS.contains(name)
+        || VALUE_OPTIONS.contains(&name)
+        || matches!(name, "important" | "media" | "all")
+}
+
+/// Whether FOP recognises a network option (exact name or known `name=` prefix).
+/// Unrecognised options get a warning from `filter_tidy`.
+pub(crate) fn is_known_option(option: &str) -> bool {
+    let stripped = option.trim_start_matches('~');
+    KNOWN_OPTIONS.contains(stripped)
+        || stripped.starts_with("csp=")
+        || stripped.starts_with("redirect=")
+        || stripped.starts_with("redirect-rule=")
+        || stripped.starts_with("rewrite=")
+        || stripped.starts_with("replace=")
+        || stripped.starts_with("header=")
+        || stripped.starts_with("permissions=")
+        || stripped.starts_with("to=")
+        || stripped.starts_with("from=")
+        || stripped.starts_with("ipaddress=")
+        || stripped.starts_with("method=")
+        || stripped.starts_with("denyallow=")
+        || stripped.starts_with("removeparam=")
+        || stripped.starts_with("urltransform=")
+        || stripped.starts_with("responseheader=")
+        || stripped.starts_with("sitekey=")
+        || stripped.starts_with("app=")
+        || stripped.starts_with("urlskip=")
+        || stripped.starts_with("uritransform=")
+        || stripped.starts_with("reason=")
+        || stripped.starts_with("addheader=")
+        || stripped.starts_with("referrerpolicy=")
+        || stripped.starts_with("cookie=")
+        || stripped.starts_with("removeheader=")
+        || stripped.starts_with("jsonprune=")
+        || stripped.starts_with("stealth=")
+        || stripped.starts_with("hls=")
+        || stripped.starts_with("xmlprune=")
+        || stripped.starts_with("tag=")
+        || stripped == "important"
+        || stripped == "media"
+        || stripped == "all"
+}
+
+/// Find a network `$options` suffix pasted onto a cosmetic selector
+/// (`example.com##.ad$third-party`). `$` inside attribute selectors, quotes or
+/// scriptlet arguments (`[href$=".js"]`, `+js(set, $x)`) is ignored, and every
+/// trailing option must be a recognised network option.
+pub(crate) fn cosmetic_network_options(selector: &str) -> Option<&str> {
+    let (mut bracket, mut paren) = (0i32, 0i32);
+    let mut quote: Option<char> = None;
+    let mut dollar = None;
+    for (i, c) in selector.char_indices() {
+        match (quote, c) {
+            (Some(q), _) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '[') => bracket += 1,
+            (None, ']') => bracket -= 1,
+            (None, '(') => paren += 1,
+            (None, ')') => paren -= 1,
+            (None, '$') if bracket == 0 && paren == 0 => dollar = Some(i),
+            _ => {}
+        }
+    }
+    let options = &selector[dollar? + 1..];
+    let all_known = !options.is_empty()
+        && options.split(',').all(|opt| {
+            let name = opt.split('=').next().unwrap_or("").trim_start_matches('~');
+            KNOWN_OPTIONS.contains(name) || VALUE_OPTIONS.contains(&name)
+        });
+    all_known.then_some(options)
 }
 
 /// Check if line is a TLD-only pattern (e.g. .com, ||.net^)
@@ -194,10 +437,17 @@ pub fn is_tld_only(line: &str) -> bool {
 // =============================================================================
 
 /// Configuration for sorting operations
+#[derive(Default)]
 pub struct SortConfig<'a> {
     pub convert_ubo: bool,
     pub no_sort: bool,
     pub alt_sort: bool,
+    /// How cosmetic sections are ordered (`None` follows `alt_sort`)
+    pub sort_key: Option<SortKey>,
+    /// Sort network sections as two groups: blocking rules, then `@@` exceptions
+    pub group_exceptions: bool,
+    /// With `group_exceptions`, write the exception group first
+    pub exceptions_first: bool,
     /// Convert ABP extended selectors to uBO format
     pub abp_convert: bool,
     /// Convert trusted-set-cookie/storage to non-trusted when value is safe
@@ -217,6 +467,98 @@ pub struct SortConfig<'a> {
     pub output_changed: bool,
     /// Update timestamp in file header
     pub add_timestamp: bool,
+    /// strftime-like pattern for updated timestamps (None = "30 Jan 2026 08:31 UTC")
+    pub timestamp_format: Option<&'a str>,
+    /// Canonicalize attribute selector quotes to double quotes
+    pub normalize_quotes: bool,
+    /// Print per-section classification details to stderr
+    pub print_sections: bool,
+    /// Sort directives inside `$csp=`/`$permissions=` values
+    pub normalize_csp: bool,
+    /// Rewrite `$redirect=`/`$redirect-rule=` resource aliases to their canonical names
+    pub normalize_redirects: bool,
+    /// Warn about blocking rules whose options make them apply site-wide
+    pub warn_broad_options: bool,
+    /// Collation for domain lists and section sorts
+    pub sort_locale: SortLocale,
+    /// Engine whose option set network rules are checked against
+    pub dialect: Dialect,
+    /// Append `^` to bare `||host` rules so they dedupe with `||host^`
+    pub normalize_anchor: bool,
+    /// Context lines around each dry-run diff hunk (`None` = 3)
+    pub diff_context: Option<usize>,
+    /// Log every pair of rules merged by `combine_filters`
+    pub warn_combine: bool,
+    /// Warn about rules longer than this many characters
+    pub max_line_length: Option<usize>,
+    /// Remove rules over `max_line_length` instead of only warning
+    pub drop_long_lines: bool,
+    /// Warn when the sorted output has more than this many rules
+    pub rules_per_file_limit: Option<usize>,
+    /// Warn about generic cosmetic rules with an overly broad selector
+    pub warn_generic_cosmetic: bool,
+    /// Generic selectors accepted by `warn_generic_cosmetic` (exact match)
+    pub generic_cosmetic_allow: &'a [String],
+    /// Warn about cosmetic rules whose only domains are bare TLDs (`com##.ad`)
+    pub warn_tld_cosmetic: bool,
+    /// Warn about AdGuard JavaScript rules (`#%#`, `#@%#`)
+    pub warn_js_rules: bool,
+    /// Warn about cosmetic rules switched off by `$generichide`/`$specifichide` exceptions in the same file
+    pub warn_hide_interactions: bool,
+    /// Warn about `$domain=` and cosmetic domains that mix scripts or look like ASCII domains
+    pub detect_confusables: bool,
+    /// Put known header metadata lines in canonical order and spacing
+    pub normalize_header: bool,
+    /// Put known header metadata lines in canonical order, keeping their text
+    pub sort_header: bool,
+    /// Record removed rules for `write_quarantine`
+    pub quarantine_invalid: bool,
+    /// Report files that aren't valid UTF-8 as errors instead of warnings
+    pub strict_utf8: bool,
+    /// Insert/update a `! Rules: N` header line
+    pub rule_count: bool,
+    /// Directory for temp files (default: alongside the source file)
+    pub temp_dir: Option<&'a Path>,
+    /// Re-add a stripped UTF-8 byte order mark on write
+    pub keep_bom: bool,
+    /// Encoding lists are read in (None = UTF-8); they are sorted as UTF-8
+    pub input_encoding: Option<&'static encoding_rs::Encoding>,
+    /// Encoding sorted lists are written in (None = `input_encoding`)
+    pub output_encoding: Option<&'static encoding_rs::Encoding>,
+    /// Per-file reporting; from `Verbose` up, "Sorted:" lines print live as each file is
+    /// written (unordered under parallel processing)
+    pub verbosity: Verbosity,
+    /// Keep cosmetic selectors byte-for-byte; only sort/validate their domains
+    pub no_selector_tidy: bool,
+    /// Only remove duplicate rules within each section; every other line is kept as written
+    pub dedupe_only: bool,
+    /// Tidy rules as usual but write each section in the order read, duplicates included
+    pub normalize_only: bool,
+    /// Selector substrings treated as extended syntax on top of `EXTENDED_MARKERS`
+    pub extended_markers: &'a [String],
+    /// Record cosmetic rules rewritten by `element_tidy` for `print_tidy_report`
+    pub report_tidy: bool,
+    /// Dry run: report rules added or removed (after tidying) instead of a line diff
+    pub rules_diff_only: bool,
+    /// Hosts-file addresses accepted in localhost mode (empty = `DEFAULT_BLACKHOLE_ADDRESSES`)
+    pub hosts_blackhole: &'a [String],
+    /// Single-label domains (`||intranet^`) kept despite having no dot
+    pub allow_dotless_domains: &'a [String],
+    /// Record every sorted rule for `print_cross_file_duplicates`
+    pub detect_cross_file_dupes: bool,
+    /// Record hosts entries and `||host^` rules for `print_hosts_abp_overlap`
+    pub warn_hosts_abp_overlap: bool,
+    /// Write the sorted file here instead of overwriting the source (--output-dir)
+    pub output_path: Option<&'a Path>,
+    /// With `output_path`, also copy files that need no changes
+    pub copy_unchanged: bool,
+}
+
+impl SortConfig<'_> {
+    /// Cosmetic sort key in effect: `sort_key`, else `Selector` for `alt_sort`
+    pub fn cosmetic_sort_key(&self) -> SortKey {
+        self.sort_key.unwrap_or(if self.alt_sort { SortKey::Selector } else { SortKey::Domain })
+    }
 }
 
 /// Track changes made during sorting
@@ -225,8 +567,12 @@ pub struct SortChanges {
     pub typos_fixed: Vec<(String, String, String)>,       // (before, after, reason)
     pub domains_combined: Vec<(Vec<String>, String)>,     // (original rules, combined rule)
     pub has_text_merged: Vec<(Vec<String>, String)>,      // (original rules, merged rule)
-    pub duplicates_removed: ahash::AHashSet<String>,      // removed duplicate rules (deduped)
+    pub duplicates_removed: std::collections::BTreeSet<String>, // removed duplicate rules (deduped, sorted for stable reports)
     pub banned_domains_found: Vec<(String, String, String)>,  // (domain, rule, file)
+    pub selectors_tidied: Vec<(PathBuf, String, String)>,     // (file, before, after)
+    pub rule_locations: ahash::AHashMap<String, Vec<(PathBuf, usize)>>,  // rule -> (file, line)
+    pub hosts_blocked: ahash::AHashMap<String, Vec<(PathBuf, usize)>>,   // hosts-file domain -> (file, line)
+    pub abp_blocked: ahash::AHashMap<String, Vec<(PathBuf, usize)>>,     // `||host^` domain -> (file, line)
 }
 
 use std::sync::Mutex;
@@ -379,7 +725,8 @@ pub(crate) fn sort_domains(domains: &mut [String]) {
         let a_has_marker = a_base.ends_with(">>");
         let b_has_marker = b_base.ends_with(">>");
         // base domain first; non-inverted before inverted; non-ancestor before ancestor
-        (a_name, a_inv, a_has_marker).cmp(&(b_name, b_inv, b_has_marker))
+        cmp_collated(a_name, b_name, |a, b| a.cmp(b))
+            .then_with(|| (a_inv, a_has_marker).cmp(&(b_inv, b_has_marker)))
     });
 }
 
@@ -387,21 +734,34 @@ pub(crate) fn sort_domains(domains: &mut [String]) {
 // Filter Processing Functions
 // =============================================================================
 
-/// Remove unnecessary wildcards from filter text
+/// Strip the exception prefix, treating a doubled `@@@@` as a single `@@`.
+/// Returns whether the filter is an exception and the remaining filter text.
+#[inline]
+fn strip_exception_prefix(filter_text: &str) -> (bool, &str) {
+    let mut rest = filter_text;
+    while let Some(stripped) = rest.strip_prefix("@@") {
+        rest = stripped;
+    }
+    (rest.len() != filter_text.len(), rest)
+}
+
+/// Remove unnecessary wildcards from filter text: leading and trailing `*` (including one
+/// after a `^` separator) and repeated `**` inside the pattern. Only `*` are dropped: the
+/// pattern keeps its casing, which `$match-case` rules depend on.
 pub(crate) fn remove_unnecessary_wildcards(filter_text: &str) -> Cow<'_, str> {
+    let (allowlist, rest) = strip_exception_prefix(filter_text);
+    // /regex/ rules (optionally followed by `*`) are matched as written
+    let is_regex = |s: &str| {
+        let s = s.trim_end_matches('*');
+        s.len() > 1 && s.starts_with('/') && s.ends_with('/')
+    };
+
     // Fast path: no wildcards to process
-    if !(filter_text.starts_with('*') || filter_text.ends_with('*')
-        || filter_text.starts_with("@@") && filter_text.get(2..3) == Some("*"))
-    {
+    if !(rest.starts_with('*') || rest.ends_with('*') || (rest.contains("**") && !is_regex(rest))) {
         return Cow::Borrowed(filter_text);
     }
 
-    let mut result = filter_text.to_string();
-    let allowlist = result.starts_with("@@");
-
-    if allowlist {
-        result = result[2..].to_string();
-    }
+    let mut result = rest.to_string();
 
     let original_len = result.len();
 
@@ -430,6 +790,13 @@ pub(crate) fn remove_unnecessary_wildcards(filter_text: &str) -> Cow<'_, str> {
         result.push('*');
     }
 
+    // Collapse repeated wildcards: a**b -> a*b
+    if !is_regex(&result) {
+        while result.contains("**") {
+            result = result.replace("**", "*");
+        }
+    }
+
     if result == "*" {
         result.clear();
     }
@@ -456,8 +823,48 @@ fn find_option_separator(filter: &str) -> Option<usize> {
     None
 }
 
+/// Split off a `urltransform=`/`uritransform=` option whose value is a regex
+/// (`/pattern/replacement/flags`). The value may hold `,`, `/` and `$`, so it is found
+/// by walking the slashes rather than by the usual `$`/comma splitting.
+/// Returns the rule without that option, the option verbatim, and whether other options remain.
+fn split_transform_option(filter: &str) -> Option<(String, &str, bool)> {
+    let (name_start, prefix) = ["urltransform=/", "uritransform=/"].iter().find_map(|name| {
+        ['$', ','].iter().find_map(|&prefix| {
+            filter
+                .find(&format!("{}{}", prefix, name))
+                .map(|pos| (pos + 1, prefix))
+        })
+    })?;
+    let bytes = filter.as_bytes();
+    let value_start = name_start + "urltransform=".len();
+    // Pattern and replacement end at the 2nd and 3rd unescaped `/`; flags run up to the next comma
+    let mut slashes = 0;
+    let mut i = value_start;
+    while i < bytes.len() && slashes < 3 {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'/' => slashes += 1,
+            _ => {}
+        }
+        i += 1;
+    }
+    let end = if slashes == 3 {
+        filter[i..].find(',').map_or(filter.len(), |pos| i + pos)
+    } else {
+        filter.len()
+    };
+    let before = &filter[..name_start - 1];
+    let after = &filter[end..];
+    let rest = match (prefix, after.strip_prefix(',')) {
+        ('$', Some(others)) => format!("{}${}", before, others),
+        _ => format!("{}{}", before, after),
+    };
+    Some((rest, &filter[name_start..end], prefix == ',' || !after.is_empty()))
+}
+
 /// Split filter options on commas, keeping values intact for options like
-/// `jsonprune=`/`xmlprune=` where commas are part of the value syntax.
+/// `jsonprune=`/`xmlprune=`/`header=`/`replace=`/`removeparam=` whose values may
+/// contain commas: following fragments are rejoined until one starts a new option.
 #[inline]
 fn split_filter_options(options: &str) -> Vec<&str> {
     let parts: Vec<&str> = options.split(',').collect();
@@ -468,17 +875,16 @@ fn split_filter_options(options: &str) -> Vec<&str> {
     let mut i = 0;
     while i < parts.len() {
         let part = parts[i];
-        // jsonprune/xmlprune values use commas in path syntax — keep them joined
-        if part.starts_with("jsonprune=") || part.starts_with("jsonprune\\=")
-            || part.starts_with("xmlprune=") || part.starts_with("xmlprune\\=") {
+        // jsonprune/xmlprune paths, header values and replace/removeparam/cookie regexes can contain commas
+        if ["jsonprune=", "jsonprune\\=", "xmlprune=", "xmlprune\\=", "header=", "replace=", "removeparam=", "cookie="]
+            .iter()
+            .any(|prefix| part.trim_start_matches('~').starts_with(prefix))
+        {
             // Find the start and end byte offsets within the original string to return a single slice
             let start_ptr = part.as_ptr() as usize - options.as_ptr() as usize;
             i += 1;
             let mut end_ptr = start_ptr + part.len();
-            while i < parts.len() && !parts[i].contains('=')
-                && !KNOWN_OPTIONS.contains(parts[i].trim_start_matches('~'))
-                && parts[i] != "important" && parts[i] != "media" && parts[i] != "all"
-            {
+            while i < parts.len() && !is_option_start(parts[i]) {
                 // +1 for the comma separator
                 end_ptr += 1 + parts[i].len();
                 i += 1;
@@ -492,15 +898,91 @@ fn split_filter_options(options: &str) -> Vec<&str> {
     result
 }
 
-pub(crate) fn filter_tidy(filter_in: &str, convert_ubo: bool) -> String {
+/// uBO redirect resource aliases and the canonical name each one resolves to
+const REDIRECT_ALIASES: &[(&str, &str)] = &[
+    ("1x1-transparent.gif", "1x1.gif"),
+    ("2x2-transparent.png", "2x2.png"),
+    ("3x2-transparent.png", "3x2.png"),
+    ("32x32-transparent.png", "32x32.png"),
+    ("amazon-adsystem.com/aax2/apstag.js", "amazon_apstag.js"),
+    ("google-analytics.com/analytics.js", "google-analytics_analytics.js"),
+    ("google-analytics.com/ga.js", "google-analytics_ga.js"),
+    ("google-ima3", "google-ima.js"),
+    ("googlesyndication-adsbygoogle", "googlesyndication_adsbygoogle.js"),
+    ("googlesyndication.com/adsbygoogle.js", "googlesyndication_adsbygoogle.js"),
+    ("googletagmanager.com/gtm.js", "googletagmanager_gtm.js"),
+    ("googletagservices.com/gpt.js", "googletagservices_gpt.js"),
+    ("noop-js", "noop.js"),
+    ("noopcss", "noop.css"),
+    ("noopframe", "noop.html"),
+    ("noopjs", "noop.js"),
+    ("noopmp3-0.1s", "noop-0.1s.mp3"),
+    ("noopmp4-1s", "noop-1s.mp4"),
+    ("nooptext", "noop.txt"),
+    ("scorecardresearch.com/beacon.js", "scorecardresearch_beacon.js"),
+];
+
+/// Canonicalize a redirect resource name, keeping any `:priority` suffix.
+/// Unknown resources are returned unchanged.
+pub(crate) fn canonical_redirect(value: &str) -> Cow<'_, str> {
+    let (resource, priority) = match value.rfind(':') {
+        Some(pos) if value[pos + 1..].parse::<i32>().is_ok() => value.split_at(pos),
+        _ => (value, ""),
+    };
+    match REDIRECT_ALIASES.iter().find(|(alias, _)| *alias == resource) {
+        Some((_, canonical)) => Cow::Owned(format!("{}{}", canonical, priority)),
+        None => Cow::Borrowed(value),
+    }
+}
+
+/// Sort and dedupe a `|`-separated domain list option value (`$denyallow=`)
+fn sort_domain_list(value: &str) -> String {
+    let mut domains: Vec<String> = value
+        .split('|')
+        .map(str::trim)
+        .filter(|d| !d.is_empty())
+        .map(String::from)
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    sort_domains(&mut domains);
+    domains.join("|")
+}
+
+/// Optional rewrites `filter_tidy` applies on top of the standard tidying
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct TidyOptions {
+    /// Convert uBO option aliases to their ABP names
+    pub convert_ubo: bool,
+    /// Canonicalize `$redirect=`/`$redirect-rule=` resource aliases
+    pub normalize_redirects: bool,
+}
+
+impl From<&SortConfig<'_>> for TidyOptions {
+    fn from(config: &SortConfig<'_>) -> Self {
+        TidyOptions { convert_ubo: config.convert_ubo, normalize_redirects: config.normalize_redirects }
+    }
+}
+
+pub(crate) fn filter_tidy(filter_in: &str, tidy: TidyOptions) -> String {
+    // Regex urltransform=/uritransform= values are kept verbatim (no uBO conversion or
+    // splitting); the remaining options are tidied as usual and the transform goes last
+    // ||example.com^$urltransform=/^(.*)\?utm=[^&]*(.*)$/\1\2/,3p
+    if let Some((rest, transform, has_options)) = split_transform_option(filter_in) {
+        let tidied = filter_tidy(&rest, tidy);
+        return format!("{}{}{}", tidied, if has_options { ',' } else { '$' }, transform);
+    }
+
     // Skip filters with regex values in options (contain =/.../ patterns)
     // ||example.com$removeparam=/^\\$ja=/
     // ||example.com$removeparam=/regex/
+    // ||example.com^$replace=/ads,banner$/x/g,domain=example.com
 
     // Fix typo: $option.option -> $option,option (before pattern matching)
     let filter_in: Cow<str> = if let Some(dollar_pos) = find_option_separator(filter_in) {
         let (base, opts) = filter_in.split_at(dollar_pos);
-        if !opts.contains('=') && opts.contains('.') {
+        // Option names never contain `/`; a `$` inside a regex value isn't the separator
+        if !opts.contains('=') && !opts.contains('/') && opts.contains('.') {
             Cow::Owned(format!("{}{}", base, opts.replace('.', ",")))
         } else {
             Cow::Borrowed(filter_in)
@@ -510,6 +992,13 @@ pub(crate) fn filter_tidy(filter_in: &str, convert_ubo: bool) -> String {
     };
     let filter_in = filter_in.as_ref();
 
+    // Collapse a doubled exception prefix: @@@@||example.com^ -> @@||example.com^
+    let filter_in: Cow<str> = match strip_exception_prefix(filter_in) {
+        (true, rest) if rest.len() + 2 < filter_in.len() => Cow::Owned(format!("@@{}", rest)),
+        _ => Cow::Borrowed(filter_in),
+    };
+    let filter_in = filter_in.as_ref();
+
     // Remove errant spaces from network filters only
     // Skip: element rules, regex patterns, and options with legitimate spaces
     let is_element_rule = (filter_in.contains('#')
@@ -532,9 +1021,15 @@ pub(crate) fn filter_tidy(filter_in: &str, convert_ubo: bool) -> String {
     };
     let filter_in = filter_in.as_ref();
 
-    if let Some(dollar_pos) = find_option_separator(filter_in) {
+    // $cookie=/regex/ is tidied like any other option (the splitter keeps its commas), and
+    // a base64 $sitekey= value may start with `/` without being a regex. The first `$` is
+    // used since a regex value may itself contain `$`
+    if let Some(dollar_pos) = filter_in.find('$') {
         let options_part = &filter_in[dollar_pos..];
-        if options_part.contains("=/") {
+        if options_part.match_indices("=/").any(|(pos, _)| {
+            let name = options_part[..pos].to_ascii_lowercase();
+            !name.ends_with("cookie") && !name.ends_with("sitekey")
+        }) {
             return filter_in.to_string();
         }
     }
@@ -549,6 +1044,7 @@ pub(crate) fn filter_tidy(filter_in: &str, convert_ubo: bool) -> String {
     match option_split {
         None => remove_unnecessary_wildcards(filter_in).into_owned(),
         Some(caps) => {
+            // Only option names are lowercased below; the pattern keeps its casing ($match-case)
             let filter_text = remove_unnecessary_wildcards(&caps[1]).into_owned();
             let option_list: Vec<String> = split_filter_options(&caps[2])
                 .into_iter()
@@ -556,6 +1052,14 @@ pub(crate) fn filter_tidy(filter_in: &str, convert_ubo: bool) -> String {
                     // Only replace underscores in option name, not in value
                     if let Some(eq_pos) = opt.find('=') {
                         let name = opt[..eq_pos].to_ascii_lowercase().replace('_', "-");
+                        if name == "denyallow" {
+                            return format!("denyallow={}", sort_domain_list(&opt[eq_pos + 1..]));
+                        }
+                        if tidy.normalize_redirects
+                            && matches!(name.trim_start_matches('~'), "redirect" | "redirect-rule" | "rewrite")
+                        {
+                            return format!("{}={}", name, canonical_redirect(&opt[eq_pos + 1..]));
+                        }
                         let value = &opt[eq_pos..]; // Keep value as-is (preserve case and underscores)
                         format!("{}{}", name, value)
                     } else {
@@ -564,14 +1068,25 @@ pub(crate) fn filter_tidy(filter_in: &str, convert_ubo: bool) -> String {
                 })
                 .collect();
 
+            // Aliases meaning the same ($3p,third-party) collapse after conversion like exact
+            // duplicates do, but are worth a look either way
+            if let Some((option, other)) = redundant_options(&option_list) {
+                write_warning(&format!(
+                    "Warning: The options \"{}\" and \"{}\" mean the same thing in the filter \"{}\"",
+                    option, other, filter_in
+                ));
+            }
+
             // Convert uBO options
-            let option_list = if convert_ubo {
+            let option_list = if tidy.convert_ubo {
                 convert_ubo_options(option_list)
             } else {
                 option_list
             };
 
             let mut domain_list: Vec<String> = Vec::new();
+            let mut from_list: Vec<&str> = Vec::new();
+            let mut to_list: Vec<&str> = Vec::new();
             let mut remove_entries: HashSet<String> = HashSet::new();
             let mut final_options: Vec<String> = Vec::new();
 
@@ -585,58 +1100,43 @@ pub(crate) fn filter_tidy(filter_in: &str, convert_ubo: bool) -> String {
                             .map(String::from)
                     );
                     remove_entries.insert(option.clone());
-                } else {
-                    let stripped = option.trim_start_matches('~');
-                    // Check if option is known (exact match or known prefix)
-                    let is_known = KNOWN_OPTIONS.contains(stripped)
-                        || stripped.starts_with("csp=")
-                        || stripped.starts_with("redirect=")
-                        || stripped.starts_with("redirect-rule=")
-                        || stripped.starts_with("rewrite=")
-                        || stripped.starts_with("replace=")
-                        || stripped.starts_with("header=")
-                        || stripped.starts_with("permissions=")
-                        || stripped.starts_with("to=")
-                        || stripped.starts_with("from=")
-                        || stripped.starts_with("ipaddress=")
-                        || stripped.starts_with("method=")
-                        || stripped.starts_with("denyallow=")
-                        || stripped.starts_with("removeparam=")
-                        || stripped.starts_with("urltransform=")
-                        || stripped.starts_with("responseheader=")
-                        || stripped.starts_with("sitekey=")
-                        || stripped.starts_with("app=")
-                        || stripped.starts_with("urlskip=")
-                        || stripped.starts_with("uritransform=")
-                        || stripped.starts_with("reason=")
-                        || stripped.starts_with("addheader=")
-                        || stripped.starts_with("referrerpolicy=")
-                        || stripped.starts_with("cookie=")
-                        || stripped.starts_with("removeheader=")
-                        || stripped.starts_with("jsonprune=")
-                        || stripped.starts_with("stealth=")
-                        || stripped.starts_with("hls=")
-                        || stripped.starts_with("xmlprune=")
-                        || stripped.starts_with("tag=")
-                        || stripped == "important"
-                        || stripped == "media"
-                        || stripped == "all";
-                    if !is_known {
-                        write_warning(&format!(
-                            "Warning: The option \"{}\" used on the filter \"{}\" is not recognised by FOP",
-                            option, filter_in
-                        ));
-                    }
+                } else if let Some(domains) = option.strip_prefix("from=") {
+                    // Left unconverted (--no-ubo-convert), but sorted like $domain=
+                    from_list.push(domains);
+                    remove_entries.insert(option.clone());
+                } else if let Some(targets) = option.strip_prefix("to=") {
+                    // uBO $to= takes a pipe-delimited domain list like $domain=
+                    to_list.push(targets);
+                    remove_entries.insert(option.clone());
+                } else if let Some(key) = option.strip_prefix("sitekey=").and_then(invalid_sitekey) {
+                    // Sitekeys are case-sensitive base64 and kept verbatim; only flag malformed ones
+                    write_warning(&format!(
+                        "Warning: The sitekey \"{}\" used on the filter \"{}\" is not valid base64",
+                        key, filter_in
+                    ));
+                } else if !is_known_option(option) {
+                    write_warning(&format!(
+                        "Warning: The option \"{}\" used on the filter \"{}\" is not recognised by FOP",
+                        option, filter_in
+                    ));
                 }
             }
 
-            // Sort options alphabetically, with inverse following non-inverse
+            // Sort options alphabetically, with inverse following non-inverse; repeated
+            // options, such as several identical $removeparam= values, collapse to one
             let mut sorted_options: Vec<String> = option_list
-                .into_iter()
-                .filter(|opt| !remove_entries.contains(opt))
+                .iter()
+                .filter(|opt| !remove_entries.contains(*opt))
+                .cloned()
                 .collect::<HashSet<_>>()
                 .into_iter()
                 .collect();
+            if !from_list.is_empty() {
+                sorted_options.push(format!("from={}", sort_domain_list(&from_list.join("|"))));
+            }
+            if !to_list.is_empty() {
+                sorted_options.push(format!("to={}", sort_domain_list(&to_list.join("|"))));
+            }
 
             sorted_options.sort_unstable_by(|a, b| {
                 let (a_base, a_inv) = a.strip_prefix('~').map(|s| (s, true)).unwrap_or((a.as_str(), false));
@@ -644,6 +1144,13 @@ pub(crate) fn filter_tidy(filter_in: &str, convert_ubo: bool) -> String {
                 (a_base, a_inv).cmp(&(b_base, b_inv))
             });
 
+            if let Some((option, negated)) = contradictory_options(&sorted_options) {
+                write_warning(&format!(
+                    "Warning: The options \"{}\" and \"{}\" cancel each other out in the filter \"{}\"",
+                    option, negated, filter_in
+                ));
+            }
+
             final_options.extend(sorted_options);
 
             // Sort and append domain restrictions
@@ -665,9 +1172,350 @@ pub(crate) fn filter_tidy(filter_in: &str, convert_ubo: bool) -> String {
     }
 }
 
+/// Add the separator to a bare host-anchored blocking rule: `||example.com` -> `||example.com^`.
+/// Only plain hostnames qualify; paths, wildcards, ports, options and exceptions are left alone.
+pub(crate) fn normalize_anchor(filter: &str) -> Option<String> {
+    let host = filter.strip_prefix("||")?;
+    let is_plain_host = host.contains('.')
+        && !host.starts_with(['.', '-'])
+        && !host.ends_with(['.', '-'])
+        && host.chars().all(|c| c.is_alphanumeric() || c == '.' || c == '-');
+    is_plain_host.then(|| format!("{}^", filter))
+}
+
+/// First key in a `$sitekey=` value (`key1|key2`) that isn't base64: only `A-Z a-z 0-9 + /`,
+/// with at most two `=` of padding at the end
+pub(crate) fn invalid_sitekey(value: &str) -> Option<&str> {
+    value.split('|').find(|key| {
+        let data = key.trim_end_matches('=');
+        data.is_empty()
+            || key.len() - data.len() > 2
+            || !data.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'+' || b == b'/')
+    })
+}
+
+/// Flag options (no `=value`) with their ABP meaning: (option, (name, inverse)),
+/// where uBO aliases are resolved (`1p` is `("third-party", true)`)
+fn option_meanings(options: &[String]) -> Vec<(&str, (&str, bool))> {
+    options
+        .iter()
+        .filter(|option| !option.contains('='))
+        .map(|option| {
+            let canonical: &str = UBO_CONVERSIONS.get(option.as_str()).copied().unwrap_or(option);
+            let meaning = match canonical.strip_prefix('~') {
+                Some(name) => (name, true),
+                None => (canonical, false),
+            };
+            (option.as_str(), meaning)
+        })
+        .collect()
+}
+
+/// Find an option together with its negation (`third-party,~third-party`), comparing
+/// uBO aliases by their ABP meaning (`1p` is `~third-party`). Value options are ignored.
+pub(crate) fn contradictory_options(options: &[String]) -> Option<(&str, &str)> {
+    let flags = option_meanings(options);
+    flags.iter().filter(|(_, (_, inverse))| !inverse).find_map(|(option, (name, _))| {
+        flags
+            .iter()
+            .find(|(_, (other, inverse))| *inverse && other == name)
+            .map(|(negated, _)| (*option, *negated))
+    })
+}
+
+/// Find two differently written options that mean the same thing (`3p,third-party`,
+/// `1p,~third-party`), comparing uBO aliases by their ABP meaning. Value options are ignored.
+pub(crate) fn redundant_options(options: &[String]) -> Option<(&str, &str)> {
+    let flags = option_meanings(options);
+    flags.iter().enumerate().find_map(|(i, (option, meaning))| {
+        flags[i + 1..]
+            .iter()
+            .find(|(other, other_meaning)| other != option && other_meaning == meaning)
+            .map(|(other, _)| (*option, *other))
+    })
+}
+
+/// Describe why a tidied blocking rule is unexpectedly broad (`--warn-broad-options`):
+/// `$all`, or `$document`/`$popup` without a `domain=` restriction. Exceptions are never flagged.
+pub(crate) fn broad_option_reason(filter: &str) -> Option<&'static str> {
+    if filter.starts_with("@@") {
+        return None;
+    }
+    let options = split_filter_options(&filter[find_option_separator(filter)? + 1..]);
+    let has_domain = options.iter().any(|opt| opt.starts_with("domain="));
+    if options.contains(&"all") {
+        Some("$all blocks every request type")
+    } else if !has_domain && options.iter().any(|opt| matches!(*opt, "document" | "doc")) {
+        Some("$document without $domain= blocks whole pages")
+    } else if !has_domain && options.contains(&"popup") {
+        Some("$popup without $domain= blocks popups from every site")
+    } else {
+        None
+    }
+}
+
+/// Class names common enough that hiding them on every site breaks pages
+const COMMON_CLASSES: &[&str] = &[
+    "active", "block", "body", "box", "btn", "button", "card", "clearfix", "col", "container",
+    "content", "footer", "header", "hidden", "image", "img", "item", "link", "main", "menu",
+    "modal", "nav", "overlay", "page", "row", "section", "sidebar", "text", "title", "widget",
+    "wrap", "wrapper",
+];
+
+/// Describe why a generic cosmetic rule (`##`/`#?#` with no domain) is too broad
+/// (`--warn-generic-cosmetic`): `*`, a bare element name, or a single common class.
+/// Selectors in `allow` are accepted as-is.
+pub(crate) fn generic_cosmetic_reason(rule: &str, allow: &[String]) -> Option<&'static str> {
+    let selector = rule.strip_prefix("##").or_else(|| rule.strip_prefix("#?#"))?.trim();
+    if allow.iter().any(|s| s == selector) {
+        return None;
+    }
+    let is_name = |s: &str| {
+        s.starts_with(|c: char| c.is_ascii_alphabetic())
+            && s.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+    };
+    if selector == "*" {
+        Some("universal selector hides everything")
+    } else if is_name(selector) {
+        Some("bare element selector")
+    } else if selector
+        .strip_prefix('.')
+        .is_some_and(|class| COMMON_CLASSES.iter().any(|c| c.eq_ignore_ascii_case(class)))
+    {
+        Some("single common class")
+    } else {
+        None
+    }
+}
+
+/// Whether a cosmetic rule's domain list names only bare TLD-like tokens (`com##.ad`,
+/// `net,org##.ad`), so it applies to every site under them (`--warn-tld-cosmetic`).
+/// Excluded `~` entries are ignored; a list of only exclusions is a generic rule.
+pub(crate) fn is_tld_cosmetic(domains: &str) -> bool {
+    let mut included = domains.split(',').filter(|d| !d.is_empty() && !d.starts_with('~')).peekable();
+    included.peek().is_some()
+        && included.all(|d| d.len() >= 2 && d.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-'))
+}
+
+/// Whether `line` is an AdGuard JavaScript rule (`example.com#%#code` or a `#@%#` exception),
+/// also behind a `[$path=...]` modifier (`--warn-js-rules`)
+pub(crate) fn is_js_rule(line: &str) -> bool {
+    let rule = line.strip_prefix("[$").and_then(|rest| rest.split_once(']')).map_or(line, |(_, rule)| rule);
+    rule.find('#').is_some_and(|pos| {
+        let (domains, rest) = rule.split_at(pos);
+        (rest.starts_with("#%#") || rest.starts_with("#@%#")) && !domains.contains(['/', '|', '@', '"', '!', '$'])
+    })
+}
+
+/// Why a domain may be a lookalike (`--detect-confusables`): a label mixing scripts
+/// (`аpple.com` with a Cyrillic `а`), or non-ASCII text that reads as an ASCII domain.
+/// Plain ASCII and single-script IDNs such as `bücher.de` or `пример.рф` pass.
+pub(crate) fn confusable_reason(domain: &str) -> Option<String> {
+    use unicode_security::{skeleton, MixedScript};
+    let domain = domain.trim_start_matches('~');
+    if domain.is_ascii() {
+        return None;
+    }
+    let mixed = domain.split('.').find(|label| !label.is_single_script());
+    // Only the non-ASCII characters are mapped, so `m` doesn't turn into `rn`
+    let lookalike: String = domain
+        .chars()
+        .flat_map(|c| -> Vec<char> {
+            if c.is_ascii() {
+                vec![c]
+            } else {
+                skeleton(c.encode_utf8(&mut [0; 4])).collect()
+            }
+        })
+        .collect();
+    match (mixed, lookalike.is_ascii()) {
+        (Some(_), true) => Some(format!("mixes scripts, looks like {}", lookalike)),
+        (Some(label), false) => Some(format!("mixes scripts in \"{}\"", label)),
+        (None, true) => Some(format!("looks like {}", lookalike)),
+        (None, false) => None,
+    }
+}
+
+/// Warn about each confusable domain in `domains` (`--detect-confusables`)
+fn warn_confusables<'a>(domains: impl Iterator<Item = &'a str>, rule: &str) {
+    for domain in domains {
+        if let Some(reason) = confusable_reason(domain) {
+            write_warning(&format!("Confusable domain: {} in {} ({})", domain.trim_start_matches('~'), rule, reason));
+        }
+    }
+}
+
+/// Host of an `@@||host^$generichide`/`$specifichide` exception, and whether it is specifichide
+fn hide_exception(line: &str) -> Option<(&str, bool)> {
+    let rest = line.strip_prefix("@@||")?;
+    let pos = find_option_separator(line)?;
+    let (mut generic, mut specific) = (false, false);
+    for option in split_filter_options(&line[pos + 1..]) {
+        match option {
+            "generichide" | "ghide" => generic = true,
+            "specifichide" | "shide" => specific = true,
+            _ => {}
+        }
+    }
+    let end = rest.find(['^', '/', '$', '*', ':', '|']).unwrap_or(rest.len());
+    let host = &rest[..end];
+    (!host.is_empty() && (generic || specific)).then_some((host, specific))
+}
+
+/// Cosmetic rules in `content` that `$generichide`/`$specifichide` exceptions in the same file
+/// switch off (`--warn-hide-interactions`). A specifichide exception is reported against each
+/// cosmetic rule for its host (or a subdomain); a generichide exception once, with how many
+/// generic cosmetic rules it disables there.
+pub(crate) fn hide_interactions(content: &str, config: &SortConfig) -> Vec<String> {
+    let mut exceptions: Vec<(&str, &str, bool)> = Vec::new();
+    let mut generic: Vec<&str> = Vec::new();
+    let mut specific: Vec<(String, &str)> = Vec::new();
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || is_comment(line, config.comment_chars, config.localhost) {
+            continue;
+        }
+        if let Some((host, is_specific)) = hide_exception(line) {
+            exceptions.push((line, host, is_specific));
+        } else if let Some(caps) = element_captures(line, config) {
+            if caps[2].contains('@') {
+                continue;
+            }
+            let domains = caps.get(1).map_or("", |m| m.as_str());
+            let included: Vec<&str> = domains.split(',').filter(|d| !d.is_empty() && !d.starts_with('~')).collect();
+            if included.is_empty() {
+                generic.push(line);
+            } else {
+                specific.extend(included.into_iter().map(|d| (d.to_ascii_lowercase(), line)));
+            }
+        }
+    }
+
+    let mut warnings = Vec::new();
+    for (exception, host, is_specific) in exceptions {
+        let host = host.to_ascii_lowercase();
+        if is_specific {
+            let covered = |domain: &str| {
+                domain == host || domain.strip_suffix(host.as_str()).is_some_and(|sub| sub.ends_with('.'))
+            };
+            for (_, rule) in specific.iter().filter(|(domain, _)| covered(domain)) {
+                warnings.push(format!("Hide interaction: {} disables {}", exception, rule));
+            }
+        } else if let Some(first) = generic.first() {
+            warnings.push(format!(
+                "Hide interaction: {} disables {} generic cosmetic rule(s) on {} (e.g. {})",
+                exception,
+                generic.len(),
+                host,
+                first
+            ));
+        }
+    }
+    warnings.dedup();
+    warnings
+}
+
+/// Sort the `;`-separated directives inside `$csp=`/`$permissions=` values.
+/// Directives are trimmed but otherwise kept verbatim (quoted keywords like `'none'` included);
+/// only their order changes. Other options and the rule text are untouched.
+pub(crate) fn normalize_csp_options(filter: &str) -> Cow<'_, str> {
+    let Some(dollar_pos) = find_option_separator(filter) else {
+        return Cow::Borrowed(filter);
+    };
+    let options = &filter[dollar_pos + 1..];
+    if !options.contains(';') {
+        return Cow::Borrowed(filter);
+    }
+
+    let mut changed = false;
+    let normalized: Vec<Cow<str>> = split_filter_options(options)
+        .into_iter()
+        .map(|opt| {
+            let Some(eq_pos) = opt.find('=') else {
+                return Cow::Borrowed(opt);
+            };
+            let (name, value) = (&opt[..eq_pos], &opt[eq_pos + 1..]);
+            if !matches!(name.trim_start_matches('~'), "csp" | "permissions") || !value.contains(';') {
+                return Cow::Borrowed(opt);
+            }
+            let mut directives: Vec<&str> = split_outside_quotes(value, ';')
+                .into_iter()
+                .map(str::trim)
+                .filter(|d| !d.is_empty())
+                .collect();
+            directives.sort_unstable_by(|a, b| cmp_ascii_case_insensitive(a, b));
+            let sorted = format!("{}={}", name, directives.join("; "));
+            if sorted != opt {
+                changed = true;
+            }
+            Cow::Owned(sorted)
+        })
+        .collect();
+
+    if !changed {
+        return Cow::Borrowed(filter);
+    }
+    Cow::Owned(format!("{}${}", &filter[..dollar_pos], normalized.join(",")))
+}
+
+/// Split on `sep`, ignoring separators inside single-quoted tokens.
+fn split_outside_quotes(value: &str, sep: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut in_quotes = false;
+    let mut start = 0;
+    for (i, c) in value.char_indices() {
+        if c == '\'' {
+            in_quotes = !in_quotes;
+        } else if c == sep && !in_quotes {
+            parts.push(&value[start..i]);
+            start = i + c.len_utf8();
+        }
+    }
+    parts.push(&value[start..]);
+    parts
+}
+
+/// Rewrite single-quoted attribute values as double-quoted, escaping embedded `"`.
+/// Uses `ATTRIBUTE_VALUE_PATTERN` to find each quoted region so text outside quotes is untouched.
+fn normalize_attribute_quotes(selector: &str) -> Cow<'_, str> {
+    if !selector.contains('\'') {
+        return Cow::Borrowed(selector);
+    }
+    let mut result = String::with_capacity(selector.len() + 8);
+    let mut rest = selector;
+    while let Some(quoted) = ATTRIBUTE_VALUE_PATTERN.captures(rest).and_then(|c| c.get(2)) {
+        result.push_str(&rest[..quoted.start()]);
+        let value = quoted.as_str();
+        if value.starts_with('\'') {
+            let inner = &value[1..value.len() - 1];
+            result.push('"');
+            let mut chars = inner.chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => match chars.next() {
+                        Some('\'') => result.push('\''),
+                        Some(next) => {
+                            result.push('\\');
+                            result.push(next);
+                        }
+                        None => result.push('\\'),
+                    },
+                    '"' => result.push_str("\\\""),
+                    _ => result.push(c),
+                }
+            }
+            result.push('"');
+        } else {
+            result.push_str(value);
+        }
+        rest = &rest[quoted.end()..];
+    }
+    result.push_str(rest);
+    Cow::Owned(result)
+}
+
 /// Sort domains and clean element hiding rules
-pub(crate) fn element_tidy(domains: &str, separator: &str, selector: &str) -> String {
-    let selector = selector.trim();
+/// Sort, dedupe and validate the domain list of a cosmetic rule
+fn tidy_element_domains(domains: &str, separator: &str, selector: &str) -> String {
     let mut domains = domains.to_ascii_lowercase();
 
     // Sort domain names alphabetically
@@ -709,6 +1557,27 @@ pub(crate) fn element_tidy(domains: &str, separator: &str, selector: &str) -> St
         valid_domains.dedup();
         domains = valid_domains.join(",");
     }
+    domains
+}
+
+/// Built-in procedural pseudo-classes that mark a selector as extended syntax.
+/// `extended-markers` in the config adds to these.
+pub const EXTENDED_MARKERS: &[&str] = &[
+    ":style(", ":has-text(", ":has(", ":remove(", ":remove-attr(", ":remove-class(",
+    ":matches-path(", ":matches-css(", ":matches-media(", ":matches-prop(", ":upward(",
+    ":xpath(", ":watch-attr(", ":min-text-length(", ":-abp-has(", ":-abp-contains(",
+    ":-abp-properties(", ":others(",
+];
+
+pub(crate) fn element_tidy(
+    domains: &str,
+    separator: &str,
+    selector: &str,
+    normalize_quotes: bool,
+    extra_markers: &[String],
+) -> String {
+    let selector = selector.trim();
+    let domains = tidy_element_domains(domains, separator, selector);
 
     // Skip selector processing for uBO/ABP/AdGuard extended syntax (preserve exactly as-is)
     let is_extended = match separator {
@@ -717,26 +1586,8 @@ pub(crate) fn element_tidy(domains: &str, separator: &str, selector: &str) -> St
             || selector.starts_with("^")
             || selector.starts_with("//scriptlet(")
             || selector.contains(" {")
-            || (selector.contains(':') && (
-                selector.contains(":style(")
-                || selector.contains(":has-text(")
-                || selector.contains(":has(")
-                || selector.contains(":remove(")
-                || selector.contains(":remove-attr(")
-                || selector.contains(":remove-class(")
-                || selector.contains(":matches-path(")
-                || selector.contains(":matches-css(")
-                || selector.contains(":matches-media(")
-                || selector.contains(":matches-prop(")
-                || selector.contains(":upward(")
-                || selector.contains(":xpath(")
-                || selector.contains(":watch-attr(")
-                || selector.contains(":min-text-length(")
-                || selector.contains(":-abp-has(")
-                || selector.contains(":-abp-contains(")
-                || selector.contains(":-abp-properties(")
-                || selector.contains(":others(")
-            ))
+            || (selector.contains(':') && EXTENDED_MARKERS.iter().any(|marker| selector.contains(marker)))
+            || extra_markers.iter().any(|marker| selector.contains(marker.as_str()))
     };
 
     if is_extended {
@@ -778,6 +1629,14 @@ pub(crate) fn element_tidy(domains: &str, separator: &str, selector: &str) -> St
         }
     }
 
+    // Canonicalize attribute value quotes so ' and " variants dedupe
+    if normalize_quotes {
+        if let Cow::Owned(normalized) = normalize_attribute_quotes(&selector) {
+            selector = normalized;
+            selector_only_strings = normalize_attribute_quotes(&selector_only_strings).into_owned();
+        }
+    }
+
     // Clean up tree selectors
     // Skip normalization if selector contains pseudo-class functions (preserve original spacing)
     let skip_tree_normalize = selector.contains(":has(") || 
@@ -1039,6 +1898,7 @@ fn combine_filters(
     mut uncombined: Vec<String>,
     domain_pattern: &Regex,
     separator: &str,
+    warn_combine: bool,
 ) -> Vec<String> {
     // No combining needed for single filter
     if uncombined.len() <= 1 {
@@ -1161,6 +2021,18 @@ fn combine_filters(
             .replace(&uncombined[i], escaped_substitute.as_str())
             .to_string();
             
+        // Log every merge for auditing (--warn-combine)
+        if warn_combine {
+            let mixed = (domain1_exclude_count == 0) != (domain2_exclude_count == 0);
+            write_warning(&format!(
+                "Combined: {} + {} -> {}{}",
+                uncombined[i],
+                uncombined[i + 1],
+                combined_filter,
+                if mixed { " (mixes included and excluded domains)" } else { "" }
+            ));
+        }
+
         // Track combination
         with_tracked_changes(|changes| {
             changes.domains_combined.push((
@@ -1181,75 +2053,1003 @@ fn combine_filters(
 // Main Sorting Function
 // =============================================================================
 
-/// Sort the sections of a filter file and save modifications
-pub fn fop_sort(filename: &Path, config: &SortConfig) -> io::Result<Option<String>> {
-    let temp_file = filename.with_extension("temp");
-    const CHECK_LINES: usize = 10;
+/// Temp files created by this process that may still exist (removed by `cleanup_temp_files`)
+static CREATED_TEMP_FILES: LazyLock<Mutex<Vec<PathBuf>>> = LazyLock::new(|| Mutex::new(Vec::new()));
+
+/// Temp file used while sorting `filename`: `<name>.fop-<pid>.temp` next to the source,
+/// or in `temp_dir` with a hash of the full source path so equal names don't collide.
+/// The process ID keeps FOP from ever reusing a user's own `*.temp` file.
+pub fn temp_path(filename: &Path, temp_dir: Option<&Path>) -> PathBuf {
+    let name = filename.file_name().and_then(|n| n.to_str()).unwrap_or("file");
+    let pid = std::process::id();
+    let Some(dir) = temp_dir else {
+        return filename.with_file_name(format!("{}.fop-{}.temp", name, pid));
+    };
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    filename.hash(&mut hasher);
+    dir.join(format!("{}.{:016x}.fop-{}.temp", name, hasher.finish(), pid))
+}
 
-    // Skip empty or tiny files
-    let metadata = fs::metadata(filename)?;
-    if metadata.len() < 3 {
-        return Ok(None);
+/// Remove any temp files this process created that weren't moved into place
+/// (e.g. after an error mid-sort). Files FOP didn't create are never touched.
+pub fn cleanup_temp_files() {
+    let created = match CREATED_TEMP_FILES.lock() {
+        Ok(mut created) => std::mem::take(&mut *created),
+        Err(_) => return,
+    };
+    for path in created {
+        if path.exists() {
+            let _ = fs::remove_file(&path);
+        }
     }
+}
 
-    // Read entire file into memory (avoids double-read for diff)
-    let Ok(original_content) = fs::read(filename) else {
-        eprintln!("Cannot open {}", filename.display());
-        return Ok(None);
+/// Move the sorted temp file over the original, falling back to copy+remove
+/// when the temp directory is on a different filesystem.
+fn move_into_place(temp_file: &Path, filename: &Path) -> io::Result<()> {
+    if fs::rename(temp_file, filename).is_ok() {
+        return Ok(());
+    }
+    fs::copy(temp_file, filename)?;
+    fs::remove_file(temp_file)
+}
+
+// =============================================================================
+// Gzip-Aware List I/O
+// =============================================================================
+
+/// Whether `path` is a gzip-compressed list (`.gz`)
+pub fn is_gzip(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("gz"))
+}
+
+/// Extension used to select filter lists: the inner one for `list.txt.gz`
+pub fn list_extension(path: &Path) -> &str {
+    let path = if is_gzip(path) { Path::new(path.file_stem().unwrap_or_default()) } else { path };
+    path.extension().and_then(|e| e.to_str()).unwrap_or("")
+}
+
+/// Decompress gzip data held in memory
+pub fn gunzip(data: &[u8]) -> io::Result<Vec<u8>> {
+    use std::io::Read;
+    let mut content = Vec::new();
+    flate2::read::GzDecoder::new(data).read_to_end(&mut content)?;
+    Ok(content)
+}
+
+/// Read a list's logical content, decompressing `.gz` files
+pub fn read_list(path: &Path) -> io::Result<Vec<u8>> {
+    let data = fs::read(path)?;
+    if is_gzip(path) { gunzip(&data) } else { Ok(data) }
+}
+
+/// Read a list's logical content as UTF-8 text, decompressing `.gz` files
+pub fn read_list_to_string(path: &Path) -> io::Result<String> {
+    String::from_utf8(read_list(path)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// A list read in `config.input_encoding`, as UTF-8. Malformed bytes become U+FFFD with
+/// a warning. UTF-8 (or no encoding) returns `data` unchanged.
+pub(crate) fn decode_input<'d>(data: &'d [u8], filename: &Path, config: &SortConfig) -> Cow<'d, [u8]> {
+    let Some(encoding) = config.input_encoding.filter(|e| *e != encoding_rs::UTF_8) else {
+        return Cow::Borrowed(data);
+    };
+    let (text, had_errors) = encoding.decode_without_bom_handling(data);
+    if had_errors {
+        write_warning(&format!(
+            "Replaced bytes that aren't valid {} in {}",
+            encoding.name(),
+            filename.display()
+        ));
+    }
+    match text {
+        Cow::Borrowed(text) => Cow::Borrowed(text.as_bytes()),
+        Cow::Owned(text) => Cow::Owned(text.into_bytes()),
+    }
+}
+
+/// A list's text from `data` as stored for `path` (on disk or in git): decompressed for
+/// `.gz` files and decoded from `config.input_encoding`, like `fop_sort` reads it
+pub fn list_text(data: &[u8], path: &Path, config: &SortConfig) -> io::Result<String> {
+    let data = if is_gzip(path) { Cow::Owned(gunzip(data)?) } else { Cow::Borrowed(data) };
+    Ok(String::from_utf8_lossy(&decode_input(&data, path, config)).into_owned())
+}
+
+/// A list's text for header edits (`--add-timestamp`, `--add-checksum`): decoded from
+/// `config.input_encoding`, or strictly UTF-8 without one
+pub fn read_list_text(path: &Path, config: &SortConfig) -> io::Result<String> {
+    if config.input_encoding.filter(|e| *e != encoding_rs::UTF_8).is_none() {
+        return read_list_to_string(path);
+    }
+    list_text(&fs::read(path)?, path, config)
+}
+
+/// Write header-edited `text` back in the list's output encoding, compressed for `.gz`
+pub fn write_list_text(path: &Path, text: &str, config: &SortConfig) -> io::Result<()> {
+    write_list(path, &encode_output(text.as_bytes(), path, config))
+}
+
+/// Sorted UTF-8 `content` in `config.output_encoding` (default: the input encoding).
+/// Characters the encoding can't represent are written as `&#NNNN;` with a warning.
+pub(crate) fn encode_output<'c>(content: &'c [u8], filename: &Path, config: &SortConfig) -> Cow<'c, [u8]> {
+    let Some(encoding) = config.output_encoding.or(config.input_encoding).filter(|e| *e != encoding_rs::UTF_8) else {
+        return Cow::Borrowed(content);
+    };
+    let text = String::from_utf8_lossy(content);
+    let (bytes, _, had_unmappable) = encoding.encode(&text);
+    if had_unmappable {
+        write_warning(&format!(
+            "Characters not representable in {} written as numeric references in {}",
+            encoding.name(),
+            filename.display()
+        ));
+    }
+    Cow::Owned(bytes.into_owned())
+}
+
+/// Bytes to store on disk for `path`: gzip-compressed for `.gz` files, otherwise as-is
+pub fn encode_list<'c>(path: &Path, content: &'c [u8]) -> io::Result<Cow<'c, [u8]>> {
+    if !is_gzip(path) {
+        return Ok(Cow::Borrowed(content));
+    }
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(content)?;
+    Ok(Cow::Owned(encoder.finish()?))
+}
+
+/// Write a list's logical content, compressing `.gz` files
+pub fn write_list(path: &Path, content: &[u8]) -> io::Result<()> {
+    fs::write(path, encode_list(path, content)?)
+}
+
+/// Rule tallies for a filter list (`--count-only`)
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct RuleCounts {
+    /// All non-comment, non-blank rules
+    pub total: usize,
+    /// Element hiding, scriptlet and other `#`-separator rules
+    pub cosmetic: usize,
+    /// Blocking rules (and host entries in localhost mode)
+    pub network: usize,
+    /// `@@` network exceptions and `#@` cosmetic exceptions (also counted above)
+    pub exception: usize,
+}
+
+impl std::ops::AddAssign for RuleCounts {
+    fn add_assign(&mut self, other: Self) {
+        self.total += other.total;
+        self.cosmetic += other.cosmetic;
+        self.network += other.network;
+        self.exception += other.exception;
+    }
+}
+
+/// Classify each line the way `fop_sort` does, without tidying or writing anything
+pub fn count_rules(content: &str, config: &SortConfig) -> RuleCounts {
+    let mut counts = RuleCounts::default();
+    let element_pattern = if config.alt_sort {
+        &*ELEMENT_PATTERN
+    } else if config.parse_adguard {
+        &*ADGUARD_ELEMENT_PATTERN
+    } else {
+        &*FOPPY_ELEMENT_PATTERN
+    };
+    for line in content.lines() {
+        let line = line.trim().trim_start_matches('\u{feff}');
+        let is_comment = is_comment(line, config.comment_chars, config.localhost);
+        if line.is_empty()
+            || is_comment
+            || line.starts_with("%include")
+            || is_preprocessor_directive(line)
+            || (line.starts_with('[') && line.ends_with(']'))
+        {
+            continue;
+        }
+        if config.localhost {
+            if is_localhost_entry(line, config.hosts_blackhole) {
+                counts.total += 1;
+                counts.network += 1;
+            }
+            continue;
+        }
+        counts.total += 1;
+        let cosmetic_separator = if line.starts_with("[$") {
+            Some(line.contains("#@"))
+        } else {
+            REGEX_ELEMENT_PATTERN
+                .captures(line)
+                .or_else(|| element_pattern.captures(line))
+                .map(|caps| caps[2].contains('@'))
+        };
+        match cosmetic_separator {
+            Some(is_exception) => {
+                counts.cosmetic += 1;
+                counts.exception += usize::from(is_exception);
+            }
+            None => {
+                counts.network += 1;
+                counts.exception += usize::from(line.starts_with("@@"));
+            }
+        }
+    }
+    counts
+}
+
+/// Result of sorting a single file
+#[derive(Debug, Default)]
+pub struct SortOutcome {
+    /// Unified diff (dry run), or a "Modified:" note when writing `--changed` output
+    pub diff: Option<String>,
+    /// The file was rewritten in place
+    pub sorted: bool,
+}
+
+/// Print the "Sorted: <file>" line
+pub fn print_sorted(filename: &Path, no_color: bool) -> io::Result<()> {
+    let mut out = std::io::stdout().lock();
+    if crate::colors_off(no_color) {
+        writeln!(out, "Sorted: {}", filename.display())
+    } else {
+        writeln!(out, "{} {}", "Sorted:".bold(), filename.display())
+    }
+}
+
+/// Print cosmetic rules rewritten by `element_tidy`, grouped by file (--report-tidy)
+pub fn print_tidy_report(no_color: bool) {
+    let Ok(mut changes) = SORT_CHANGES.lock() else {
+        return;
     };
+    let mut tidied = std::mem::take(&mut changes.selectors_tidied);
+    drop(changes);
+    if tidied.is_empty() {
+        println!("No cosmetic rules would be changed by selector tidying.");
+        return;
+    }
+    tidied.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut out = std::io::stdout().lock();
+    let mut current: Option<&Path> = None;
+    for (file, before, after) in &tidied {
+        if current != Some(file.as_path()) {
+            current = Some(file.as_path());
+            if crate::colors_off(no_color) {
+                let _ = writeln!(out, "\n{}:", file.display());
+            } else {
+                let _ = writeln!(out, "\n{}:", file.display().to_string().bold());
+            }
+        }
+        if crate::colors_off(no_color) {
+            let _ = writeln!(out, "  - {}\n  + {}", before, after);
+        } else {
+            let _ = writeln!(out, "  {}\n  {}", format!("- {}", before).red(), format!("+ {}", after).green());
+        }
+    }
+    let _ = writeln!(out, "\n{} cosmetic rule(s) would be changed by selector tidying.", tidied.len());
+}
+
+/// Record the rules of a file's sorted output for cross-file duplicate detection
+fn record_rule_locations(filename: &Path, content: &[u8], config: &SortConfig) {
+    let content = String::from_utf8_lossy(content);
+    let rules: Vec<(&str, usize)> = content
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (line.trim(), i + 1))
+        .filter(|(line, _)| {
+            !line.is_empty()
+                && !line.starts_with('[')
+                && !line.starts_with("!#")
+                && !is_comment(line, config.comment_chars, config.localhost)
+        })
+        .collect();
+    if let Ok(mut changes) = SORT_CHANGES.lock() {
+        for (rule, line_no) in rules {
+            changes
+                .rule_locations
+                .entry(rule.to_string())
+                .or_default()
+                .push((filename.to_path_buf(), line_no));
+        }
+    }
+}
+
+/// Domains a line blocks outright: every host of a hosts-file entry in localhost mode,
+/// otherwise the host of a plain `||host^` rule (options or a path make it narrower, so
+/// those don't count). Domains are lowercased.
+pub(crate) fn blocked_hosts(line: &str, config: &SortConfig) -> Vec<String> {
+    if config.localhost {
+        if !is_localhost_entry(line, config.hosts_blackhole) {
+            return Vec::new();
+        }
+        return localhost_domain(line)
+            .split_ascii_whitespace()
+            .take_while(|host| !host.starts_with('#'))
+            .map(|host| host.to_ascii_lowercase())
+            .collect();
+    }
+    line.strip_prefix("||")
+        .and_then(|rest| rest.strip_suffix('^'))
+        .filter(|host| {
+            host.contains('.')
+                && host.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'.' || b == b'-' || b == b'_')
+        })
+        .map(|host| vec![host.to_ascii_lowercase()])
+        .unwrap_or_default()
+}
+
+/// Record the domains a file's sorted output blocks, split by hosts files and ABP lists
+fn record_blocked_hosts(filename: &Path, content: &[u8], config: &SortConfig) {
+    let content = String::from_utf8_lossy(content);
+    let blocked: Vec<(String, usize)> = content
+        .lines()
+        .enumerate()
+        .flat_map(|(i, line)| blocked_hosts(line.trim(), config).into_iter().map(move |host| (host, i + 1)))
+        .collect();
+    if let Ok(mut changes) = SORT_CHANGES.lock() {
+        let map = if config.localhost { &mut changes.hosts_blocked } else { &mut changes.abp_blocked };
+        for (host, line_no) in blocked {
+            map.entry(host).or_default().push((filename.to_path_buf(), line_no));
+        }
+    }
+}
+
+/// Forget the domains recorded for `print_hosts_abp_overlap` (between benchmark runs)
+pub fn clear_blocked_hosts() {
+    if let Ok(mut changes) = SORT_CHANGES.lock() {
+        changes.hosts_blocked.clear();
+        changes.abp_blocked.clear();
+    }
+}
+
+/// Print domains blocked both by a hosts file and by a `||host^` rule in an ABP list
+/// (--warn-hosts-abp-overlap), then reset both maps
+pub fn print_hosts_abp_overlap(no_color: bool) {
+    let Ok(mut changes) = SORT_CHANGES.lock() else {
+        return;
+    };
+    let hosts = std::mem::take(&mut changes.hosts_blocked);
+    let mut abp = std::mem::take(&mut changes.abp_blocked);
+    drop(changes);
+
+    let mut overlaps: Vec<_> = hosts
+        .into_iter()
+        .filter_map(|(host, in_hosts)| abp.remove(&host).map(|in_abp| (host, in_hosts, in_abp)))
+        .collect();
+    if overlaps.is_empty() {
+        println!("No domains blocked by both a hosts file and an ABP list.");
+        return;
+    }
+    overlaps.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut out = std::io::stdout().lock();
+    for (host, in_hosts, in_abp) in &mut overlaps {
+        in_hosts.sort();
+        in_abp.sort();
+        if crate::colors_off(no_color) {
+            let _ = writeln!(out, "\n{}", host);
+        } else {
+            let _ = writeln!(out, "\n{}", host.bold());
+        }
+        for (file, line_no) in in_hosts.iter() {
+            let _ = writeln!(out, "  hosts: {}:{}", file.display(), line_no);
+        }
+        for (file, line_no) in in_abp.iter() {
+            let _ = writeln!(out, "  abp:   {}:{}", file.display(), line_no);
+        }
+    }
+    let _ = writeln!(out, "\n{} domain(s) blocked by both a hosts file and an ABP list.", overlaps.len());
+}
+
+/// Print rules found in more than one file (--detect-cross-file-dupes), then reset the map
+pub fn print_cross_file_duplicates(no_color: bool) {
+    let Ok(mut changes) = SORT_CHANGES.lock() else {
+        return;
+    };
+    let locations = std::mem::take(&mut changes.rule_locations);
+    drop(changes);
+
+    let mut dupes: Vec<(String, Vec<(PathBuf, usize)>)> = locations
+        .into_iter()
+        .filter(|(_, places)| places.iter().any(|(file, _)| file != &places[0].0))
+        .collect();
+    if dupes.is_empty() {
+        println!("No rules duplicated across files.");
+        return;
+    }
+    dupes.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut out = std::io::stdout().lock();
+    for (rule, places) in &mut dupes {
+        places.sort();
+        if crate::colors_off(no_color) {
+            let _ = writeln!(out, "\n{}", rule);
+        } else {
+            let _ = writeln!(out, "\n{}", rule.bold());
+        }
+        for (file, line_no) in places.iter() {
+            let _ = writeln!(out, "  {}:{}", file.display(), line_no);
+        }
+    }
+    let _ = writeln!(out, "\n{} rule(s) appear in more than one file.", dupes.len());
+}
+
+/// Optional passes `sort_content` runs with this config, by option name
+pub(crate) fn optional_passes(config: &SortConfig) -> Vec<&'static str> {
+    let tidy = !config.dedupe_only;
+    [
+        (config.dedupe_only, "dedupe-only"),
+        (tidy && config.normalize_only, "normalize-only"),
+        (tidy && config.no_sort, "no-sort"),
+        (tidy && config.convert_ubo, "ubo-convert"),
+        (tidy && config.abp_convert, "abp-convert"),
+        (tidy && config.convert_trusted, "convert-trusted"),
+        (tidy && !config.no_selector_tidy, "selector-tidy"),
+        (tidy && config.normalize_quotes, "normalize-quotes"),
+        (tidy && config.normalize_csp, "normalize-csp"),
+        (tidy && config.normalize_redirects, "normalize-redirects"),
+        (tidy && config.normalize_anchor, "normalize-anchor"),
+        (tidy && config.fix_typos, "fix-typos"),
+        (tidy && config.drop_long_lines && config.max_line_length.is_some(), "drop-long-lines"),
+        (config.add_timestamp, "add-timestamp"),
+        (config.normalize_header, "normalize-header"),
+        (config.sort_header, "sort-header"),
+        (config.rule_count, "rule-count"),
+    ]
+    .into_iter()
+    .filter_map(|(on, name)| on.then_some(name))
+    .collect()
+}
+
+/// One-line summary of how a file was handled (`-v`): its classification, rule counts
+/// before and after sorting, and the optional passes that ran
+pub(crate) fn file_decisions(filename: &Path, before: &str, after: &str, config: &SortConfig) -> String {
+    let counts = count_rules(after, config);
+    let kind = if config.localhost {
+        "localhost"
+    } else if counts.cosmetic > counts.network {
+        "cosmetic"
+    } else {
+        "network"
+    };
+    let passes = optional_passes(config);
+    format!(
+        "File: {} -> {} ({} rules before, {} after; passes: {})",
+        filename.display(),
+        kind,
+        count_rules(before, config).total,
+        counts.total,
+        if passes.is_empty() { "none".to_string() } else { passes.join(", ") }
+    )
+}
+
+/// Whether sorting `filename` would change it, worked out in memory with nothing written
+/// (`--plan`). Tiny files and files that aren't valid UTF-8 are left alone by `fop_sort`,
+/// so they report `false`.
+pub fn would_change(filename: &Path, config: &SortConfig) -> Result<bool, FopError> {
+    let raw_content = read_list(filename)?;
+    let original_content = decode_input(&raw_content, filename, config);
+    if original_content.len() < 3 || std::str::from_utf8(&original_content).is_err() {
+        return Ok(false);
+    }
+    let new_content = sort_content(&original_content, filename, config)?;
+    Ok(*encode_output(&new_content, filename, config) != *raw_content)
+}
+
+/// Sort the sections of a filter file and save modifications.
+/// Files that aren't valid UTF-8 are skipped with a warning, or fail with
+/// `FopError::Utf8` under `strict_utf8`; either way they are left untouched.
+pub fn fop_sort(filename: &Path, config: &SortConfig) -> Result<SortOutcome, FopError> {
+    let _warning_file = crate::WarningFileGuard::set(filename);
+    let temp_file = temp_path(filename, config.temp_dir);
+
+    // Skip empty or tiny files
+    let metadata = fs::metadata(filename)?;
+    if metadata.len() < 3 {
+        return Ok(SortOutcome::default());
+    }
+
+    // Read entire file into memory (avoids double-read for diff); `.gz` lists are
+    // decompressed and other encodings (`input_encoding`) decoded here, then sorted,
+    // compared and diffed as UTF-8 text
+    let raw_content = read_list(filename)
+        .map_err(|source| FopError::Open { path: filename.to_path_buf(), source })?;
+    let original_content = decode_input(&raw_content, filename, config);
+    // Skip files that aren't valid UTF-8 before writing anything, leaving them untouched
+    if let Err(e) = std::str::from_utf8(&original_content) {
+        let valid = &original_content[..e.valid_up_to()];
+        let line_no = valid.iter().filter(|&&b| b == b'\n').count() + 1;
+        crate::INVALID_UTF8_FILES.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        if config.strict_utf8 {
+            return Err(FopError::Utf8 { path: filename.to_path_buf(), line: line_no });
+        }
+        write_warning(&format!(
+            "Skipped {}: not valid UTF-8 (line {}), file left untouched",
+            filename.display(),
+            line_no
+        ));
+        return Ok(SortOutcome::default());
+    }
     // Detect Windows line endings
     if original_content.windows(2).any(|w| w == b"\r\n") {
         crate::CRLF_FILES.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
     }
 
-    let reader = BufReader::new(Cursor::new(&original_content));
-    let mut output = match File::create(&temp_file) {
-        Ok(f) => BufWriter::with_capacity(64 * 1024, f),
-        Err(e) => {
-            eprintln!("Cannot create temp file for {}: {}", filename.display(), e);
-            return Ok(None);
+    let new_content = sort_content(&original_content, filename, config)?;
+    if config.verbosity >= Verbosity::Verbose && !config.quiet {
+        eprintln!(
+            "{}",
+            file_decisions(
+                filename,
+                &String::from_utf8_lossy(&original_content),
+                &String::from_utf8_lossy(&new_content),
+                config
+            )
+        );
+    }
+    if let Some(limit) = config.rules_per_file_limit {
+        let rules = count_rules(&String::from_utf8_lossy(&new_content), config).total;
+        if rules > limit {
+            write_warning(&format!(
+                "Large list: {} has {} rules (limit {}); consider splitting it",
+                filename.display(),
+                rules,
+                limit
+            ));
+        }
+    }
+    if config.warn_hide_interactions {
+        for warning in hide_interactions(&String::from_utf8_lossy(&new_content), config) {
+            write_warning(&format!("{} ({})", warning, filename.display()));
+        }
+    }
+    if config.detect_cross_file_dupes {
+        record_rule_locations(filename, &new_content, config);
+    }
+    if config.warn_hosts_abp_overlap {
+        record_blocked_hosts(filename, &new_content, config);
+    }
+    if let Ok(mut created) = CREATED_TEMP_FILES.lock() {
+        created.push(temp_file.clone());
+    }
+    let output_content = encode_output(&new_content, filename, config);
+    encode_list(filename, &output_content)
+        .and_then(|data| fs::write(&temp_file, data))
+        .map_err(|source| FopError::TempFile { path: filename.to_path_buf(), source })?;
+
+    // Compare files and replace if different (in the encodings read and written)
+
+    if *raw_content != *output_content {
+        if config.dry_run {
+            if config.output_changed {
+                // Write to filename--changed.ext
+                let stem = filename.file_stem().and_then(|s| s.to_str()).unwrap_or("file");
+                let ext = filename.extension().and_then(|e| e.to_str()).unwrap_or("txt");
+                let changed_filename = filename.with_file_name(format!("{}--changed.{}", stem, ext));
+                
+                write_list(&changed_filename, &output_content)?;
+                fs::remove_file(&temp_file)?;
+                
+                if !config.quiet {
+                    println!("Changed file written to: {}", changed_filename.display());
+                }
+                
+                return Ok(SortOutcome {
+                    diff: Some(format!("Modified: {} -> {}", filename.display(), changed_filename.display())),
+                    sorted: false,
+                });
+            }
+            let original_str = String::from_utf8_lossy(&original_content);
+            let new_str = String::from_utf8_lossy(&new_content);
+            if config.rules_diff_only {
+                fs::remove_file(&temp_file)?;
+                return Ok(SortOutcome { diff: rules_diff(filename, &original_str, &new_str, config), sorted: false });
+            }
+
+            // Generate unified diff
+
+            let diff = similar::TextDiff::from_lines(&*original_str, &*new_str)
+                .unified_diff()
+                .context_radius(config.diff_context.unwrap_or(3))
+                .header(
+                    &format!("a/{}", filename.display()),
+                    &format!("b/{}", filename.display()),
+                )
+                .to_string();
+
+            fs::remove_file(&temp_file)?;
+            return Ok(SortOutcome { diff: Some(diff), sorted: false });
+        } else if let Some(output) = config.output_path {
+            // Leave the source untouched and write the sorted copy into the output tree
+            if let Some(parent) = output.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            move_into_place(&temp_file, output)?;
+            if config.verbosity >= Verbosity::Verbose && !config.quiet {
+                print_sorted(filename, config.no_color)?;
+            }
+            return Ok(SortOutcome { diff: None, sorted: true });
+        } else {
+            // Create backup if requested
+            if config.backup {
+                let backup_file = filename.with_extension("backup");
+                fs::copy(filename, &backup_file)?;
+            }
+            move_into_place(&temp_file, filename)?;
+            if config.verbosity >= Verbosity::Verbose && !config.quiet {
+                print_sorted(filename, config.no_color)?;
+            }
+            return Ok(SortOutcome { diff: None, sorted: true });
+        }
+    } else {
+        fs::remove_file(&temp_file)?;
+        if let (Some(output), true, false) = (config.output_path, config.copy_unchanged, config.dry_run) {
+            if let Some(parent) = output.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(filename, output)?;
+        }
+    }
+
+    Ok(SortOutcome::default())
+}
+
+/// UTF-8 byte order mark
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Split a cosmetic rule into (domains, separator, selector), using the pattern for the sort mode.
+/// HTML filters go first: their selectors may contain `{}`, which FOPPY_ELEMENT_PATTERN rejects.
+fn element_captures<'a>(line: &'a str, config: &SortConfig) -> Option<regex::Captures<'a>> {
+    if let Some(caps) = HTML_FILTER_PATTERN.captures(line) {
+        Some(caps)
+    } else if config.alt_sort {
+        ELEMENT_PATTERN.captures(line)
+    } else if config.parse_adguard {
+        ADGUARD_ELEMENT_PATTERN.captures(line)
+    } else {
+        FOPPY_ELEMENT_PATTERN.captures(line)
+    }
+}
+
+/// `! FOP: sort` (Some(true)) or `! FOP: nosort` (Some(false)) directive comment, with any
+/// comment prefix and case
+pub(crate) fn sort_directive(line: &str) -> Option<bool> {
+    let text = line.trim().trim_start_matches(['!', '#']).trim_start();
+    let (key, value) = text.split_once(':')?;
+    if !key.trim_end().eq_ignore_ascii_case("fop") {
+        return None;
+    }
+    match value.trim().to_ascii_lowercase().as_str() {
+        "sort" => Some(true),
+        "nosort" => Some(false),
+        _ => None,
+    }
+}
+
+/// Trace how FOP tidies a single rule (`--explain`) without reading or writing any files.
+/// Returns (step, rule after the step) pairs; the last step is the line exactly as a full
+/// sort would write it, or empty if the rule would be removed.
+pub fn explain_rule(rule: &str, config: &SortConfig) -> Vec<(&'static str, String)> {
+    let rule = rule.trim();
+    let mut steps = vec![("Input", rule.to_string())];
+
+    if rule.is_empty()
+        || is_comment(rule, config.comment_chars, config.localhost)
+        || (rule.starts_with('[') && rule.ends_with(']'))
+    {
+        steps.push(("Comment or header: written unchanged", rule.to_string()));
+    } else if config.localhost {
+        steps.push(("Hosts entry: kept as-is, sorted by domain", rule.to_string()));
+    } else if let Some(caps) = element_captures(rule, config) {
+        let domains = caps[1].to_ascii_lowercase();
+        let (separator, selector) = (&caps[2], &caps[3]);
+        steps.push((
+            "Cosmetic rule: domains lowercased, sorted and deduplicated",
+            format!("{}{}{}", tidy_element_domains(&domains, separator, selector), separator, selector),
+        ));
+        if !config.no_selector_tidy {
+            steps.push(("Selector tidied", element_tidy(&domains, separator, selector, config.normalize_quotes, config.extended_markers)));
+        }
+    } else {
+        let (text, options) = rule.split_at(find_option_separator(rule).unwrap_or(rule.len()));
+        steps.push((
+            "Network rule: unnecessary wildcards removed",
+            format!("{}{}", remove_unnecessary_wildcards(text), options),
+        ));
+        let tidy = TidyOptions::from(config);
+        steps.push((
+            "Options lowercased, deduplicated and sorted",
+            filter_tidy(rule, TidyOptions { convert_ubo: false, ..tidy }),
+        ));
+        if config.convert_ubo {
+            steps.push(("uBO option aliases converted", filter_tidy(rule, tidy)));
         }
+    }
+
+    let mut output = Vec::new();
+    let content = format!("{}\n", rule);
+    let result = match sort_lines(content.as_bytes(), Path::new("--explain"), config, &mut output) {
+        Ok(()) => String::from_utf8_lossy(&output).trim_end().to_string(),
+        Err(_) => String::new(),
     };
+    steps.push(("Final", result));
+    steps
+}
+
+/// A rule as the sort would tidy it, or None for blank lines, comments and headers.
+/// Hosts entries only have their spacing collapsed.
+fn normalized_rule(line: &str, config: &SortConfig) -> Option<String> {
+    let line = line.trim().trim_start_matches('\u{feff}');
+    if line.is_empty()
+        || is_comment(line, config.comment_chars, config.localhost)
+        || (line.starts_with('[') && line.ends_with(']'))
+    {
+        return None;
+    }
+    if config.localhost {
+        return Some(line.split_whitespace().collect::<Vec<_>>().join(" "));
+    }
+    Some(match element_captures(line, config) {
+        Some(caps) => element_tidy(
+            &caps[1].to_ascii_lowercase(),
+            &caps[2],
+            &caps[3],
+            config.normalize_quotes,
+            config.extended_markers,
+        ),
+        None => filter_tidy(line, config.into()),
+    })
+}
+
+/// Rules in `new` but not `old`, and in `old` but not `new`, each sorted. Both sides are
+/// tidied first, so reordering, spacing and option order don't count as changes.
+pub(crate) fn rule_set_diff(old: &str, new: &str, config: &SortConfig) -> (Vec<String>, Vec<String>) {
+    let (old, new): (std::collections::BTreeSet<String>, std::collections::BTreeSet<String>) = crate::muted_warnings(|| {
+        (
+            old.lines().filter_map(|line| normalized_rule(line, config)).collect(),
+            new.lines().filter_map(|line| normalized_rule(line, config)).collect(),
+        )
+    });
+    (new.difference(&old).cloned().collect(), old.difference(&new).cloned().collect())
+}
+
+/// `--rules-diff-only` report for one file: a header, then `+rule`/`-rule` lines.
+/// None when both sides hold the same rules.
+pub fn rules_diff(filename: &Path, old: &str, new: &str, config: &SortConfig) -> Option<String> {
+    let (added, removed) = rule_set_diff(old, new, config);
+    if added.is_empty() && removed.is_empty() {
+        return None;
+    }
+    let mut out = format!("Rules diff: {} (+{} -{})\n", filename.display(), added.len(), removed.len());
+    for rule in &added {
+        out.push_str(&format!("+{}\n", rule));
+    }
+    for rule in &removed {
+        out.push_str(&format!("-{}\n", rule));
+    }
+    Some(out)
+}
+
+/// Sort rules by a derived key under the active collator, falling back to the full rule on ties
+fn sort_collated_by_key(rules: &mut Vec<String>, key: impl Fn(&str) -> String) {
+    let mut keyed: Vec<(String, String)> = rules.drain(..).map(|rule| (key(&rule), rule)).collect();
+    keyed.sort_by(|a, b| cmp_collated(&a.0, &b.0, |a, b| a.cmp(b)).then_with(|| a.1.cmp(&b.1)));
+    rules.extend(keyed.into_iter().map(|(_, rule)| rule));
+}
+
+/// Sort and tidy filter list content in memory, then apply whole-file header updates.
+/// `filename` is only used in diagnostics.
+pub(crate) fn sort_content(content: &[u8], filename: &Path, config: &SortConfig) -> io::Result<Vec<u8>> {
+    let _warning_file = crate::WarningFileGuard::set(filename);
+    // Strip a UTF-8 BOM before any line classification so the header line is detected
+    let (content, had_bom) = match content.strip_prefix(UTF8_BOM) {
+        Some(rest) => (rest, true),
+        None => (content, false),
+    };
+    if had_bom && !config.keep_bom && !config.quiet {
+        write_warning(&format!("Removed UTF-8 byte order mark: {}", filename.display()));
+    }
+
+    let mut output = Vec::with_capacity(content.len() + UTF8_BOM.len());
+    sort_lines(content, filename, config, &mut output)?;
+    if config.normalize_header {
+        let sorted = String::from_utf8_lossy(&output);
+        if let Some(updated) = normalize_header(&sorted, config.localhost) {
+            output = updated.into_bytes();
+        }
+    }
+    if config.sort_header {
+        let sorted = String::from_utf8_lossy(&output);
+        if let Some(updated) = sort_header(&sorted, config.localhost) {
+            output = updated.into_bytes();
+        }
+    }
+    if config.rule_count {
+        let sorted = String::from_utf8_lossy(&output);
+        if let Some(updated) = update_rule_count(&sorted, config.comment_chars, config.localhost, config.hosts_blackhole) {
+            output = updated.into_bytes();
+        }
+    }
+    if had_bom && config.keep_bom {
+        output.splice(0..0, UTF8_BOM.iter().copied());
+    }
+    Ok(output)
+}
+
+/// A rule `sort_lines` removed: (file, line number, reason, rule)
+type QuarantinedRule = (PathBuf, usize, &'static str, String);
+
+/// Rules removed during sorting, for --quarantine-invalid
+static QUARANTINE: LazyLock<Mutex<Vec<QuarantinedRule>>> =
+    LazyLock::new(|| Mutex::new(Vec::new()));
+
+/// Remember a removed rule when `quarantine_invalid` is set (the warning is still emitted)
+fn quarantine_rule(config: &SortConfig, filename: &Path, line_no: usize, reason: &'static str, rule: &str) {
+    if config.quarantine_invalid {
+        if let Ok(mut quarantine) = QUARANTINE.lock() {
+            quarantine.push((filename.to_path_buf(), line_no, reason, rule.to_string()));
+        }
+    }
+}
+
+/// Append every quarantined rule to `path`, each preceded by a `! reason (file:line)` comment,
+/// then clear the list. Entries are ordered by file and line. Returns how many were written.
+pub fn write_quarantine(path: &Path) -> Result<usize, FopError> {
+    let mut entries = match QUARANTINE.lock() {
+        Ok(mut quarantine) => std::mem::take(&mut *quarantine),
+        Err(_) => return Ok(0),
+    };
+    if entries.is_empty() {
+        return Ok(0);
+    }
+    entries.sort_by(|a, b| (&a.0, a.1).cmp(&(&b.0, b.1)));
+
+    let mut out = String::new();
+    for (file, line_no, reason, rule) in &entries {
+        out.push_str(&format!("! {} ({}:{})\n{}\n", reason, file.display(), line_no, rule));
+    }
+    let mut file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(out.as_bytes())?;
+    Ok(entries.len())
+}
+
+/// Remove repeated lines, keeping the first occurrence of each in its original position,
+/// so the result never depends on hash order. Removed lines are pushed to `dupes`
+/// (once each, in first-seen order) when given.
+pub(crate) fn dedupe_keep_first(
+    lines: impl IntoIterator<Item = String>,
+    mut dupes: Option<&mut Vec<String>>,
+) -> Vec<String> {
+    let lines = lines.into_iter();
+    let mut seen: HashSet<String> = HashSet::with_capacity(lines.size_hint().0);
+    let mut reported: HashSet<String> = HashSet::new();
+    let mut unique = Vec::with_capacity(lines.size_hint().0);
+    for line in lines {
+        if seen.contains(&line) {
+            if let Some(dupes) = dupes.as_deref_mut() {
+                if reported.insert(line.clone()) {
+                    dupes.push(line);
+                }
+            }
+        } else {
+            seen.insert(line.clone());
+            unique.push(line);
+        }
+    }
+    unique
+}
+
+/// Write a section with repeated rules removed, leaving order and content untouched
+fn write_deduped<W: Write>(section: &mut Vec<String>, dupes: Option<&mut Vec<String>>, output: &mut W) -> io::Result<()> {
+    for rule in dedupe_keep_first(section.drain(..), dupes) {
+        write!(output, "{}\n", rule)?;
+    }
+    Ok(())
+}
+
+/// `--dedupe-only`: remove repeated rules within each section and write every other line
+/// exactly as read. Blank lines, comments and directives end a section; no rule is
+/// tidied, converted, validated, combined or sorted.
+fn dedupe_only_lines<W: Write>(content: &[u8], config: &SortConfig, output: &mut W) -> io::Result<()> {
+    let track_changes = TRACK_CHANGES.load(std::sync::atomic::Ordering::Relaxed);
+    let mut dupes: Vec<String> = Vec::new();
+    let mut section: Vec<String> = Vec::new();
+    let mut in_header = true;
+
+    for line in BufReader::new(Cursor::new(content)).lines() {
+        let line = line?;
+        let trimmed = line.trim();
+        let ends_section = trimmed.is_empty()
+            || is_comment(trimmed, config.comment_chars, config.localhost)
+            || trimmed.starts_with("%include")
+            || is_preprocessor_directive(trimmed)
+            || (trimmed.starts_with('[') && trimmed.ends_with(']'));
+        if !ends_section {
+            in_header = false;
+            section.push(line);
+            continue;
+        }
+        write_deduped(&mut section, track_changes.then_some(&mut dupes), output)?;
+        // Header timestamps are still kept current
+        let updated = if config.add_timestamp && in_header {
+            update_timestamp_line(trimmed, config.timestamp_format).or_else(|| update_version_line(trimmed))
+        } else {
+            None
+        };
+        write!(output, "{}\n", updated.as_deref().unwrap_or(&line))?;
+    }
+    write_deduped(&mut section, track_changes.then_some(&mut dupes), output)?;
+
+    if !dupes.is_empty() {
+        if let Ok(mut changes) = SORT_CHANGES.lock() {
+            changes.duplicates_removed.extend(dupes);
+        }
+    }
+    Ok(())
+}
+
+/// Sort and tidy filter list content, writing the result to `output`.
+/// `filename` is only used in diagnostics.
+pub(crate) fn sort_lines<W: Write>(
+    content: &[u8],
+    filename: &Path,
+    config: &SortConfig,
+    output: &mut W,
+) -> io::Result<()> {
+    // Timestamp/version lines are only looked for within the first rules of a section
+    const CHECK_LINES: usize = 10;
 
+    if config.dedupe_only {
+        return dedupe_only_lines(content, config, output);
+    }
+
+    let _collator = CollatorGuard::set(config.sort_locale);
+    let json_warnings = crate::warnings_as_json();
+    let reader = BufReader::new(Cursor::new(content));
     let mut section: Vec<String> = Vec::with_capacity(2000);
     let mut lines_checked: usize = 1;
+    // Every rule in the section is counted so the majority decides how it sorts
     let mut filter_lines: usize = 0;
     let mut element_lines: usize = 0;
+    // Inside a `! FOP: nosort` block, and whether a `! FOP: sort` line closes it
+    let mut nosort_block = false;
+    let mut nosort_until_marker = false;
+    let sort_markers: Vec<usize> = content
+        .split(|&b| b == b'\n')
+        .enumerate()
+        .filter(|(_, line)| std::str::from_utf8(line).ok().and_then(sort_directive) == Some(true))
+        .map(|(index, _)| index)
+        .collect();
 
     let write_filters = |section: &mut Vec<String>,
-                         output: &mut BufWriter<File>,
+                         output: &mut W,
                          element_lines: usize,
                          filter_lines: usize,
                          no_sort: bool,
-                         alt_sort: bool,
+                         keep_order: bool,
+                         sort_key: SortKey,
                          localhost: bool,
                          parse_adguard: bool|
      -> io::Result<()> {
+        // Warnings from sorting and combining are about the section, not the last line read
+        crate::clear_warning_line();
         if section.is_empty() {
             return Ok(());
         }
 
+        // --normalize-only: the tidied rules go out as read, with no dedupe, sort or combine
+        if config.normalize_only {
+            for filter in section.drain(..) {
+                write!(output, "{}\n", filter)?;
+            }
+            return Ok(());
+        }
+
         // Collect duplicates locally, merge once (reduces lock contention)
         let track_changes = TRACK_CHANGES.load(std::sync::atomic::Ordering::Relaxed);
-        let mut dupes_local: HashSet<String> = HashSet::new();
-
-        // Remove duplicates while preserving order if no_sort
-        let mut unique: Vec<String> = {
-            let mut seen = HashSet::with_capacity(section.len());
-            section
-                .drain(..)
-                .filter(|x| {
-                    if !seen.insert(x.clone()) {
-                        if track_changes {
-                            dupes_local.insert(x.clone());
-                        }
-                        false
-                    } else {
-                        true
-                    }
-                })
-                .collect()
-        };
+        let mut dupes_local: Vec<String> = Vec::new();
+
+        // Keep the first occurrence of each rule, before any sorting
+        let mut unique = dedupe_keep_first(section.drain(..), track_changes.then_some(&mut dupes_local));
 
         // Merge tracked duplicates into global changes once
         if track_changes && !dupes_local.is_empty() {
@@ -1258,24 +3058,69 @@ pub fn fop_sort(filename: &Path, config: &SortConfig) -> io::Result<Option<Strin
             }
         }
 
+        if (config.print_sections || config.verbosity == Verbosity::VeryVerbose) && !config.quiet {
+            let (kind, key) = if localhost {
+                ("hosts", "localhost domain")
+            } else if element_lines > filter_lines {
+                ("cosmetic", match sort_key {
+                    SortKey::Full => "full rule",
+                    _ if parse_adguard => "ADGUARD_ELEMENT_DOMAIN_PATTERN",
+                    SortKey::Selector => "ELEMENT_DOMAIN_PATTERN",
+                    SortKey::Domain => "FOPPY_ELEMENT_DOMAIN_PATTERN",
+                })
+            } else {
+                ("network", "case-insensitive rule text")
+            };
+            eprintln!(
+                "Section: {} ({} rules, first: {}) -> {} [element={}, filter={}, key={}]",
+                filename.display(),
+                unique.len(),
+                unique[0],
+                kind,
+                element_lines,
+                filter_lines,
+                if keep_order { "none (! FOP: nosort)" } else if no_sort { "none (--no-sort)" } else { key }
+            );
+        }
+
+        // `! FOP: nosort` block: rules stay in the order written and aren't combined
+        if keep_order {
+            for filter in unique {
+                write!(output, "{}\n", filter)?;
+            }
+            return Ok(());
+        }
+
         if localhost {
             // Sort hosts file entries by domain
             if !no_sort {
-                unique.sort_by_cached_key(|s| localhost_domain(s).to_ascii_lowercase());
+                if collating() {
+                    sort_collated_by_key(&mut unique, |s| localhost_domain(s).to_string());
+                } else {
+                    unique.sort_by_cached_key(|s| localhost_domain(s).to_ascii_lowercase());
+                }
             }
             for filter in unique {
                 write!(output, "{}\n", filter)?;
             }
         } else if element_lines > filter_lines {
-            if !no_sort {
+            if sort_key == SortKey::Full && !no_sort {
+                unique.sort_by(|a, b| cmp_collated(a, b, |a, b| a.cmp(b)));
+            } else if !no_sort {
                 let pattern = if parse_adguard {
                     &*ADGUARD_ELEMENT_DOMAIN_PATTERN
-                } else if alt_sort {
+                } else if sort_key == SortKey::Selector {
                     &*ELEMENT_DOMAIN_PATTERN
                 } else {
                     &*FOPPY_ELEMENT_DOMAIN_PATTERN
                 };
-                unique.sort_by_cached_key(|s| pattern.replace(s, "").into_owned());
+                // Key on the selector; rules with the same selector fall back to the
+                // full rule so the output doesn't depend on input order
+                if collating() {
+                    sort_collated_by_key(&mut unique, |s| pattern.replace(s, "").into_owned());
+                } else {
+                    unique.sort_by_cached_key(|s| (pattern.replace(s, "").into_owned(), s.clone()));
+                }
             }
             // Merge :has-text() rules first, then combine domains
             let merged = combine_has_text_rules(unique);
@@ -1284,32 +3129,51 @@ pub fn fop_sort(filename: &Path, config: &SortConfig) -> io::Result<Option<Strin
             } else {
                 &*ELEMENT_DOMAIN_PATTERN
             };
-            let combined = combine_filters(merged, combine_pattern, ",");
+            let combined = combine_filters(merged, combine_pattern, ",", config.warn_combine);
             for filter in combined {
                 write!(output, "{}\n", filter)?;
             }
         } else {
-            // Sort blocking rules (unless no_sort)
-            if !no_sort {
-                unique.sort_by(|a, b| cmp_ascii_case_insensitive(a, b));
-            }
-            let combined = combine_filters(unique, &FILTER_DOMAIN_PATTERN, "|");
-            for filter in combined {
-                write!(output, "{}\n", filter)?;
+            // With --group-exceptions, blocking rules and @@ exceptions are sorted
+            // and combined as separate groups
+            let groups = if config.group_exceptions && !no_sort {
+                let (blocking, exceptions): (Vec<String>, Vec<String>) =
+                    unique.into_iter().partition(|rule| !rule.starts_with("@@"));
+                if config.exceptions_first {
+                    [exceptions, blocking]
+                } else {
+                    [blocking, exceptions]
+                }
+            } else {
+                [unique, Vec::new()]
+            };
+            for mut group in groups {
+                // Sort blocking rules (unless no_sort)
+                if !no_sort {
+                    group.sort_by(|a, b| cmp_collated(a, b, cmp_ascii_case_insensitive));
+                }
+                let combined = combine_filters(group, &FILTER_DOMAIN_PATTERN, "|", config.warn_combine);
+                for filter in combined {
+                    write!(output, "{}\n", filter)?;
+                }
             }
         }
 
         Ok(())
     };
 
-    for line in reader.lines() {
+    for (index, line) in reader.lines().enumerate() {
         let line_owned = line?;
         let line = line_owned.trim();
+        let line_no = index + 1;
+        if json_warnings {
+            crate::set_warning_line(line_no, line);
+        }
 
         // Update timestamp if enabled and within first 10 lines
         let updated_line;
         let line = if config.add_timestamp && lines_checked <= CHECK_LINES {
-            if let Some(updated) = update_timestamp_line(line) {
+            if let Some(updated) = update_timestamp_line(line, config.timestamp_format) {
                 updated_line = updated;
                 updated_line.as_str()
             } else if let Some(updated) = update_version_line(line) {
@@ -1327,11 +3191,12 @@ pub fn fop_sort(filename: &Path, config: &SortConfig) -> io::Result<Option<Strin
                 if !section.is_empty() {
                     write_filters(
                         &mut section,
-                        &mut output,
+                        output,
                         element_lines,
                         filter_lines,
                         config.no_sort,
-                        config.alt_sort,
+                        nosort_block,
+                        config.cosmetic_sort_key(),
                         config.localhost,
                         config.parse_adguard,
                     )?;
@@ -1345,22 +3210,21 @@ pub fn fop_sort(filename: &Path, config: &SortConfig) -> io::Result<Option<Strin
         }
 
         // Comments and special lines
-        let is_comment = config.comment_chars.iter().any(|c| line.starts_with(c))
-            || (config.localhost
-                && line.starts_with('#')
-                && !config.comment_chars.iter().any(|c| c == "#"));
+        let is_comment = is_comment(line, config.comment_chars, config.localhost);
         if is_comment
             || line.starts_with("%include")
+            || is_preprocessor_directive(line)
             || (line.starts_with('[') && line.ends_with(']'))
         {
             if !section.is_empty() {
                 write_filters(
                     &mut section,
-                    &mut output,
+                    output,
                     element_lines,
                     filter_lines,
                     config.no_sort,
-                    config.alt_sort,
+                    nosort_block,
+                    config.cosmetic_sort_key(),
                     config.localhost,
                     config.parse_adguard,
                 )?;
@@ -1368,23 +3232,69 @@ pub fn fop_sort(filename: &Path, config: &SortConfig) -> io::Result<Option<Strin
                 filter_lines = 0;
                 element_lines = 0;
             }
+            // `! FOP: nosort` protects the rules after it until `! FOP: sort`, or without
+            // one, until the next comment
+            match sort_directive(line).filter(|_| is_comment) {
+                Some(false) => {
+                    nosort_block = true;
+                    nosort_until_marker = sort_markers.iter().any(|&marker| marker > index);
+                }
+                Some(true) => nosort_block = false,
+                None if !nosort_until_marker => nosort_block = false,
+                None => {}
+            }
             write!(output, "{}\n", line)?;
             continue;
         }
 
+        // Overlong rules are usually pasted data URIs or obfuscated selectors
+        if let Some(max) = config.max_line_length {
+            let length = line.chars().count();
+            if length > max {
+                let preview: String = line.chars().take(80).collect();
+                write_warning(&format!(
+                    "{} rule longer than {} characters ({}): {}{} in {}",
+                    if config.drop_long_lines { "Removed" } else { "Long" },
+                    max,
+                    length,
+                    preview,
+                    if length > 80 { "..." } else { "" },
+                    filename.display()
+                ));
+                if config.drop_long_lines {
+                    quarantine_rule(config, filename, line_no, "longer than max-line-length", line);
+                    continue;
+                }
+            }
+        }
+
         // Validate localhost entries when in localhost mode
-        if config.localhost && !is_localhost_entry(line) {
-            write_warning(&format!("Removed invalid localhost entry: {}", line));
+        if config.localhost {
+            if !is_localhost_entry(line, config.hosts_blackhole) {
+                write_warning(&format!("Removed invalid localhost entry: {}", line));
+                quarantine_rule(config, filename, line_no, "invalid localhost entry", line);
+                continue;
+            }
+            // Host entries skip filter_tidy, which would strip the address/domain separator
+            filter_lines += 1;
+            if lines_checked <= CHECK_LINES {
+                lines_checked += 1;
+            }
+            section.push(line.to_string());
             continue;
-
         }
 
         // Skip filters less than 4 characters — no valid rule is that short
         if line.len() < 4 {
             write_warning(&format!("Removed malformed rule (too short): {}", line));
+            quarantine_rule(config, filename, line_no, "too short", line);
             continue;
         }
 
+        if config.warn_js_rules && is_js_rule(line) {
+            write_warning(&format!("AdGuard JS rule: {} (AdGuard only; check it belongs in this list)", line));
+        }
+
         // [$path=/\/(dom|pro)/]rambler.ru##div[style^="order:"][style*="-1"]
         // AdGuard cosmetic rule modifiers - pass through unchanged
         if line.starts_with("[$") {
@@ -1394,29 +3304,44 @@ pub fn fop_sort(filename: &Path, config: &SortConfig) -> io::Result<Option<Strin
 
         // Handle regex domain rules (uBO) - pass through unchanged
         if REGEX_ELEMENT_PATTERN.is_match(line) {
-            section.push(filter_tidy(line, config.convert_ubo));
+            section.push(filter_tidy(line, config.into()));
             continue;
         }
 
         // Process element hiding rules
-        let element_caps = if config.alt_sort {
-            ELEMENT_PATTERN.captures(line)
-        } else if config.parse_adguard {
-            ADGUARD_ELEMENT_PATTERN.captures(line)
-        } else {
-            FOPPY_ELEMENT_PATTERN.captures(line)
-        };
-        if let Some(caps) = element_caps {
+        if let Some(caps) = element_captures(line, config) {
             let domains = caps[1].to_ascii_lowercase();
             let separator = &caps[2];
             let selector = &caps[3];
 
+            // example.com##.ad$third-party is neither a valid cosmetic nor network rule
+            if let Some(options) = cosmetic_network_options(selector) {
+                write_warning(&format!(
+                    "Removed malformed rule (cosmetic selector with network options ${}): {}",
+                    options, line
+                ));
+                quarantine_rule(config, filename, line_no, "cosmetic selector with network options", line);
+                continue;
+            }
+
+            element_lines += 1;
             if lines_checked <= CHECK_LINES {
-                element_lines += 1;
                 lines_checked += 1;
             }
 
-            let mut tidied = element_tidy(&domains, separator, selector);
+            let mut tidied = if config.no_selector_tidy {
+                format!("{}{}{}", tidy_element_domains(&domains, separator, selector), separator, selector)
+            } else {
+                element_tidy(&domains, separator, selector, config.normalize_quotes, config.extended_markers)
+            };
+            // Only selector rewrites are reported; domain sorting isn't selector tidying
+            if config.report_tidy
+                && tidied.split_once(separator).is_some_and(|(_, tidied_selector)| tidied_selector != selector)
+            {
+                if let Ok(mut changes) = SORT_CHANGES.lock() {
+                    changes.selectors_tidied.push((filename.to_path_buf(), line.to_string(), tidied.clone()));
+                }
+            }
 
             // Convert ABP extended selectors
             if config.abp_convert {
@@ -1478,6 +3403,17 @@ pub fn fop_sort(filename: &Path, config: &SortConfig) -> io::Result<Option<Strin
                     tidied = fixed;
                 }
             }
+            if config.warn_generic_cosmetic {
+                if let Some(reason) = generic_cosmetic_reason(&tidied, config.generic_cosmetic_allow) {
+                    write_warning(&format!("Broad generic rule: {} ({})", tidied, reason));
+                }
+            }
+            if config.warn_tld_cosmetic && is_tld_cosmetic(&domains) {
+                write_warning(&format!("TLD-wide cosmetic rule: {} (applies to every site under {})", tidied, domains));
+            }
+            if config.detect_confusables {
+                warn_confusables(domains.split(','), &tidied);
+            }
             section.push(tidied);
             continue;
         }
@@ -1494,6 +3430,7 @@ pub fn fop_sort(filename: &Path, config: &SortConfig) -> io::Result<Option<Strin
                 let has_wildcard = domain.contains('*');
 
                 if !config.ignore_dot_domains
+                    && !config.allow_dotless_domains.iter().any(|d| d.eq_ignore_ascii_case(domain))
                     && !is_ip
                     && !has_wildcard
                     && !domain.contains('.')
@@ -1503,6 +3440,7 @@ pub fn fop_sort(filename: &Path, config: &SortConfig) -> io::Result<Option<Strin
                         "Skipped network rule without dot in domain: {} (domain: {})",
                         line, domain
                     ));
+                    quarantine_rule(config, filename, line_no, "no dot in domain", line);
                     continue;
                 }
             }
@@ -1511,15 +3449,45 @@ pub fn fop_sort(filename: &Path, config: &SortConfig) -> io::Result<Option<Strin
         // Remove TLD-only patterns
         if is_tld_only(line) {
             write_warning(&format!("Removed overly broad TLD-only rule: {}", line));
+            quarantine_rule(config, filename, line_no, "TLD-only rule", line);
             continue;
         }
 
+        filter_lines += 1;
         if lines_checked <= CHECK_LINES {
-            filter_lines += 1;
             lines_checked += 1;
         }
 
-        let mut tidied = filter_tidy(line, config.convert_ubo);
+        let mut tidied = filter_tidy(line, config.into());
+        if config.normalize_csp {
+            if let Cow::Owned(normalized) = normalize_csp_options(&tidied) {
+                tidied = normalized;
+            }
+        }
+        if config.normalize_anchor {
+            if let Some(anchored) = normalize_anchor(&tidied) {
+                write_warning(&format!("Normalized anchor: {} -> {}", tidied, anchored));
+                tidied = anchored;
+            }
+        }
+        if config.warn_broad_options {
+            if let Some(reason) = broad_option_reason(&tidied) {
+                write_warning(&format!("Broad rule: {} ({})", tidied, reason));
+            }
+        }
+        if config.detect_confusables {
+            if let Some(caps) = FILTER_DOMAIN_PATTERN.captures(&tidied) {
+                warn_confusables(caps[1].split('|'), &tidied);
+            }
+        }
+        for option in dialect_unsupported_options(&tidied, config.dialect) {
+            write_warning(&format!(
+                "Warning: The option \"{}\" used on the filter \"{}\" is not supported by {}",
+                option,
+                tidied,
+                config.dialect.name()
+            ));
+        }
 
         // Fix typos if enabled (network rules)
         if config.fix_typos {
@@ -1537,75 +3505,22 @@ pub fn fop_sort(filename: &Path, config: &SortConfig) -> io::Result<Option<Strin
         }
         section.push(tidied);
     }
+    crate::clear_warning_line();
 
     // Write remaining filters
     if !section.is_empty() {
         write_filters(
             &mut section,
-            &mut output,
+            output,
             element_lines,
             filter_lines,
             config.no_sort,
-            config.alt_sort,
+            nosort_block,
+            config.cosmetic_sort_key(),
             config.localhost,
             config.parse_adguard,
         )?;
     }
 
-    drop(output);
-
-    // Compare files and replace if different
-    let new_content = fs::read(&temp_file)?;
-
-    if original_content != new_content {
-        if config.dry_run {
-            if config.output_changed {
-                // Write to filename--changed.ext
-                let stem = filename.file_stem().and_then(|s| s.to_str()).unwrap_or("file");
-                let ext = filename.extension().and_then(|e| e.to_str()).unwrap_or("txt");
-                let changed_filename = filename.with_file_name(format!("{}--changed.{}", stem, ext));
-                
-                fs::write(&changed_filename, &new_content)?;
-                fs::remove_file(&temp_file)?;
-                
-                if !config.quiet {
-                    println!("Changed file written to: {}", changed_filename.display());
-                }
-                
-                return Ok(Some(format!("Modified: {} -> {}", filename.display(), changed_filename.display())));
-            }
-            // Generate unified diff
-            let original_str = String::from_utf8_lossy(&original_content);
-            let new_str = String::from_utf8_lossy(&new_content);
-
-            let diff = similar::TextDiff::from_lines(&*original_str, &*new_str)
-                .unified_diff()
-                .header(
-                    &format!("a/{}", filename.display()),
-                    &format!("b/{}", filename.display()),
-                )
-                .to_string();
-
-            fs::remove_file(&temp_file)?;
-            return Ok(Some(diff));
-        } else {
-            // Create backup if requested
-            if config.backup {
-                let backup_file = filename.with_extension("backup");
-                fs::copy(filename, &backup_file)?;
-            }
-            fs::rename(&temp_file, filename)?;
-            if !config.quiet {
-                if config.no_color {
-                    let _ = writeln!(std::io::stdout().lock(), "Sorted: {}", filename.display());
-                } else {
-                    println!("{} {}", "Sorted:".bold(), filename.display());
-                }
-            }
-        }
-    } else {
-        fs::remove_file(&temp_file)?;
-    }
-
-    Ok(None)
+    Ok(())
 }
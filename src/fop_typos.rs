@@ -6,9 +6,11 @@
 //! - domain#.class ? domain##.class
 //! - domain,,domain##.ad ? domain,domain##.ad
 
+use crate::fop_error::FopError;
 use regex::Regex;
 use std::borrow::Cow;
-use std::sync::LazyLock;
+use std::path::Path;
+use std::sync::{LazyLock, OnceLock};
 
 // =============================================================================
 // Cosmetic Typo Patterns
@@ -94,6 +96,67 @@ static WRONG_DOMAIN_SEPARATOR: LazyLock<Regex> = LazyLock::new(|| {
         .unwrap()
 });
 
+// =============================================================================
+// Custom Typo Rules (--typo-rules)
+// =============================================================================
+
+/// A project-specific typo pattern: `regex => replacement => description`
+#[derive(Debug)]
+pub struct CustomTypoRule {
+    pattern: Regex,
+    replacement: String,
+    description: String,
+}
+
+/// Custom rules loaded at startup, checked after the built-in patterns
+static CUSTOM_TYPO_RULES: OnceLock<Vec<CustomTypoRule>> = OnceLock::new();
+
+/// Parse `regex => replacement => description` lines. Blank lines and `!` comments are skipped.
+/// Errors name the 1-based line number.
+pub fn parse_typo_rules(content: &str) -> Result<Vec<CustomTypoRule>, FopError> {
+    let mut rules = Vec::new();
+    for (line_num, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('!') {
+            continue;
+        }
+        let parts: Vec<&str> = line.splitn(3, " => ").collect();
+        let [pattern, replacement, description] = parts[..] else {
+            return Err(FopError::Parse(format!(
+                "line {}: expected \"regex => replacement => description\"",
+                line_num + 1
+            )));
+        };
+        let pattern = Regex::new(pattern.trim())
+            .map_err(|e| FopError::Parse(format!("line {}: invalid regex: {}", line_num + 1, e)))?;
+        rules.push(CustomTypoRule {
+            pattern,
+            replacement: replacement.trim().to_string(),
+            description: description.trim().to_string(),
+        });
+    }
+    Ok(rules)
+}
+
+/// Load custom typo rules from a file for the rest of the run; returns how many were loaded
+pub fn load_typo_rules(path: &Path) -> Result<usize, FopError> {
+    let content = std::fs::read_to_string(path)?;
+    let rules = parse_typo_rules(&content)?;
+    let count = rules.len();
+    CUSTOM_TYPO_RULES
+        .set(rules)
+        .map_err(|_| FopError::Parse("typo rules already loaded".to_string()))?;
+    Ok(count)
+}
+
+/// First custom rule that changes the line
+fn detect_custom_typo<'a>(line: &str, rules: &'a [CustomTypoRule]) -> Option<(String, &'a str)> {
+    rules.iter().find_map(|rule| match rule.pattern.replace_all(line, rule.replacement.as_str()) {
+        Cow::Owned(fixed) if fixed != line => Some((fixed, rule.description.as_str())),
+        _ => None,
+    })
+}
+
 // =============================================================================
 // Typo Detection
 // =============================================================================
@@ -145,7 +208,19 @@ fn fix_leading_comma(line: &str) -> Option<Typo> {
     }
 }
 
-/// Check a cosmetic rule for typos
+/// Collapse a doubled exception prefix (`@@@@||example.com^` -> `@@||example.com^`)
+fn fix_doubled_exception(line: &str) -> Option<Typo> {
+    let at_count = line.bytes().take_while(|&b| b == b'@').count();
+    if at_count < 3 {
+        return None;
+    }
+    Some(Typo {
+        fixed: format!("@@{}", &line[at_count..]),
+        description: Cow::Owned(format!("Doubled exception prefix ({} ? @@)", &line[..at_count])),
+    })
+}
+
+/// Check a rule for typos: built-in patterns first, then any `--typo-rules`
 #[inline]
 pub fn detect_typo(line: &str) -> Option<Typo> {
     // Skip comments, empty lines, special directives, short lines
@@ -153,6 +228,18 @@ pub fn detect_typo(line: &str) -> Option<Typo> {
         return None;
     }
 
+    detect_builtin_typo(line).or_else(|| {
+        let (fixed, description) = detect_custom_typo(line, CUSTOM_TYPO_RULES.get()?)?;
+        Some(Typo { fixed, description: Cow::Borrowed(description) })
+    })
+}
+
+/// Check a rule against the built-in typo patterns
+fn detect_builtin_typo(line: &str) -> Option<Typo> {
+    if let Some(typo) = fix_doubled_exception(line) {
+        return Some(typo);
+    }
+
     // Fast reject: no trigger characters means no possible typo
     // All patterns require at least one of: # $ , |
     if !line.bytes().any(|b| b == b'#' || b == b'$' || b == b',' || b == b'|') {
@@ -273,7 +360,7 @@ pub fn report_addition_typos(typos: &[(&Addition, Typo)], no_color: bool) {
 
     println!("\nTypos found in added lines:");
     for (add, typo) in typos {
-        if no_color {
+        if crate::colors_off(no_color) {
             println!(
                 "  {}:{}: {} ? {}",
                 add.file, add.line_num, add.content, typo.fixed
@@ -300,6 +387,29 @@ pub fn report_addition_typos(typos: &[(&Addition, Typo)], no_color: bool) {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_custom_typo_rules() {
+        let rules = parse_typo_rules(
+            "! project typos\n\nAdvertisment => Advertisement => Misspelled brand\n(banner)-+ad => $1-ad => Repeated dash\n",
+        )
+        .unwrap();
+        assert_eq!(rules.len(), 2);
+        assert_eq!(
+            detect_custom_typo("##.Advertisment-box", &rules),
+            Some(("##.Advertisement-box".to_string(), "Misspelled brand"))
+        );
+        assert_eq!(
+            detect_custom_typo("##.banner---ad", &rules),
+            Some(("##.banner-ad".to_string(), "Repeated dash"))
+        );
+        assert_eq!(detect_custom_typo("##.banner-ad", &rules), None);
+
+        let err = parse_typo_rules("ok => fine => desc\n([a-z => x => broken\n").unwrap_err().to_string();
+        assert!(err.starts_with("line 2: invalid regex"), "{}", err);
+        let err = parse_typo_rules("\nmissing parts\n").unwrap_err().to_string();
+        assert!(err.starts_with("line 2: expected"), "{}", err);
+    }
+
     #[test]
     fn test_extra_hash() {
         let typo = detect_typo("###.ad-banner").unwrap();
@@ -312,6 +422,21 @@ mod tests {
         assert_eq!(typo.fixed, "##.ad");
     }
 
+    #[test]
+    fn test_doubled_exception_prefix() {
+        let typo = detect_typo("@@@@||example.com^").unwrap();
+        assert_eq!(typo.fixed, "@@||example.com^");
+
+        let typo = detect_typo("@@@@example.com/ads.js").unwrap();
+        assert_eq!(typo.fixed, "@@example.com/ads.js");
+
+        let typo = detect_typo("@@@||example.com^$script").unwrap();
+        assert_eq!(typo.fixed, "@@||example.com^$script");
+
+        assert!(detect_typo("@@||example.com^").is_none());
+        assert!(detect_typo("@@||example.com^$domain=a.com").is_none());
+    }
+
     #[test]
     fn test_single_hash() {
         let typo = detect_typo("domain#.ad").unwrap();
@@ -0,0 +1,585 @@
+//! FOP - Filter Orderer and Preener
+//!
+//! Library interface for sorting and cleaning ad-blocking filter lists.
+//! The `fop` binary is a thin CLI over this crate; the same sorting, typo,
+//! checksum and datestamp logic can be embedded in other build pipelines.
+//!
+//! ```
+//! use fop::SortConfig;
+//!
+//! let comments = vec!["!".to_string()];
+//! let config = SortConfig { comment_chars: &comments, convert_ubo: true, ..Default::default() };
+//! let sorted = fop::sort_string("||b.com^\n||a.com^\n", &config)?;
+//! assert_eq!(sorted, "||a.com^\n||b.com^\n");
+//! # Ok::<(), fop::FopError>(())
+//! ```
+//!
+//! Copyright (C) 2025 FanboyNZ (FOP Rust)
+//! https://github.com/ryanbr/fop-rs
+//!
+//! Copyright (C) 2011 Michael (original Python version)
+//! Rust port maintains GPL-3.0 license compatibility.
+
+#![allow(clippy::write_with_newline)]
+
+pub mod fop_checksum;
+pub mod fop_datestamp;
+pub mod fop_error;
+pub mod fop_sort;
+pub mod fop_typos;
+/// Git commit/PR workflow used by the CLI (interactive; not a stable API)
+#[doc(hidden)]
+pub mod fop_git;
+/// Run-to-run file cache used by the CLI's --cache (not a stable API)
+#[doc(hidden)]
+pub mod fop_cache;
+
+#[cfg(test)]
+mod test_support;
+#[cfg(test)]
+mod tests;
+
+use ahash::AHashMap;
+use ahash::AHashSet as HashSet;
+use regex::Regex;
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex};
+
+pub use fop_checksum::{add_checksum, calculate_checksum, verify_checksum, ChecksumResult};
+pub use fop_datestamp::add_timestamp;
+pub use fop_error::FopError;
+pub use fop_sort::{SortConfig, SortKey, SortLocale, SortOutcome};
+pub use fop_typos::{detect_typo, fix_all_typos};
+
+// =============================================================================
+// Public API
+// =============================================================================
+
+/// Sort and tidy a filter list file in place.
+///
+/// The outcome carries a diff when `config.dry_run` is set and the file would change,
+/// and `sorted` when the file was rewritten. Files that aren't valid UTF-8 are left untouched
+/// (an [`FopError::Utf8`] error with `config.strict_utf8`).
+pub fn sort_file(path: &Path, config: &SortConfig) -> Result<SortOutcome, FopError> {
+    fop_sort::fop_sort(path, config)
+}
+
+/// Sort and tidy filter list text, returning the result with `\n` line endings.
+///
+/// Applies the same section handling, tidying and combining as [`sort_file`],
+/// without touching the filesystem.
+pub fn sort_string(content: &str, config: &SortConfig) -> Result<String, FopError> {
+    let path = Path::new("<string>");
+    let output = fop_sort::sort_content(content.as_bytes(), path, config)?;
+    String::from_utf8(output).map_err(|e| {
+        let valid = &e.as_bytes()[..e.utf8_error().valid_up_to()];
+        let line = valid.iter().filter(|&&b| b == b'\n').count() + 1;
+        FopError::Utf8 { path: path.to_path_buf(), line }
+    })
+}
+
+// =============================================================================
+// Warning Output
+// =============================================================================
+
+/// Thread-safe warning output
+pub(crate) static WARNING_BUFFER: LazyLock<Mutex<Vec<String>>> =
+    LazyLock::new(|| Mutex::new(Vec::with_capacity(100)));
+#[doc(hidden)]
+pub static WARNING_OUTPUT: LazyLock<Mutex<Option<PathBuf>>> =
+    LazyLock::new(|| Mutex::new(None));
+/// Fast flag to avoid mutex lock on every write_warning call
+#[doc(hidden)]
+pub static WARNING_TO_FILE: std::sync::atomic::AtomicBool =
+    std::sync::atomic::AtomicBool::new(false);
+/// Counter for files with Windows line endings (CRLF)
+#[doc(hidden)]
+pub static CRLF_FILES: std::sync::atomic::AtomicUsize =
+    std::sync::atomic::AtomicUsize::new(0);
+/// Counter for files skipped because they aren't valid UTF-8
+#[doc(hidden)]
+pub static INVALID_UTF8_FILES: std::sync::atomic::AtomicUsize =
+    std::sync::atomic::AtomicUsize::new(0);
+
+/// Counter for warnings emitted this run, wherever they were written
+#[doc(hidden)]
+pub static WARNING_COUNT: std::sync::atomic::AtomicUsize =
+    std::sync::atomic::AtomicUsize::new(0);
+
+/// How warnings are written (`--warning-format`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WarningFormat {
+    /// One plain message per line
+    #[default]
+    Text,
+    /// One JSON object per line: severity, message, file, line and rule
+    Json,
+}
+
+/// Fast flag for `WarningFormat::Json`, checked on every write_warning call
+static WARNING_JSON: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// Set the format used for every warning written from now on
+#[doc(hidden)]
+pub fn set_warning_format(format: WarningFormat) {
+    WARNING_JSON.store(format == WarningFormat::Json, std::sync::atomic::Ordering::Relaxed);
+}
+
+/// Whether warnings are written as JSON (callers only track line context when they are)
+#[inline]
+pub(crate) fn warnings_as_json() -> bool {
+    WARNING_JSON.load(std::sync::atomic::Ordering::Relaxed)
+}
+
+/// Per-file warning cap (`--max-warnings-per-file`); `usize::MAX` = unlimited
+static MAX_WARNINGS_PER_FILE: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(usize::MAX);
+
+/// Stop writing a file's warnings after `limit`, summarising the rest (`None` = unlimited)
+#[doc(hidden)]
+pub fn set_max_warnings_per_file(limit: Option<usize>) {
+    MAX_WARNINGS_PER_FILE.store(limit.unwrap_or(usize::MAX), std::sync::atomic::Ordering::Relaxed);
+}
+
+/// What a warning is about, and how many warnings the file has had
+#[derive(Debug, Default)]
+struct WarningContext {
+    /// File being processed; `None` when warnings aren't about a file
+    file: Option<PathBuf>,
+    /// Line number and rule being processed
+    line: Option<(usize, String)>,
+    /// Warnings written for `file`
+    written: usize,
+    /// Warnings held back by the per-file cap
+    suppressed: usize,
+}
+
+thread_local! {
+    /// Warning context for this thread, attached to JSON warnings and used for the per-file cap
+    static WARNING_CONTEXT: std::cell::RefCell<WarningContext> =
+        const { std::cell::RefCell::new(WarningContext { file: None, line: None, written: 0, suppressed: 0 }) };
+    /// Warnings on this thread are dropped (see `muted_warnings`)
+    static WARNINGS_MUTED: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+/// Run `f` with this thread's warnings dropped, e.g. when tidying rules only to compare them
+pub(crate) fn muted_warnings<T>(f: impl FnOnce() -> T) -> T {
+    let previous = WARNINGS_MUTED.with(|m| m.replace(true));
+    let result = f();
+    WARNINGS_MUTED.with(|m| m.set(previous));
+    result
+}
+
+/// Sets the file for warnings on this thread, restoring the previous context when dropped.
+/// Setting the file already being processed is a no-op, so nested calls share one count.
+pub(crate) struct WarningFileGuard(Option<WarningContext>);
+
+impl WarningFileGuard {
+    pub(crate) fn set(path: &Path) -> Self {
+        WARNING_CONTEXT.with(|c| {
+            if c.borrow().file.as_deref() == Some(path) {
+                return WarningFileGuard(None);
+            }
+            let context = WarningContext { file: Some(path.to_path_buf()), ..Default::default() };
+            WarningFileGuard(Some(c.replace(context)))
+        })
+    }
+}
+
+impl Drop for WarningFileGuard {
+    fn drop(&mut self) {
+        let Some(previous) = self.0.take() else { return };
+        let summary = WARNING_CONTEXT.with(|c| {
+            let mut context = c.borrow_mut();
+            context.line = None;
+            let file = context.file.as_deref().map_or_else(String::new, |f| f.display().to_string());
+            (context.suppressed > 0).then(|| format!("... and {} more in {}", context.suppressed, file))
+        });
+        if let Some(summary) = summary {
+            emit_warning(&summary);
+        }
+        WARNING_CONTEXT.with(|c| *c.borrow_mut() = previous);
+    }
+}
+
+/// Attach `line_no` and `rule` to warnings on this thread until cleared
+pub(crate) fn set_warning_line(line_no: usize, rule: &str) {
+    WARNING_CONTEXT.with(|c| c.borrow_mut().line = Some((line_no, rule.to_string())));
+}
+
+/// Stop attaching a line to warnings (e.g. once a whole section is being written)
+pub(crate) fn clear_warning_line() {
+    WARNING_CONTEXT.with(|c| c.borrow_mut().line = None);
+}
+
+/// Count a warning against the current file; false once the file is over `limit`
+fn within_file_limit(limit: usize) -> bool {
+    if limit == usize::MAX {
+        return true;
+    }
+    WARNING_CONTEXT.with(|c| {
+        let mut context = c.borrow_mut();
+        if context.file.is_none() {
+            true
+        } else if context.written < limit {
+            context.written += 1;
+            true
+        } else {
+            context.suppressed += 1;
+            false
+        }
+    })
+}
+
+/// Severity of a warning message: automatic fixes are `info`, failures `error`
+fn warning_severity(message: &str) -> &'static str {
+    const INFO: [&str; 5] = ["Fixed ", "Normalized ", "Converted ", "Combined", "... and "];
+    if message.starts_with("Error") {
+        "error"
+    } else if INFO.iter().any(|prefix| message.starts_with(prefix)) {
+        "info"
+    } else {
+        "warning"
+    }
+}
+
+/// `s` as a quoted JSON string
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// `message` as a JSON object with this thread's file and line context
+#[doc(hidden)]
+pub fn warning_json(message: &str) -> String {
+    WARNING_CONTEXT.with(|c| {
+        let context = c.borrow();
+        let file = context.file.as_ref()
+            .map_or_else(|| "null".to_string(), |path| json_string(&path.display().to_string()));
+        let (line, rule) = context.line.as_ref()
+            .map_or_else(|| ("null".to_string(), "null".to_string()), |(line, rule)| (line.to_string(), json_string(rule)));
+        format!(
+            "{{\"severity\":\"{}\",\"message\":{},\"file\":{},\"line\":{},\"rule\":{}}}",
+            warning_severity(message),
+            json_string(message),
+            file,
+            line,
+            rule
+        )
+    })
+}
+
+/// Write warning to buffer (if file output) or stderr, as JSON with `WarningFormat::Json`.
+/// Every warning is counted, including those held back by `--max-warnings-per-file`; warnings
+/// raised inside `muted_warnings` are dropped uncounted.
+#[doc(hidden)]
+pub fn write_warning(message: &str) {
+    if WARNINGS_MUTED.with(std::cell::Cell::get) {
+        return;
+    }
+    WARNING_COUNT.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    if within_file_limit(MAX_WARNINGS_PER_FILE.load(std::sync::atomic::Ordering::Relaxed)) {
+        emit_warning(message);
+    }
+}
+
+/// Write `message` without counting it
+fn emit_warning(message: &str) {
+    let json;
+    let message = if warnings_as_json() {
+        json = warning_json(message);
+        json.as_str()
+    } else {
+        message
+    };
+    if !WARNING_TO_FILE.load(std::sync::atomic::Ordering::Relaxed) {
+        eprintln!("{}", message);
+        return;
+    }
+    if let Ok(mut buffer) = WARNING_BUFFER.lock() {
+        buffer.push(message.to_string());
+    }
+}
+
+/// Flush buffered warnings to file
+#[doc(hidden)]
+pub fn flush_warnings() {
+    // Clone the output path and take the warnings out of the mutex so we don't hold locks during I/O.
+    let path = {
+        let Ok(guard) = WARNING_OUTPUT.lock() else { return };
+        let Some(ref path) = *guard else { return };
+        path.clone()
+    };
+
+    let warnings = {
+        let Ok(mut buffer) = WARNING_BUFFER.lock() else { return };
+        if buffer.is_empty() { return; }
+        std::mem::take(&mut *buffer)
+    };
+    
+    use std::fs::OpenOptions;
+    use std::io::{BufWriter, Write};
+    if let Ok(file) = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(path)
+    {
+        let mut writer = BufWriter::new(file);
+        for msg in warnings {
+            let _ = write!(writer, "{}\n", msg);
+        }
+    }
+}
+
+// =============================================================================
+// Color Output
+// =============================================================================
+
+/// Crate-wide color switch, set once by the CLI (`--no-color`, or stdout isn't a terminal)
+static NO_COLOR: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// Turn colored output off (or back on) for every reporter in the crate
+pub fn set_no_color(disabled: bool) {
+    NO_COLOR.store(disabled, std::sync::atomic::Ordering::Relaxed);
+}
+
+/// Whether output should be plain: `no_color` for this call, or colors are off crate-wide
+#[doc(hidden)]
+pub fn colors_off(no_color: bool) -> bool {
+    no_color || NO_COLOR.load(std::sync::atomic::Ordering::Relaxed)
+}
+
+// =============================================================================
+// Regex Patterns (shared by the sorting modules)
+// =============================================================================
+
+/// Pattern for extracting domain from blocking filter options
+pub(crate) static FILTER_DOMAIN_PATTERN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\$(?:[^,]*,)*domain=([^,]+)").unwrap());
+
+/// Pattern for extracting domain from element hiding rules  
+pub(crate) static ELEMENT_DOMAIN_PATTERN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"^([^/|@"!]*?)#[@?$%]?#"#).unwrap());
+
+/// Pattern for extracting domain from AdGuard extended element rules
+pub(crate) static ADGUARD_ELEMENT_DOMAIN_PATTERN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"^([^/|@"!]*?)(#[@?$%]?#|#\$\?#|#@\$\?#|\$\$|\$@\$)"#).unwrap());
+
+/// Pattern for AdGuard extended element matching (includes #$?# and #@$?#)
+pub(crate) static ADGUARD_ELEMENT_PATTERN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"^([^/|@"!]*?)(#[@?$%]?#|#@[$%?]#|#\$\?#|#@\$\?#|\$\$|\$@\$)(.+)$"#).unwrap());
+
+/// Pattern for FOP element matching (no {} in selector)
+pub(crate) static FOPPY_ELEMENT_PATTERN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"^([^/|@"!]*?)(#[@?$%]?#|#@[$%?]#)([^{}]+)$"#).unwrap());
+
+/// Pattern for FOP.py compatible sorting (only ## and #@#)
+pub(crate) static FOPPY_ELEMENT_DOMAIN_PATTERN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"^[^/|@"!]*?#@?#"#).unwrap());
+
+/// Pattern for element hiding rules (standard, uBO, and AdGuard extended syntax)
+pub(crate) static ELEMENT_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"^([^/|@"!]*?)(##|#@#|#\?#|#@\?#|#\$#|#@\$#|#%#|#@%#)(.+)$"#).unwrap()
+});
+
+/// Pattern for uBO HTML filtering rules (`##^`/`#@#^`)
+pub(crate) static HTML_FILTER_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"^([^/|@"!]*?)(##|#@#)(\^.+)$"#).unwrap()
+});
+
+/// Pattern for regex domain element hiding rules (uBO/AdGuard specific)
+pub(crate) static REGEX_ELEMENT_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"^(/[^#]+/)(##|#@#|#\?#|#@\?#|#\$#|#@\$#|#%#|#@%#)(.+)$"#).unwrap()
+});
+
+/// Filter text and its options; a `/regex/` value (`$cookie=/a,b/`) may contain commas
+pub(crate) static OPTION_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(.*[^\\]|)\$(~?[\w\-]+(?:=(?:/\S*/|[^,\s]+))?(?:,~?[\w\-]+(?:=(?:/\S*/|[^,\s]+))?)*)$").unwrap()
+});
+
+pub(crate) static PSEUDO_PATTERN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(:[a-zA-Z\-]*[A-Z][a-zA-Z\-]*)").unwrap());
+
+pub(crate) static REMOVAL_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"([>+~,@\s])(\*)([#.\[:])").expect("Invalid REMOVAL_PATTERN regex")
+});
+
+pub(crate) static ATTRIBUTE_VALUE_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"^([^'"\\]|\\.)*("(?:[^"\\]|\\.)*"|'(?:[^'\\]|\\.)*')|\*"#).unwrap()
+});
+
+pub(crate) static TREE_SELECTOR: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(\\.|[^+>~ \t])\s*([+>~ \t])\s*(\D)").unwrap());
+
+pub(crate) static UNICODE_SELECTOR: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\\[0-9a-fA-F]{1,6}\s[a-zA-Z]*[A-Z]").unwrap());
+
+pub(crate) static DOMAIN_EXTRACT_PATTERN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\|*([^/\^\$]+)").unwrap());
+
+pub(crate) static IP_ADDRESS_PATTERN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\d+\.\d+\.\d+\.\d+").unwrap());
+
+// =============================================================================
+// Constants
+// =============================================================================
+
+/// Known Adblock Plus options (HashSet for O(1) lookup)
+pub(crate) static KNOWN_OPTIONS: LazyLock<HashSet<&'static str>> = LazyLock::new(|| {
+    [
+        // Standard ABP options
+        "collapse",
+        "csp",
+        "csp=frame-src",
+        "csp=img-src",
+        "csp=media-src",
+        "csp=script-src",
+        "csp=worker-src",
+        "document",
+        "elemhide",
+        "font",
+        "genericblock",
+        "generichide",
+        "image",
+        "match-case",
+        "media",
+        "object-subrequest",
+        "object",
+        "other",
+        "ping",
+        "popup",
+        "script",
+        "stylesheet",
+        "subdocument",
+        "third-party",
+        "webrtc",
+        "websocket",
+        "xmlhttprequest",
+        // uBO short options
+        "xhr",
+        "css",
+        "1p",
+        "3p",
+        "frame",
+        "doc",
+        "ghide",
+        "xml",
+        "iframe",
+        "first-party",
+        "strict1p",
+        "strict3p",
+        "strict-first-party",
+        "strict-third-party",
+        "beacon",
+        "ehide",
+        "shide",
+        "specifichide",
+        // uBO/ABP specific
+        "all",
+        "badfilter",
+        "important",
+        "popunder",
+        "empty",
+        "cname",
+        "inline-font",
+        "inline-script",
+        "removeparam",
+        "redirect-rule",
+        "_____",
+        "-----",
+        // Adguard
+        "network",
+        "content",
+        "extension",
+        "jsinject",
+        "stealth",
+        "cookie",
+        "urlblock",
+        // ABP rewrite resources
+        "rewrite=abp-resource:1x1-transparent-gif",
+        "rewrite=abp-resource:2x2-transparent-png",
+        "rewrite=abp-resource:32x32-transparent-png",
+        "rewrite=abp-resource:3x2-transparent-png",
+        "rewrite=abp-resource:blank-css",
+        "rewrite=abp-resource:blank-html",
+        "rewrite=abp-resource:blank-js",
+        "rewrite=abp-resource:blank-mp3",
+        "rewrite=abp-resource:blank-mp4",
+        "rewrite=abp-resource:blank-text",
+    ]
+    .into_iter()
+    .collect()
+});
+
+/// Network option names each engine accepts (`--dialect`), without `~` or `=value`.
+/// Options outside `KNOWN_OPTIONS` are left to the "not recognised" warning instead.
+pub(crate) const ABP_OPTIONS: &[&str] = &[
+    "collapse", "csp", "document", "domain", "elemhide", "font", "genericblock", "generichide",
+    "header", "image", "match-case", "media", "object", "object-subrequest", "other", "ping",
+    "popup", "rewrite", "script", "sitekey", "stylesheet", "subdocument", "third-party",
+    "webrtc", "websocket", "xmlhttprequest",
+];
+
+pub(crate) const UBO_OPTIONS: &[&str] = &[
+    "1p", "3p", "all", "badfilter", "beacon", "cname", "css", "csp", "denyallow", "doc",
+    "document", "domain", "ehide", "elemhide", "empty", "first-party", "font", "frame", "from",
+    "generichide", "ghide", "header", "iframe", "image", "important", "inline-font",
+    "inline-script", "ipaddress", "match-case", "media", "method", "object", "other",
+    "permissions", "ping", "popunder", "popup", "reason", "redirect", "redirect-rule",
+    "removeparam", "replace", "script", "shide", "specifichide", "strict-first-party",
+    "strict-third-party", "strict1p", "strict3p", "stylesheet", "subdocument", "third-party",
+    "to", "uritransform", "urlskip", "urltransform", "websocket", "xhr", "xml", "xmlhttprequest",
+];
+
+pub(crate) const ADGUARD_OPTIONS: &[&str] = &[
+    "1p", "3p", "all", "app", "badfilter", "content", "cookie", "css", "csp", "denyallow", "doc",
+    "document", "domain", "ehide", "elemhide", "empty", "extension", "first-party", "font", "frame",
+    "generichide", "genericblock", "ghide", "header", "hls", "image", "important", "jsinject",
+    "jsonprune", "match-case", "media", "method", "network", "object", "other", "permissions",
+    "ping", "popup", "redirect", "redirect-rule", "referrerpolicy", "removeheader",
+    "removeparam", "replace", "script", "shide", "specifichide", "stealth", "stylesheet",
+    "subdocument", "third-party", "to", "urlblock", "urltransform", "webrtc", "websocket", "xhr",
+    "xmlhttprequest", "xmlprune",
+];
+
+/// uBO to ABP option conversions. `popup` is spelled the same in ABP, uBO and AdGuard, and
+/// uBO's `popunder` (block the opener, not the popup) has no ABP equivalent, so neither is mapped.
+pub(crate) static UBO_CONVERSIONS: LazyLock<AHashMap<&'static str, &'static str>> =
+    LazyLock::new(|| {
+        [
+            ("xhr", "xmlhttprequest"),
+            ("~xhr", "~xmlhttprequest"),
+            ("css", "stylesheet"),
+            ("~css", "~stylesheet"),
+            ("1p", "~third-party"),
+            ("~1p", "third-party"),
+            ("3p", "third-party"),
+            ("~3p", "~third-party"),
+            ("frame", "subdocument"),
+            ("~frame", "~subdocument"),
+            ("doc", "document"),
+            ("ghide", "generichide"),
+            ("xml", "xmlhttprequest"),
+            ("~xml", "~xmlhttprequest"),
+            ("iframe", "subdocument"),
+            ("~iframe", "~subdocument"),
+            ("beacon", "ping"),
+            ("~beacon", "~ping"),
+            ("ehide", "elemhide"),
+        ]
+        .into_iter()
+        .collect()
+    });
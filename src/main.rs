@@ -14,37 +14,164 @@
 #[global_allocator]
 static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
 
-mod fop_git;
-mod fop_checksum;
-mod fop_sort;
-mod fop_typos;
-mod fop_datestamp;
+use fop::{fop_cache, fop_checksum, fop_datestamp, fop_git, fop_sort, fop_typos};
+use fop::{flush_warnings, write_warning, FopError, WarningFormat, CRLF_FILES, INVALID_UTF8_FILES, WARNING_COUNT, WARNING_OUTPUT, WARNING_TO_FILE};
 
-#[cfg(test)]
-mod tests;
-
-use ahash::AHashMap;
 use ahash::AHashSet as HashSet;
 use std::collections::HashMap;
 use std::env;
 use std::fs;
-use std::io::{self, Write};
+use std::io::{self, IsTerminal, Write};
 use std::path::{Path, PathBuf};
-use std::sync::Mutex;
 use owo_colors::OwoColorize;
 
-use std::sync::LazyLock;
-/// Thread-safe warning output
-pub(crate) static WARNING_BUFFER: LazyLock<Mutex<Vec<String>>> =
-    LazyLock::new(|| Mutex::new(Vec::with_capacity(100)));
-pub(crate) static WARNING_OUTPUT: LazyLock<Mutex<Option<PathBuf>>> =
-    LazyLock::new(|| Mutex::new(None));
-/// Fast flag to avoid mutex lock on every write_warning call
-pub(crate) static WARNING_TO_FILE: std::sync::atomic::AtomicBool =
-    std::sync::atomic::AtomicBool::new(false);
-/// Counter for files with Windows line endings (CRLF)
-pub(crate) static CRLF_FILES: std::sync::atomic::AtomicUsize =
-    std::sync::atomic::AtomicUsize::new(0);
+#[cfg(test)]
+mod main_tests;
+#[cfg(test)]
+mod test_support;
+
+/// Exit with an error if --strict-utf8 is set and any file failed UTF-8 validation
+fn check_strict_utf8(strict_utf8: bool) {
+    let invalid = INVALID_UTF8_FILES.swap(0, std::sync::atomic::Ordering::Relaxed);
+    if strict_utf8 && invalid > 0 {
+        flush_warnings();
+        eprintln!("Error: {} file(s) are not valid UTF-8 (--strict-utf8)", invalid);
+        std::process::exit(1);
+    }
+}
+
+/// Exit with an error if more warnings than --fail-on-warning-count were emitted
+fn check_warning_limit(limit: Option<usize>, warning_output: Option<&Path>) {
+    let Some(limit) = limit else { return };
+    let count = WARNING_COUNT.load(std::sync::atomic::Ordering::Relaxed);
+    if count > limit {
+        flush_warnings();
+        let location = warning_output
+            .map(|path| format!(" (written to {})", path.display()))
+            .unwrap_or_default();
+        eprintln!(
+            "Error: {} warning(s) emitted{}, more than the --fail-on-warning-count limit of {}",
+            count, location, limit
+        );
+        std::process::exit(1);
+    }
+}
+
+/// --fail-fast state: set by the first per-file error, after which workers skip the
+/// remaining files
+struct FailFast {
+    aborted: std::sync::atomic::AtomicBool,
+    /// The file (and error) that triggered the abort
+    cause: std::sync::OnceLock<String>,
+}
+
+impl FailFast {
+    const fn new() -> Self {
+        FailFast { aborted: std::sync::atomic::AtomicBool::new(false), cause: std::sync::OnceLock::new() }
+    }
+
+    /// Whether an earlier file stopped the run
+    fn aborted(&self) -> bool {
+        self.aborted.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// Report a per-file error; with `fail_fast` it stops the remaining files. Lists that
+    /// aren't valid UTF-8 only fail here under --strict-utf8; otherwise `fop_sort` skips
+    /// them with a warning.
+    fn record(&self, fail_fast: bool, path: &Path, error: &FopError) {
+        let cause = match error {
+            // These name the file themselves
+            FopError::Open { .. } | FopError::TempFile { .. } | FopError::Utf8 { .. } => error.to_string(),
+            _ => format!("{}: {}", path.display(), error),
+        };
+        match error {
+            FopError::Io(_) => eprintln!("Error processing {}", cause),
+            FopError::Utf8 { .. } => eprintln!("Error: {}", cause),
+            _ => eprintln!("{}", cause),
+        }
+        if fail_fast {
+            let _ = self.cause.set(cause);
+            self.aborted.store(true, std::sync::atomic::Ordering::Relaxed);
+        }
+    }
+
+    /// Run one file's work unless an earlier file stopped the run, recording its error
+    fn run<T>(&self, fail_fast: bool, path: &Path, work: impl FnOnce() -> Result<T, FopError>) -> Option<T> {
+        if self.aborted() {
+            return None;
+        }
+        work().map_err(|e| self.record(fail_fast, path, &e)).ok()
+    }
+}
+
+static FAIL_FAST: FailFast = FailFast::new();
+
+/// Exit with an error, before any commit, if --fail-fast stopped the run
+fn check_fail_fast() {
+    if !FAIL_FAST.aborted() {
+        return;
+    }
+    fop_sort::cleanup_temp_files();
+    flush_warnings();
+    eprintln!(
+        "Error: stopped after a failure in {} (--fail-fast); no commit attempted",
+        FAIL_FAST.cause.get().map_or("a file", String::as_str)
+    );
+    std::process::exit(1);
+}
+
+/// Explain a checksum mismatch (--verify-verbose): what was hashed, plus prefix checksums
+/// to compare against a good copy of the list
+fn print_checksum_details(path: &Path, config: &SortConfig) {
+    match fop_checksum::checksum_details(path, config) {
+        Ok(details) => {
+            eprintln!(
+                "  Hashed {} bytes in {} lines ({} stray CR, {} blank line(s) ignored)",
+                details.normalized_len, details.lines, details.carriage_returns, details.blank_lines
+            );
+            let prefixes: Vec<String> = details
+                .prefix_checksums
+                .iter()
+                .map(|(line, checksum)| format!("line {}: {}", line, checksum))
+                .collect();
+            if !prefixes.is_empty() {
+                eprintln!("  Prefix checksums: {}", prefixes.join(", "));
+            }
+        }
+        Err(e) => eprintln!("  Cannot read {} for details: {}", path.display(), e),
+    }
+}
+
+/// Fingerprint for --cache: the FOP version, command-line options, FOP_* environment
+/// variables and .fopconfig contents, so a run that sorts differently starts a fresh cache
+fn cache_fingerprint(config_path: Option<&str>) -> String {
+    let mut input = env!("CARGO_PKG_VERSION").to_string();
+    for arg in env::args().skip(1).filter(|arg| arg.starts_with('-')) {
+        input.push('\n');
+        input.push_str(&arg);
+    }
+    let mut vars: Vec<(String, String)> = env::vars().filter(|(key, _)| key.starts_with("FOP_")).collect();
+    vars.sort();
+    for (key, value) in vars {
+        input.push_str(&format!("\n{}={}", key, value));
+    }
+    if let Some(content) = config_path.and_then(|path| fs::read_to_string(path).ok()) {
+        input.push('\n');
+        input.push_str(&content);
+    }
+    format!("{:x}", md5::compute(input))
+}
+
+/// Append the rules removed during sorting to --quarantine-invalid FILE
+fn write_quarantine_file(path: Option<&Path>, quiet: bool) {
+    let Some(path) = path else { return };
+    match fop_sort::write_quarantine(path) {
+        Ok(0) => {}
+        Ok(count) if !quiet => println!("Quarantined {} removed rule(s) to {}", count, path.display()),
+        Ok(_) => {}
+        Err(e) => eprintln!("Error writing --quarantine-invalid {}: {}", path.display(), e),
+    }
+}
 
 /// Get user's home directory (cross-platform)
 fn home_dir() -> Option<PathBuf> {
@@ -63,56 +190,14 @@ fn get_git_username() -> Option<String> {
         .map(|s| s.trim().to_lowercase())
 }
 
-/// Write warning to buffer (if file output) or stderr
-pub(crate) fn write_warning(message: &str) {
-    if !WARNING_TO_FILE.load(std::sync::atomic::Ordering::Relaxed) {
-        eprintln!("{}", message);
-        return;
-    }
-    if let Ok(mut buffer) = WARNING_BUFFER.lock() {
-        buffer.push(message.to_string());
-    }
-}
-
-/// Flush buffered warnings to file
-pub(crate) fn flush_warnings() {
-    // Clone the output path and take the warnings out of the mutex so we don't hold locks during I/O.
-    let path = {
-        let Ok(guard) = WARNING_OUTPUT.lock() else { return };
-        let Some(ref path) = *guard else { return };
-        path.clone()
-    };
-
-    let warnings = {
-        let Ok(mut buffer) = WARNING_BUFFER.lock() else { return };
-        if buffer.is_empty() { return; }
-        std::mem::take(&mut *buffer)
-    };
-    
-    use std::fs::OpenOptions;
-    use std::io::{BufWriter, Write};
-    if let Ok(file) = OpenOptions::new()
-        .create(true)
-        .write(true)
-        .truncate(true)
-        .open(path)
-    {
-        let mut writer = BufWriter::new(file);
-        for msg in warnings {
-            let _ = write!(writer, "{}\n", msg);
-        }
-    }
-}
-
 use rayon::prelude::*;
-use regex::Regex;
 use walkdir::{DirEntry, WalkDir};
 
 use fop_git::{
-    build_base_command, check_repo_changes, commit_changes, create_pull_request, get_added_lines,
+    build_base_command, commit_changes, create_pull_request, get_added_lines,
     git_available, get_remote_name, check_banned_domains, RepoDefinition, REPO_TYPES,
 };
-use fop_sort::{fop_sort, SortConfig, TRACK_CHANGES};
+use fop_sort::{fop_sort, Dialect, SortConfig, SortKey, SortLocale, Verbosity, TRACK_CHANGES};
 
 // FOP version number
 const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -135,8 +220,18 @@ struct Args {
     disable_ignored: bool,
     /// Skip sorting (only combine rules)
     no_sort: bool,
+    /// Only remove duplicate rules within sections; keep every other line as written
+    dedupe_only: bool,
+    /// Tidy every rule but keep line order and duplicates as written
+    normalize_only: bool,
     /// Use alternative sorting (sort by selector for all rule types)
     alt_sort: bool,
+    /// Cosmetic sort key (None follows alt_sort)
+    sort_key: Option<SortKey>,
+    /// Sort blocking rules and @@ exceptions in network sections as separate groups
+    group_exceptions: bool,
+    /// With group_exceptions, write the exception group first
+    exceptions_first: bool,
     /// Convert ABP extended selectors to uBO format
     abp_convert: bool,
     /// Convert trusted scriptlets to non-trusted when value is safe
@@ -155,6 +250,10 @@ struct Args {
     ignore_dirs: Vec<String>,
     /// Only process these files, ignore all others (comma-separated)
     ignore_all_but: Vec<String>,
+    /// Glob patterns for files to exclude (relative to the scanned root)
+    exclude_glob: Vec<String>,
+    /// Glob patterns for files to include; when set, only matching files are processed
+    include_glob: Vec<String>,
     /// Disable large change warning prompt
     no_large_warning: bool,
     /// File extensions to process (default: .txt)
@@ -169,14 +268,24 @@ struct Args {
     ignore_dot_domains: bool,
     /// Output warnings to file instead of stderr
     warning_output: Option<PathBuf>,
+    /// Write warnings as plain text or newline-delimited JSON
+    warning_format: WarningFormat,
     /// Create PR branch instead of committing to master (optional: PR title)
     create_pr: Option<String>,
     /// Fix cosmetic typos in all processed files
     fix_typos: bool,
+    /// Prompt before applying each --fix-typos fix
+    interactive_typos: bool,
+    /// Extra `regex => replacement => description` typo patterns
+    typo_rules: Option<PathBuf>,
     /// Base branch for PR (default: auto-detect main/master)
     git_pr_branch: Option<String>,
     /// Include rule changes in PR body
     pr_show_changes: bool,
+    /// Text placed at the top of the PR body
+    pr_body: Option<String>,
+    /// Labels applied to the PR
+    pr_labels: Vec<String>,
     /// Path to banned domain list file
     check_banned_list: Option<PathBuf>,
     /// Auto-remove banned domains and commit
@@ -191,6 +300,14 @@ struct Args {
     output_diff: Option<PathBuf>,  // Combined mode: single file
     /// Output individual .diff files alongside source files
     output_diff_individual: bool,
+    /// Write one patch per changed file into this directory
+    diff_dir: Option<PathBuf>,
+    /// Write sorted files into a mirror tree here instead of overwriting sources
+    output_dir: Option<PathBuf>,
+    /// With --output-dir, also copy files that need no changes
+    copy_unchanged: bool,
+    /// Write sorted files next to the source with this suffix instead of overwriting
+    suffix_output: Option<String>,
     /// Suppress most output (for CI)
     quiet: bool,
     /// Suppress directory listing only
@@ -203,8 +320,14 @@ struct Args {
     git_message: Option<String>,
     /// Only sort files changed according to git
     only_sort_changed: bool,
+    /// Only process files that differ from this git ref
+    since: Option<String>,
     /// Auto rebase and retry if push fails
     rebase_on_fail: bool,
+    /// Abort before sorting a repository that already has uncommitted changes
+    require_clean_tree: bool,
+    /// Number of rebase+push retries after a failed push
+    push_retries: usize,
     /// CI mode - exit with error code on failures
     ci: bool,
     /// Show applied configuration
@@ -217,20 +340,132 @@ struct Args {
     help: bool,
     /// Show version
     version: bool,
+    /// Print a .fopconfig listing every key with its default, then exit
+    dump_config_template: bool,
     /// Update timestamp in file header
     add_timestamp: Vec<String>,
+    /// Timestamp pattern (None = "30 Jan 2026 08:31 UTC")
+    timestamp_format: Option<String>,
     /// Add/update checksum for specific files
     add_checksum: Vec<String>,
     /// Validate checksum for specific files
     validate_checksum: Vec<String>,
     /// Validate and fix checksum for specific files
     validate_checksum_and_fix: Vec<String>,
+    /// Explain checksum mismatches (hashed length, stray CRs, prefix checksums)
+    verify_verbose: bool,
     /// Custom git binary path
     git_binary: Option<String>,
     /// Benchmark mode - time processing and report metrics
     benchmark: bool,
+    /// Canonicalize attribute selector quotes to double quotes
+    normalize_quotes: bool,
+    /// Print per-section classification details to stderr
+    print_sections: bool,
+    /// Log each file or directory process_location skips, with the reason
+    print_ignored: bool,
+    /// Sort directives inside $csp=/$permissions= values
+    normalize_csp: bool,
+    /// Rewrite redirect resource aliases to their canonical names
+    normalize_redirects: bool,
+    /// Warn about $all, and $document/$popup blocking rules without domain=
+    warn_broad_options: bool,
+    /// Byte-wise or Unicode collation for domain and section sorting
+    sort_locale: SortLocale,
+    /// Engine whose supported options network rules are checked against
+    dialect: Dialect,
+    /// Append ^ to bare ||host rules
+    normalize_anchor: bool,
+    /// Context lines in dry-run diffs (default 3)
+    diff_context: Option<usize>,
+    /// Print each tidying step for a single rule, then exit
+    explain: Option<String>,
+    /// Log every rule combination performed
+    warn_combine: bool,
+    /// Warn about rules longer than N characters
+    max_line_length: Option<usize>,
+    /// Remove rules over --max-line-length instead of warning
+    drop_long_lines: bool,
+    /// Warn when a sorted file has more than this many rules
+    rules_per_file_limit: Option<usize>,
+    /// Warn about generic cosmetic rules with overly broad selectors
+    warn_generic_cosmetic: bool,
+    /// Canonical order and spacing for header metadata lines
+    normalize_header: bool,
+    /// Canonical order for header metadata lines, text unchanged
+    sort_header: bool,
+    /// Append rules removed during sorting (with reason and location) to this file
+    quarantine_invalid: Option<PathBuf>,
+    /// Skip lists unchanged (mtime and size) since the last run recorded in this file
+    cache: Option<PathBuf>,
+    /// Generic selectors --warn-generic-cosmetic accepts
+    generic_cosmetic_allow: Vec<String>,
+    /// Warn about cosmetic rules whose only domains are bare TLDs
+    warn_tld_cosmetic: bool,
+    /// Warn about AdGuard JavaScript rules (#%#, #@%#)
+    warn_js_rules: bool,
+    /// Warn about cosmetic rules disabled by $generichide/$specifichide exceptions in the same file
+    warn_hide_interactions: bool,
+    /// Warn about $domain= and cosmetic domains that mix scripts or look like ASCII domains
+    detect_confusables: bool,
+    /// Fail with non-zero exit when a file isn't valid UTF-8
+    strict_utf8: bool,
+    /// Insert/update a "! Rules: N" header line
+    rule_count: bool,
+    /// Directory for temp files (default: alongside each source file)
+    temp_dir: Option<PathBuf>,
+    /// Keep a UTF-8 byte order mark instead of stripping it
+    keep_bom: bool,
+    /// Encoding lists are read in (None = UTF-8)
+    input_encoding: Option<&'static encoding_rs::Encoding>,
+    /// Encoding sorted lists are written in (None = input encoding)
+    output_encoding: Option<&'static encoding_rs::Encoding>,
+    /// Per-file reporting (-v, -vv); "Sorted:" lines print live from -v up
+    verbosity: Verbosity,
+    /// Skip selector rewrites in cosmetic rules (domains are still sorted)
+    no_selector_tidy: bool,
+    /// Extra selector substrings that mark extended syntax (passed through untouched)
+    extended_markers: Vec<String>,
+    /// Dry run that reports cosmetic rules element_tidy would rewrite
+    report_tidy: bool,
+    /// Dry run that reports rules added or removed, ignoring reorders and formatting
+    rules_diff_only: bool,
+    /// Report sorted rules that appear in more than one file
+    detect_cross_file_dupes: bool,
+    /// Report domains blocked by both a hosts file and a `||host^` rule
+    warn_hosts_abp_overlap: bool,
+    /// Addresses accepted as hosts-file blackholes in localhost mode
+    hosts_blackhole: Vec<String>,
+    /// Dotless domains kept in network rules (e.g. intranet hosts)
+    allow_dotless_domains: Vec<String>,
+    /// Print rule tallies per file without sorting or writing
+    count_only: bool,
+    /// Print the files that would change and the git step that would follow, then exit
+    plan: bool,
+    /// Time each file and print the N slowest at the end
+    time_per_file: Option<usize>,
+    /// Exit non-zero when more than this many warnings are emitted
+    fail_on_warning_count: Option<usize>,
+    /// Warnings shown per file before the rest are summarised
+    max_warnings_per_file: Option<usize>,
+    /// Stop at the first per-file error and exit without committing
+    fail_fast: bool,
+    /// Descend into symlinked directories
+    follow_symlinks: bool,
+    /// Only commit modified files matching this glob
+    commit_pathspec: Option<String>,
+    /// Command run after sorting (`{}` = each sorted file)
+    post_hook: Option<String>,
+    /// Fail the run when the post-hook exits non-zero (default: warn)
+    post_hook_fail: bool,
     /// Per-file configuration overrides from [filename] sections in .fopconfig
     file_overrides: ahash::AHashMap<String, FileOverrides>,
+    /// [profile:name] section layered over the base config (--profile)
+    profile: Option<String>,
+    /// Warnings for .fopconfig keys that don't match any setting, or values that can't be used
+    config_warnings: Vec<String>,
+    /// FOP_* environment variables that overrode config values
+    env_config_vars: Vec<String>,
 }
 
 /// Per-file configuration overrides from [filename] sections
@@ -248,6 +483,8 @@ struct FileOverrides {
     keep_empty_lines: Option<bool>,
     ignore_dot_domains: Option<bool>,
     fix_typos: Option<bool>,
+    rule_count: Option<bool>,
+    no_selector_tidy: Option<bool>,
 }
 
 impl FileOverrides {
@@ -262,6 +499,8 @@ impl FileOverrides {
         if let Some(v) = self.fix_typos { config.fix_typos = v; }
         if let Some(v) = self.abp_convert { config.abp_convert = v; }
         if let Some(v) = self.convert_trusted { config.convert_trusted = v; }
+        if let Some(v) = self.rule_count { config.rule_count = v; }
+        if let Some(v) = self.no_selector_tidy { config.no_selector_tidy = v; }
         if let Some(v) = self.no_ubo_convert { config.convert_ubo = !v; }
         if let Some(true) = self.add_timestamp { config.add_timestamp = true; }
     }
@@ -283,15 +522,291 @@ fn apply_file_override(entry: &mut FileOverrides, key: &str, value: &str) {
         "keep-empty-lines" => entry.keep_empty_lines = Some(b),
         "ignore-dot-domains" => entry.ignore_dot_domains = Some(b),
         "fix-typos" => entry.fix_typos = Some(b),
+        "rule-count" => entry.rule_count = Some(b),
+        "no-selector-tidy" => entry.no_selector_tidy = Some(b),
         _ => {}
     }
 }
 
-/// Load configuration from .fopconfig file
-fn load_config(custom_path: Option<&PathBuf>) -> (HashMap<String, String>, ahash::AHashMap<String, FileOverrides>, Option<PathBuf>) {
+/// Compiled --exclude-glob/--include-glob patterns
+struct FileGlobs {
+    exclude: Option<globset::GlobSet>,
+    include: Option<globset::GlobSet>,
+}
+
+impl FileGlobs {
+    /// Compile glob patterns (empty lists match nothing / everything respectively)
+    fn new(exclude: &[String], include: &[String]) -> Result<Self, globset::Error> {
+        let build = |patterns: &[String]| -> Result<Option<globset::GlobSet>, globset::Error> {
+            if patterns.is_empty() {
+                return Ok(None);
+            }
+            let mut builder = globset::GlobSetBuilder::new();
+            for pattern in patterns {
+                builder.add(globset::Glob::new(pattern)?);
+            }
+            builder.build().map(Some)
+        };
+        Ok(Self { exclude: build(exclude)?, include: build(include)? })
+    }
+
+    /// Check a file path (relative to the scanned root) against the patterns
+    #[inline]
+    fn allows(&self, relative: &Path) -> bool {
+        self.skip_reason(relative).is_none()
+    }
+
+    /// Why the patterns reject a file path (relative to the scanned root), if they do
+    fn skip_reason(&self, relative: &Path) -> Option<&'static str> {
+        if self.exclude.as_ref().is_some_and(|set| set.is_match(relative)) {
+            Some("matched --exclude-glob")
+        } else if self.include.as_ref().is_some_and(|set| !set.is_match(relative)) {
+            Some("not matched by --include-glob")
+        } else {
+            None
+        }
+    }
+}
+
+/// Keys recognised at the top level of .fopconfig: (key, default, description).
+/// `--dump-config-template` prints these; keys without a default are commented out.
+const CONFIG_KEYS: &[(&str, &str, &str)] = &[
+    ("abp-convert", "false", "Convert ABP extended selectors to uBO format"),
+    ("add-checksum", "", "Files to add/update a checksum line in (comma-separated)"),
+    ("add-timestamp", "", "Files to update the Last modified timestamp in (comma-separated)"),
+    ("allow-dotless-domains", "", "Dotless domains to keep in network rules (comma-separated)"),
+    ("alt-sort", "false", "Alternative sorting method"),
+    ("auto-banned-remove", "false", "Auto-remove banned domains and commit"),
+    ("auto-fix", "false", "Auto-fix typos without prompting"),
+    ("backup", "false", "Create .backup files before modifying"),
+    ("cache", "", "Skip lists unchanged (mtime and size) since the last run recorded in this file"),
+    ("check-banned-list", "", "Path to banned domain list file"),
+    ("ci", "false", "CI mode - exit with error code on failures"),
+    ("comments", "!", "Comment line prefixes (comma-separated)"),
+    ("commit-pathspec", "", "Only commit the files sorted in this run that match this glob (relative to the repository root)"),
+    ("convert-trusted", "false", "Convert trusted scriptlets to non-trusted when value is safe"),
+    ("copy-unchanged", "false", "With output-dir, also copy files that need no changes"),
+    ("create-pr", "", "Create PR branch instead of committing (value is the PR title; empty prompts)"),
+    ("dedupe-only", "false", "Only remove duplicate rules within sections; no sorting, tidying, combining or typo fixes"),
+    ("detect-confusables", "false", "Warn about $domain= and cosmetic domains that mix scripts or look like ASCII domains"),
+    ("dialect", "all", "Warn about options the target engine (abp, ubo, adguard) doesn't support; all = no check"),
+    ("diff-context", "3", "Context lines around each diff hunk"),
+    ("diff-dir", "", "Write one .diff per changed file into this directory (no files modified)"),
+    ("direct-push-users", "", "Users allowed to push directly when create-pr is enabled (comma-separated)"),
+    ("disable-ignored", "false", "Process all files (ignore the built-in ignored files and directories)"),
+    ("drop-long-lines", "false", "Remove rules over max-line-length instead of only warning"),
+    ("exceptions-first", "false", "With group-exceptions, write the @@ exception group first"),
+    ("exclude-glob", "", "Glob patterns to exclude, relative to the scanned root (comma-separated)"),
+    ("extended-markers", "", "Extra selector markers treated as extended syntax, e.g. :matches-attr( (comma-separated)"),
+    ("fail-fast", "false", "Stop at the first file that fails to process (no commit)"),
+    ("fail-on-warning-count", "", "Exit with an error when more than N warnings are emitted"),
+    ("file-extensions", "txt", "File extensions to process (comma-separated; list.txt.gz matches txt)"),
+    ("follow-symlinks", "false", "Descend into symlinked directories (files reached twice are sorted once)"),
+    ("fix-typos", "false", "Fix cosmetic typos during sort"),
+    ("fix-typos-on-add", "false", "Check typos in git additions"),
+    ("generic-cosmetic-allow", "", "Generic selectors warn-generic-cosmetic accepts (comma-separated)"),
+    ("git-binary", "", "Path to git binary (default: git in PATH)"),
+    ("git-pr-branch", "", "Base branch for PR (default: auto-detect)"),
+    ("group-exceptions", "false", "Sort network sections as two groups: blocking rules, then @@ exceptions"),
+    ("history", "", "Predefined commit messages for arrow key selection (comma-separated)"),
+    ("hosts-blackhole", "", "Addresses accepted in hosts entries (default: 0.0.0.0,127.0.0.1,::1,::)"),
+    ("ignore-all-but", "", "Only process these files (comma-separated)"),
+    ("ignore-dot-domains", "false", "Don't skip rules without dot in domain"),
+    ("ignoredirs", "", "Additional directories to ignore (comma-separated)"),
+    ("ignorefiles", "", "Additional files to ignore (comma-separated)"),
+    ("include-glob", "", "Glob patterns to include, relative to the scanned root (comma-separated)"),
+    ("input-encoding", "utf-8", "Encoding lists are read in, e.g. windows-1251 or latin1 (sorted as UTF-8)"),
+    ("keep-bom", "false", "Keep a UTF-8 byte order mark (default: strip it)"),
+    ("keep-empty-lines", "false", "Keep empty lines in output"),
+    ("limited-quiet", "false", "Suppress directory listing only"),
+    ("localhost", "false", "Sort hosts file entries"),
+    ("localhost-files", "", "Files to sort as hosts files (comma-separated)"),
+    ("max-line-length", "", "Warn about rules longer than N characters"),
+    ("max-warnings-per-file", "", "Show at most N warnings per file, then a count of the rest"),
+    ("no-color", "false", "Disable colored output"),
+    ("no-commit", "false", "Skip commit prompt"),
+    ("no-large-warning", "false", "Disable large change warning prompt"),
+    ("no-msg-check", "false", "Skip commit message format validation"),
+    ("no-selector-tidy", "false", "Leave cosmetic selectors as written; only sort and validate their domains"),
+    ("no-sort", "false", "Skip sorting (only tidy and combine rules)"),
+    ("no-ubo-convert", "false", "Skip uBO to ABP option conversion"),
+    ("normalize-anchor", "false", "Append ^ to bare ||host rules (||example.com -> ||example.com^)"),
+    ("normalize-csp", "false", "Sort directives inside $csp=/$permissions= values"),
+    ("normalize-header", "false", "Order Version/Title/Last modified/Expires/Homepage header lines, one space after the colon"),
+    ("normalize-only", "false", "Tidy every rule (options, domains, selectors, uBO conversion) but keep line order and duplicates"),
+    ("normalize-quotes", "false", "Use double quotes for attribute selector values"),
+    ("normalize-redirects", "false", "Rewrite redirect resource aliases to their canonical names (noopjs -> noop.js)"),
+    ("only-sort-changed", "false", "Only sort git-changed files (skip unchanged)"),
+    ("output-diff", "", "Write changes as a diff to this file (no files modified)"),
+    ("output-dir", "", "Write sorted files into a mirror tree in this directory (implies no-commit)"),
+    ("output-encoding", "", "Encoding sorted lists are written in (default: the input encoding)"),
+    ("parse-adguard", "false", "Parse AdGuard extended CSS"),
+    ("parse-adguard-files", "", "Files to parse as AdGuard lists (comma-separated)"),
+    ("post-hook", "", "Command to run after sorting; {} is each sorted file, otherwise it runs once per location"),
+    ("post-hook-fail", "false", "Fail the run, before committing, when --post-hook exits non-zero (default: warn)"),
+    ("pr-body", "", "Text placed at the top of the PR body"),
+    ("pr-labels", "", "Labels applied to the PR (comma-separated)"),
+    ("pr-show-changes", "false", "Include rule changes in PR body"),
+    ("push-retries", "2", "Rebase and retry a failed push up to N times (with backoff)"),
+    ("quarantine-invalid", "", "Append rules removed during sorting (with reason and file:line) to this file"),
+    ("quiet", "false", "Suppress most output (for CI)"),
+    ("rebase-on-fail", "true", "Auto rebase and retry if push fails"),
+    ("require-clean-tree", "false", "Abort before sorting a repository with uncommitted changes to tracked files"),
+    ("rule-count", "false", "Insert/update a \"! Rules: N\" header line"),
+    ("rules-per-file-limit", "", "Warn when a sorted file has more than N rules (suggests splitting it)"),
+    ("since", "", "Only process files changed since this git ref"),
+    ("sort-header", "false", "Order Version/Title/Last modified/Expires/Homepage lines in the leading comment block, text unchanged"),
+    ("sort-key", "", "Cosmetic sort key: domain (default), full (whole rule) or selector (same as alt-sort)"),
+    ("sort-locale", "ascii", "Sort order: ascii (byte-wise) or unicode (collation, mainly for IDN-heavy lists)"),
+    ("strict-utf8", "false", "Exit with an error when a file isn't valid UTF-8 (default: skip it with a warning)"),
+    ("suffix-output", "", "Write sorted files next to the source with this suffix (list.txt.sorted; implies no-commit)"),
+    ("temp-dir", "", "Directory for temp files (default: next to each list)"),
+    ("timestamp-format", "default", "Layout for updated timestamps: default, iso8601 or a pattern like %Y-%m-%d %H:%M"),
+    ("typo-rules", "", "Extra typo patterns file (regex => replacement => description per line)"),
+    ("verbose", "false", "Print per-file classification, rule counts and passes, and \"Sorted:\" lines live (2 = also section details)"),
+    ("warn-broad-options", "false", "Warn about $all, and $document/$popup blocking rules without $domain="),
+    ("warn-combine", "false", "Log every pair of rules combined into one"),
+    ("warn-generic-cosmetic", "false", "Warn about generic ##/#?# rules on *, a bare tag or a common class"),
+    ("warn-hide-interactions", "false", "Warn about cosmetic rules a $generichide/$specifichide exception in the same file disables"),
+    ("warn-js-rules", "false", "Warn about AdGuard JavaScript rules (#%#, #@%#) for review"),
+    ("warn-tld-cosmetic", "false", "Warn about cosmetic rules whose only domains are bare TLDs (com##.ad)"),
+    ("warning-format", "text", "Write warnings as plain text or JSON lines (text, json)"),
+    ("warning-output", "", "Output warnings to file"),
+];
+
+/// Keys no longer used, but still present in the shipped sample configs
+const RETIRED_CONFIG_KEYS: &[&str] = &["disable-domain-limit"];
+
+/// Keys recognised inside [filename] sections (see apply_file_override)
+const FILE_OVERRIDE_KEYS: &[&str] = &[
+    "no-sort", "alt-sort", "parse-adguard", "localhost", "add-checksum", "add-timestamp",
+    "no-ubo-convert", "abp-convert", "convert-trusted", "keep-empty-lines", "ignore-dot-domains",
+    "fix-typos", "rule-count", "no-selector-tidy",
+];
+
+/// Environment variable for a config key (`file-extensions` -> `FOP_FILE_EXTENSIONS`)
+fn env_var_name(key: &str) -> String {
+    format!("FOP_{}", key.to_ascii_uppercase().replace('-', "_"))
+}
+
+/// Layer FOP_* environment variables (looked up with `env_var`) over config file values
+/// (file < env < CLI). Returns the names of the variables that were applied.
+fn apply_env_overrides(config: &mut HashMap<String, String>, env_var: &dyn Fn(&str) -> Option<String>) -> Vec<String> {
+    let mut applied = Vec::new();
+    for (key, ..) in CONFIG_KEYS {
+        let name = env_var_name(key);
+        if let Some(value) = env_var(&name) {
+            config.insert(key.to_string(), value.trim().to_string());
+            applied.push(name);
+        }
+    }
+    applied
+}
+
+/// Levenshtein distance between two strings (byte-wise, for short ASCII keys)
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.as_bytes();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut curr = vec![0; b.len() + 1];
+    for (i, ca) in a.bytes().enumerate() {
+        curr[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != cb);
+            curr[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+    prev[b.len()]
+}
+
+/// Closest candidate to a misspelled word, if it's within a few edits
+fn closest_match<'a>(word: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    let max_distance = (word.len() / 3).clamp(1, 3);
+    candidates
+        .iter()
+        .map(|c| (edit_distance(word, c), *c))
+        .filter(|(d, _)| *d <= max_distance)
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, c)| c)
+}
+
+/// Describe an unrecognised config key, suggesting the closest known one
+fn unknown_key_message(key: &str, section: Option<&str>) -> String {
+    let known: Vec<&str> = match section {
+        Some(s) if !s.starts_with("profile:") => FILE_OVERRIDE_KEYS.to_vec(),
+        _ => CONFIG_KEYS.iter().map(|(name, ..)| *name).collect(),
+    };
+    let location = section.map(|s| format!(" in [{}]", s)).unwrap_or_default();
+    match closest_match(key, &known) {
+        Some(suggestion) => format!("Unknown config key \"{}\"{} (did you mean \"{}\"?)", key, location, suggestion),
+        None => format!("Unknown config key \"{}\"{}", key, location),
+    }
+}
+
+/// Read a --git-message-file commit message. With `check_format` it is checked up front
+/// so a bad message fails before anything is sorted; A:/P: against "no changes" is still
+/// caught by commit_changes.
+fn read_message_file(path: &Path, check_format: bool) -> Result<String, String> {
+    let message = fs::read_to_string(path)
+        .map_err(|e| format!("Cannot read --git-message-file {}: {}", path.display(), e))?
+        .trim_end_matches(['\n', '\r'])
+        .to_string();
+    if message.trim().is_empty() {
+        return Err(format!("Invalid --git-message-file value: {} is empty", path.display()));
+    }
+    if check_format && !fop_git::check_comment(&message, true) {
+        return Err("Invalid --git-message-file value: use an M:/A:/P: commit message".to_string());
+    }
+    Ok(message)
+}
+
+/// Describe a --profile name that has no [profile:name] section, if it doesn't
+fn unknown_profile_message(name: &str, profile_names: &[String]) -> Option<String> {
+    if profile_names.iter().any(|p| p == name) {
+        None
+    } else if profile_names.is_empty() {
+        Some(format!("Unknown profile \"{}\" (no [profile:name] sections in the config file)", name))
+    } else {
+        Some(format!("Unknown profile \"{}\" (available: {})", name, profile_names.join(", ")))
+    }
+}
+
+/// A complete .fopconfig listing every key in `CONFIG_KEYS` with its default
+fn config_template() -> String {
+    let mut template = String::from("# FOP configuration (generated by fop --dump-config-template)\n");
+    for (key, default, description) in CONFIG_KEYS {
+        template.push_str(&format!("\n# {}\n", description));
+        if default.is_empty() {
+            template.push_str(&format!("# {} =\n", key));
+        } else {
+            template.push_str(&format!("{} = {}\n", key, default));
+        }
+    }
+    template
+}
+
+/// (settings, [filename] overrides, config path, unknown key messages, profile names)
+type LoadedConfig = (
+    HashMap<String, String>,
+    ahash::AHashMap<String, FileOverrides>,
+    Option<PathBuf>,
+    Vec<String>,
+    Vec<String>,
+);
+
+/// Whether `key` is a setting (current or retired) allowed outside [filename] sections
+fn is_config_key(key: &str) -> bool {
+    CONFIG_KEYS.iter().any(|(name, ..)| *name == key) || RETIRED_CONFIG_KEYS.contains(&key)
+}
+
+/// Load configuration from .fopconfig file, layering the [profile:name] section for
+/// `profile` (if there is one) over the base settings
+fn load_config(custom_path: Option<&PathBuf>, profile: Option<&str>) -> LoadedConfig {
     // pre-allocated config settings
     let mut config = HashMap::with_capacity(28);
     let mut file_overrides: ahash::AHashMap<String, FileOverrides> = ahash::AHashMap::new();
+    let mut profiles: HashMap<String, HashMap<String, String>> = HashMap::new();
+    let mut unknown_keys: Vec<String> = Vec::new();
 
     // If custom path provided, use that only
     let config_path: Option<PathBuf> = if let Some(path) = custom_path {
@@ -321,7 +836,7 @@ fn load_config(custom_path: Option<&PathBuf>) -> (HashMap<String, String>, ahash
                 if line.is_empty() || line.starts_with('#') {
                     continue;
                 }
-                // Detect [filename] section header
+                // Detect [filename] or [profile:name] section header
                 if line.starts_with('[') && line.ends_with(']') {
                     current_section = Some(line[1..line.len() - 1].trim().to_string());
                     continue;
@@ -330,10 +845,21 @@ fn load_config(custom_path: Option<&PathBuf>) -> (HashMap<String, String>, ahash
                 if let Some(eq_pos) = line.find('=') {
                     let key = line[..eq_pos].trim();
                     let value = line[eq_pos + 1..].trim();
-                    if let Some(ref section) = current_section {
+                    if let Some(name) = current_section.as_deref().and_then(|s| s.strip_prefix("profile:")) {
+                        if !is_config_key(key) {
+                            unknown_keys.push(unknown_key_message(key, current_section.as_deref()));
+                        }
+                        profiles.entry(name.trim().to_string()).or_default().insert(key.to_string(), value.to_string());
+                    } else if let Some(ref section) = current_section {
+                        if !FILE_OVERRIDE_KEYS.contains(&key) {
+                            unknown_keys.push(unknown_key_message(key, Some(section)));
+                        }
                         let entry = file_overrides.entry(section.clone()).or_default();
                         apply_file_override(entry, key, value);
                     } else {
+                        if !is_config_key(key) {
+                            unknown_keys.push(unknown_key_message(key, None));
+                        }
                         config.insert(key.to_string(), value.to_string());
                     }
                 }
@@ -341,7 +867,13 @@ fn load_config(custom_path: Option<&PathBuf>) -> (HashMap<String, String>, ahash
         }
     }
 
-    (config, file_overrides, config_path)
+    let mut profile_names: Vec<String> = profiles.keys().cloned().collect();
+    profile_names.sort_unstable();
+    if let Some(values) = profile.and_then(|name| profiles.remove(name)) {
+        config.extend(values);
+    }
+
+    (config, file_overrides, config_path, unknown_keys, profile_names)
 }
 
 /// Parse boolean value from config
@@ -355,6 +887,88 @@ fn parse_bool(config: &HashMap<String, String>, key: &str, default: bool) -> boo
         .unwrap_or(default)
 }
 
+/// Parse a --sort-locale value
+fn parse_sort_locale(value: &str) -> Option<SortLocale> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "ascii" => Some(SortLocale::Ascii),
+        "unicode" => Some(SortLocale::Unicode),
+        _ => None,
+    }
+}
+
+/// Parse a --sort-key value
+fn parse_sort_key(value: &str) -> Option<SortKey> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "domain" => Some(SortKey::Domain),
+        "full" => Some(SortKey::Full),
+        "selector" => Some(SortKey::Selector),
+        _ => None,
+    }
+}
+
+/// Parse a --dialect value
+fn parse_dialect(value: &str) -> Option<Dialect> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "all" => Some(Dialect::All),
+        "abp" => Some(Dialect::Abp),
+        "ubo" => Some(Dialect::Ubo),
+        "adguard" => Some(Dialect::Adguard),
+        _ => None,
+    }
+}
+
+/// Parse a --timestamp-format value: None for the default layout, otherwise a pattern.
+/// Fails with the first unknown `%` token of a custom pattern.
+fn parse_timestamp_format(value: &str) -> Result<Option<String>, String> {
+    let value = value.trim();
+    if value.is_empty() || value.eq_ignore_ascii_case("default") {
+        Ok(None)
+    } else if value.eq_ignore_ascii_case("iso8601") {
+        Ok(Some(fop_datestamp::ISO8601_FORMAT.to_string()))
+    } else {
+        match fop_datestamp::unknown_timestamp_token(value) {
+            Some(token) => Err(token),
+            None => Ok(Some(value.to_string())),
+        }
+    }
+}
+
+/// timestamp-format from the config or FOP_TIMESTAMP_FORMAT; an invalid pattern is
+/// reported with the config warnings and the default layout is kept
+fn config_timestamp_format(config: &HashMap<String, String>, warnings: &mut Vec<String>) -> Option<String> {
+    let value = config.get("timestamp-format")?;
+    parse_timestamp_format(value).unwrap_or_else(|token| {
+        warnings.push(format!(
+            "Invalid timestamp-format value \"{}\" (unknown token {}; using the default layout)",
+            value.trim(),
+            token
+        ));
+        None
+    })
+}
+
+/// Parse an --input-encoding/--output-encoding label (`latin1`, `windows-1251`, ...)
+fn parse_encoding(value: &str) -> Option<&'static encoding_rs::Encoding> {
+    encoding_rs::Encoding::for_label(value.trim().as_bytes())
+}
+
+/// Parse a --warning-format value
+fn parse_warning_format(value: &str) -> Option<WarningFormat> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "text" => Some(WarningFormat::Text),
+        "json" => Some(WarningFormat::Json),
+        _ => None,
+    }
+}
+
+/// Parse unsigned integer value from config
+fn parse_usize(config: &HashMap<String, String>, key: &str, default: usize) -> usize {
+    config
+        .get(key)
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(default)
+}
+
 /// Parse string list from config (comma-separated)
 fn parse_list(config: &HashMap<String, String>, key: &str) -> Vec<String> {
     config
@@ -400,9 +1014,19 @@ fn parse_comment_chars(config: &HashMap<String, String>, key: &str) -> Vec<Strin
 }
 
 impl Args {
+    /// --print-ignored, which -v also turns on to explain why files were left out
+    fn print_ignored(&self) -> bool {
+        self.print_ignored || self.verbosity >= Verbosity::Verbose
+    }
+
     fn parse() -> (Self, Option<String>) {
         // Collect args once so we don't re-iterate env::args() multiple times.
-        let argv: Vec<String> = env::args().skip(1).collect();
+        Self::parse_from(env::args().skip(1).collect(), &|name| env::var(name).ok())
+    }
+
+    /// Build the settings from `argv` (without the program name), the config file and
+    /// FOP_* variables read through `env_var`
+    fn parse_from(argv: Vec<String>, env_var: &dyn Fn(&str) -> Option<String>) -> (Self, Option<String>) {
 
         // First pass: look for --ignore-config and --config-file arguments
         let ignore_config = argv.iter().any(|arg| arg == "--ignore-config");
@@ -417,13 +1041,29 @@ impl Args {
                 }
             }
         }
+        // --profile=NAME or --profile NAME; the last one wins
+        let mut profile = None;
+        let mut rest = argv.iter();
+        while let Some(arg) = rest.next() {
+            if let Some(name) = arg.strip_prefix("--profile=") {
+                profile = Some(name);
+            } else if arg == "--profile" {
+                profile = rest.next().map(String::as_str);
+            }
+        }
+        let profile = profile.map(str::trim).filter(|name| !name.is_empty()).map(str::to_string);
 
         // Load config file and track path
-        let (config, file_overrides, found_config_path) = if ignore_config {
-            (HashMap::new(), ahash::AHashMap::new(), None)
+        let (mut config, file_overrides, found_config_path, mut config_warnings, profile_names) = if ignore_config {
+            (HashMap::new(), ahash::AHashMap::new(), None, Vec::new(), Vec::new())
         } else {
-            load_config(config_file.as_ref())
+            load_config(config_file.as_ref(), profile.as_deref())
         };
+        if let Some(message) = profile.as_deref().and_then(|name| unknown_profile_message(name, &profile_names)) {
+            eprintln!("Error: {}", message);
+            std::process::exit(1);
+        }
+        let env_config_vars = apply_env_overrides(&mut config, env_var);
         // Store for --show-config
         let config_path_str = found_config_path.as_ref().map(|p| p.display().to_string());
 
@@ -437,7 +1077,12 @@ impl Args {
             abp_convert: parse_bool(&config, "abp-convert", false),
             convert_trusted: parse_bool(&config, "convert-trusted", false),
             no_sort: parse_bool(&config, "no-sort", false),
+            dedupe_only: parse_bool(&config, "dedupe-only", false),
+            normalize_only: parse_bool(&config, "normalize-only", false),
             alt_sort: parse_bool(&config, "alt-sort", false),
+            sort_key: config.get("sort-key").and_then(|v| parse_sort_key(v)),
+            group_exceptions: parse_bool(&config, "group-exceptions", false),
+            exceptions_first: parse_bool(&config, "exceptions-first", false),
             parse_adguard: parse_bool(&config, "parse-adguard", false),
             parse_adguard_files: parse_list(&config, "parse-adguard-files"),
             localhost: parse_bool(&config, "localhost", false),
@@ -446,6 +1091,8 @@ impl Args {
             ignore_files: parse_list(&config, "ignorefiles"),
             ignore_dirs: parse_list(&config, "ignoredirs"),
             ignore_all_but: parse_list(&config, "ignore-all-but"),
+            exclude_glob: parse_list(&config, "exclude-glob"),
+            include_glob: parse_list(&config, "include-glob"),
             git_message: None,
             show_config: false,
             no_large_warning: parse_bool(&config, "no-large-warning", false),
@@ -455,6 +1102,7 @@ impl Args {
             keep_empty_lines: parse_bool(&config, "keep-empty-lines", false),
             ignore_dot_domains: parse_bool(&config, "ignore-dot-domains", false),
             warning_output: config.get("warning-output").map(PathBuf::from),
+            warning_format: config.get("warning-format").and_then(|v| parse_warning_format(v)).unwrap_or_default(),
             create_pr: config.get("create-pr").and_then(|v| {
                 match v.to_lowercase().as_str() {
                     "" | "true" | "yes" | "1" => Some(String::new()), // Enable with prompt
@@ -464,9 +1112,13 @@ impl Args {
             }),
             git_pr_branch: config.get("git-pr-branch").cloned(),
             pr_show_changes: parse_bool(&config, "pr-show-changes", false),
+            pr_body: config.get("pr-body").filter(|v| !v.is_empty()).cloned(),
+            pr_labels: parse_list(&config, "pr-labels"),
             check_banned_list: config.get("check-banned-list").map(PathBuf::from),
             auto_banned_remove: parse_bool(&config, "auto-banned-remove", false),
             fix_typos: parse_bool(&config, "fix-typos", false),
+            interactive_typos: false,
+            typo_rules: config.get("typo-rules").filter(|s| !s.is_empty()).map(PathBuf::from),
             fix_typos_on_add: parse_bool(&config, "fix-typos-on-add", false),
             direct_push_users: config.get("direct-push-users")
                 .map(|s| s.split(',').map(|u| u.trim().to_lowercase()).collect())
@@ -476,10 +1128,17 @@ impl Args {
             auto_fix: parse_bool(&config, "auto-fix", false),
             output_diff: config.get("output-diff").map(PathBuf::from),
             output_diff_individual: false,
+            diff_dir: config.get("diff-dir").filter(|s| !s.is_empty()).map(PathBuf::from),
+            output_dir: config.get("output-dir").filter(|s| !s.is_empty()).map(PathBuf::from),
+            suffix_output: config.get("suffix-output").filter(|s| !s.is_empty()).cloned(),
+            copy_unchanged: parse_bool(&config, "copy-unchanged", false),
             check_file: None,
             output_changed: false,
             only_sort_changed: parse_bool(&config, "only-sort-changed", false),
+            since: config.get("since").filter(|s| !s.is_empty()).cloned(),
             rebase_on_fail: parse_bool(&config, "rebase-on-fail", true),
+            require_clean_tree: parse_bool(&config, "require-clean-tree", false),
+            push_retries: parse_usize(&config, "push-retries", 2),
             ci: parse_bool(&config, "ci", false),
             history: config.get("history")
                 .map(|s| s.split(',')
@@ -488,28 +1147,105 @@ impl Args {
                 .unwrap_or_default(),
             help: false,
             version: false,
+            dump_config_template: false,
             add_timestamp: parse_list(&config, "add-timestamp"),
+            timestamp_format: config_timestamp_format(&config, &mut config_warnings),
             validate_checksum: Vec::new(),
             validate_checksum_and_fix: Vec::new(),
+            verify_verbose: false,
             add_checksum: config.get("add-checksum")
                 .map(|s| s.split(',').map(|f| f.trim().to_string()).collect())
                 .unwrap_or_default(),
             git_binary: config.get("git-binary").cloned(),
             benchmark: false,
+            normalize_quotes: parse_bool(&config, "normalize-quotes", false),
+            print_sections: false,
+            print_ignored: false,
+            normalize_csp: parse_bool(&config, "normalize-csp", false),
+            normalize_redirects: parse_bool(&config, "normalize-redirects", false),
+            warn_broad_options: parse_bool(&config, "warn-broad-options", false),
+            normalize_anchor: parse_bool(&config, "normalize-anchor", false),
+            explain: None,
+            warn_combine: parse_bool(&config, "warn-combine", false),
+            max_line_length: config.get("max-line-length").and_then(|v| v.trim().parse().ok()).filter(|&n| n > 0),
+            rules_per_file_limit: config.get("rules-per-file-limit").and_then(|v| v.trim().parse().ok()).filter(|&n| n > 0),
+            drop_long_lines: parse_bool(&config, "drop-long-lines", false),
+            warn_generic_cosmetic: parse_bool(&config, "warn-generic-cosmetic", false),
+            warn_tld_cosmetic: parse_bool(&config, "warn-tld-cosmetic", false),
+            warn_js_rules: parse_bool(&config, "warn-js-rules", false),
+            warn_hide_interactions: parse_bool(&config, "warn-hide-interactions", false),
+            detect_confusables: parse_bool(&config, "detect-confusables", false),
+            normalize_header: parse_bool(&config, "normalize-header", false),
+            sort_header: parse_bool(&config, "sort-header", false),
+            quarantine_invalid: config.get("quarantine-invalid").filter(|s| !s.is_empty()).map(PathBuf::from),
+            cache: config.get("cache").filter(|s| !s.is_empty()).map(PathBuf::from),
+            generic_cosmetic_allow: parse_list(&config, "generic-cosmetic-allow"),
+            diff_context: config.get("diff-context").and_then(|v| v.trim().parse().ok()),
+            sort_locale: config.get("sort-locale").and_then(|v| parse_sort_locale(v)).unwrap_or_default(),
+            dialect: config.get("dialect").and_then(|v| parse_dialect(v)).unwrap_or_default(),
+            strict_utf8: parse_bool(&config, "strict-utf8", false),
+            rule_count: parse_bool(&config, "rule-count", false),
+            temp_dir: config.get("temp-dir").filter(|s| !s.is_empty()).map(PathBuf::from),
+            keep_bom: parse_bool(&config, "keep-bom", false),
+            input_encoding: config.get("input-encoding").and_then(|v| parse_encoding(v)),
+            output_encoding: config.get("output-encoding").and_then(|v| parse_encoding(v)),
+            verbosity: match config.get("verbose").map(|v| v.trim()) {
+                Some("2") => Verbosity::VeryVerbose,
+                _ if parse_bool(&config, "verbose", false) => Verbosity::Verbose,
+                _ => Verbosity::Normal,
+            },
+            no_selector_tidy: parse_bool(&config, "no-selector-tidy", false),
+            extended_markers: parse_list(&config, "extended-markers"),
+            report_tidy: false,
+            rules_diff_only: false,
+            detect_cross_file_dupes: false,
+            warn_hosts_abp_overlap: false,
+            hosts_blackhole: parse_list(&config, "hosts-blackhole"),
+            allow_dotless_domains: parse_list(&config, "allow-dotless-domains"),
+            count_only: false,
+            plan: false,
+            time_per_file: None,
+            fail_on_warning_count: config.get("fail-on-warning-count").and_then(|v| v.parse().ok()),
+            max_warnings_per_file: config.get("max-warnings-per-file").and_then(|v| v.parse().ok()),
+            fail_fast: parse_bool(&config, "fail-fast", false),
+            follow_symlinks: parse_bool(&config, "follow-symlinks", false),
+            commit_pathspec: config.get("commit-pathspec").filter(|v| !v.is_empty()).cloned(),
+            post_hook: config.get("post-hook").filter(|v| !v.is_empty()).cloned(),
+            post_hook_fail: parse_bool(&config, "post-hook-fail", false),
             file_overrides,
+            profile,
+            config_warnings,
+            env_config_vars,
         };
 
         // Command line args override config
-        for arg in argv {
+        let mut message_file: Option<String> = None;
+        let mut argv = argv.into_iter();
+        while let Some(arg) = argv.next() {
             match arg.as_str() {
                 "-h" | "--help" => args.help = true,
                 "-V" | "--version" => args.version = true,
+                "--dump-config-template" => args.dump_config_template = true,
                 "-n" | "--no-commit" | "--just-sort" | "--justsort" => args.no_commit = true,
                 "--no-ubo-convert" => args.no_ubo_convert = true,
                 "--no-msg-check" => args.no_msg_check = true,
                 "--disable-ignored" => args.disable_ignored = true,
                 "--no-sort" => args.no_sort = true,
+                "--dedupe-only" => args.dedupe_only = true,
+                "--normalize-only" => args.normalize_only = true,
                 "--alt-sort" => args.alt_sort = true,
+                _ if arg.starts_with("--sort-key=") => {
+                    let value = arg.trim_start_matches("--sort-key=");
+                    match parse_sort_key(value) {
+                        Some(key) => args.sort_key = Some(key),
+                        None => {
+                            eprintln!("Invalid --sort-key value: {} (expected domain, full or selector)", value);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                "--group-exceptions" => args.group_exceptions = true,
+                "--exceptions-first" => args.exceptions_first = true,
                 "--abp-convert" => args.abp_convert = true,
                 "--convert-trusted" => args.convert_trusted = true,
                 "--parse-adguard" => args.parse_adguard = true,
@@ -528,7 +1264,28 @@ impl Args {
                 "--only-sort-changed" => args.only_sort_changed = true,
                 "--rebase-on-fail" => args.rebase_on_fail = true,
                 "--no-rebase-on-fail" => args.rebase_on_fail = false,
+                "--require-clean-tree" => args.require_clean_tree = true,
+                _ if arg.starts_with("--push-retries=") => {
+                    let value = arg.trim_start_matches("--push-retries=");
+                    match value.parse() {
+                        Ok(n) => args.push_retries = n,
+                        Err(_) => {
+                            eprintln!("Invalid value for --push-retries: {}", value);
+                            std::process::exit(1);
+                        }
+                    }
+                }
                 "--pr-show-changes" => args.pr_show_changes = true,
+                _ if arg.starts_with("--pr-body=") => {
+                    args.pr_body = Some(arg.trim_start_matches("--pr-body=").to_string());
+                }
+                _ if arg.starts_with("--pr-labels=") => {
+                    args.pr_labels = arg.trim_start_matches("--pr-labels=")
+                        .split(',')
+                        .map(|s| s.trim().to_string())
+                        .filter(|s| !s.is_empty())
+                        .collect();
+                }
                 _ if arg.starts_with("--check-banned-list=") => {
                     args.check_banned_list = Some(PathBuf::from(arg.trim_start_matches("--check-banned-list=")));
                 }
@@ -541,6 +1298,17 @@ impl Args {
                     let files = arg.trim_start_matches("--ignore-all-but=");
                     args.ignore_all_but = files.split(',').map(|s| s.trim().to_string()).collect();
                 }
+                _ if arg.starts_with("--temp-dir=") => {
+                    args.temp_dir = Some(PathBuf::from(arg.trim_start_matches("--temp-dir=")));
+                }
+                _ if arg.starts_with("--exclude-glob=") => {
+                    let patterns = arg.trim_start_matches("--exclude-glob=");
+                    args.exclude_glob.extend(patterns.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()));
+                }
+                _ if arg.starts_with("--include-glob=") => {
+                    let patterns = arg.trim_start_matches("--include-glob=");
+                    args.include_glob.extend(patterns.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()));
+                }
                 _ if arg.starts_with("--file-extensions=") => {
                     args.file_extensions = arg
                         .trim_start_matches("--file-extensions=")
@@ -563,9 +1331,15 @@ impl Args {
                     args.warning_output =
                         Some(PathBuf::from(arg.trim_start_matches("--warning-output=")));
                 }
-                _ if arg.starts_with("--config-file=") => {
+                _ if arg.starts_with("--config-file=") || arg.starts_with("--profile=") => {
                     // Already handled in first pass
                 }
+                "--profile" => {
+                    if argv.next().is_none() {
+                        eprintln!("--profile needs a profile name, e.g. fop --profile hosts");
+                        std::process::exit(1);
+                    }
+                }
                 _ if arg.starts_with("--ignoredirs=") => {
                     args.ignore_dirs = arg
                         .trim_start_matches("--ignoredirs=")
@@ -583,6 +1357,10 @@ impl Args {
                 }
                 "--fix-typos" => args.fix_typos = true,
                 "--fix-typos-on-add" => args.fix_typos_on_add = true,
+                "--interactive-typos" => args.interactive_typos = true,
+                _ if arg.starts_with("--typo-rules=") => {
+                    args.typo_rules = Some(PathBuf::from(arg.trim_start_matches("--typo-rules=")));
+                }
                 "--auto-fix" => args.auto_fix = true,
                 _ if arg.starts_with("--add-timestamp=") => {
                     args.add_timestamp = arg.trim_start_matches("--add-timestamp=")
@@ -590,6 +1368,19 @@ impl Args {
                         .map(|s| s.trim().to_string())
                         .collect();
                 }
+                _ if arg.starts_with("--timestamp-format=") => {
+                    let value = arg.trim_start_matches("--timestamp-format=");
+                    match parse_timestamp_format(value) {
+                        Ok(format) => args.timestamp_format = format,
+                        Err(token) => {
+                            eprintln!(
+                                "Invalid --timestamp-format value: {} (unknown token {}; expected default, iso8601 or a pattern like %Y-%m-%d %H:%M)",
+                                value, token
+                            );
+                            std::process::exit(1);
+                        }
+                    }
+                }
                 _ if arg.starts_with("--add-checksum=") => {
                     args.add_checksum = arg.trim_start_matches("--add-checksum=")
                         .split(',')
@@ -601,9 +1392,119 @@ impl Args {
                     args.check_file = Some(PathBuf::from(arg.trim_start_matches("--check-file=")));
                 }
                 "--quiet" | "-q" => args.quiet = true,
+                "-v" | "--verbose" => args.verbosity = args.verbosity.max(Verbosity::Verbose),
+                "-vv" => args.verbosity = Verbosity::VeryVerbose,
+                "--count-only" => args.count_only = true,
+                "--plan" => args.plan = true,
+                "--time-per-file" => args.time_per_file = Some(10),
+                "--fail-fast" => args.fail_fast = true,
+                "--follow-symlinks" => args.follow_symlinks = true,
+                _ if arg.starts_with("--fail-on-warning-count=") => {
+                    let value = arg.trim_start_matches("--fail-on-warning-count=");
+                    match value.parse::<usize>() {
+                        Ok(n) => args.fail_on_warning_count = Some(n),
+                        Err(_) => {
+                            eprintln!("Invalid --fail-on-warning-count value: {} (expected a number)", value);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                _ if arg.starts_with("--max-warnings-per-file=") => {
+                    let value = arg.trim_start_matches("--max-warnings-per-file=");
+                    match value.parse::<usize>() {
+                        Ok(n) => args.max_warnings_per_file = Some(n),
+                        Err(_) => {
+                            eprintln!("Invalid --max-warnings-per-file value: {} (expected a number)", value);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                _ if arg.starts_with("--time-per-file=") => {
+                    let value = arg.trim_start_matches("--time-per-file=");
+                    match value.parse::<usize>() {
+                        Ok(n) if n > 0 => args.time_per_file = Some(n),
+                        _ => {
+                            eprintln!("Invalid --time-per-file value: {} (expected a positive number)", value);
+                            std::process::exit(1);
+                        }
+                    }
+                }
                 "--limited-quiet" => args.limited_quiet = true,
                 "--ci" => args.ci = true,
                 "--benchmark" => args.benchmark = true,
+                "--normalize-quotes" => args.normalize_quotes = true,
+                "--print-sections" => args.print_sections = true,
+                "--print-ignored" => args.print_ignored = true,
+                "--normalize-csp" => args.normalize_csp = true,
+                "--normalize-redirects" => args.normalize_redirects = true,
+                "--warn-broad-options" => args.warn_broad_options = true,
+                "--normalize-anchor" => args.normalize_anchor = true,
+                "--warn-combine" => args.warn_combine = true,
+                "--drop-long-lines" => args.drop_long_lines = true,
+                "--warn-generic-cosmetic" => args.warn_generic_cosmetic = true,
+                "--warn-tld-cosmetic" => args.warn_tld_cosmetic = true,
+                "--warn-js-rules" => args.warn_js_rules = true,
+                "--warn-hide-interactions" => args.warn_hide_interactions = true,
+                "--detect-confusables" => args.detect_confusables = true,
+                "--normalize-header" => args.normalize_header = true,
+                "--sort-header" => args.sort_header = true,
+                _ if arg.starts_with("--sort-locale=") => {
+                    let value = arg.trim_start_matches("--sort-locale=");
+                    match parse_sort_locale(value) {
+                        Some(locale) => args.sort_locale = locale,
+                        None => {
+                            eprintln!("Invalid --sort-locale value: {} (expected ascii or unicode)", value);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                _ if arg.starts_with("--warning-format=") => {
+                    let value = arg.trim_start_matches("--warning-format=");
+                    match parse_warning_format(value) {
+                        Some(format) => args.warning_format = format,
+                        None => {
+                            eprintln!("Invalid --warning-format value: {} (expected text or json)", value);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                _ if arg.starts_with("--dialect=") => {
+                    let value = arg.trim_start_matches("--dialect=");
+                    match parse_dialect(value) {
+                        Some(dialect) => args.dialect = dialect,
+                        None => {
+                            eprintln!("Invalid --dialect value: {} (expected all, abp, ubo or adguard)", value);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                "--strict-utf8" => args.strict_utf8 = true,
+                "--rule-count" => args.rule_count = true,
+                "--keep-bom" => args.keep_bom = true,
+                _ if arg.starts_with("--input-encoding=") || arg.starts_with("--output-encoding=") => {
+                    let (option, value) = arg.split_once('=').unwrap_or_default();
+                    let Some(encoding) = parse_encoding(value) else {
+                        eprintln!("Invalid {} value: {} (expected an encoding label such as utf-8, windows-1251 or latin1)", option, value);
+                        std::process::exit(1);
+                    };
+                    if option == "--input-encoding" {
+                        args.input_encoding = Some(encoding);
+                    } else {
+                        args.output_encoding = Some(encoding);
+                    }
+                }
+                "--no-selector-tidy" => args.no_selector_tidy = true,
+                _ if arg.starts_with("--extended-markers=") => {
+                    args.extended_markers = arg.trim_start_matches("--extended-markers=")
+                        .split(',')
+                        .map(|s| s.trim().to_string())
+                        .filter(|s| !s.is_empty())
+                        .collect();
+                }
+                "--report-tidy" => args.report_tidy = true,
+                "--rules-diff-only" => args.rules_diff_only = true,
+                "--detect-cross-file-dupes" => args.detect_cross_file_dupes = true,
+                "--warn-hosts-abp-overlap" => args.warn_hosts_abp_overlap = true,
                 _ if arg.starts_with("--history=") => {
                     args.history = arg.trim_start_matches("--history=")
                         .split(',')
@@ -617,12 +1518,109 @@ impl Args {
                 "--output" => {
                     args.output_changed = true;
                 }
+                _ if arg.starts_with("--hosts-blackhole=") => {
+                    args.hosts_blackhole = arg.trim_start_matches("--hosts-blackhole=")
+                        .split(',')
+                        .map(|s| s.trim().to_string())
+                        .filter(|s| !s.is_empty())
+                        .collect();
+                }
+                _ if arg.starts_with("--generic-cosmetic-allow=") => {
+                    args.generic_cosmetic_allow = arg.trim_start_matches("--generic-cosmetic-allow=")
+                        .split(',')
+                        .map(|s| s.trim().to_string())
+                        .filter(|s| !s.is_empty())
+                        .collect();
+                }
+                _ if arg.starts_with("--allow-dotless-domains=") => {
+                    args.allow_dotless_domains = arg.trim_start_matches("--allow-dotless-domains=")
+                        .split(',')
+                        .map(|s| s.trim().to_string())
+                        .filter(|s| !s.is_empty())
+                        .collect();
+                }
+                _ if arg.starts_with("--cache=") => {
+                    args.cache = Some(PathBuf::from(arg.trim_start_matches("--cache=")));
+                }
+                _ if arg.starts_with("--quarantine-invalid=") => {
+                    args.quarantine_invalid = Some(PathBuf::from(arg.trim_start_matches("--quarantine-invalid=")));
+                }
+                _ if arg.starts_with("--commit-pathspec=") => {
+                    args.commit_pathspec = Some(arg.trim_start_matches("--commit-pathspec=").to_string());
+                }
+                _ if arg.starts_with("--post-hook=") => {
+                    args.post_hook = Some(arg.trim_start_matches("--post-hook=").to_string());
+                }
+                "--post-hook-fail" => args.post_hook_fail = true,
+                _ if arg.starts_with("--since=") => {
+                    args.since = Some(arg.trim_start_matches("--since=").to_string());
+                }
+                // The rule is usually quoted as the next argument, and may itself start with '-'
+                "--explain" => match argv.next() {
+                    Some(rule) => args.explain = Some(rule),
+                    None => {
+                        eprintln!("--explain needs a rule, e.g. fop --explain '||example.com^$3p'");
+                        std::process::exit(1);
+                    }
+                },
+                _ if arg.starts_with("--explain=") => {
+                    args.explain = Some(arg.trim_start_matches("--explain=").to_string());
+                }
+                _ if arg.starts_with("--diff-context=") => {
+                    let value = arg.trim_start_matches("--diff-context=");
+                    match value.parse::<usize>() {
+                        Ok(n) => args.diff_context = Some(n),
+                        Err(_) => {
+                            eprintln!("Invalid --diff-context value: {} (expected a non-negative number)", value);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                _ if arg.starts_with("--max-line-length=") => {
+                    let value = arg.trim_start_matches("--max-line-length=");
+                    match value.parse::<usize>() {
+                        Ok(n) if n > 0 => args.max_line_length = Some(n),
+                        _ => {
+                            eprintln!("Invalid --max-line-length value: {} (expected a positive number)", value);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                _ if arg.starts_with("--rules-per-file-limit=") => {
+                    let value = arg.trim_start_matches("--rules-per-file-limit=");
+                    match value.parse::<usize>() {
+                        Ok(n) if n > 0 => args.rules_per_file_limit = Some(n),
+                        _ => {
+                            eprintln!("Invalid --rules-per-file-limit value: {} (expected a positive number)", value);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                _ if arg.starts_with("--diff-dir=") => {
+                    args.diff_dir = Some(PathBuf::from(arg.trim_start_matches("--diff-dir=")));
+                }
+                _ if arg.starts_with("--output-dir=") => {
+                    args.output_dir = Some(PathBuf::from(arg.trim_start_matches("--output-dir=")));
+                }
+                "--copy-unchanged" => args.copy_unchanged = true,
+                _ if arg.starts_with("--suffix-output=") => {
+                    let suffix = arg.trim_start_matches("--suffix-output=");
+                    if suffix.is_empty() {
+                        eprintln!("Invalid --suffix-output value: (expected a suffix such as .sorted)");
+                        std::process::exit(1);
+                    }
+                    args.suffix_output = Some(suffix.to_string());
+                }
                 _ if arg.starts_with("--output-diff=") => {
                     args.output_diff =
                         Some(PathBuf::from(arg.trim_start_matches("--output-diff=")));
                 }
                 _ if arg.starts_with("--git-message=") => {
                     args.git_message = Some(arg.trim_start_matches("--git-message=").to_string());
+                    message_file = None;
+                }
+                _ if arg.starts_with("--git-message-file=") => {
+                    message_file = Some(arg.trim_start_matches("--git-message-file=").to_string());
                 }
                 _ if arg.starts_with("--validate-checksum=") => {
                     args.validate_checksum = arg.trim_start_matches("--validate-checksum=")
@@ -636,6 +1634,7 @@ impl Args {
                         .map(|s| s.trim().to_string())
                         .collect();
                 }
+                "--verify-verbose" => args.verify_verbose = true,
                 _ if arg.starts_with("--git-binary=") => {
                     args.git_binary = Some(arg.trim_start_matches("--git-binary=").to_string());
                 }
@@ -648,12 +1647,23 @@ impl Args {
             }
         }
 
+        if let Some(path) = message_file {
+            match read_message_file(Path::new(&path), !args.no_msg_check) {
+                Ok(message) => args.git_message = Some(message),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+
         // Warn about incompatible options
-        if (args.output_diff.is_some() || args.output_diff_individual) && args.create_pr.is_some() {
+        let diff_mode = args.output_diff.is_some() || args.output_diff_individual || args.diff_dir.is_some();
+        if diff_mode && args.create_pr.is_some() {
             // Silently disable create-pr when using output-diff (common when config has create-pr)
             args.create_pr = None;
         }
-        if (args.output_diff.is_some() || args.output_diff_individual) && args.git_message.is_some() {
+        if diff_mode && args.git_message.is_some() {
             // Silently disable git-message when using output-diff
             args.git_message = None;
         }
@@ -662,15 +1672,22 @@ impl Args {
             eprintln!("Using individual mode (--output-diff)");
             args.output_diff = None;
         }
+        if args.diff_dir.is_some() && (args.output_diff.is_some() || args.output_diff_individual) {
+            eprintln!("Warning: --diff-dir and --output-diff are mutually exclusive");
+            eprintln!("Using --diff-dir");
+            args.output_diff = None;
+            args.output_diff_individual = false;
+        }
         if args.no_commit && args.create_pr.is_some() {
             eprintln!("Warning: --no-commit and --create-pr are incompatible");
             args.create_pr = None;
         }
-        if args.output_changed && (args.output_diff.is_some() || args.output_diff_individual) {
-            eprintln!("Warning: --output and --output-diff are mutually exclusive");
+        if args.output_changed && (args.output_diff.is_some() || args.output_diff_individual || args.diff_dir.is_some()) {
+            eprintln!("Warning: --output and --output-diff/--diff-dir are mutually exclusive");
             eprintln!("Using --output");
             args.output_diff = None;
             args.output_diff_individual = false;
+            args.diff_dir = None;
         }
         if args.output_changed && args.create_pr.is_some() {
             eprintln!("Warning: --output and --create-pr are incompatible");
@@ -679,6 +1696,16 @@ impl Args {
         if args.no_commit && args.git_message.is_some() {
             eprintln!("Warning: --no-commit and --git-message are incompatible");
         }
+        if args.dedupe_only && args.fix_typos {
+            eprintln!("Warning: --dedupe-only and --fix-typos are incompatible");
+            eprintln!("Using --dedupe-only");
+            args.fix_typos = false;
+        }
+        if args.dedupe_only && args.normalize_only {
+            eprintln!("Warning: --dedupe-only and --normalize-only are incompatible");
+            eprintln!("Using --dedupe-only");
+            args.normalize_only = false;
+        }
 
         (args, config_path_str)
     }
@@ -697,47 +1724,122 @@ impl Args {
         println!("        --just-sort     Alias for --no-commit");
         println!("        --no-ubo-convert  Skip uBO to ABP option conversion");
         println!("        --no-msg-check  Skip commit message format validation (M:/A:/P:)");
+        println!("        --require-clean-tree  Abort if the repository already has uncommitted changes");
         println!("        --disable-ignored  Process all files (ignore IGNORE_FILES/IGNORE_DIRS)");
+        println!("        --follow-symlinks  Descend into symlinked directories (loops are reported and skipped)");
         println!("        --no-sort       Skip sorting (only tidy and combine rules)");
+        println!("        --dedupe-only   Only remove duplicate rules; keep everything else as written");
+        println!("        --normalize-only  Tidy every rule but keep line order and duplicates as written");
         println!("        --alt-sort      Alternative sorting (by selector for all rule types)");
+        println!("        --sort-key=KEY  Cosmetic sort key: domain (default), full (whole rule) or selector (same as --alt-sort)");
+        println!("        --group-exceptions  Sort network sections as blocking rules, then @@ exceptions");
+        println!("        --exceptions-first  With --group-exceptions, put the @@ exceptions first");
         println!("        --parse-adguard Parse AdGuard extended CSS (#$?#, #@$?#, $$, $@$)");
         println!("        --parse-adguard=  Files to parse as AdGuard extended CSS (comma-separated)");
-        println!("        --localhost     Sort hosts file entries (0.0.0.0/127.0.0.1 domain)");
+        println!("        --localhost     Sort hosts file entries (0.0.0.0/127.0.0.1/::1/:: domain)");
         println!("        --localhost-files=  Files to sort as localhost format (comma-separated)");
+        println!("        --hosts-blackhole=  Addresses accepted in hosts entries (default: 0.0.0.0,127.0.0.1,::1,::)");
         println!("        --no-color      Disable colored output");
         println!("        --no-large-warning  Disable large change warning prompt");
         println!("        --ignorefiles=  Additional files to ignore (comma-separated, partial names)");
         println!("        --abp-convert          Convert :-abp-has/:-abp-contains to :has/:has-text");
         println!("        --convert-trusted      Convert trusted scriptlets to non-trusted when value is safe");
+        println!("        --normalize-quotes     Use double quotes for attribute selector values");
+        println!("        --normalize-csp        Sort directives inside $csp=/$permissions= values");
+        println!("        --normalize-redirects  Rewrite redirect resource aliases (noopjs -> noop.js)");
+        println!("        --normalize-anchor     Append ^ to bare ||host rules (||example.com -> ||example.com^)");
+        println!("        --normalize-header     Order Version/Title/Last modified/Expires/Homepage header lines, one space after ':'");
+        println!("        --sort-header          Order the same header lines in the leading comment block, leaving their text as written");
+        println!("        --warn-broad-options   Warn about $all, and $document/$popup rules without $domain=");
+        println!("        --warn-combine         Log every pair of rules combined into one (source rules and result)");
+        println!("        --max-line-length=N    Warn about rules longer than N characters");
+        println!("        --drop-long-lines      With --max-line-length, remove overlong rules instead of warning");
+        println!("        --rules-per-file-limit=N  Warn when a sorted file has more than N rules");
+        println!("        --quarantine-invalid=FILE  Append removed rules (reason, file:line) to FILE; warnings still shown");
+        println!("        --cache=FILE           Skip lists unchanged (mtime and size) since the last run recorded in FILE");
+        println!("        --warn-generic-cosmetic  Warn about generic ##/#?# rules on *, a bare tag or a common class");
+        println!("        --generic-cosmetic-allow=  Generic selectors to accept (comma-separated)");
+        println!("        --warn-tld-cosmetic    Warn about cosmetic rules whose only domains are bare TLDs (com##.ad)");
+        println!("        --warn-js-rules        Warn about AdGuard JavaScript rules (#%#, #@%#) so reviewers can confirm them");
+        println!("        --warn-hide-interactions  Warn about cosmetic rules a $generichide/$specifichide exception disables");
+        println!("        --detect-confusables   Warn about domains that mix scripts or look like ASCII domains (аpple.com)");
+        println!("        --sort-locale=MODE     Sort order: ascii (byte-wise, default) or unicode (collation)");
+        println!("        --dialect=ENGINE       Warn about options not supported by abp, ubo or adguard (default: all)");
+        println!("        --rule-count           Insert/update a '! Rules: N' header line");
+        println!("        --temp-dir=PATH        Write temp files to PATH instead of next to each file");
+        println!("        --keep-bom             Keep a UTF-8 byte order mark (default: strip it)");
+        println!("        --input-encoding=NAME  Read lists in this encoding, e.g. windows-1251 or latin1 (default: utf-8)");
+        println!("        --output-encoding=NAME Write sorted lists in this encoding (default: the input encoding)");
+        println!("        --no-selector-tidy     Leave cosmetic selectors as written (only sort their domains)");
+        println!("        --extended-markers=    Extra extended-syntax markers to pass through, e.g. :matches-attr(");
+        println!("        --report-tidy          Dry run: list cosmetic rules selector tidying would change, per file");
+        println!("        --rules-diff-only      Dry run: print only rules added/removed by sorting (or since --since=REF)");
+        println!("        --detect-cross-file-dupes  Report rules that appear in more than one file (nothing is removed)");
+        println!("        --warn-hosts-abp-overlap  Report domains blocked by both a hosts file and a ||host^ rule (nothing is removed)");
+        println!("        --print-sections       Print how each section was classified and sorted (stderr)");
+        println!("        --print-ignored        Log each skipped file or directory and the reason (implied by -v)");
+        println!("        --count-only           Print rule counts per file (total/cosmetic/network/exception), no changes");
+        println!("        --plan                 Show the files that would change and the git step that would follow, no changes");
+        println!("        --time-per-file[=N]    Print the N slowest files to sort (default: 10)");
+        println!("        --fail-on-warning-count=N  Exit with error if more than N warnings are emitted");
+        println!("        --max-warnings-per-file=N  Show at most N warnings per file, then \"... and M more\"");
+        println!("        --fail-fast            Stop at the first file that fails and exit without committing");
+        println!("        --strict-utf8          Exit with error if a file isn't valid UTF-8 (default: skip it)");
         println!("        --ignoredirs=   Additional directories to ignore (comma-separated, partial names)");
         println!("        --ignore-all-but=   Only process these files, ignore all others (comma-separated)");
+        println!("        --exclude-glob=     Exclude files matching glob patterns, relative to the root (repeatable)");
+        println!("        --include-glob=     Only process files matching glob patterns, relative to the root (repeatable)");
         println!("        --config-file=  Custom config file path");
-        println!("        --file-extensions=  File extensions to process (default: .txt)");
+        println!("        --profile=NAME  Layer the config file's [profile:NAME] section over its base settings (or --profile NAME)");
+        println!("        --file-extensions=  File extensions to process (default: .txt; .txt.gz lists are matched too)");
         println!("        --comments=     Comment line prefixes (default: !)");
         println!("        --backup        Create .backup files before modifying");
         println!("        --keep-empty-lines  Keep empty lines in output");
         println!("        --ignore-dot-domains  Don't skip rules without dot in domain");
+        println!("        --allow-dotless-domains=  Dotless domains to keep in network rules (comma-separated)");
         println!("        --warning-output=   Output warnings to file instead of stderr");
+        println!("        --warning-format=FORMAT  Write warnings as text or JSON lines (default: text)");
         println!("        --git-message=  Git commit message (skip interactive prompt)");
+        println!("        --git-message-file=PATH  Read the git commit message from PATH");
+        println!("        --commit-pathspec=GLOB  Only show and commit the files sorted in this run that match GLOB");
         println!("        --create-pr[=TITLE]  Create PR branch instead of committing to master");
         println!("        --git-pr-branch=NAME   Base branch for PR (default: main/master)");
+        println!("        --pr-body=TEXT         Text placed at the top of the PR body");
+        println!("        --pr-labels=A,B        Labels applied to the PR");
+        println!("        --push-retries=N       Rebase and retry a failed push up to N times (default: 2)");
+        println!("        --since=REF            Only process files that differ from a git ref (e.g. origin/master)");
         println!("        --fix-typos      Fix cosmetic rule typos in all files");
         println!("        --fix-typos-on-add   Check cosmetic rule typos in git additions");
+        println!("        --interactive-typos  With --fix-typos, confirm each fix (y/n/a/q)");
+        println!("        --typo-rules=FILE    Extra typo patterns, one \"regex => replacement => description\" per line");
         println!("        --auto-fix           Auto-fix typos without prompting");
         println!("    -q, --quiet                Suppress most output (for CI)");
+        println!("    -v, --verbose              Print each file's classification, rule counts and passes, and skipped files (as --print-ignored); \"Sorted:\" lines live");
+        println!("        -vv                    As -v, plus how each section was classified (as --print-sections)");
         println!("        --limited-quiet        Suppress directory listing only");
         println!("        --check-file=FILE      Process a single file");
+        println!("        --explain RULE         Show step by step how FOP tidies one rule (no files read)");
         println!("        --output-diff=FILE     Output changes as diff (no files modified)");
         println!("        --output-diff          Output individual .diff files per source file");
+        println!("        --diff-dir=DIR         Write one .diff per changed file into DIR (no files modified)");
+        println!("        --diff-context=N       Context lines around each diff hunk (default: 3, 0 for minimal)");
+        println!("        --output-dir=DIR       Write sorted files into a mirror tree in DIR (implies --no-commit)");
+        println!("        --copy-unchanged       With --output-dir, also copy files that need no changes");
+        println!("        --suffix-output=SUFFIX Write changed files to FILE+SUFFIX, e.g. list.txt.sorted (implies --no-commit)");
         println!("        --output               Output changed files with --changed suffix");
         println!("        --ignore-config        Ignore .fopconfig file");
         println!("        --add-timestamp        Update 'Last modified/updated' timestamp in header");
         println!("        --add-timestamp=FILES  Add/update timestamp for specific files (comma-separated)");
+        println!("        --timestamp-format=FMT Timestamp layout: default, iso8601 or a pattern (%Y %m %d %H %M %S %b %Z ...)");
         println!("        --add-checksum=FILES   Add/update checksum for specific files (comma-separated)");
         println!("        --validate-checksum=FILES  Validate checksum for specific files (exit 1 on failure)");
         println!("        --validate-checksum-and-fix=FILES  Validate and fix invalid checksums");
+        println!("        --post-hook=CMD        Run CMD after sorting; {{}} is replaced with each sorted file");
+        println!("        --post-hook-fail       Fail the run (no commit) when --post-hook exits non-zero");
+        println!("        --verify-verbose       On a checksum mismatch, show what was hashed and prefix checksums");
         println!("        --benchmark     Benchmark sorting performance (3 iterations, dry-run)");
         println!("        --show-config   Show applied configuration and exit");
+        println!("        --dump-config-template  Print a .fopconfig with every key and its default, then exit");
         println!("        --git-binary=<path>    Path to git binary (default: git in PATH)");
         println!("    -h, --help          Show this help message");
         println!("    -V, --version       Show version number");
@@ -771,19 +1873,54 @@ impl Args {
         } else {
             println!("Config file: (none found, using defaults)");
         }
+        if let Some(ref profile) = self.profile {
+            println!("Profile: {}", profile);
+        }
+        if !self.env_config_vars.is_empty() {
+            println!("Environment: {}", self.env_config_vars.join(", "));
+        }
+        if !self.config_warnings.is_empty() {
+            println!();
+            println!("Config warnings (ignored):");
+            for message in &self.config_warnings {
+                println!("  {}", message);
+            }
+        }
         println!();
         println!("Settings:");
         println!("  no-commit       = {}", self.no_commit);
         println!("  only-sort-changed = {}", self.only_sort_changed);
+        if let Some(ref git_ref) = self.since {
+            println!("  since           = {}", git_ref);
+        }
         println!("  rebase-on-fail  = {}", self.rebase_on_fail);
+        println!("  require-clean-tree = {}", self.require_clean_tree);
+        println!("  push-retries    = {}", self.push_retries);
         println!("  ci              = {}", self.ci);
         println!("  pr-show-changes = {}", self.pr_show_changes);
+        if let Some(ref body) = self.pr_body {
+            println!("  pr-body         = {}", body);
+        }
+        if !self.pr_labels.is_empty() {
+            println!("  pr-labels       = {}", self.pr_labels.join(","));
+        }
         println!("  check-banned-list = {:?}", self.check_banned_list);
         println!("  no-ubo-convert  = {}", self.no_ubo_convert);
         println!("  no-msg-check    = {}", self.no_msg_check);
         println!("  disable-ignored = {}", self.disable_ignored);
         println!("  no-sort         = {}", self.no_sort);
+        println!("  dedupe-only     = {}", self.dedupe_only);
+        println!("  normalize-only  = {}", self.normalize_only);
         println!("  alt-sort        = {}", self.alt_sort);
+        println!("  sort-key        = {}", match self.sort_key {
+            Some(SortKey::Domain) => "domain",
+            Some(SortKey::Full) => "full",
+            Some(SortKey::Selector) => "selector",
+            None if self.alt_sort => "selector (alt-sort)",
+            None => "domain",
+        });
+        println!("  group-exceptions = {}", self.group_exceptions);
+        println!("  exceptions-first = {}", self.exceptions_first);
         println!("  parse-adguard   = {}", self.parse_adguard);
         if self.parse_adguard_files.is_empty() {
             println!("  parse-adguard-files = (none)");
@@ -796,6 +1933,11 @@ impl Args {
         } else {
             println!("  localhost-files = {}", self.localhost_files.join(","));
         }
+        if self.hosts_blackhole.is_empty() {
+            println!("  hosts-blackhole = {}", fop_sort::DEFAULT_BLACKHOLE_ADDRESSES.join(","));
+        } else {
+            println!("  hosts-blackhole = {}", self.hosts_blackhole.join(","));
+        }
         println!("  no-color        = {}", self.no_color);
         println!("  no-large-warning= {}", self.no_large_warning);
         println!();
@@ -814,6 +1956,12 @@ impl Args {
         } else {
             println!("  ignore-all-but  = {}", self.ignore_all_but.join(","));
         }
+        if !self.exclude_glob.is_empty() {
+            println!("  exclude-glob    = {}", self.exclude_glob.join(","));
+        }
+        if !self.include_glob.is_empty() {
+            println!("  include-glob    = {}", self.include_glob.join(","));
+        }
         if self.file_extensions.is_empty()
             || (self.file_extensions.len() == 1 && self.file_extensions[0] == "txt")
         {
@@ -829,11 +1977,90 @@ impl Args {
         println!("  backup          = {}", self.backup);
         println!("  keep-empty-lines= {}", self.keep_empty_lines);
         println!("  ignore-dot-domains= {}", self.ignore_dot_domains);
+        if !self.allow_dotless_domains.is_empty() {
+            println!("  allow-dotless-domains = {}", self.allow_dotless_domains.join(","));
+        }
+        println!("  normalize-quotes= {}", self.normalize_quotes);
+        println!("  normalize-csp   = {}", self.normalize_csp);
+        println!("  normalize-redirects = {}", self.normalize_redirects);
+        println!("  normalize-anchor = {}", self.normalize_anchor);
+        println!("  normalize-header = {}", self.normalize_header);
+        println!("  sort-header     = {}", self.sort_header);
+        println!("  warn-broad-options = {}", self.warn_broad_options);
+        println!("  warn-combine    = {}", self.warn_combine);
+        println!("  max-line-length = {}", self.max_line_length.map_or("off".to_string(), |n| n.to_string()));
+        println!("  drop-long-lines = {}", self.drop_long_lines);
+        println!("  rules-per-file-limit = {}", self.rules_per_file_limit.map_or("off".to_string(), |n| n.to_string()));
+        if let Some(ref path) = self.quarantine_invalid {
+            println!("  quarantine-invalid = {}", path.display());
+        }
+        if let Some(ref path) = self.cache {
+            println!("  cache           = {}", path.display());
+        }
+        println!("  warn-generic-cosmetic = {}", self.warn_generic_cosmetic);
+        if !self.generic_cosmetic_allow.is_empty() {
+            println!("  generic-cosmetic-allow = {}", self.generic_cosmetic_allow.join(","));
+        }
+        println!("  warn-tld-cosmetic = {}", self.warn_tld_cosmetic);
+        println!("  warn-js-rules   = {}", self.warn_js_rules);
+        println!("  warn-hide-interactions = {}", self.warn_hide_interactions);
+        println!("  detect-confusables = {}", self.detect_confusables);
+        println!("  sort-locale     = {}", if self.sort_locale == SortLocale::Unicode { "unicode" } else { "ascii" });
+        println!("  dialect         = {}", match self.dialect {
+            Dialect::All => "all",
+            Dialect::Abp => "abp",
+            Dialect::Ubo => "ubo",
+            Dialect::Adguard => "adguard",
+        });
+        println!("  strict-utf8     = {}", self.strict_utf8);
+        println!("  rule-count      = {}", self.rule_count);
+        println!("  timestamp-format = {}", self.timestamp_format.as_deref().unwrap_or("default"));
+        println!("  keep-bom        = {}", self.keep_bom);
+        println!("  input-encoding  = {}", self.input_encoding.map_or("UTF-8", |e| e.name()));
+        println!("  output-encoding = {}", self.output_encoding.or(self.input_encoding).map_or("UTF-8", |e| e.name()));
+        println!("  verbose         = {}", match self.verbosity {
+            Verbosity::Normal => "false",
+            Verbosity::Verbose => "true",
+            Verbosity::VeryVerbose => "2",
+        });
+        println!("  no-selector-tidy= {}", self.no_selector_tidy);
+        if !self.extended_markers.is_empty() {
+            println!("  extended-markers = {}", self.extended_markers.join(","));
+        }
+        if let Some(ref dir) = self.temp_dir {
+            println!("  temp-dir        = {}", dir.display());
+        }
         if let Some(ref path) = self.warning_output {
             println!("  warning-output  = {}", path.display());
         } else {
             println!("  warning-output  = (stderr)");
         }
+        println!("  warning-format  = {}", if self.warning_format == WarningFormat::Json { "json" } else { "text" });
+        if let Some(limit) = self.fail_on_warning_count {
+            println!("  fail-on-warning-count = {}", limit);
+        }
+        if let Some(limit) = self.max_warnings_per_file {
+            println!("  max-warnings-per-file = {}", limit);
+        }
+        println!("  fail-fast       = {}", self.fail_fast);
+        println!("  follow-symlinks = {}", self.follow_symlinks);
+        if let Some(ref pathspec) = self.commit_pathspec {
+            println!("  commit-pathspec = {}", pathspec);
+        }
+        if let Some(ref command) = self.post_hook {
+            println!("  post-hook       = {}", command);
+            println!("  post-hook-fail  = {}", self.post_hook_fail);
+        }
+        if let Some(ref path) = self.typo_rules {
+            println!("  typo-rules      = {}", path.display());
+        }
+        if let Some(ref dir) = self.output_dir {
+            println!("  output-dir      = {}", dir.display());
+            println!("  copy-unchanged  = {}", self.copy_unchanged);
+        }
+        if let Some(ref suffix) = self.suffix_output {
+            println!("  suffix-output   = {}", suffix);
+        }
         if let Some(ref title) = self.create_pr {
             println!("  create-pr       = {}", if title.is_empty() { "(prompt)" } else { title });
             if !self.direct_push_users.is_empty() {
@@ -870,70 +2097,117 @@ impl Args {
     }
 }
 
-// =============================================================================
-// Regex Patterns (shared with fop_sort module)
-// =============================================================================
-
-/// Pattern for extracting domain from blocking filter options
-pub(crate) static FILTER_DOMAIN_PATTERN: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r"\$(?:[^,]*,)*domain=([^,]+)").unwrap());
-
-/// Pattern for extracting domain from element hiding rules  
-pub(crate) static ELEMENT_DOMAIN_PATTERN: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r#"^([^/|@"!]*?)#[@?$%]?#"#).unwrap());
-
-/// Pattern for extracting domain from AdGuard extended element rules
-pub(crate) static ADGUARD_ELEMENT_DOMAIN_PATTERN: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r#"^([^/|@"!]*?)(#[@?$%]?#|#\$\?#|#@\$\?#|\$\$|\$@\$)"#).unwrap());
-
-/// Pattern for AdGuard extended element matching (includes #$?# and #@$?#)
-pub(crate) static ADGUARD_ELEMENT_PATTERN: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r#"^([^/|@"!]*?)(#[@?$%]?#|#@[$%?]#|#\$\?#|#@\$\?#|\$\$|\$@\$)(.+)$"#).unwrap());
-
-/// Pattern for FOP element matching (no {} in selector)
-pub(crate) static FOPPY_ELEMENT_PATTERN: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r#"^([^/|@"!]*?)(#[@?$%]?#|#@[$%?]#)([^{}]+)$"#).unwrap());
-
-/// Pattern for FOP.py compatible sorting (only ## and #@#)
-pub(crate) static FOPPY_ELEMENT_DOMAIN_PATTERN: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r#"^[^/|@"!]*?#@?#"#).unwrap());
-
-/// Pattern for element hiding rules (standard, uBO, and AdGuard extended syntax)
-pub(crate) static ELEMENT_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
-    Regex::new(r#"^([^/|@"!]*?)(##|#@#|#\?#|#@\?#|#\$#|#@\$#|#%#|#@%#)(.+)$"#).unwrap()
-});
-
-/// Pattern for regex domain element hiding rules (uBO/AdGuard specific)
-pub(crate) static REGEX_ELEMENT_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
-    Regex::new(r#"^(/[^#]+/)(##|#@#|#\?#|#@\?#|#\$#|#@\$#|#%#|#@%#)(.+)$"#).unwrap()
-});
-
-pub(crate) static OPTION_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
-    Regex::new(r"^(.*[^\\]|)\$(~?[\w\-]+(?:=[^,\s]+)?(?:,~?[\w\-]+(?:=[^,\s]+)?)*)$").unwrap()
-});
-
-pub(crate) static PSEUDO_PATTERN: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r"(:[a-zA-Z\-]*[A-Z][a-zA-Z\-]*)").unwrap());
-
-pub(crate) static REMOVAL_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
-    Regex::new(r"([>+~,@\s])(\*)([#.\[:])").expect("Invalid REMOVAL_PATTERN regex")
-});
+/// Answer state for --interactive-typos prompts
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TypoPrompt {
+    Ask,
+    AcceptAll,
+    Quit,
+}
 
-pub(crate) static ATTRIBUTE_VALUE_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
-    Regex::new(r#"^([^'"\\]|\\.)*("(?:[^"\\]|\\.)*"|'(?:[^'\\]|\\.)*')|\*"#).unwrap()
-});
+/// Ask whether to apply one typo fix, reading the answer from `input`; "a" accepts the
+/// rest, "q" (or end of input) declines the rest
+fn prompt_typo_fix(
+    state: &mut TypoPrompt,
+    input: &mut dyn io::BufRead,
+    location: &str,
+    before: &str,
+    after: &str,
+    reasons: &str,
+) -> bool {
+    match *state {
+        TypoPrompt::AcceptAll => return true,
+        TypoPrompt::Quit => return false,
+        TypoPrompt::Ask => {}
+    }
+    println!("\n{} ({})", location, reasons);
+    println!("  - {}", before);
+    println!("  + {}", after);
+    loop {
+        print!("Apply fix? [y]es/[n]o/[a]ll/[q]uit: ");
+        io::stdout().flush().ok();
+        let mut answer = String::new();
+        if input.read_line(&mut answer).unwrap_or(0) == 0 {
+            *state = TypoPrompt::Quit;
+            return false;
+        }
+        match answer.trim().to_lowercase().as_str() {
+            "y" | "yes" => return true,
+            "n" | "no" => return false,
+            "a" | "all" => {
+                *state = TypoPrompt::AcceptAll;
+                return true;
+            }
+            "q" | "quit" => {
+                *state = TypoPrompt::Quit;
+                return false;
+            }
+            _ => {}
+        }
+    }
+}
 
-pub(crate) static TREE_SELECTOR: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r"(\\.|[^+>~ \t])\s*([+>~ \t])\s*(\D)").unwrap());
+/// Fix cosmetic typos in one file, returning the number of fixes applied.
+/// With `prompt`, each fix is confirmed with an answer read from its input; otherwise all
+/// are applied.
+fn fix_file_typos(
+    path: &Path,
+    quiet: bool,
+    write: bool,
+    mut prompt: Option<(&mut TypoPrompt, &mut dyn io::BufRead)>,
+) -> usize {
+    let Ok(content) = fop_sort::read_list_to_string(path) else {
+        return 0;
+    };
+    // Skip files without cosmetic rules
+    if !content.contains('#') {
+        return 0;
+    }
 
-pub(crate) static UNICODE_SELECTOR: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r"\\[0-9a-fA-F]{1,6}\s[a-zA-Z]*[A-Z]").unwrap());
+    let mut file_typo_count = 0;
+    let mut new_lines: Vec<String> = Vec::new();
 
-pub(crate) static DOMAIN_EXTRACT_PATTERN: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r"^\|*([^/\^\$]+)").unwrap());
+    for (line_num, line) in content.lines().enumerate() {
+        let (fixed, fixes) = fop_typos::fix_all_typos(line);
+        if fixes.is_empty() {
+            new_lines.push(line.to_string());
+            continue;
+        }
+        let accepted = match prompt.as_mut() {
+            Some((state, input)) => {
+                let location = format!("{}:{}", path.display(), line_num + 1);
+                prompt_typo_fix(state, *input, &location, line, &fixed, &fixes.join(", "))
+            }
+            None => {
+                if !quiet {
+                    let _ = writeln!(
+                        std::io::stdout().lock(),
+                        "{}:{}: {} ? {} ({})",
+                        path.display(),
+                        line_num + 1,
+                        line,
+                        fixed,
+                        fixes.join(", ")
+                    );
+                }
+                true
+            }
+        };
+        if accepted {
+            file_typo_count += 1;
+            new_lines.push(fixed);
+        } else {
+            new_lines.push(line.to_string());
+        }
+    }
 
-pub(crate) static IP_ADDRESS_PATTERN: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r"^\d+\.\d+\.\d+\.\d+").unwrap());
+    if file_typo_count > 0 && write {
+        if let Err(e) = fop_sort::write_list(path, (new_lines.join("\n") + "\n").as_bytes()) {
+            eprintln!("Error writing {}: {}", path.display(), e);
+        }
+    }
+    file_typo_count
+}
 
 // =============================================================================
 // Constants
@@ -945,116 +2219,206 @@ const IGNORE_FILES: &[&str] = &["test-files-to-ingore.txt"];
 /// Directories to ignore
 const IGNORE_DIRS: &[&str] = &["folders-to-ingore"];
 
-/// Known Adblock Plus options (HashSet for O(1) lookup)
-pub(crate) static KNOWN_OPTIONS: LazyLock<HashSet<&'static str>> = LazyLock::new(|| {
-    [
-        // Standard ABP options
-        "collapse",
-        "csp",
-        "csp=frame-src",
-        "csp=img-src",
-        "csp=media-src",
-        "csp=script-src",
-        "csp=worker-src",
-        "document",
-        "elemhide",
-        "font",
-        "genericblock",
-        "generichide",
-        "image",
-        "match-case",
-        "media",
-        "object-subrequest",
-        "object",
-        "other",
-        "ping",
-        "popup",
-        "script",
-        "stylesheet",
-        "subdocument",
-        "third-party",
-        "webrtc",
-        "websocket",
-        "xmlhttprequest",
-        // uBO short options
-        "xhr",
-        "css",
-        "1p",
-        "3p",
-        "frame",
-        "doc",
-        "ghide",
-        "xml",
-        "iframe",
-        "first-party",
-        "strict1p",
-        "strict3p",
-        "ehide",
-        "shide",
-        "specifichide",
-        // uBO/ABP specific
-        "all",
-        "badfilter",
-        "important",
-        "popunder",
-        "empty",
-        "cname",
-        "inline-script",
-        "removeparam",
-        "redirect-rule",
-        "_____",
-        "-----",
-        // Adguard
-        "network",
-        "content",
-        "extension",
-        "jsinject",
-        "stealth",
-        "cookie",
-        // ABP rewrite resources
-        "rewrite=abp-resource:1x1-transparent-gif",
-        "rewrite=abp-resource:2x2-transparent-png",
-        "rewrite=abp-resource:32x32-transparent-png",
-        "rewrite=abp-resource:3x2-transparent-png",
-        "rewrite=abp-resource:blank-css",
-        "rewrite=abp-resource:blank-html",
-        "rewrite=abp-resource:blank-js",
-        "rewrite=abp-resource:blank-mp3",
-        "rewrite=abp-resource:blank-mp4",
-        "rewrite=abp-resource:blank-text",
-    ]
-    .into_iter()
-    .collect()
-});
-
-/// uBO to ABP option conversions
-pub(crate) static UBO_CONVERSIONS: LazyLock<AHashMap<&'static str, &'static str>> =
-    LazyLock::new(|| {
-        [
-            ("xhr", "xmlhttprequest"),
-            ("~xhr", "~xmlhttprequest"),
-            ("css", "stylesheet"),
-            ("~css", "~stylesheet"),
-            ("1p", "~third-party"),
-            ("~1p", "third-party"),
-            ("3p", "third-party"),
-            ("~3p", "~third-party"),
-            ("frame", "subdocument"),
-            ("~frame", "~subdocument"),
-            ("doc", "document"),
-            ("ghide", "generichide"),
-            ("xml", "xmlhttprequest"),
-            ("~xml", "~xmlhttprequest"),
-            ("iframe", "subdocument"),
-            ("~iframe", "~subdocument"),
-        ]
+// =============================================================================
+// Main Processing
+// =============================================================================
+
+/// List filter files under a location, applying the same directory/file filters as sorting
+fn list_filter_files(location: &Path, args: &Args, file_globs: &FileGlobs) -> Vec<PathBuf> {
+    let mut seen = HashSet::new();
+    walk_location(location, args.follow_symlinks)
         .into_iter()
+        .filter_entry(|e| {
+            let name = e.file_name().to_string_lossy();
+            !name.starts_with('.')
+                && (args.disable_ignored || !IGNORE_DIRS.contains(&name.as_ref()))
+                && !should_ignore_dir(e.path(), &args.ignore_dirs)
+        })
+        .filter_map(walk_entry)
+        .filter(|entry| {
+            if !entry_is_file(entry) {
+                return false;
+            }
+            let path = entry.path();
+            let filename = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+            let extension = fop_sort::list_extension(path);
+            args.file_extensions.iter().any(|ext| ext == extension)
+                && (args.disable_ignored || !IGNORE_FILES.contains(&filename))
+                && !should_ignore_file(filename, &args.ignore_files)
+                && (args.ignore_all_but.is_empty()
+                    || args.ignore_all_but.iter().any(|f| filename.contains(f)))
+                && file_globs.allows(path.strip_prefix(location).unwrap_or(path))
+                && (!args.follow_symlinks || first_visit(&mut seen, path))
+        })
+        .map(|entry| entry.into_path())
         .collect()
-    });
+}
+
+/// Write a changed file's diff into --diff-dir, with `a/`/`b/` paths relative to its location
+fn write_diff_patch(diff_dir: &Path, rel_path: &Path, diff: &str, quiet: bool) {
+    let rel = rel_path.to_string_lossy().replace('\\', "/");
+    // Replace the "--- a/<path>" / "+++ b/<path>" header generated from the full path
+    let body = diff.splitn(3, '\n').nth(2).unwrap_or("");
+    let patch = format!("--- a/{0}\n+++ b/{0}\n{1}", rel, body);
+    let patch_path = diff_dir.join(format!("{}.diff", rel.replace('/', "__")));
+    if let Err(e) = fs::write(&patch_path, patch) {
+        eprintln!("Error writing diff file {}: {}", patch_path.display(), e);
+    } else if !quiet {
+        println!("Diff written to: {}", patch_path.display());
+    }
+}
+
+/// Per-file sort durations collected for --time-per-file
+type FileTimes = std::sync::Mutex<Vec<(std::time::Duration, PathBuf)>>;
+
+/// Print the slowest files to sort, slowest first (--time-per-file)
+fn print_slowest_files(mut times: Vec<(std::time::Duration, PathBuf)>, limit: usize, no_color: bool) {
+    if times.is_empty() {
+        return;
+    }
+    times.sort_unstable_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+    let title = format!("Slowest {} of {} file(s):", limit.min(times.len()), times.len());
+    if fop::colors_off(no_color) {
+        println!("\n{}", title);
+    } else {
+        println!("\n{}", title.bold());
+    }
+    for (duration, path) in times.iter().take(limit) {
+        println!("  {:>10.3}ms  {}", duration.as_secs_f64() * 1000.0, path.display());
+    }
+}
+
+/// Print the steps of --explain, marking those that left the rule unchanged
+fn print_explanation(steps: &[(&str, String)], no_color: bool) {
+    let mut previous: Option<&str> = None;
+    for (label, rule) in steps {
+        let shown = if rule.is_empty() { "(removed, see warnings above)" } else { rule.as_str() };
+        let note = if previous == Some(rule.as_str()) { " (no change)" } else { "" };
+        if fop::colors_off(no_color) {
+            println!("{}:{}\n  {}", label, note, shown);
+        } else {
+            println!("{}:{}\n  {}", label.bold(), note, shown.green());
+        }
+        previous = Some(rule);
+    }
+}
+
+/// Print per-file and total rule tallies (--count-only)
+fn print_rule_counts(files: &[PathBuf], args: &Args, sort_config: &SortConfig) {
+    let mut counts: Vec<(&PathBuf, fop_sort::RuleCounts)> = files
+        .par_iter()
+        .filter_map(|path| {
+            let content = match fop_sort::read_list_to_string(path) {
+                Ok(content) => content,
+                Err(e) => {
+                    eprintln!("Cannot read {}: {}", path.display(), e);
+                    return None;
+                }
+            };
+            let config = SortConfig {
+                parse_adguard: is_adguard_file(path, sort_config.parse_adguard, &args.parse_adguard_files),
+                localhost: is_localhost_file(path, sort_config.localhost, &args.localhost_files),
+                ..*sort_config
+            };
+            Some((path, fop_sort::count_rules(&content, &config)))
+        })
+        .collect();
+    counts.sort_unstable_by(|a, b| a.0.cmp(b.0));
+
+    let mut total = fop_sort::RuleCounts::default();
+    for (path, c) in &counts {
+        total += *c;
+        println!(
+            "{}: {} rules ({} cosmetic, {} network, {} exception)",
+            path.display(), c.total, c.cosmetic, c.network, c.exception
+        );
+    }
+    let label = if fop::colors_off(args.no_color) { "Total:".to_string() } else { "Total:".bold().to_string() };
+    println!(
+        "{} {} rules in {} file(s) ({} cosmetic, {} network, {} exception)",
+        label, total.total, counts.len(), total.cosmetic, total.network, total.exception
+    );
+}
+
+/// Files among `files` whose sorted output differs from what is on disk (--plan)
+fn planned_changes(files: Vec<PathBuf>, args: &Args, sort_config: &SortConfig) -> Vec<PathBuf> {
+    let mut changed: Vec<PathBuf> = files
+        .into_par_iter()
+        .filter(|path| {
+            let mut config = SortConfig {
+                parse_adguard: is_adguard_file(path, sort_config.parse_adguard, &args.parse_adguard_files),
+                localhost: is_localhost_file(path, sort_config.localhost, &args.localhost_files),
+                ..*sort_config
+            };
+            if let Some(overrides) = path.file_name().and_then(|n| n.to_str()).and_then(|n| args.file_overrides.get(n)) {
+                overrides.apply_to(&mut config);
+            }
+            fop_sort::would_change(path, &config).unwrap_or_else(|e| {
+                eprintln!("Cannot read {}: {}", path.display(), e);
+                false
+            })
+        })
+        .collect();
+    changed.sort_unstable();
+    changed
+}
+
+/// Current branch of the repository in `git_dir`, read from `HEAD` without running git
+fn head_branch(git_dir: &Path) -> String {
+    match fs::read_to_string(git_dir.join("HEAD")) {
+        Ok(head) => match head.trim().strip_prefix("ref: refs/heads/") {
+            Some(branch) => format!("branch {}", branch),
+            None => "detached HEAD".to_string(),
+        },
+        Err(_) => "current branch".to_string(),
+    }
+}
+
+/// The git step a run would take after sorting in `repo_dir` (--plan)
+fn planned_git_action(repo_dir: &Path, args: &Args, sort_config: &SortConfig, has_changes: bool, allow_pr: bool) -> String {
+    if args.no_commit {
+        return "nothing (commits disabled)".to_string();
+    }
+    if sort_config.dry_run {
+        return "nothing (files are not modified)".to_string();
+    }
+    let Some(repo) = REPO_TYPES.iter().find(|r| repo_dir.join(r.directory).is_dir()) else {
+        return format!("nothing (no repository in {})", repo_dir.display());
+    };
+    if !has_changes {
+        return "nothing (no sorting changes to commit)".to_string();
+    }
+    let branch = head_branch(&repo_dir.join(repo.directory));
+    let scope = match args.commit_pathspec {
+        Some(ref glob) => format!(" (only files matching {})", glob),
+        None => String::new(),
+    };
+    match args.create_pr {
+        Some(_) if allow_pr => format!(
+            "commit to a new PR branch from {} and push it (base: {}){}",
+            branch,
+            args.git_pr_branch.as_deref().unwrap_or("auto-detected main/master"),
+            scope
+        ),
+        _ => format!("commit to {} and push{}", branch, scope),
+    }
+}
 
-// =============================================================================
-// Main Processing
-// =============================================================================
+/// Print a --plan section: the files that would change and the git step that follows
+fn print_plan(label: &Path, changed: &[PathBuf], git_action: &str, no_color: bool) {
+    if fop::colors_off(no_color) {
+        println!("Plan for {}:", label.display());
+    } else {
+        println!("{} {}:", "Plan for".bold(), label.display());
+    }
+    if changed.is_empty() {
+        println!("  No files would change");
+    }
+    for path in changed {
+        println!("  Would change: {}", path.display());
+    }
+    println!("  Git: {}", git_action);
+}
 
 /// Check if filename matches any ignore pattern (exact or partial)
 #[inline]
@@ -1067,14 +2431,51 @@ fn should_ignore_file(filename: &str, ignore_files: &[String]) -> bool {
 /// Check if directory path matches any ignore pattern
 #[inline]
 fn should_ignore_dir(path: &Path, ignore_dirs: &[String]) -> bool {
-    for component in path.components() {
-        if let Some(name) = component.as_os_str().to_str() {
-            if ignore_dirs.iter().any(|p| name == p || name.contains(p)) {
-                return true;
-            }
-        }
+    ignored_dir_pattern(path, ignore_dirs).is_some()
+}
+
+/// The --ignoredirs pattern matching a component of `path`, if any
+fn ignored_dir_pattern<'a>(path: &Path, ignore_dirs: &'a [String]) -> Option<&'a str> {
+    path.components()
+        .filter_map(|component| component.as_os_str().to_str())
+        .find_map(|name| ignore_dirs.iter().find(|p| name == p.as_str() || name.contains(p.as_str())))
+        .map(String::as_str)
+}
+
+/// Why the directory walk in `process_location` prunes an entry (--print-ignored), if it does
+fn walk_skip_reason(path: &Path, name: &str, disable_ignored: bool, ignore_dirs: &[String]) -> Option<String> {
+    if name.starts_with('.') {
+        Some("name starts with '.'".to_string())
+    } else if !disable_ignored && IGNORE_DIRS.contains(&name) {
+        Some(format!("built-in ignored directory '{}'", name))
+    } else {
+        ignored_dir_pattern(path, ignore_dirs).map(|pattern| format!("matched ignoredirs pattern '{}'", pattern))
+    }
+}
+
+/// Why `process_location` skips a file it walked (--print-ignored), if it does
+fn file_skip_reason(
+    path: &Path,
+    location: &Path,
+    file_extensions: &[String],
+    disable_ignored: bool,
+    ignore_files: &[String],
+    ignore_all_but: &[String],
+    file_globs: &FileGlobs,
+) -> Option<String> {
+    let filename = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+    let extension = fop_sort::list_extension(path);
+    if !file_extensions.iter().any(|ext| ext == extension) {
+        Some(format!("extension '{}' not in file-extensions ({})", extension, file_extensions.join(",")))
+    } else if !disable_ignored && IGNORE_FILES.contains(&filename) {
+        Some("built-in ignored file".to_string())
+    } else if let Some(pattern) = ignore_files.iter().find(|p| filename == p.as_str() || filename.contains(p.as_str())) {
+        Some(format!("matched ignorefiles pattern '{}'", pattern))
+    } else if !ignore_all_but.is_empty() && !ignore_all_but.iter().any(|f| filename.contains(f.as_str())) {
+        Some(format!("not in ignore-all-but ({})", ignore_all_but.join(",")))
+    } else {
+        file_globs.skip_reason(path.strip_prefix(location).unwrap_or(path)).map(str::to_string)
     }
-    false
 }
 
 #[inline]
@@ -1083,6 +2484,131 @@ fn entry_is_dir(entry: &DirEntry) -> bool {
     ft.is_dir() || (ft.is_symlink() && entry.path().is_dir())
 }
 
+/// Append --suffix-output to where a sorted file is written: the --output-dir copy when
+/// there is one, otherwise the source itself (`list.txt` -> `list.txt.sorted`)
+fn with_output_suffix(output_path: Option<PathBuf>, source: &Path, suffix: Option<&str>) -> Option<PathBuf> {
+    let Some(suffix) = suffix else {
+        return output_path;
+    };
+    let mut name = output_path.unwrap_or_else(|| source.to_path_buf()).into_os_string();
+    name.push(suffix);
+    Some(PathBuf::from(name))
+}
+
+/// `--rules-diff-only --since=REF`: compare the rules in `path` at `git_ref` with the file
+/// as it is now. A file that didn't exist at the ref has all of its rules added.
+fn rules_diff_since(
+    path: &Path,
+    git_ref: &str,
+    git_binary: Option<&str>,
+    config: &SortConfig,
+) -> Result<fop_sort::SortOutcome, FopError> {
+    let open_error = |source| FopError::Open { path: path.to_path_buf(), source };
+    let old = match fop_git::file_at_ref(path, git_ref, git_binary)? {
+        Some(data) => fop_sort::list_text(&data, path, config).map_err(open_error)?,
+        None => String::new(),
+    };
+    let new = fs::read(path).and_then(|data| fop_sort::list_text(&data, path, config)).map_err(open_error)?;
+    Ok(fop_sort::SortOutcome { diff: fop_sort::rules_diff(path, &old, &new, config), sorted: false })
+}
+
+/// Print collected `--rules-diff-only` reports when they aren't going to `--output-diff`
+fn print_rules_diffs(diffs: &[String]) {
+    if !diffs.is_empty() {
+        print!("{}", diffs.join("\n"));
+    }
+}
+
+/// `path` quoted for the platform shell
+fn shell_quote(path: &Path) -> String {
+    let path = path.display().to_string();
+    if cfg!(windows) {
+        format!("\"{}\"", path)
+    } else {
+        format!("'{}'", path.replace('\'', "'\\''"))
+    }
+}
+
+/// Run --post-hook through the shell: once per file in `files` when the command contains
+/// `{}`, otherwise once in `dir`. A hook that exits non-zero is a warning, or with
+/// --post-hook-fail stops the run before anything is committed.
+fn run_post_hook(command: &str, files: &[&Path], dir: &Path, fail_run: bool) {
+    let commands: Vec<String> = if command.contains("{}") {
+        files.iter().map(|path| command.replace("{}", &shell_quote(path))).collect()
+    } else {
+        vec![command.to_string()]
+    };
+    let mut failed = false;
+    for command in commands {
+        let mut shell = if cfg!(windows) {
+            let mut cmd = std::process::Command::new("cmd");
+            cmd.arg("/C");
+            cmd
+        } else {
+            let mut cmd = std::process::Command::new("sh");
+            cmd.arg("-c");
+            cmd
+        };
+        let status = shell.arg(&command).current_dir(dir).status();
+        let problem = match status {
+            Ok(status) if status.success() => continue,
+            Ok(status) => match status.code() {
+                Some(code) => format!("exited with status {}", code),
+                None => "was terminated by a signal".to_string(),
+            },
+            Err(e) => format!("could not be run: {}", e),
+        };
+        failed = true;
+        if fail_run {
+            eprintln!("Error: post-hook `{}` {}", command, problem);
+        } else {
+            write_warning(&format!("Warning: post-hook `{}` {}", command, problem));
+        }
+    }
+    if failed && fail_run {
+        fop_sort::cleanup_temp_files();
+        flush_warnings();
+        eprintln!("Error: --post-hook failed (--post-hook-fail); no commit attempted");
+        std::process::exit(1);
+    }
+}
+
+/// Log a skipped walk entry and why (--print-ignored)
+fn print_ignored_entry(enabled: bool, path: &Path, reason: &str) {
+    if enabled {
+        println!("Ignored: {} ({})", path.display(), reason);
+    }
+}
+
+/// Walk a location; with --follow-symlinks, symlinked directories are descended into too
+fn walk_location(location: &Path, follow_symlinks: bool) -> WalkDir {
+    WalkDir::new(location).follow_links(follow_symlinks)
+}
+
+/// Keep readable walk entries. WalkDir detects symlink cycles (only possible with
+/// --follow-symlinks) and returns them as errors; those are reported rather than dropped.
+fn walk_entry(entry: walkdir::Result<DirEntry>) -> Option<DirEntry> {
+    match entry {
+        Ok(entry) => Some(entry),
+        Err(e) => {
+            if let (Some(path), Some(ancestor)) = (e.path(), e.loop_ancestor()) {
+                write_warning(&format!(
+                    "Skipped symlink loop: {} points back to {}",
+                    path.display(),
+                    ancestor.display()
+                ));
+            }
+            None
+        }
+    }
+}
+
+/// Whether a file is reached for the first time, comparing canonical paths, so a file
+/// reachable through several symlinked directories is only processed once
+fn first_visit(seen: &mut HashSet<PathBuf>, path: &Path) -> bool {
+    seen.insert(fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()))
+}
+
 #[inline]
 fn entry_is_file(entry: &DirEntry) -> bool {
     let ft = entry.file_type();
@@ -1147,47 +2673,81 @@ fn get_git_changed_files(location: &Path) -> Option<ahash::AHashSet<PathBuf>> {
     Some(files)
 }
 
-#[allow(clippy::too_many_arguments)]
-fn process_location(
-    location: &Path,
+/// Settings for `process_location`, built once from `Args`
+#[derive(Clone, Copy)]
+struct LocationOptions<'a> {
     no_commit: bool,
     no_msg_check: bool,
     disable_ignored: bool,
     no_color: bool,
     no_large_warning: bool,
-    ignore_files: &[String],
-    ignore_dirs: &[String],
-    ignore_all_but: &[String],
-    file_extensions: &[String],
-    sort_config: &SortConfig,
-    create_pr: &Option<String>,
-    git_pr_branch: &Option<String>,
+    ignore_files: &'a [String],
+    ignore_dirs: &'a [String],
+    ignore_all_but: &'a [String],
+    file_globs: &'a FileGlobs,
+    file_extensions: &'a [String],
+    sort_config: &'a SortConfig<'a>,
+    create_pr: &'a Option<String>,
+    git_pr_branch: &'a Option<String>,
     pr_show_changes: bool,
-    banned_domains: &Option<ahash::AHashSet<String>>,
+    pr_body: Option<&'a str>,
+    pr_labels: &'a [String],
+    banned_domains: &'a Option<ahash::AHashSet<String>>,
     auto_banned_remove: bool,
-    direct_push_users: &[String],
-    banned_list_file: Option<&str>,
+    direct_push_users: &'a [String],
+    banned_list_file: Option<&'a str>,
     fix_typos: bool,
     fix_typos_on_add: bool,
     auto_fix: bool,
     only_sort_changed: bool,
+    since: Option<&'a str>,
     rebase_on_fail: bool,
+    push_retries: usize,
     ci: bool,
     quiet: bool,
     limited_quiet: bool,
     output_diff_individual: bool,
-    diff_output: &std::sync::Mutex<Vec<String>>,
-    git_message: &Option<String>,
-    history: &[String],
-    git_binary: Option<&str>,
-    add_checksum: &[String],
-    validate_checksum_and_fix: &[String],
-    add_timestamp: &[String],
+    diff_dir: Option<&'a Path>,
+    output_dir: Option<&'a Path>,
+    suffix_output: Option<&'a str>,
+    git_message: &'a Option<String>,
+    history: &'a [String],
+    git_binary: Option<&'a str>,
+    add_checksum: &'a [String],
+    validate_checksum_and_fix: &'a [String],
+    verify_verbose: bool,
+    add_timestamp: &'a [String],
     localhost: bool,
-    localhost_files: &[String],
-    parse_adguard_files: &[String],
-    file_overrides: &ahash::AHashMap<String, FileOverrides>,
+    localhost_files: &'a [String],
+    parse_adguard_files: &'a [String],
+    file_overrides: &'a ahash::AHashMap<String, FileOverrides>,
+    fail_fast: bool,
+    commit_pathspec: Option<&'a globset::GlobMatcher>,
+    follow_symlinks: bool,
+    print_ignored: bool,
+    require_clean_tree: bool,
+    post_hook: Option<&'a str>,
+    post_hook_fail: bool,
+}
+
+fn process_location(
+    location: &Path,
+    options: &LocationOptions,
+    diff_output: &std::sync::Mutex<Vec<String>>,
+    file_times: Option<&FileTimes>,
+    cache: Option<&mut fop_cache::FileCache>,
 ) -> io::Result<()> {
+    let LocationOptions {
+        no_commit, no_msg_check, disable_ignored, no_color, no_large_warning, ignore_files,
+        ignore_dirs, ignore_all_but, file_globs, file_extensions, sort_config, create_pr,
+        git_pr_branch, pr_show_changes, pr_body, pr_labels, banned_domains, auto_banned_remove,
+        direct_push_users, banned_list_file, fix_typos, fix_typos_on_add, auto_fix,
+        only_sort_changed, since, rebase_on_fail, push_retries, ci, quiet, limited_quiet,
+        output_diff_individual, diff_dir, output_dir, suffix_output, git_message, history,
+        git_binary, add_checksum, validate_checksum_and_fix, verify_verbose, add_timestamp,
+        localhost, localhost_files, parse_adguard_files, file_overrides, fail_fast, commit_pathspec,
+        follow_symlinks, print_ignored, require_clean_tree, post_hook, post_hook_fail,
+    } = *options;
     if !location.is_dir() {
         eprintln!("{} does not exist or is not a folder.", location.display());
         return Ok(());
@@ -1206,8 +2766,20 @@ fn process_location(
     // Check initial repository state
     let (base_cmd, original_difference) = if let Some(repo) = repository {
         let base_cmd = build_base_command(repo, location, git_binary);
-        match check_repo_changes(&base_cmd, repo) {
-            Some(diff) => (Some(base_cmd), diff),
+        match fop_git::uncommitted_changes(&base_cmd, repo) {
+            // Committing uses `commit -a`, which would sweep these changes in with FOP's
+            Some(changes) if require_clean_tree && !changes.is_empty() => {
+                flush_warnings();
+                eprintln!(
+                    "Error: {} has uncommitted changes (--require-clean-tree); commit or stash them before running FOP:",
+                    location.display()
+                );
+                for change in &changes {
+                    eprintln!("  {}", change);
+                }
+                std::process::exit(1);
+            }
+            Some(changes) => (Some(base_cmd), !changes.is_empty()),
             None => {
                 eprintln!(
                     "The repository command was unable to run; FOP will not attempt to use repository tools."
@@ -1220,7 +2792,7 @@ fn process_location(
     };
 
     if !quiet {
-        if no_color {
+        if fop::colors_off(no_color) {
             println!("\nPrimary location: {}", location.display());
         } else {
             println!("\n{} {}", "Primary location:".bold(), location.display());
@@ -1228,23 +2800,25 @@ fn process_location(
     }
 
     // Collect directories and files
-    let entries: Vec<_> = WalkDir::new(location)
+    let entries: Vec<_> = walk_location(location, follow_symlinks)
         .min_depth(0)
         .into_iter()
         .filter_entry(|e| {
             let name = e.file_name().to_string_lossy();
-            !name.starts_with('.')
-                && (disable_ignored || !IGNORE_DIRS.contains(&name.as_ref()))
-                && !should_ignore_dir(e.path(), ignore_dirs)
+            let reason = walk_skip_reason(e.path(), &name, disable_ignored, ignore_dirs);
+            if let Some(ref reason) = reason {
+                print_ignored_entry(print_ignored && !quiet, e.path(), reason);
+            }
+            reason.is_none()
         })
-        .filter_map(|e| e.ok())
+        .filter_map(walk_entry)
         .collect();
 
     // Print directories first (sequential for ordered output)
     for entry in &entries {
         let path = entry.path();
         if entry_is_dir(entry) && !quiet && !limited_quiet {
-            if no_color {
+            if fop::colors_off(no_color) {
                 println!("Current directory: {}", path.display());
             } else {
                 println!("{} {}", "Current directory:".bold(), path.display());
@@ -1253,6 +2827,7 @@ fn process_location(
     }
 
     // Collect text files to process
+    let mut seen = HashSet::new();
     let txt_files: Vec<_> = entries
         .iter()
         .filter(|entry| {
@@ -1260,23 +2835,40 @@ fn process_location(
             if entry_is_dir(entry) {
                 return false;
             }
-            let filename = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
-            let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
-            file_extensions.iter().any(|ext| ext == extension)
-                && (disable_ignored || !IGNORE_FILES.contains(&filename))
-                && !should_ignore_file(filename, ignore_files)
-                && (ignore_all_but.is_empty()
-                    || ignore_all_but.iter().any(|f| filename.contains(f)))
+            let reason = file_skip_reason(
+                path, location, file_extensions, disable_ignored, ignore_files, ignore_all_but, file_globs,
+            )
+            .or_else(|| {
+                (follow_symlinks && !first_visit(&mut seen, path))
+                    .then(|| "already found through another symlink".to_string())
+            });
+            if let Some(ref reason) = reason {
+                print_ignored_entry(print_ignored && !quiet, path, reason);
+            }
+            reason.is_none()
         })
         .collect();
 
     // Get list of changed files from git (if flag enabled)
-    let changed_files: Option<HashSet<PathBuf>> = if only_sort_changed {
+    let mut changed_files: Option<HashSet<PathBuf>> = if only_sort_changed {
         get_git_changed_files(location).map(|v| v.into_iter().collect())
     } else {
         None
     };
-    
+
+    // --since=REF: files differing from the ref (plus uncommitted ones with --only-sort-changed)
+    if let Some(git_ref) = since {
+        match fop_git::files_changed_since(location, git_ref, git_binary) {
+            Ok(files) => changed_files.get_or_insert_with(HashSet::default).extend(files),
+            // Keep any --only-sort-changed set; only the ref's files are missing
+            Err(_) => eprintln!(
+                "Warning: --since={} failed (not a git repository or unknown ref), {}",
+                git_ref,
+                if changed_files.is_some() { "using uncommitted changes only" } else { "processing all files" }
+            ),
+        }
+    }
+
     if !quiet {
         if let Some(ref files) = changed_files {
             println!("Git detected: processing {} changed file(s)", files.len());
@@ -1285,8 +2877,36 @@ fn process_location(
         }
     }
 
+    if let Some(ref changed) = changed_files {
+        for entry in txt_files.iter().filter(|entry| !changed.contains(entry.path())) {
+            print_ignored_entry(print_ignored && !quiet, entry.path(), "unchanged according to git");
+        }
+    }
+
+    // --cache: skip lists untouched since the last run
+    let txt_files: Vec<_> = match cache.as_deref() {
+        Some(cache) => {
+            let total = txt_files.len();
+            let stale: Vec<_> = txt_files
+                .into_iter()
+                .filter(|entry| {
+                    let fresh = cache.is_fresh(entry.path());
+                    if fresh {
+                        print_ignored_entry(print_ignored && !quiet, entry.path(), "unchanged since the last run (--cache)");
+                    }
+                    !fresh
+                })
+                .collect();
+            if !quiet && stale.len() < total {
+                println!("Cache: skipping {} unchanged file(s)", total - stale.len());
+            }
+            stale
+        }
+        None => txt_files,
+    };
+
     // Process files in parallel
-    let diffs: Vec<String> = txt_files
+    let results: Vec<(Option<String>, Option<&Path>, &Path)> = txt_files
         .par_iter()
         .filter_map(|entry| {
         // Skip files git says are unchanged
@@ -1297,24 +2917,19 @@ fn process_location(
         }
 
         let path = entry.path();
+        let output_path = with_output_suffix(
+            output_dir.map(|dir| dir.join(path.strip_prefix(location).unwrap_or(path))),
+            path,
+            suffix_output,
+        );
         let mut config = SortConfig {
-            convert_ubo: sort_config.convert_ubo,
-            no_sort: sort_config.no_sort,
-            alt_sort: sort_config.alt_sort,
             parse_adguard: is_adguard_file(path, sort_config.parse_adguard, parse_adguard_files),
+            output_path: output_path.as_deref(),
             localhost: is_localhost_file(path, sort_config.localhost, localhost_files),
-            comment_chars: sort_config.comment_chars,
-            backup: sort_config.backup,
-            keep_empty_lines: sort_config.keep_empty_lines,
-            ignore_dot_domains: sort_config.ignore_dot_domains,
             fix_typos,
-            abp_convert: sort_config.abp_convert,
-            convert_trusted: sort_config.convert_trusted,
             quiet,
             no_color,
-            dry_run: sort_config.dry_run,
-            output_changed: sort_config.output_changed,
-            add_timestamp: sort_config.add_timestamp,
+            ..*sort_config
         };
         // Apply per-file overrides from [filename] sections in .fopconfig
         if let Some(fname) = path.file_name().and_then(|n| n.to_str()) {
@@ -1323,40 +2938,78 @@ fn process_location(
             }
         }
 
-        match fop_sort(path, &config) {
-            Ok(Some(diff)) => {
-                if output_diff_individual {
-                    // Individual mode: write .diff file alongside source
-                    let diff_path = entry.path().with_extension("diff");
-                    if let Err(e) = fs::write(&diff_path, &diff) {
-                        eprintln!("Error writing diff file {}: {}", diff_path.display(), e);
-                    } else if !quiet {
-                        println!("Diff written to: {}", diff_path.display());
-                    }
-                    None
-                } else {
-                    // Combined mode: return diff for collection outside the parallel loop
-                    Some(diff)
+        // Another file already failed under --fail-fast; skip the rest
+        let outcome = FAIL_FAST.run(fail_fast, path, || {
+            let start = file_times.map(|_| std::time::Instant::now());
+            let result = match since.filter(|_| config.rules_diff_only) {
+                Some(git_ref) => rules_diff_since(path, git_ref, git_binary, &config),
+                None => fop_sort(path, &config),
+            };
+            if let (Some(times), Some(start)) = (file_times, start) {
+                times.lock().unwrap().push((start.elapsed(), path.to_path_buf()));
+            }
+            result
+        })?;
+        let diff = match outcome.diff {
+            Some(diff) if diff_dir.is_some() => {
+                if let Some(dir) = diff_dir {
+                    write_diff_patch(dir, path.strip_prefix(location).unwrap_or(path), &diff, quiet);
                 }
+                None
             }
-            Ok(None) => None,
-            Err(e) => {
-                eprintln!("Error processing {}: {}", entry.path().display(), e);
+            Some(diff) if output_diff_individual => {
+                // Individual mode: write .diff file alongside source
+                let diff_path = entry.path().with_extension("diff");
+                if let Err(e) = fs::write(&diff_path, &diff) {
+                    eprintln!("Error writing diff file {}: {}", diff_path.display(), e);
+                } else if !quiet {
+                    println!("Diff written to: {}", diff_path.display());
+                }
                 None
             }
-        }
+            // Combined mode: return diff for collection outside the parallel loop
+            diff => diff,
+        };
+        Some((diff, outcome.sorted.then_some(path), path))
         })
         .collect();
 
+    // Abort before checksums and any commit if a file failed under --fail-fast
+    check_fail_fast();
+
+    let mut diffs: Vec<String> = Vec::new();
+    let mut sorted_files: Vec<&Path> = Vec::new();
+    let mut processed: Vec<&Path> = Vec::with_capacity(results.len());
+    for (diff, sorted, path) in results {
+        diffs.extend(diff);
+        sorted_files.extend(sorted);
+        processed.push(path);
+    }
+
+    // Print sorted files in path order once the parallel phase is done (--verbose prints live)
+    sorted_files.sort_unstable();
+    if !quiet && sort_config.verbosity == Verbosity::Normal {
+        for path in &sorted_files {
+            fop_sort::print_sorted(path, no_color)?;
+        }
+    }
+
+    // Rules shared between files in this location (report only)
+    if sort_config.detect_cross_file_dupes {
+        fop_sort::print_cross_file_duplicates(no_color);
+    }
     // Single lock acquisition (reduces mutex pressure)
-    if !output_diff_individual && !diffs.is_empty() {
+    if !output_diff_individual && diff_dir.is_none() && !diffs.is_empty() {
         diff_output.lock().unwrap().extend(diffs);
     }
 
+    // Abort before any commit if --strict-utf8 rejected a file
+    check_strict_utf8(sort_config.strict_utf8);
+
     // Warn about CRLF files
     let crlf_count = CRLF_FILES.swap(0, std::sync::atomic::Ordering::Relaxed);
     if crlf_count > 0 && !quiet {
-        if no_color {
+        if fop::colors_off(no_color) {
             println!("Warning: {} file(s) contain Windows line endings (CRLF), converting to Unix (LF).", crlf_count);
             println!("  Tip: Set 'git config core.autocrlf input' or add '* text eol=lf' to .gitattributes");
         } else {
@@ -1367,16 +3020,8 @@ fn process_location(
         }
     }
 
-    // Delete backup and temp files (sequential, usually few files)
-    for entry in &entries {
-        let path = entry.path();
-        if entry_is_file(entry) {
-            let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
-            if extension == "orig" || extension == "temp" {
-                let _ = fs::remove_file(path);
-            }
-        }
-    }
+    // Delete leftover temp files (only those this run created)
+    fop_sort::cleanup_temp_files();
 
     // Add timestamps to specified files (after sorting, before checksum)
     if !add_timestamp.is_empty() {
@@ -1392,7 +3037,9 @@ fn process_location(
                     || add_timestamp.iter().any(|f| path.ends_with(f.as_str()))
                 {
                     let is_localhost = is_localhost_file(path, localhost, localhost_files);
-                    let _ = fop_datestamp::add_timestamp(path, is_localhost, quiet, no_color);
+                    if let Err(e) = fop_datestamp::add_timestamp(path, is_localhost, quiet, no_color, sort_config) {
+                        eprintln!("Error adding timestamp to {}: {}", path.display(), e);
+                    }
                 }
             }
         }
@@ -1412,7 +3059,7 @@ fn process_location(
                     || add_checksum.iter().any(|f| path.ends_with(f.as_str()))
                 {
                     let is_localhost = is_localhost_file(path, localhost, localhost_files);
-                    match fop_checksum::add_checksum(path, is_localhost, quiet, no_color) {
+                    match fop_checksum::add_checksum(path, is_localhost, quiet, no_color, sort_config) {
                         Ok(Some(_checksum)) => {
                             // File was modified, checksum written successfully
                         }
@@ -1440,7 +3087,7 @@ fn process_location(
                 if validate_checksum_and_fix.iter().any(|f| filename == f.as_str())
                     || validate_checksum_and_fix.iter().any(|f| path.ends_with(f.as_str()))
                 {
-                    match fop_checksum::verify_checksum(path) {
+                    match fop_checksum::verify_checksum(path, sort_config) {
                         Ok(fop_checksum::ChecksumResult::Valid) => {
                             if !quiet {
                                 println!("Checksum OK: {}", path.display());
@@ -1451,8 +3098,11 @@ fn process_location(
                                 eprintln!("Checksum INVALID: {} (expected {}, found {}) - fixing...",
                                     path.display(), expected, found);
                             }
+                            if verify_verbose {
+                                print_checksum_details(path, sort_config);
+                            }
                             let is_localhost = is_localhost_file(path, localhost, localhost_files);
-                            if let Err(e) = fop_checksum::add_checksum(path, is_localhost, quiet, no_color) {
+                            if let Err(e) = fop_checksum::add_checksum(path, is_localhost, quiet, no_color, sort_config) {
                                 eprintln!("Error fixing checksum for {}: {}", path.display(), e);
                             }
                         }
@@ -1461,7 +3111,7 @@ fn process_location(
                                 eprintln!("Checksum MISSING: {} - adding...", path.display());
                             }
                             let is_localhost = is_localhost_file(path, localhost, localhost_files);
-                            if let Err(e) = fop_checksum::add_checksum(path, is_localhost, quiet, no_color) {
+                            if let Err(e) = fop_checksum::add_checksum(path, is_localhost, quiet, no_color, sort_config) {
                                 eprintln!("Error adding checksum for {}: {}", path.display(), e);
                             }
                         }
@@ -1474,6 +3124,31 @@ fn process_location(
         }
     }
 
+    // --post-hook: after timestamps and checksums, so the hook sees each file as written
+    if let Some(command) = post_hook.filter(|_| !sort_config.dry_run) {
+        let written: Vec<PathBuf> = processed
+            .iter()
+            .map(|path| {
+                let mirrored = output_dir.map(|dir| dir.join(path.strip_prefix(location).unwrap_or(path)));
+                with_output_suffix(mirrored, path, suffix_output).unwrap_or_else(|| path.to_path_buf())
+            })
+            .filter(|path| path.is_file())
+            .collect();
+        let mut files: Vec<&Path> = written.iter().map(PathBuf::as_path).collect();
+        files.sort_unstable();
+        run_post_hook(command, &files, location, post_hook_fail);
+    }
+
+    // --cache: remember processed lists as they are on disk now. Dry runs, --output-dir and
+    // --suffix-output leave the sources unsorted, so nothing is recorded for them.
+    if let Some(cache) = cache {
+        if !sort_config.dry_run && output_dir.is_none() && suffix_output.is_none() {
+            for path in processed {
+                cache.record(path);
+            }
+        }
+    }
+
     // Offer to commit changes (skip if no_commit mode)
     if !no_commit {
         if let (Some(repo), Some(base_cmd)) = (repository, base_cmd) {
@@ -1482,6 +3157,21 @@ fn process_location(
                 return Ok(());
             }
 
+            // --commit-pathspec: only the files sorted in this run that match the glob are
+            // shown and committed; other uncommitted changes are left alone
+            let commit_paths: Vec<String> = match commit_pathspec {
+                Some(pathspec) => {
+                    let matched = fop_git::sorted_files_matching(&sorted_files, location, pathspec);
+                    if matched.is_empty() {
+                        flush_warnings();
+                        eprintln!("Error: no file sorted in this run matches --commit-pathspec={}", pathspec.glob());
+                        std::process::exit(1);
+                    }
+                    matched
+                }
+                None => Vec::new(),
+            };
+
             // Check for typos in added lines
             // Get added lines once for both typo and banned domain checks
             let additions = if fix_typos_on_add || banned_domains.as_ref().is_some_and(|b| !b.is_empty()) {
@@ -1551,7 +3241,7 @@ fn process_location(
                     if !quiet {
                         println!("Direct push authorized for user.");
                     }
-                    commit_changes(repo, &base_cmd, original_difference, no_msg_check, no_color, no_large_warning, quiet, limited_quiet, rebase_on_fail, git_message, history)?;
+                    commit_changes(repo, &base_cmd, original_difference, no_msg_check, no_color, no_large_warning, quiet, limited_quiet, rebase_on_fail, push_retries, git_message, history, &commit_paths)?;
                 } else {
                 // Use provided title or prompt
                 let message = if !pr_title.is_empty() {
@@ -1581,7 +3271,7 @@ fn process_location(
                     return Ok(());
                 }
                 
-                create_pull_request(repo, &base_cmd, &message, &remote, &base_branch, quiet, pr_show_changes, no_color)?;
+                create_pull_request(repo, &base_cmd, &message, &remote, &base_branch, quiet, pr_show_changes, pr_body, pr_labels, no_color, &commit_paths)?;
                 }
             } else {
 
@@ -1600,8 +3290,10 @@ fn process_location(
                     quiet,
                     limited_quiet,
                     rebase_on_fail,
+                    push_retries,
                     git_message,
                     history,
+                    &commit_paths,
                 )?;
             }
         }
@@ -1619,7 +3311,7 @@ fn print_greeting(no_commit: bool, no_color: bool, config_path: Option<&str>, ba
     let config_line = config_path.map(|p| format!("Using config file: {}", p));
     let banned_line = banned_info.map(|(count, file)| format!("Loaded {} banned domains from {}", count, file));
 
-    if no_color {
+    if fop::colors_off(no_color) {
         let separator = "=".repeat(version_line.len());
         println!("{}", separator);
         println!("{}", version_line);
@@ -1670,17 +3362,83 @@ fn main() {
         return;
     }
 
+    if args.dump_config_template {
+        print!("{}", config_template());
+        return;
+    }
+
     if args.show_config {
         args.print_config(config_path.as_deref());
         return;
     }
 
+    // Color only when stdout is a terminal, so redirected logs carry no escape codes
+    if !io::stdout().is_terminal() {
+        args.no_color = true;
+    }
+    fop::set_no_color(args.no_color);
+
+    // Typos in .fopconfig would otherwise be silently ignored
+    for message in &args.config_warnings {
+        eprintln!("Warning: {}", message);
+    }
+
     // Benchmark mode: force dry-run, no-commit, quiet
     if args.benchmark {
         args.no_commit = true;
         args.quiet = true;
     }
 
+    if let Some(ref dir) = args.diff_dir {
+        if let Err(e) = fs::create_dir_all(dir) {
+            eprintln!("Error: cannot create --diff-dir {}: {}", dir.display(), e);
+            std::process::exit(1);
+        }
+    }
+
+    // Output tree: sources stay untouched, so nothing to commit or stamp in place
+    if let Some(ref dir) = args.output_dir {
+        if let Err(e) = fs::create_dir_all(dir) {
+            eprintln!("Error: cannot create --output-dir {}: {}", dir.display(), e);
+            std::process::exit(1);
+        }
+        args.no_commit = true;
+        args.add_checksum.clear();
+        args.add_timestamp.clear();
+        args.validate_checksum_and_fix.clear();
+    }
+
+    // Suffixed copies: sources stay untouched, as with --output-dir
+    if args.suffix_output.is_some() {
+        args.no_commit = true;
+        args.add_checksum.clear();
+        args.add_timestamp.clear();
+        args.validate_checksum_and_fix.clear();
+    }
+
+    // Tidy report and rules diff: dry runs with no commits or header rewrites
+    if args.report_tidy || args.rules_diff_only {
+        args.no_commit = true;
+        args.add_checksum.clear();
+        args.add_timestamp.clear();
+        args.validate_checksum_and_fix.clear();
+    }
+
+    // Custom typo patterns apply to every typo check, so load them before any processing
+    if let Some(ref path) = args.typo_rules {
+        match fop_typos::load_typo_rules(path) {
+            Ok(count) => {
+                if !args.quiet {
+                    println!("Loaded {} custom typo rule(s) from {}", count, path.display());
+                }
+            }
+            Err(e) => {
+                eprintln!("Error: --typo-rules {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        }
+    }
+
     // Load banned list early so we can show count in greeting
     let banned_domains_early = args.check_banned_list.as_ref().and_then(|list_path| {
         match fop_sort::load_banned_list(list_path) {
@@ -1694,12 +3452,14 @@ fn main() {
     let banned_info = banned_domains_early.as_ref()
         .map(|set| (set.len(), args.check_banned_list.as_ref().unwrap().to_string_lossy().to_string()));
 
-    if !args.quiet {
+    if !args.quiet && args.explain.is_none() {
         print_greeting(args.no_commit, args.no_color, config_path.as_deref(),
             banned_info.as_ref().map(|(count, path)| (*count, path.as_str())));
     }
 
-    // Set warning output path
+    // Set warning format and output path
+    fop::set_warning_format(args.warning_format);
+    fop::set_max_warnings_per_file(args.max_warnings_per_file);
     if let Some(ref path) = args.warning_output {
         *WARNING_OUTPUT.lock().unwrap() = Some(path.clone());
         WARNING_TO_FILE.store(true, std::sync::atomic::Ordering::Relaxed);
@@ -1720,11 +3480,22 @@ fn main() {
         None
     };
 
+    // Interactive review replaces the automatic typo fixes applied while sorting
+    let interactive_typos = args.fix_typos && args.interactive_typos && io::stdin().is_terminal();
+    if args.fix_typos && args.interactive_typos && !interactive_typos {
+        eprintln!("Warning: --interactive-typos needs a terminal on stdin, applying typo fixes without prompting");
+    }
+
     // Build sort config
     let sort_config = SortConfig {
         convert_ubo: !args.no_ubo_convert,
         no_sort: args.no_sort,
+        dedupe_only: args.dedupe_only,
+        normalize_only: args.normalize_only,
         alt_sort: args.alt_sort,
+        sort_key: args.sort_key,
+        group_exceptions: args.group_exceptions,
+        exceptions_first: args.exceptions_first,
         parse_adguard: args.parse_adguard,
         localhost: args.localhost,
         comment_chars: &args.comment_chars,
@@ -1733,15 +3504,63 @@ fn main() {
         ignore_dot_domains: args.ignore_dot_domains,
         abp_convert: args.abp_convert,
         convert_trusted: args.convert_trusted,
-        fix_typos: args.fix_typos,
+        fix_typos: args.fix_typos && !interactive_typos,
         quiet: args.quiet,
         no_color: args.no_color,
-        dry_run: args.output_diff.is_some() || args.output_diff_individual || args.output_changed || args.benchmark,
+        dry_run: args.output_diff.is_some() || args.output_diff_individual || args.diff_dir.is_some()
+            || args.output_changed || args.benchmark || args.report_tidy || args.rules_diff_only,
         output_changed: args.output_changed,
         add_timestamp: !args.add_timestamp.is_empty(),
+        timestamp_format: args.timestamp_format.as_deref(),
+        normalize_quotes: args.normalize_quotes,
+        print_sections: args.print_sections,
+        normalize_csp: args.normalize_csp,
+        normalize_redirects: args.normalize_redirects,
+        warn_broad_options: args.warn_broad_options,
+        sort_locale: args.sort_locale,
+        dialect: args.dialect,
+        normalize_anchor: args.normalize_anchor,
+        diff_context: args.diff_context,
+        warn_combine: args.warn_combine,
+        max_line_length: args.max_line_length,
+        rules_per_file_limit: args.rules_per_file_limit,
+        drop_long_lines: args.drop_long_lines,
+        warn_generic_cosmetic: args.warn_generic_cosmetic,
+        normalize_header: args.normalize_header,
+        sort_header: args.sort_header,
+        quarantine_invalid: args.quarantine_invalid.is_some(),
+        generic_cosmetic_allow: &args.generic_cosmetic_allow,
+        warn_tld_cosmetic: args.warn_tld_cosmetic,
+        warn_js_rules: args.warn_js_rules,
+        warn_hide_interactions: args.warn_hide_interactions,
+        detect_confusables: args.detect_confusables,
+        strict_utf8: args.strict_utf8,
+        rule_count: args.rule_count,
+        temp_dir: args.temp_dir.as_deref(),
+        keep_bom: args.keep_bom,
+        input_encoding: args.input_encoding,
+        output_encoding: args.output_encoding,
+        verbosity: args.verbosity,
+        no_selector_tidy: args.no_selector_tidy,
+        extended_markers: &args.extended_markers,
+        report_tidy: args.report_tidy,
+        rules_diff_only: args.rules_diff_only,
+        detect_cross_file_dupes: args.detect_cross_file_dupes,
+        warn_hosts_abp_overlap: args.warn_hosts_abp_overlap,
+        output_path: None,
+        copy_unchanged: args.copy_unchanged,
+        hosts_blackhole: &args.hosts_blackhole,
+        allow_dotless_domains: &args.allow_dotless_domains,
     };
 
+    if let Some(ref rule) = args.explain {
+        print_explanation(&fop_sort::explain_rule(rule, &sort_config), args.no_color);
+        flush_warnings();
+        return;
+    }
+
     let diff_output: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(Vec::new());
+    let file_times: FileTimes = std::sync::Mutex::new(Vec::new());
 
     // Build list of locations to process
     let locations: Vec<PathBuf> = if args.directories.is_empty() {
@@ -1802,9 +3621,9 @@ fn main() {
         let mut any_failed = false;
 
         for location in &locations {
-            for entry in WalkDir::new(location)
+            for entry in walk_location(location, args.follow_symlinks)
                 .into_iter()
-                .filter_map(|e| e.ok())
+                .filter_map(walk_entry)
                 .filter(|e| e.path().is_file())
             {
                 let path = entry.path();
@@ -1813,7 +3632,7 @@ fn main() {
                 if args.validate_checksum.iter().any(|f| filename == f.as_str())
                     || args.validate_checksum.iter().any(|f| path.ends_with(f.as_str()))
                 {
-                    match fop_checksum::verify_checksum(path) {
+                    match fop_checksum::verify_checksum(path, &sort_config) {
                         Ok(fop_checksum::ChecksumResult::Valid) => {
                             if !args.quiet {
                                 println!("Checksum OK: {}", path.display());
@@ -1821,6 +3640,9 @@ fn main() {
                         }
                         Ok(fop_checksum::ChecksumResult::Invalid { expected, found }) => {
                             eprintln!("Checksum FAILED: {} (expected {}, found {})", path.display(), expected, found);
+                            if args.verify_verbose {
+                                print_checksum_details(path, &sort_config);
+                            }
                             any_failed = true;
                         }
                         Ok(fop_checksum::ChecksumResult::Missing) => {
@@ -1846,9 +3668,12 @@ fn main() {
     if args.fix_typos {
         let total_typos = AtomicUsize::new(0);
         let files_with_typos = AtomicUsize::new(0);
+        let mut prompt_state = TypoPrompt::Ask;
+        let mut stdin = io::stdin().lock();
 
         for location in &locations {
-            let entries: Vec<_> = WalkDir::new(location)
+            let mut seen = HashSet::new();
+            let entries: Vec<_> = walk_location(location, args.follow_symlinks)
                 .into_iter()
                 .filter_entry(|e| {
                     let name = e.file_name().to_string_lossy();
@@ -1856,67 +3681,41 @@ fn main() {
                         && (args.disable_ignored || !IGNORE_DIRS.contains(&name.as_ref()))
                         && !should_ignore_dir(e.path(), &args.ignore_dirs)
                 })
-                .filter_map(|e| e.ok())
+                .filter_map(walk_entry)
                 .filter(|e| {
                     if !e.path().is_file() {
                         return false;
                     }
-                    let ext = e
-                        .path()
-                        .extension()
-                        .and_then(|ext| ext.to_str())
-                        .unwrap_or("");
+                    let ext = fop_sort::list_extension(e.path());
                     let filename = e.path().file_name().and_then(|n| n.to_str()).unwrap_or("");
                     args.file_extensions.iter().any(|fe| fe == ext)
                         && !should_ignore_file(filename, &args.ignore_files)
+                        && (!args.follow_symlinks || first_visit(&mut seen, e.path()))
                 })
                 .collect();
 
-            entries.par_iter().for_each(|entry| {
-                let path = entry.path();
-                if let Ok(content) = fs::read_to_string(path) {
-                    // Skip files without cosmetic rules
-                    if !content.contains('#') {
-                        return;
-                    }
-
-                    let mut file_modified = false;
-                    let mut file_typo_count = 0;
-                    let mut new_lines: Vec<String> = Vec::new();
-
-                    for (line_num, line) in content.lines().enumerate() {
-                        let (fixed, fixes) = fop_typos::fix_all_typos(line);
-                        if !fixes.is_empty() {
-                            file_typo_count += 1;
-                            file_modified = true;
-                            if !args.quiet {
-                                let _ = writeln!(
-                                    std::io::stdout().lock(),
-                                    "{}:{}: {} ? {} ({})",
-                                    path.display(),
-                                    line_num + 1,
-                                    line,
-                                    fixed,
-                                    fixes.join(", ")
-                                );
-                            }
-                            new_lines.push(fixed);
-                        } else {
-                            new_lines.push(line.to_string());
-                        }
+            let write = args.output_diff.is_none();
+            if interactive_typos {
+                // Prompts need a stable order, so files are reviewed one at a time
+                for entry in &entries {
+                    if prompt_state == TypoPrompt::Quit {
+                        break;
                     }
-
-                    if file_modified {
-                        if args.output_diff.is_none() {
-                            if let Err(e) = fs::write(path, new_lines.join("\n") + "\n") {
-                                eprintln!("Error writing {}: {}", path.display(), e);
-                            }
-                        }
-                        total_typos.fetch_add(file_typo_count, Ordering::Relaxed);
+                    let fixed = fix_file_typos(entry.path(), args.quiet, write, Some((&mut prompt_state, &mut stdin)));
+                    if fixed > 0 {
+                        total_typos.fetch_add(fixed, Ordering::Relaxed);
                         files_with_typos.fetch_add(1, Ordering::Relaxed);
                     }
                 }
-            });
+            } else {
+                entries.par_iter().for_each(|entry| {
+                    let fixed = fix_file_typos(entry.path(), args.quiet, write, None);
+                    if fixed > 0 {
+                        total_typos.fetch_add(fixed, Ordering::Relaxed);
+                        files_with_typos.fetch_add(1, Ordering::Relaxed);
+                    }
+                });
+            }
         }
 
         if !args.quiet {
@@ -1930,6 +3729,55 @@ fn main() {
         }
     }
 
+    if let Some(ref dir) = args.temp_dir {
+        if !dir.is_dir() {
+            eprintln!("Error: --temp-dir {} does not exist or is not a folder", dir.display());
+            std::process::exit(1);
+        }
+    }
+
+    let file_globs = match FileGlobs::new(&args.exclude_glob, &args.include_glob) {
+        Ok(globs) => globs,
+        Err(e) => {
+            eprintln!("Error: invalid glob pattern: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let commit_pathspec = match args.commit_pathspec.as_deref().map(globset::Glob::new).transpose() {
+        Ok(glob) => glob.map(|g| g.compile_matcher()),
+        Err(e) => {
+            eprintln!("Error: invalid --commit-pathspec: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // Read-only rule tallies, no sorting or writing
+    if args.count_only {
+        let files: Vec<PathBuf> = match args.check_file {
+            Some(ref file_path) => vec![file_path.clone()],
+            None => locations.iter().flat_map(|location| list_filter_files(location, &args, &file_globs)).collect(),
+        };
+        print_rule_counts(&files, &args, &sort_config);
+        return;
+    }
+
+    // Preview: in-memory sort comparison and the git step that would follow, nothing written
+    if args.plan {
+        if let Some(ref file_path) = args.check_file {
+            let changed = planned_changes(vec![file_path.clone()], &args, &sort_config);
+            let parent = file_path.parent().unwrap_or(Path::new("."));
+            let action = planned_git_action(parent, &args, &sort_config, !changed.is_empty(), false);
+            print_plan(file_path, &changed, &action, args.no_color);
+        } else {
+            for location in &locations {
+                let changed = planned_changes(list_filter_files(location, &args, &file_globs), &args, &sort_config);
+                let action = planned_git_action(location, &args, &sort_config, !changed.is_empty(), true);
+                print_plan(location, &changed, &action, args.no_color);
+            }
+        }
+        return;
+    }
+
     // Process single file if --check-file specified
     if let Some(ref file_path) = args.check_file {
         if !file_path.is_file() {
@@ -1941,7 +3789,15 @@ fn main() {
             println!("Processing file: {}", file_path.display());
         }
 
+        let output_path = args.output_dir.as_ref().map(|dir| {
+            let cwd = std::env::current_dir().unwrap_or_default();
+            dir.join(file_path.strip_prefix(&cwd).ok()
+                .or_else(|| file_path.file_name().map(Path::new))
+                .unwrap_or(file_path))
+        });
+        let output_path = with_output_suffix(output_path, file_path, args.suffix_output.as_deref());
         let mut check_file_config = SortConfig {
+            output_path: output_path.as_deref(),
             localhost: is_localhost_file(file_path, sort_config.localhost, &args.localhost_files),
             parse_adguard: is_adguard_file(file_path, sort_config.parse_adguard, &args.parse_adguard_files),
             ..sort_config
@@ -1954,7 +3810,7 @@ fn main() {
 
         // Benchmark: count lines/bytes for the single file
         let (bench_lines, bench_bytes) = if args.benchmark {
-            if let Ok(content) = fs::read_to_string(file_path) {
+            if let Ok(content) = fop_sort::read_list_to_string(file_path) {
                 (content.lines().count(), content.len() as u64)
             } else {
                 (0, 0)
@@ -1973,22 +3829,40 @@ fn main() {
 
             let iter_start = std::time::Instant::now();
 
-            match fop_sort::fop_sort(file_path, &check_file_config) {
-                Ok(Some(diff)) => {
-                    if args.output_diff_individual {
-                        let diff_path = file_path.with_extension("diff");
-                        if let Err(e) = fs::write(&diff_path, &diff) {
-                            eprintln!("Error writing diff file: {}", e);
-                        } else if !args.quiet {
-                            println!("Diff written to: {}", diff_path.display());
+            let result = match args.since.as_deref().filter(|_| args.rules_diff_only) {
+                Some(git_ref) => rules_diff_since(file_path, git_ref, args.git_binary.as_deref(), &check_file_config),
+                None => fop_sort::fop_sort(file_path, &check_file_config),
+            };
+            match result {
+                Ok(outcome) => {
+                    if outcome.sorted && !args.quiet && args.verbosity == Verbosity::Normal {
+                        if let Err(e) = fop_sort::print_sorted(file_path, args.no_color) {
+                            eprintln!("Error writing output: {}", e);
+                        }
+                    }
+                    if let Some(diff) = outcome.diff {
+                        if let Some(ref dir) = args.diff_dir {
+                            let cwd = std::env::current_dir().unwrap_or_default();
+                            let rel = file_path.strip_prefix(&cwd).ok()
+                                .or_else(|| file_path.file_name().map(Path::new))
+                                .unwrap_or(file_path);
+                            write_diff_patch(dir, rel, &diff, args.quiet);
+                        } else if args.output_diff_individual {
+                            let diff_path = file_path.with_extension("diff");
+                            if let Err(e) = fs::write(&diff_path, &diff) {
+                                eprintln!("Error writing diff file: {}", e);
+                            } else if !args.quiet {
+                                println!("Diff written to: {}", diff_path.display());
+                            }
+                        } else {
+                            diff_output.lock().unwrap().push(diff);
                         }
-                    } else {
-                        diff_output.lock().unwrap().push(diff);
                     }
                 }
-                Ok(None) => {}
-                Err(e) => eprintln!("Error processing {}: {}", file_path.display(), e),
+                Err(e) => FAIL_FAST.record(args.fail_fast, file_path, &e),
             }
+            check_fail_fast();
+            check_strict_utf8(args.strict_utf8);
 
             let elapsed = iter_start.elapsed();
             if args.benchmark {
@@ -1996,6 +3870,8 @@ fn main() {
             }
         }
 
+        fop_sort::cleanup_temp_files();
+
         // Print benchmark results for --check-file
         if args.benchmark {
             let min = bench_times.iter().min().unwrap();
@@ -2034,7 +3910,18 @@ fn main() {
                     || args.add_checksum.iter().any(|f| file_path.ends_with(f.as_str()))
                 {
                 let is_localhost = is_localhost_file(file_path, args.localhost, &args.localhost_files);
-                let _ = fop_checksum::add_checksum(file_path, is_localhost, args.quiet, args.no_color);
+                if let Err(e) = fop_checksum::add_checksum(file_path, is_localhost, args.quiet, args.no_color, &sort_config) {
+                    eprintln!("Error adding checksum to {}: {}", file_path.display(), e);
+                }
+            }
+        }
+
+        // Run --post-hook on the file as written
+        if let Some(ref command) = args.post_hook {
+            if !args.benchmark && !sort_config.dry_run {
+                let written = output_path.as_deref().unwrap_or(file_path);
+                let dir = file_path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+                run_post_hook(command, &[written], dir, args.post_hook_fail);
             }
         }
 
@@ -2062,8 +3949,10 @@ fn main() {
                     args.quiet,
                     args.limited_quiet,
                     args.rebase_on_fail,
+                    args.push_retries,
                     &args.git_message,
                     &args.history,
+                    &[],
                 ) {
                     eprintln!("Git error: {}", e);
                 }
@@ -2076,7 +3965,20 @@ fn main() {
             if let Err(e) = fs::write(diff_path, diffs.join("\n")) {
                 eprintln!("Error writing diff file: {}", e);
             }
+        } else if args.rules_diff_only {
+            print_rules_diffs(&diff_output.lock().unwrap());
+        }
+        if args.report_tidy {
+            fop_sort::print_tidy_report(args.no_color);
+        }
+        if args.detect_cross_file_dupes {
+            fop_sort::print_cross_file_duplicates(args.no_color);
+        }
+        if args.warn_hosts_abp_overlap {
+            fop_sort::print_hosts_abp_overlap(args.no_color);
         }
+        write_quarantine_file(args.quarantine_invalid.as_deref(), args.quiet);
+        check_warning_limit(args.fail_on_warning_count, args.warning_output.as_deref());
         return;
     }
 
@@ -2092,27 +3994,9 @@ fn main() {
         let mut lines = 0usize;
         let mut bytes = 0u64;
         for location in &locations {
-            for entry in WalkDir::new(location)
-                .into_iter()
-                .filter_entry(|e| {
-                    let name = e.file_name().to_string_lossy();
-                    !name.starts_with('.')
-                        && (args.disable_ignored || !IGNORE_DIRS.contains(&name.as_ref()))
-                        && !should_ignore_dir(e.path(), &args.ignore_dirs)
-                })
-                .filter_map(|e| e.ok())
-            {
-                if !entry_is_file(&entry) { continue; }
-                let path = entry.path();
-                let filename = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
-                let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
-                if !args.file_extensions.iter().any(|ext| ext == extension) { continue; }
-                if !args.disable_ignored && IGNORE_FILES.contains(&filename) { continue; }
-                if should_ignore_file(filename, &args.ignore_files) { continue; }
-                if !args.ignore_all_but.is_empty()
-                    && !args.ignore_all_but.iter().any(|f| filename.contains(f)) { continue; }
+            for path in list_filter_files(location, &args, &file_globs) {
                 files += 1;
-                if let Ok(content) = fs::read_to_string(path) {
+                if let Ok(content) = fop_sort::read_list_to_string(&path) {
                     lines += content.lines().count();
                     bytes += content.len() as u64;
                 }
@@ -2126,10 +4010,70 @@ fn main() {
     let bench_iterations = if args.benchmark { 3 } else { 1 };
     let mut bench_times: Vec<std::time::Duration> = Vec::with_capacity(bench_iterations);
 
+    // Benchmarks always sort every file
+    let mut file_cache = args.cache.as_deref().filter(|_| !args.benchmark)
+        .map(|path| fop_cache::FileCache::load(path, &cache_fingerprint(config_path.as_deref())));
+
+    let location_options = LocationOptions {
+        no_commit: args.no_commit,
+        no_msg_check: args.no_msg_check,
+        disable_ignored: args.disable_ignored,
+        no_color: args.no_color,
+        no_large_warning: args.no_large_warning,
+        ignore_files: &args.ignore_files,
+        ignore_dirs: &args.ignore_dirs,
+        ignore_all_but: &args.ignore_all_but,
+        file_globs: &file_globs,
+        file_extensions: &args.file_extensions,
+        sort_config: &sort_config,
+        create_pr: &args.create_pr,
+        git_pr_branch: &args.git_pr_branch,
+        pr_show_changes: args.pr_show_changes,
+        pr_body: args.pr_body.as_deref(),
+        pr_labels: &args.pr_labels,
+        banned_domains: &banned_domains,
+        auto_banned_remove: args.auto_banned_remove,
+        direct_push_users: &args.direct_push_users,
+        banned_list_file: args.check_banned_list.as_ref().and_then(|p| p.file_name()).and_then(|n| n.to_str()),
+        fix_typos: args.fix_typos && !interactive_typos,
+        fix_typos_on_add: args.fix_typos_on_add,
+        auto_fix: args.auto_fix,
+        only_sort_changed: args.only_sort_changed,
+        since: args.since.as_deref(),
+        rebase_on_fail: args.rebase_on_fail,
+        push_retries: args.push_retries,
+        ci: args.ci,
+        quiet: args.quiet,
+        limited_quiet: args.limited_quiet,
+        output_diff_individual: args.output_diff_individual,
+        diff_dir: args.diff_dir.as_deref(),
+        output_dir: args.output_dir.as_deref(),
+        suffix_output: args.suffix_output.as_deref(),
+        git_message: &args.git_message,
+        history: &args.history,
+        git_binary: args.git_binary.as_deref(),
+        add_checksum: &args.add_checksum,
+        validate_checksum_and_fix: &args.validate_checksum_and_fix,
+        verify_verbose: args.verify_verbose,
+        add_timestamp: &args.add_timestamp,
+        localhost: args.localhost,
+        localhost_files: &args.localhost_files,
+        parse_adguard_files: &args.parse_adguard_files,
+        file_overrides: &args.file_overrides,
+        fail_fast: args.fail_fast,
+        commit_pathspec: commit_pathspec.as_ref(),
+        follow_symlinks: args.follow_symlinks,
+        print_ignored: args.print_ignored(),
+        require_clean_tree: args.require_clean_tree,
+        post_hook: args.post_hook.as_deref(),
+        post_hook_fail: args.post_hook_fail,
+    };
+
     for iteration in 0..bench_iterations {
         if args.benchmark && iteration > 0 {
             // Clear diff output between iterations
             diff_output.lock().unwrap().clear();
+            fop_sort::clear_blocked_hosts();
         }
 
         let iter_start = std::time::Instant::now();
@@ -2138,43 +4082,10 @@ fn main() {
         for (i, location) in locations.iter().enumerate() {
             if let Err(e) = process_location(
                 location,
-                args.no_commit,
-                args.no_msg_check,
-                args.disable_ignored,
-                args.no_color,
-                args.no_large_warning,
-                &args.ignore_files,
-                &args.ignore_dirs,
-                &args.ignore_all_but,
-                &args.file_extensions,
-                &sort_config,
-                &args.create_pr,
-                &args.git_pr_branch,
-                args.pr_show_changes,
-                &banned_domains,
-                args.auto_banned_remove,
-                &args.direct_push_users,
-                args.check_banned_list.as_ref().and_then(|p| p.file_name()).and_then(|n| n.to_str()),
-                args.fix_typos,
-                args.fix_typos_on_add,
-                args.auto_fix,
-                args.only_sort_changed,
-                args.rebase_on_fail,
-                args.ci,
-                args.quiet,
-                args.limited_quiet,
-                args.output_diff_individual,
+                &location_options,
                 &diff_output,
-                &args.git_message,
-                &args.history,
-                args.git_binary.as_deref(),
-                &args.add_checksum,
-                &args.validate_checksum_and_fix,
-                &args.add_timestamp,
-                args.localhost,
-                &args.localhost_files,
-                &args.parse_adguard_files,
-                &args.file_overrides,
+                args.time_per_file.is_some().then_some(&file_times),
+                file_cache.as_mut(),
             ) {
                 eprintln!("Error: {}", e);
             }
@@ -2190,6 +4101,17 @@ fn main() {
         }
     }
 
+    // Hosts files and ABP lists are often in different locations
+    if args.warn_hosts_abp_overlap {
+        fop_sort::print_hosts_abp_overlap(args.no_color);
+    }
+
+    if let (Some(cache), Some(path)) = (&file_cache, &args.cache) {
+        if let Err(e) = cache.save(path) {
+            eprintln!("Error writing --cache {}: {}", path.display(), e);
+        }
+    }
+
     // Print benchmark results
     if args.benchmark {
         let min = bench_times.iter().min().unwrap();
@@ -2236,8 +4158,23 @@ fn main() {
         } else if !args.quiet && !diffs.is_empty() {
             println!("Diff written to: {}", diff_path.display());
         }
+    } else if args.rules_diff_only {
+        print_rules_diffs(&diff_output.lock().unwrap());
+    }
+
+    if args.report_tidy {
+        fop_sort::print_tidy_report(args.no_color);
+    }
+
+    if let Some(limit) = args.time_per_file {
+        if !args.quiet {
+            print_slowest_files(file_times.into_inner().unwrap(), limit, args.no_color);
+        }
     }
 
+    write_quarantine_file(args.quarantine_invalid.as_deref(), args.quiet);
+
     // Flush any buffered warnings to file
     flush_warnings();
+    check_warning_limit(args.fail_on_warning_count, args.warning_output.as_deref());
 }
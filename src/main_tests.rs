@@ -0,0 +1,387 @@
+//! FOP CLI Tests
+//! Tests for helpers that live in main.rs (the binary crate)
+//!
+//! Copyright (C) 2025 FanboyNZ (FOP Rust)
+//! https://github.com/ryanbr/fop-rs
+//!
+//! Copyright (C) 2011 Michael (original Python version)
+//! Rust port maintains GPL-3.0 license compatibility.
+
+use super::*;
+use crate::test_support::TestDir;
+
+// =============================================================================
+// Config Tests
+// =============================================================================
+
+#[test]
+fn test_closest_match() {
+    let keys = ["no-commit", "no-color", "backup", "localhost"];
+    assert_eq!(closest_match("no-comit", &keys), Some("no-commit"));
+    assert_eq!(closest_match("backpu", &keys), Some("backup"));
+    assert_eq!(closest_match("localhots", &keys), Some("localhost"));
+    assert_eq!(closest_match("something-else", &keys), None);
+    assert_eq!(edit_distance("kitten", "sitting"), 3);
+}
+
+#[test]
+fn test_config_env_cli_precedence() {
+    assert_eq!(env_var_name("max-line-length"), "FOP_MAX_LINE_LENGTH");
+    assert_eq!(env_var_name("git-binary"), "FOP_GIT_BINARY");
+
+    let dir = TestDir::new("env-precedence");
+    let config_file = dir.join(".fopconfig");
+    fs::write(&config_file, "max-line-length = 100\ndiff-context = 1\ngit-binary = file-git\n").unwrap();
+    let env_var = |name: &str| match name {
+        "FOP_DIFF_CONTEXT" => Some("2".to_string()),
+        "FOP_GIT_BINARY" => Some(" env-git ".to_string()),
+        _ => None,
+    };
+    let argv = vec![format!("--config-file={}", config_file.display()), "--git-binary=cli-git".to_string()];
+    let (args, _) = Args::parse_from(argv, &env_var);
+    // File only
+    assert_eq!(args.max_line_length, Some(100));
+    // Environment over file
+    assert_eq!(args.diff_context, Some(2));
+    assert_eq!(args.env_config_vars, vec!["FOP_DIFF_CONTEXT", "FOP_GIT_BINARY"]);
+    // Command line over both
+    assert_eq!(args.git_binary.as_deref(), Some("cli-git"));
+
+    let (args, _) = Args::parse_from(vec![format!("--config-file={}", config_file.display())], &env_var);
+    assert_eq!(args.git_binary.as_deref(), Some("env-git"));
+}
+
+#[test]
+fn test_config_profiles() {
+    let dir = TestDir::new("profiles");
+    let config_file = dir.join(".fopconfig");
+    fs::write(
+        &config_file,
+        "fix-typos = true\nfile-extensions = txt\n\n[profile:hosts]\nlocalhost = true\nfile-extensions = txt,hosts\n\n[profile:adguard]\nparse-adguard = true\n",
+    )
+    .unwrap();
+    let parse = |extra: &[&str]| {
+        let mut argv = vec![format!("--config-file={}", config_file.display())];
+        argv.extend(extra.iter().map(|arg| arg.to_string()));
+        Args::parse_from(argv, &|_| None).0
+    };
+
+    // Without a profile only the base settings apply
+    let args = parse(&[]);
+    assert!(args.fix_typos && !args.localhost && !args.parse_adguard);
+    assert_eq!(args.file_extensions, ["txt"]);
+
+    // A profile's keys are layered over the base keys, in either argument form
+    for extra in [&["--profile=hosts"][..], &["--profile", "hosts"]] {
+        let args = parse(extra);
+        assert_eq!(args.profile.as_deref(), Some("hosts"));
+        assert!(args.fix_typos && args.localhost && !args.parse_adguard);
+        assert_eq!(args.file_extensions, ["txt", "hosts"]);
+    }
+    // The last --profile wins, and the value isn't taken as a location
+    let args = parse(&["--profile=hosts", "--profile", "adguard"]);
+    assert!(args.parse_adguard && !args.localhost);
+    assert!(args.directories.is_empty());
+
+    let names = ["adguard".to_string(), "hosts".to_string()];
+    assert_eq!(unknown_profile_message("hosts", &names), None);
+    assert_eq!(
+        unknown_profile_message("host", &names).as_deref(),
+        Some("Unknown profile \"host\" (available: adguard, hosts)")
+    );
+    assert_eq!(
+        unknown_profile_message("hosts", &[]).as_deref(),
+        Some("Unknown profile \"hosts\" (no [profile:name] sections in the config file)")
+    );
+}
+
+#[test]
+fn test_invalid_timestamp_format_in_config() {
+    let dir = TestDir::new("timestamp-format");
+    let config_file = dir.join(".fopconfig");
+    fs::write(&config_file, "timestamp-format = %d.%Q.%Y\n").unwrap();
+    let argv = || vec![format!("--config-file={}", config_file.display())];
+    let (args, _) = Args::parse_from(argv(), &|_| None);
+    assert_eq!(args.timestamp_format, None);
+    assert_eq!(
+        args.config_warnings,
+        ["Invalid timestamp-format value \"%d.%Q.%Y\" (unknown token %Q; using the default layout)"]
+    );
+
+    // The same check covers FOP_TIMESTAMP_FORMAT, and valid patterns are kept
+    let (args, _) = Args::parse_from(argv(), &|name| (name == "FOP_TIMESTAMP_FORMAT").then(|| "%Q".to_string()));
+    assert_eq!(args.config_warnings.len(), 1);
+    fs::write(&config_file, "timestamp-format = %d.%m.%Y\n").unwrap();
+    let (args, _) = Args::parse_from(argv(), &|_| None);
+    assert_eq!(args.timestamp_format.as_deref(), Some("%d.%m.%Y"));
+    assert!(args.config_warnings.is_empty());
+}
+
+#[test]
+fn test_git_message_file() {
+    let dir = TestDir::new("message-file");
+    let path = dir.join("message.txt");
+
+    let missing = read_message_file(&path, true).unwrap_err();
+    assert!(missing.starts_with(&format!("Cannot read --git-message-file {}: ", path.display())), "{}", missing);
+
+    fs::write(&path, " \n\n").unwrap();
+    assert_eq!(read_message_file(&path, true), Err(format!("Invalid --git-message-file value: {} is empty", path.display())));
+
+    // Trailing newlines are dropped; the format is only checked without --no-msg-check
+    fs::write(&path, "M: Update filters\r\n").unwrap();
+    assert_eq!(read_message_file(&path, true).as_deref(), Ok("M: Update filters"));
+    fs::write(&path, "Update filters\n").unwrap();
+    assert_eq!(
+        read_message_file(&path, true).unwrap_err(),
+        "Invalid --git-message-file value: use an M:/A:/P: commit message"
+    );
+    assert_eq!(read_message_file(&path, false).as_deref(), Ok("Update filters"));
+
+    // The last of --git-message and --git-message-file wins
+    let file_arg = format!("--git-message-file={}", path.display());
+    let parse = |argv: &[&str]| {
+        let argv = ["--ignore-config"].iter().chain(argv).map(|arg| arg.to_string()).collect();
+        Args::parse_from(argv, &|_| None).0
+    };
+    let args = parse(&["--no-msg-check", &file_arg, "--git-message=M: cli"]);
+    assert_eq!(args.git_message.as_deref(), Some("M: cli"));
+    let args = parse(&["--git-message=M: cli", &file_arg, "--no-msg-check"]);
+    assert_eq!(args.git_message.as_deref(), Some("Update filters"));
+}
+
+#[test]
+fn test_verbosity_levels() {
+    let dir = TestDir::new("verbosity");
+    let config_file = dir.join(".fopconfig");
+    let parse = |config: &str, argv: &[&str]| {
+        fs::write(&config_file, config).unwrap();
+        let config_arg = format!("--config-file={}", config_file.display());
+        let argv = [config_arg.as_str()].iter().chain(argv).map(|arg| arg.to_string()).collect();
+        let (args, _) = Args::parse_from(argv, &|_| None);
+        (args.verbosity, args.print_ignored())
+    };
+    assert_eq!(parse("", &[]), (Verbosity::Normal, false));
+    assert_eq!(parse("", &["--print-ignored"]), (Verbosity::Normal, true));
+    // -v also lists skipped files
+    assert_eq!(parse("", &["-v"]), (Verbosity::Verbose, true));
+    assert_eq!(parse("", &["--verbose"]), (Verbosity::Verbose, true));
+    assert_eq!(parse("", &["-vv"]), (Verbosity::VeryVerbose, true));
+    // A later -v doesn't lower -vv
+    assert_eq!(parse("", &["-vv", "-v"]), (Verbosity::VeryVerbose, true));
+    assert_eq!(parse("verbose = true\n", &[]), (Verbosity::Verbose, true));
+    assert_eq!(parse("verbose = 2\n", &[]), (Verbosity::VeryVerbose, true));
+    assert_eq!(parse("verbose = 2\n", &["-v"]), (Verbosity::VeryVerbose, true));
+    assert_eq!(parse("verbose = false\n", &[]), (Verbosity::Normal, false));
+}
+
+// =============================================================================
+// Walk Tests
+// =============================================================================
+
+#[cfg(unix)]
+#[test]
+fn test_follow_symlinks_loop_and_duplicates() {
+    use std::os::unix::fs::symlink;
+    let dir = TestDir::new("follow-symlinks");
+    fs::create_dir_all(dir.join("lists")).unwrap();
+    fs::write(dir.join("lists/list.txt"), "||a.com^\n").unwrap();
+    // A second route to the same list, and a link back up to the walk root
+    symlink(dir.join("lists"), dir.join("mirror")).unwrap();
+    symlink(dir.path(), dir.join("lists/loop")).unwrap();
+
+    let entries: Vec<_> = walk_location(dir.path(), true).into_iter().collect();
+    assert_eq!(entries.iter().filter(|e| e.as_ref().is_err_and(|e| e.loop_ancestor().is_some())).count(), 2);
+
+    // Loops are dropped (with a warning) and the list is only visited once
+    let mut seen = HashSet::default();
+    let files: Vec<PathBuf> = entries
+        .into_iter()
+        .filter_map(walk_entry)
+        .filter(|e| entry_is_file(e) && first_visit(&mut seen, e.path()))
+        .map(|e| e.path().to_path_buf())
+        .collect();
+    assert_eq!(files.len(), 1);
+    assert!(files[0].ends_with("list.txt"));
+
+    // Without --follow-symlinks neither link is descended into
+    let files = walk_location(dir.path(), false).into_iter().filter_map(walk_entry).filter(entry_is_file).count();
+    assert_eq!(files, 1);
+}
+
+#[test]
+fn test_walk_skip_reasons() {
+    let ignore_dirs = ["build".to_string()];
+    let cases: &[(&str, bool, Option<&str>)] = &[
+        (".git", false, Some("name starts with '.'")),
+        (".git", true, Some("name starts with '.'")),
+        ("folders-to-ingore", false, Some("built-in ignored directory 'folders-to-ingore'")),
+        ("folders-to-ingore", true, None),
+        ("lists/build-old", false, Some("matched ignoredirs pattern 'build'")),
+        ("lists", false, None),
+    ];
+    for &(path, disable_ignored, expected) in cases {
+        let path = Path::new(path);
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap();
+        assert_eq!(walk_skip_reason(path, name, disable_ignored, &ignore_dirs).as_deref(), expected, "{}", path.display());
+    }
+}
+
+#[test]
+fn test_file_skip_reasons() {
+    let location = Path::new("root");
+    let extensions = ["txt".to_string()];
+    let ignore_files = [".bak".to_string()];
+    let only = ["easylist".to_string()];
+    let globs = FileGlobs::new(&["extra/*".to_string()], &["*.txt".to_string(), "*.txt.gz".to_string()]).unwrap();
+    let cases: &[(&str, bool, &[String], Option<&str>)] = &[
+        ("root/notes.md", false, &[], Some("extension 'md' not in file-extensions (txt)")),
+        ("root/list.txt.gz", false, &[], None),
+        ("root/test-files-to-ingore.txt", false, &[], Some("built-in ignored file")),
+        ("root/test-files-to-ingore.txt", true, &[], None),
+        ("root/list.bak.txt", false, &[], Some("matched ignorefiles pattern '.bak'")),
+        ("root/other.txt", false, &only, Some("not in ignore-all-but (easylist)")),
+        ("root/easylist.txt", false, &only, None),
+        ("root/extra/list.txt", false, &[], Some("matched --exclude-glob")),
+        ("root/lists/list.txt", false, &[], None),
+    ];
+    for &(path, disable_ignored, ignore_all_but, expected) in cases {
+        let path = Path::new(path);
+        let reason = file_skip_reason(path, location, &extensions, disable_ignored, &ignore_files, ignore_all_but, &globs);
+        assert_eq!(reason.as_deref(), expected, "{}", path.display());
+    }
+    let include = FileGlobs::new(&[], &["lists/*".to_string()]).unwrap();
+    let reason = file_skip_reason(Path::new("root/top.txt"), location, &extensions, false, &[], &[], &include);
+    assert_eq!(reason.as_deref(), Some("not matched by --include-glob"));
+}
+
+// =============================================================================
+// Fail-fast Tests
+// =============================================================================
+
+#[test]
+fn test_fail_fast_stops_after_first_error() {
+    let dir = TestDir::new("fail-fast");
+    let missing = dir.join("a.txt");
+    let path = dir.join("b.txt");
+    fs::write(&path, "||b.com^\n||a.com^\n").unwrap();
+    static COMMENTS: std::sync::LazyLock<Vec<String>> = std::sync::LazyLock::new(|| vec!["!".to_string()]);
+    let config = SortConfig { comment_chars: &COMMENTS, ..Default::default() };
+
+    // Without --fail-fast the run continues, and a non-UTF-8 list is only skipped
+    let state = FailFast::new();
+    assert!(state.run(false, &missing, || fop_sort(&missing, &config)).is_none());
+    let invalid = dir.join("latin1.txt");
+    fs::write(&invalid, b"||caf\xe9.com^\n||a.com^\n").unwrap();
+    assert!(!state.run(true, &invalid, || fop_sort(&invalid, &config)).unwrap().sorted);
+    assert!(!state.aborted());
+
+    // A list that can't be opened stops the run
+    let unreadable = dir.join("unreadable.txt");
+    fs::create_dir(&unreadable).unwrap();
+    assert!(state.run(true, &unreadable, || fop_sort(&unreadable, &config)).is_none());
+    assert!(state.aborted());
+    assert_eq!(state.cause.get().map(String::as_str), Some(format!("Cannot open {}", unreadable.display()).as_str()));
+
+    // So does any other read/write error, recording its file
+    let state = FailFast::new();
+    assert!(state.run(true, &missing, || fop_sort(&missing, &config)).is_none());
+    assert!(state.aborted());
+    assert!(state.cause.get().unwrap().starts_with(&format!("{}: ", missing.display())));
+
+    // Later files are skipped without being sorted
+    assert!(state.run(true, &path, || fop_sort(&path, &config)).is_none());
+    assert_eq!(fs::read_to_string(&path).unwrap(), "||b.com^\n||a.com^\n");
+}
+
+// =============================================================================
+// Output Tests
+// =============================================================================
+
+#[test]
+fn test_with_output_suffix() {
+    let source = Path::new("lists/easylist.txt");
+    // Without --suffix-output the --output-dir copy (or the source itself) is used as is
+    assert_eq!(with_output_suffix(None, source, None), None);
+    assert_eq!(with_output_suffix(Some(PathBuf::from("out/easylist.txt")), source, None), Some(PathBuf::from("out/easylist.txt")));
+
+    let cases = [
+        ("lists/easylist.txt", None, "lists/easylist.txt.sorted"),
+        ("lists/README", None, "lists/README.sorted"),
+        ("lists/list.txt.gz", None, "lists/list.txt.gz.sorted"),
+        ("lists/easylist.txt", Some("out/easylist.txt"), "out/easylist.txt.sorted"),
+    ];
+    for (source, output_path, expected) in cases {
+        let output_path = output_path.map(PathBuf::from);
+        assert_eq!(with_output_suffix(output_path, Path::new(source), Some(".sorted")), Some(PathBuf::from(expected)));
+    }
+}
+
+#[test]
+fn test_write_diff_patch() {
+    let dir = TestDir::new("diff-dir");
+    let diff = "--- a//tmp/lists/sub/list.txt\n+++ b//tmp/lists/sub/list.txt\n@@ -1,2 +1,2 @@\n+||a.com^\n ||b.com^\n-||a.com^\n";
+    write_diff_patch(dir.path(), Path::new("sub/list.txt"), diff, true);
+
+    // Nested paths are flattened into the file name; headers are relative to the location
+    let patch = fs::read_to_string(dir.join("sub__list.txt.diff")).unwrap();
+    assert_eq!(
+        patch,
+        "--- a/sub/list.txt\n+++ b/sub/list.txt\n@@ -1,2 +1,2 @@\n+||a.com^\n ||b.com^\n-||a.com^\n"
+    );
+}
+
+// =============================================================================
+// Post-hook Tests
+// =============================================================================
+
+#[cfg(unix)]
+#[test]
+fn test_shell_quote() {
+    let cases = [
+        ("lists/easylist.txt", "'lists/easylist.txt'"),
+        ("my lists/easy list.txt", "'my lists/easy list.txt'"),
+        ("lists/it's.txt", "'lists/it'\\''s.txt'"),
+        ("lists/$HOME`id`.txt", "'lists/$HOME`id`.txt'"),
+    ];
+    for (path, quoted) in cases {
+        assert_eq!(shell_quote(Path::new(path)), quoted);
+        // The shell hands the hook the path unchanged
+        let output = std::process::Command::new("sh").arg("-c").arg(format!("printf %s {}", quoted)).output().unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), path);
+    }
+}
+
+// =============================================================================
+// Typo Tests
+// =============================================================================
+
+#[test]
+fn test_interactive_typo_prompts() {
+    // Unrecognised answers are asked again; y applies, n skips, end of input quits
+    let mut state = TypoPrompt::Ask;
+    let mut input = io::Cursor::new("maybe\ny\nn\n");
+    assert!(prompt_typo_fix(&mut state, &mut input, "list.txt:1", "###..ad", "##.ad", "typo"));
+    assert!(!prompt_typo_fix(&mut state, &mut input, "list.txt:2", "###..ad", "##.ad", "typo"));
+    assert_eq!(state, TypoPrompt::Ask);
+    assert!(!prompt_typo_fix(&mut state, &mut input, "list.txt:3", "###..ad", "##.ad", "typo"));
+    assert_eq!(state, TypoPrompt::Quit);
+
+    // a applies this fix and every later one without reading more answers
+    let dir = TestDir::new("typo-prompts");
+    let path = dir.join("list.txt");
+    fs::write(&path, "###..ad\ndomain,,,b#.ad\nexample.com###..x\n").unwrap();
+    let mut state = TypoPrompt::Ask;
+    let mut input = io::Cursor::new("n\na\n");
+    assert_eq!(fix_file_typos(&path, true, true, Some((&mut state, &mut input))), 2);
+    assert_eq!(state, TypoPrompt::AcceptAll);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "###..ad\ndomain,b##.ad\nexample.com##.x\n");
+
+    // q declines this fix and the rest
+    fs::write(&path, "###..ad\ndomain,,,b#.ad\nexample.com###..x\n").unwrap();
+    let mut state = TypoPrompt::Ask;
+    let mut input = io::Cursor::new("y\nq\ny\n");
+    assert_eq!(fix_file_typos(&path, true, true, Some((&mut state, &mut input))), 1);
+    assert_eq!(state, TypoPrompt::Quit);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "##.ad\ndomain,,,b#.ad\nexample.com###..x\n");
+}
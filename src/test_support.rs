@@ -0,0 +1,56 @@
+//! Helpers shared by the library tests (tests.rs) and the CLI tests (main_tests.rs)
+
+// Compiled into both crates; each uses only some of the helpers
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+
+/// A fresh `fop-test-{pid}-{name}` directory under the system temp dir, removed on drop
+pub struct TestDir(PathBuf);
+
+impl TestDir {
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("fop-test-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        TestDir(dir)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    pub fn join(&self, name: &str) -> PathBuf {
+        self.0.join(name)
+    }
+
+    /// Make this directory a git repository whose first commit holds `files` (name, content),
+    /// returning a runner for further git commands (true on success). None if git isn't installed.
+    pub fn git_repo(&self, files: &[(&str, &str)]) -> Option<impl Fn(&[&str]) -> bool + '_> {
+        let git = |args: &[&str]| {
+            std::process::Command::new("git")
+                .args(["-c", "user.name=fop", "-c", "user.email=fop@example.com"])
+                .args(args)
+                .current_dir(&self.0)
+                .output()
+                .is_ok_and(|o| o.status.success())
+        };
+        if !git(&["init", "-q"]) {
+            return None;
+        }
+        for (name, content) in files {
+            let path = self.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        assert!(git(&["add", "."]));
+        assert!(git(&["commit", "-q", "-m", "base"]));
+        Some(git)
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
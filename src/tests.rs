@@ -7,14 +7,30 @@
 //! Copyright (C) 2011 Michael (original Python version)
 //! Rust port maintains GPL-3.0 license compatibility.
 
-use crate::fop_git::{check_comment, valid_url};
+use crate::fop_git::{check_comment, diff_stats, files_changed_since, generate_pr_url, valid_url, DiffStats};
 use crate::fop_sort::is_tld_only;
 
 use crate::fop_sort::{
-    convert_ubo_options, filter_tidy, is_localhost_entry, localhost_domain,
-    remove_unnecessary_wildcards, sort_domains,
+    convert_ubo_options, cosmetic_network_options, filter_tidy, fop_sort, TidyOptions, temp_path, is_localhost_entry, is_preprocessor_directive,
+    localhost_domain, normalize_csp_options, remove_unnecessary_wildcards, sort_domains, SortConfig,
 };
 
+use crate::sort_string;
+use crate::test_support::TestDir;
+use std::path::Path;
+use std::sync::LazyLock;
+
+/// `filter_tidy` with uBO option conversion, as most network rule tests use
+fn ubo_tidy() -> TidyOptions {
+    TidyOptions { convert_ubo: true, ..Default::default() }
+}
+
+/// `!` comments and defaults for everything else; tests set only the fields they check
+fn test_config() -> SortConfig<'static> {
+    static COMMENTS: LazyLock<Vec<String>> = LazyLock::new(|| vec!["!".to_string()]);
+    SortConfig { comment_chars: &COMMENTS, ..Default::default() }
+}
+
 // =============================================================================
 // Main.rs Tests
 // =============================================================================
@@ -54,24 +70,46 @@ fn test_check_comment() {
 #[test]
 fn test_is_localhost_entry() {
     // Valid 0.0.0.0 entries
-    assert!(is_localhost_entry("0.0.0.0 domain.com"));
-    assert!(is_localhost_entry("0.0.0.0 sub.domain.com"));
-    assert!(is_localhost_entry("0.0.0.0 ads.example.org"));
-    assert!(is_localhost_entry("0.0.0.0\tdomain.com"));
+    assert!(is_localhost_entry("0.0.0.0 domain.com", &[]));
+    assert!(is_localhost_entry("0.0.0.0 sub.domain.com", &[]));
+    assert!(is_localhost_entry("0.0.0.0 ads.example.org", &[]));
+    assert!(is_localhost_entry("0.0.0.0\tdomain.com", &[]));
 
     // Valid 127.0.0.1 entries
-    assert!(is_localhost_entry("127.0.0.1 domain.com"));
-    assert!(is_localhost_entry("127.0.0.1 sub.domain.com"));
-    assert!(is_localhost_entry("127.0.0.1 tracker.net"));
+    assert!(is_localhost_entry("127.0.0.1 domain.com", &[]));
+    assert!(is_localhost_entry("127.0.0.1 sub.domain.com", &[]));
+    assert!(is_localhost_entry("127.0.0.1 tracker.net", &[]));
 
     // Invalid entries
-    assert!(!is_localhost_entry("# comment"));
-    assert!(!is_localhost_entry("192.168.1.1 domain.com"));
-    assert!(!is_localhost_entry("domain.com"));
-    assert!(!is_localhost_entry("0.0.0.0"));
-    assert!(!is_localhost_entry("0.0.0.0 "));
-    assert!(!is_localhost_entry("127.0.0.1"));
-    assert!(!is_localhost_entry(""));
+    assert!(!is_localhost_entry("# comment", &[]));
+    assert!(!is_localhost_entry("192.168.1.1 domain.com", &[]));
+    assert!(!is_localhost_entry("domain.com", &[]));
+    assert!(!is_localhost_entry("0.0.0.0", &[]));
+    assert!(!is_localhost_entry("0.0.0.0 ", &[]));
+    assert!(!is_localhost_entry("127.0.0.1", &[]));
+    assert!(!is_localhost_entry("", &[]));
+
+    // IPv6 blackhole addresses
+    assert!(is_localhost_entry("::1 domain.com", &[]));
+    assert!(is_localhost_entry(":: domain.com", &[]));
+    assert!(!is_localhost_entry("::2 domain.com", &[]));
+    assert!(!is_localhost_entry("::1", &[]));
+
+    // --hosts-blackhole replaces the default list
+    let only_zero = vec!["0.0.0.0".to_string()];
+    assert!(is_localhost_entry("0.0.0.0 domain.com", &only_zero));
+    assert!(!is_localhost_entry("127.0.0.1 domain.com", &only_zero));
+    assert!(!is_localhost_entry("::1 domain.com", &only_zero));
+}
+
+#[test]
+fn test_ipv6_localhost_entries_sorted() {
+    let config = SortConfig { localhost: true, ..Default::default() };
+    let input = "# Title: Hosts\n::1 z-ads.com\n0.0.0.0 m-ads.com\n:: a-ads.com\n192.168.0.1 gone.com\n";
+    assert_eq!(
+        sort_string(input, &config).unwrap(),
+        "# Title: Hosts\n:: a-ads.com\n0.0.0.0 m-ads.com\n::1 z-ads.com\n"
+    );
 }
 
 #[test]
@@ -84,9 +122,11 @@ fn test_localhost_domain_extraction() {
     assert_eq!(localhost_domain("0.0.0.0   spaced.com"), "spaced.com");
     // Fallback for non-localhost lines
     assert_eq!(localhost_domain("plain.domain.com"), "plain.domain.com");
+    assert_eq!(localhost_domain("::1 v6.domain.com"), "v6.domain.com");
 }
 
 #[test]
+#[allow(clippy::useless_vec)]
 fn test_localhost_domain_sort_order() {
     let mut entries = vec![
         "0.0.0.0 z-tracker.com".to_string(),
@@ -110,6 +150,17 @@ fn test_remove_unnecessary_wildcards() {
     assert_eq!(remove_unnecessary_wildcards("@@*example*"), "@@example");
     assert_eq!(remove_unnecessary_wildcards("*|example"), "*|example");
     assert_eq!(remove_unnecessary_wildcards("example|*"), "example|*");
+    // A trailing `*` after the separator, and repeated wildcards inside the pattern
+    assert_eq!(remove_unnecessary_wildcards("||a.com^*"), "||a.com^");
+    assert_eq!(remove_unnecessary_wildcards("@@||a.com^**"), "@@||a.com^");
+    assert_eq!(remove_unnecessary_wildcards("a**b"), "a*b");
+    assert_eq!(remove_unnecessary_wildcards("*ads***banner*"), "ads*banner");
+    assert_eq!(remove_unnecessary_wildcards("**|example"), "*|example");
+    assert_eq!(filter_tidy("||a.com^*$script", ubo_tidy()), "||a.com^$script");
+    // Regex rules keep their wildcards
+    assert_eq!(remove_unnecessary_wildcards("/regex*/"), "/regex*/");
+    assert_eq!(remove_unnecessary_wildcards("/re**gex/"), "/re**gex/");
+    assert_eq!(remove_unnecessary_wildcards("/regex/**"), "/regex/*");
     // Fast path: no wildcards
     assert_eq!(remove_unnecessary_wildcards("example.com"), "example.com");
     assert_eq!(remove_unnecessary_wildcards("@@||example.com^"), "@@||example.com^");
@@ -127,60 +178,559 @@ fn test_convert_ubo_options() {
     assert_eq!(result2, vec!["domain=example.com"]);
 }
 
+#[test]
+fn test_popup_options_unchanged_by_ubo_conversion() {
+    // $popup means the same in every dialect; $popunder is uBO-only with no ABP form
+    let options: Vec<String> = ["popup", "~popup", "popunder"].iter().map(|s| s.to_string()).collect();
+    assert_eq!(convert_ubo_options(options.clone()), options);
+    for rule in ["||ads.example^$popup,third-party", "||ads.example^$popunder,domain=a.com", "@@||ads.example^$popup"] {
+        assert_eq!(filter_tidy(rule, ubo_tidy()), rule);
+        assert_eq!(filter_tidy(rule, TidyOptions::default()), rule);
+    }
+    // Other uBO aliases on a popup rule still convert
+    assert_eq!(filter_tidy("||ads.example^$3p,popup", ubo_tidy()), "||ads.example^$popup,third-party");
+}
+
+#[test]
+fn test_doubled_exception_prefix_tidy() {
+    assert_eq!(filter_tidy("@@@@||example.com^", ubo_tidy()), "@@||example.com^");
+    assert_eq!(filter_tidy("@@@@||example.com^$script,image", ubo_tidy()), "@@||example.com^$image,script");
+    assert_eq!(remove_unnecessary_wildcards("@@@@*ad*"), "@@ad");
+    assert_eq!(filter_tidy("@@@@*ad*", ubo_tidy()), "@@ad");
+    // Legitimate exceptions untouched
+    assert_eq!(filter_tidy("@@||example.com^", ubo_tidy()), "@@||example.com^");
+    assert_eq!(remove_unnecessary_wildcards("@@||example.com^"), "@@||example.com^");
+    assert_eq!(remove_unnecessary_wildcards("@@*ad*"), "@@ad");
+}
+
+#[test]
+fn test_to_domains_sorted_and_deduped() {
+    assert_eq!(
+        filter_tidy("||ads.example^$to=b.com|a.com|b.com,script", ubo_tidy()),
+        "||ads.example^$script,to=a.com|b.com"
+    );
+    // Negated entries sort alongside their positive counterparts
+    assert_eq!(
+        filter_tidy("||ads.example^$script,to=~c.com|b.com|c.com|~a.com,domain=z.com|y.com", ubo_tidy()),
+        "||ads.example^$script,to=~a.com|b.com|c.com|~c.com,domain=y.com|z.com"
+    );
+    // Repeated to= options merge into one
+    assert_eq!(filter_tidy("||cdn.example^$to=x.com,to=c.com|x.com", ubo_tidy()), "||cdn.example^$to=c.com|x.com");
+}
+
+#[test]
+fn test_header_value_commas_preserved() {
+    assert_eq!(
+        filter_tidy("||example.com^$script,header=cache-control:no-store,max-age=0,domain=b.com|a.com", ubo_tidy()),
+        "||example.com^$header=cache-control:no-store,max-age=0,script,domain=a.com|b.com"
+    );
+    // The value ends at the next recognised option
+    assert_eq!(
+        filter_tidy("||example.com^$header=link:a,b,~third-party", ubo_tidy()),
+        "||example.com^$header=link:a,b,~third-party"
+    );
+}
+
+#[test]
+fn test_replace_value_preserved() {
+    // Commas inside the regex must not be split into options
+    let rule = "||example.com^$xmlhttprequest,replace=/\"ads\",\"banner\"/\"\"/g,domain=b.com|a.com";
+    assert_eq!(filter_tidy(rule, ubo_tidy()), rule);
+    // AdGuard-style escaped commas
+    let rule = "||example.com/api$replace=/a\\,b/c\\,d/";
+    assert_eq!(filter_tidy(rule, ubo_tidy()), rule);
+    // A `$` anchor inside the regex must not be taken as the option separator
+    let rule = "||example.com^$replace=/ad.js,tracker.js$/x.js/i";
+    assert_eq!(filter_tidy(rule, ubo_tidy()), rule);
+    // The rest of the rule is tidied like any other
+    assert_eq!(
+        filter_tidy("@@@@||example.com^$replace=/a,b/c/", ubo_tidy()),
+        "@@||example.com^$replace=/a,b/c/"
+    );
+}
+
+#[test]
+fn test_replace_rule_preserved_through_sort() {
+    let config = test_config();
+    let input = "||z.com^\n||example.com^$replace=/\"a\",\"b\"/\"\"/g,xhr\n";
+    assert_eq!(
+        sort_string(input, &config).unwrap(),
+        "||example.com^$replace=/\"a\",\"b\"/\"\"/g,xhr\n||z.com^\n"
+    );
+}
+
+#[test]
+fn test_denyallow_sorted_and_deduped() {
+    assert_eq!(
+        filter_tidy("||ads.example^$script,denyallow=b.com|a.com|b.com,domain=z.com|y.com", ubo_tidy()),
+        "||ads.example^$denyallow=a.com|b.com,script,domain=y.com|z.com"
+    );
+    assert_eq!(filter_tidy("||cdn.example^$3p,denyallow=x.com|c.com", ubo_tidy()), "||cdn.example^$denyallow=c.com|x.com,third-party");
+    assert_eq!(filter_tidy("||ads.example^$denyallow=a.com", ubo_tidy()), "||ads.example^$denyallow=a.com");
+}
+
 #[test]
 fn test_filter_tidy() {
     // Test option sorting
-    let result = filter_tidy("||example.com^$image,script,third-party", true);
+    let result = filter_tidy("||example.com^$image,script,third-party", ubo_tidy());
     assert!(result.contains("image"));
     assert!(result.contains("script"));
     assert!(result.contains("third-party"));
 
     // Test domain sorting
-    let result = filter_tidy("||ad.com^$domain=z.com|a.com|m.com", true);
+    let result = filter_tidy("||ad.com^$domain=z.com|a.com|m.com", ubo_tidy());
     assert!(result.contains("domain=a.com|m.com|z.com"));
 }
 
 #[test]
 fn test_filter_tidy_no_options() {
     // Fast path: no $ in filter
-    let result = filter_tidy("||example.com^", true);
+    let result = filter_tidy("||example.com^", ubo_tidy());
     assert_eq!(result, "||example.com^");
 }
 
 #[test]
 fn test_filter_tidy_regex_preserved() {
     // Regex value options should be preserved
-    let result = filter_tidy("||example.com$removeparam=/regex/", true);
+    let result = filter_tidy("||example.com$removeparam=/regex/", ubo_tidy());
     assert!(result.contains("removeparam=/regex/"));
 }
 
+#[test]
+fn test_filter_tidy_removeparam_dedupe() {
+    assert_eq!(filter_tidy("||example.com^$removeparam=a,removeparam=a", ubo_tidy()), "||example.com^$removeparam=a");
+    assert_eq!(
+        filter_tidy("||example.com^$removeparam=utm_source,script,removeparam=fbclid,removeparam=utm_source", ubo_tidy()),
+        "||example.com^$removeparam=fbclid,removeparam=utm_source,script"
+    );
+    // Regex values are kept verbatim, repeats included
+    assert_eq!(
+        filter_tidy("||example.com^$removeparam=/re/,removeparam=/re/", ubo_tidy()),
+        "||example.com^$removeparam=/re/,removeparam=/re/"
+    );
+}
+
 #[test]
 fn test_filter_tidy_space_removal() {
     // Spaces should be removed from network filters
-    let result = filter_tidy("|| example .com ^$script", true);
+    let result = filter_tidy("|| example .com ^$script", ubo_tidy());
     assert_eq!(result, "||example.com^$script");
 }
 
 #[test]
 fn test_filter_tidy_space_preserved_element() {
     // Spaces in element rules should be preserved
-    let result = filter_tidy("example.com##div .ad", true);
+    let result = filter_tidy("example.com##div .ad", ubo_tidy());
     assert_eq!(result, "example.com##div .ad");
 }
 
 #[test]
 fn test_filter_tidy_adguard_js_no_space_strip() {
     // #%# rules should not have spaces stripped
-    let result = filter_tidy("example.com#%#(()=>{ console.log('test') })();", true);
+    let result = filter_tidy("example.com#%#(()=>{ console.log('test') })();", ubo_tidy());
     assert!(result.contains(" "), "#%# rule spaces should be preserved, got: {}", result);
 }
 
+#[test]
+fn test_filter_tidy_urltransform_regex() {
+    // Commas, escaped slashes and `$` in the regex survive; other options are still sorted
+    let transform = r"urltransform=/^https?:\/\/([a-z]{2,3})\.example\.com\/(.*)$/https:\/\/\1.example.net\/\2/";
+    assert_eq!(
+        filter_tidy(&format!("||example.com^${},3p,script", transform), ubo_tidy()),
+        format!("||example.com^$script,third-party,{}", transform)
+    );
+    assert_eq!(
+        filter_tidy(&format!("||example.com^$doc,{},domain=b.com|a.com", transform), ubo_tidy()),
+        format!("||example.com^$document,domain=a.com|b.com,{}", transform)
+    );
+    // Flags and a transform as the only option
+    let uri = r"uritransform=/(\?|&)(utm_[a-z]+|fbclid)=[^&#]*/\1/gi";
+    assert_eq!(
+        filter_tidy(&format!("||example.com/redirect?$~3p,{}", uri), TidyOptions::default()),
+        format!("||example.com/redirect?$~3p,{}", uri)
+    );
+    assert_eq!(
+        filter_tidy(&format!("||example.com/out$DOC,{}", uri), TidyOptions::default()),
+        format!("||example.com/out$doc,{}", uri)
+    );
+    assert_eq!(
+        filter_tidy(&format!("@@||example.com^${}", uri), ubo_tidy()),
+        format!("@@||example.com^${}", uri)
+    );
+}
+
+#[test]
+fn test_filter_tidy_normalize_redirects() {
+    let redirects = TidyOptions { normalize_redirects: true, ..Default::default() };
+    assert_eq!(
+        filter_tidy("||ads.example^$script,redirect=noopjs", redirects),
+        "||ads.example^$redirect=noop.js,script"
+    );
+    assert_eq!(
+        filter_tidy("||ads.example^$image,redirect-rule=1x1-transparent.gif:10", redirects),
+        "||ads.example^$image,redirect-rule=1x1.gif:10"
+    );
+    // Unknown resources and the flag being off leave the value alone
+    assert_eq!(
+        filter_tidy("||ads.example^$script,redirect=custom-noop.js", redirects),
+        "||ads.example^$redirect=custom-noop.js,script"
+    );
+    assert_eq!(
+        filter_tidy("||ads.example^$script,redirect=noopjs", TidyOptions::default()),
+        "||ads.example^$redirect=noopjs,script"
+    );
+}
+
+#[test]
+fn test_match_case_pattern_casing_preserved() {
+    // Wildcards are trimmed and options sorted, but the pattern itself keeps its casing
+    assert_eq!(filter_tidy("*/AdBanner.JS*$Match-Case,script", ubo_tidy()), "/AdBanner.JS$match-case,script");
+    assert_eq!(filter_tidy("@@||Example.com/Ads/*$match-case", ubo_tidy()), "@@||Example.com/Ads/$match-case");
+
+    let config = SortConfig { normalize_anchor: true, fix_typos: true, ..test_config() };
+    assert_eq!(
+        sort_string("/AdBanner/$match-case\n||Example.com/BigAd.GIF$match-case,image\n", &config).unwrap(),
+        "/AdBanner/$match-case\n||Example.com/BigAd.GIF$image,match-case\n"
+    );
+}
+
+#[test]
+fn test_filter_tidy_cookie_option() {
+    // Plain names keep their casing; a repeated $cookie= is dropped
+    assert_eq!(
+        filter_tidy("||example.com^$Cookie=NAME,script,cookie=NAME", ubo_tidy()),
+        "||example.com^$cookie=NAME,script"
+    );
+    assert_eq!(filter_tidy("$cookie=_ga;maxAge=3600,3p", ubo_tidy()), "$cookie=_ga;maxAge=3600,third-party");
+    // Regex values with commas stay whole while the other options are sorted
+    assert_eq!(
+        filter_tidy("||example.com^$third-party,cookie=/a,b/", ubo_tidy()),
+        "||example.com^$cookie=/a,b/,third-party"
+    );
+    assert_eq!(
+        filter_tidy("||example.com^$script,cookie=/^__u{1,3}[a-z]+$/,domain=b.com|a.com", ubo_tidy()),
+        "||example.com^$cookie=/^__u{1,3}[a-z]+$/,script,domain=a.com|b.com"
+    );
+    // Other regex values still leave the rule verbatim
+    assert_eq!(
+        filter_tidy("||example.com^$script,cookie=/a,b/,removeparam=/x/", ubo_tidy()),
+        "||example.com^$script,cookie=/a,b/,removeparam=/x/"
+    );
+}
+
+#[test]
+fn test_filter_tidy_sitekey_verbatim() {
+    use crate::fop_sort::invalid_sitekey;
+    let key = "MFwwDQYJKoZIhvcNAQEBBQADSwAwSAJBANnylWw2vLY4hUn9w06zQKbhKBfvjFUCsdFlb6TdQhxb9RXWXuI4t31c+o8fYOv/s8q1LGPga3DE1L/tHU4LENMCAwEAAQ";
+    let rule = format!("@@$document,sitekey={}", key);
+    assert_eq!(filter_tidy(&rule, ubo_tidy()), rule);
+    assert_eq!(invalid_sitekey(key), None);
+    // Option names are lowercased and sorted; keys (case-sensitive, may start with `/`) are not
+    assert_eq!(
+        filter_tidy("@@$Sitekey=/AbC+dE==|xYz9,document", ubo_tidy()),
+        "@@$document,sitekey=/AbC+dE==|xYz9"
+    );
+    assert_eq!(invalid_sitekey("/AbC+dE==|xYz9"), None);
+    assert_eq!(invalid_sitekey("AbC+dE==|not*base64"), Some("not*base64"));
+    assert_eq!(invalid_sitekey("AbC=d"), Some("AbC=d"));
+    assert_eq!(invalid_sitekey("AbC==="), Some("AbC==="));
+    assert_eq!(invalid_sitekey("AbC||dE"), Some(""));
+}
+
+#[test]
+fn test_normalize_anchor() {
+    use crate::fop_sort::normalize_anchor;
+    assert_eq!(normalize_anchor("||example.com").as_deref(), Some("||example.com^"));
+    assert_eq!(normalize_anchor("||sub.b\u{fc}cher.example").as_deref(), Some("||sub.b\u{fc}cher.example^"));
+    for rule in ["||example.com^", "||example.com/ads", "||ads.*.com", "||example.com$script",
+                 "||example.com:8080", "@@||example.com", "||localhost", "|example.com", "||example."] {
+        assert_eq!(normalize_anchor(rule), None, "{}", rule);
+    }
+
+    let config = SortConfig { normalize_anchor: true, ..test_config() };
+    assert_eq!(
+        sort_string("||example.com\n||example.com^\n||example.com/ads\n", &config).unwrap(),
+        "||example.com/ads\n||example.com^\n"
+    );
+}
+
+#[test]
+fn test_current_options_are_known() {
+    use crate::fop_sort::is_known_option;
+    // Options in current uBO/AdGuard lists that must not trigger "not recognised" warnings
+    let options = [
+        "1p", "3p", "all", "badfilter", "beacon", "cname", "content", "cookie", "css", "denyallow=a.com",
+        "doc", "document", "ehide", "elemhide", "empty", "extension", "font", "frame", "from=a.com",
+        "generichide", "genericblock", "ghide", "header=via:1.1 google", "image", "important",
+        "inline-font", "inline-script", "ipaddress=/^10\\./", "jsinject", "match-case", "media",
+        "method=post", "network", "object", "other", "permissions=browsing-topics=()", "ping",
+        "popunder", "popup", "redirect-rule=noop.js", "redirect=noop.js", "removeparam=utm_source",
+        "replace=/a/b/", "script", "shide", "specifichide", "stealth", "strict-first-party",
+        "strict-third-party", "strict1p", "strict3p", "stylesheet", "subdocument", "third-party",
+        "to=a.com", "urlblock", "urlskip=?url", "urltransform=/a/b/", "webrtc", "websocket", "xhr",
+        "xmlhttprequest", "~third-party", "~inline-font",
+    ];
+    for option in options {
+        assert!(is_known_option(option), "{} should be a known option", option);
+    }
+    assert!(!is_known_option("not-an-option"));
+
+    // uBO aliases with ABP equivalents are converted
+    assert_eq!(filter_tidy("||ads.example^$beacon,3p", ubo_tidy()), "||ads.example^$ping,third-party");
+    assert_eq!(filter_tidy("||ads.example^$ehide", ubo_tidy()), "||ads.example^$elemhide");
+    assert_eq!(filter_tidy("||ads.example^$inline-font", ubo_tidy()), "||ads.example^$inline-font");
+}
+
+#[test]
+fn test_max_line_length() {
+    let long_rule = format!("example.com##div[style*=\"url(data:image/png;base64,{})\"]", "A".repeat(200));
+    let input = format!("! {}\n{}\n||ads.example^\n", "x".repeat(300), long_rule);
+
+    // Warning only by default; comments are never checked
+    let config = SortConfig { max_line_length: Some(100), ..test_config() };
+    let output = sort_string(&input, &config).unwrap();
+    assert!(output.contains(&long_rule));
+    assert!(output.contains(&"x".repeat(300)));
+
+    let config = SortConfig { drop_long_lines: true, ..config };
+    let output = sort_string(&input, &config).unwrap();
+    assert!(!output.contains("base64"));
+    assert!(output.contains("||ads.example^"));
+    assert!(output.contains(&"x".repeat(300)));
+}
+
+#[test]
+fn test_generic_cosmetic_reason() {
+    use crate::fop_sort::generic_cosmetic_reason;
+    assert!(generic_cosmetic_reason("##div", &[]).is_some());
+    assert!(generic_cosmetic_reason("##*", &[]).is_some());
+    assert!(generic_cosmetic_reason("#?#.Container", &[]).is_some());
+    assert_eq!(generic_cosmetic_reason("##.ad-banner", &[]), None);
+    assert_eq!(generic_cosmetic_reason("##div.ad", &[]), None);
+    assert_eq!(generic_cosmetic_reason("##.container > .ad", &[]), None);
+    assert_eq!(generic_cosmetic_reason("example.com##div", &[]), None);
+    assert_eq!(generic_cosmetic_reason("#@#div", &[]), None);
+    assert_eq!(generic_cosmetic_reason("##ins", &["ins".to_string()]), None);
+}
+
+#[test]
+fn test_dedupe_keeps_first_occurrence() {
+    use crate::fop_sort::dedupe_keep_first;
+    let lines = ["||b.example^", "||a.example^", "||b.example^", "||c.example^", "||a.example^", "||b.example^"]
+        .map(String::from);
+    let mut dupes = Vec::new();
+    assert_eq!(
+        dedupe_keep_first(lines.clone(), Some(&mut dupes)),
+        ["||b.example^", "||a.example^", "||c.example^"]
+    );
+    assert_eq!(dupes, ["||b.example^", "||a.example^"]);
+
+    // Same result on every run, with or without sorting
+    let input = "||b.example^\n||a.example^\n||b.example^\n||c.example^\n||a.example^\n";
+    let unsorted = SortConfig { no_sort: true, ..test_config() };
+    let sorted = test_config();
+    for _ in 0..5 {
+        assert_eq!(sort_string(input, &unsorted).unwrap(), "||b.example^\n||a.example^\n||c.example^\n");
+        assert_eq!(sort_string(input, &sorted).unwrap(), "||a.example^\n||b.example^\n||c.example^\n");
+    }
+}
+
+#[test]
+fn test_normalize_header() {
+    use crate::fop_datestamp::normalize_header;
+    let content = "[Adblock Plus 2.0]\n! Homepage:https://example.com\n! Title :  Example List\n\
+                   ! Some notes: keep me here\n!Version: 202601010000\n! Expires: 4 days\n||ads.example^\n";
+    assert_eq!(
+        normalize_header(content, false).as_deref(),
+        Some("[Adblock Plus 2.0]\n! Version: 202601010000\n! Title: Example List\n! Expires: 4 days\n\
+              ! Homepage: https://example.com\n! Some notes: keep me here\n||ads.example^\n")
+    );
+    // Metadata after the first rule is left alone; canonical headers are unchanged
+    let canonical = "! Version: 1\n! Title: Example\n! Homepage: https://example.com\n||a.example^\n! Title: later\n";
+    assert_eq!(normalize_header(canonical, false), None);
+    assert_eq!(normalize_header("||a.example^\n", false), None);
+    // Hosts files use # comments
+    assert_eq!(
+        normalize_header("# Title:Hosts\n# Version:2\n0.0.0.0 ads.example\n", true).as_deref(),
+        Some("# Version: 2\n# Title: Hosts\n0.0.0.0 ads.example\n")
+    );
+
+    let config = SortConfig { normalize_header: true, ..test_config() };
+    assert_eq!(
+        sort_string("! Title:List\n! Version: 3\n||b.example^\n||a.example^\n", &config).unwrap(),
+        "! Version: 3\n! Title: List\n||a.example^\n||b.example^\n"
+    );
+}
+
+#[test]
+fn test_leading_comment_block_kept_and_sort_header() {
+    use crate::fop_datestamp::sort_header;
+    let header = "[Adblock Plus 2.0]\n! Homepage:https://example.com\n! Checksum: abc\n! Title : Example\n\
+                  ! Expires: 4 days\n! Last modified: 30 Jan 2026 08:31 UTC\n";
+    let input = format!("{}||b.example^\n! Title: later\n||a.example^\n", header);
+
+    // Without --sort-header the leading block is written verbatim, in order, above every rule
+    let config = test_config();
+    let output = sort_string(&format!("{}||c.example^\n||b.example^\n", header), &config).unwrap();
+    assert_eq!(output, format!("{}||b.example^\n||c.example^\n", header));
+
+    // --sort-header reorders the known fields only; text, other comments and later lines are kept
+    assert_eq!(
+        sort_header(&input, false).as_deref(),
+        Some("[Adblock Plus 2.0]\n! Title : Example\n! Last modified: 30 Jan 2026 08:31 UTC\n! Expires: 4 days\n\
+              ! Homepage:https://example.com\n! Checksum: abc\n||b.example^\n! Title: later\n||a.example^\n")
+    );
+    // The block ends at the first blank line
+    assert_eq!(sort_header("! Homepage: h\n\n! Title: t\n||a.example^\n", false), None);
+    assert_eq!(sort_header("# Title: Hosts\n# Version: 2\n0.0.0.0 a.example\n", true).as_deref(),
+        Some("# Version: 2\n# Title: Hosts\n0.0.0.0 a.example\n"));
+
+    let config = SortConfig { sort_header: true, ..test_config() };
+    assert_eq!(
+        sort_string("! Homepage: h\n! Title: List\n||b.example^\n||a.example^\n", &config).unwrap(),
+        "! Title: List\n! Homepage: h\n||a.example^\n||b.example^\n"
+    );
+}
+
+#[test]
+fn test_nosort_directive_keeps_section_order() {
+    let config = test_config();
+    let input = "! Sorted\n||b.example^\n||a.example^\n\
+                 ! FOP: nosort\n||z.example^\n||y.example^\nb.com##.ad\na.com##.ad\n\
+                 ! Step 2 depends on step 1\n||x.example^$script,image\n||w.example^\n\
+                 ! fop: SORT\n||d.example^\n||c.example^\n";
+    assert_eq!(
+        sort_string(input, &config).unwrap(),
+        "! Sorted\n||a.example^\n||b.example^\n\
+         ! FOP: nosort\n||z.example^\n||y.example^\nb.com##.ad\na.com##.ad\n\
+         ! Step 2 depends on step 1\n||x.example^$image,script\n||w.example^\n\
+         ! fop: SORT\n||c.example^\n||d.example^\n"
+    );
+
+    // Without a closing `! FOP: sort`, only the section after the directive is protected
+    let input = "! FOP: nosort\n||b.example^\n||a.example^\n! Next\n||d.example^\n||c.example^\n";
+    assert_eq!(
+        sort_string(input, &config).unwrap(),
+        "! FOP: nosort\n||b.example^\n||a.example^\n! Next\n||c.example^\n||d.example^\n"
+    );
+}
+
+#[test]
+fn test_normalize_only_keeps_order_and_duplicates() {
+    let config = SortConfig { convert_ubo: true, normalize_only: true, ..test_config() };
+    let input = "! Title\n||b.example^$script,3p\n||a.example^\nb.com,a.com##.ad\n||a.example^\nc.com##.ad\n";
+    assert_eq!(
+        sort_string(input, &config).unwrap(),
+        "! Title\n||b.example^$script,third-party\n||a.example^\na.com,b.com##.ad\n||a.example^\nc.com##.ad\n"
+    );
+}
+
+#[test]
+fn test_verbose_file_decisions() {
+    use crate::fop_sort::{file_decisions, optional_passes};
+    let config = SortConfig { convert_ubo: true, fix_typos: true, ..test_config() };
+    assert_eq!(optional_passes(&config), vec!["ubo-convert", "selector-tidy", "fix-typos"]);
+    assert_eq!(
+        file_decisions(std::path::Path::new("list.txt"), "! T\na.com##.ad\nb.com##.ad\n||x.example^\n", "! T\na.com,b.com##.ad\n||x.example^\n", &config),
+        "File: list.txt -> network (3 rules before, 2 after; passes: ubo-convert, selector-tidy, fix-typos)"
+    );
+
+    // --dedupe-only skips the tidy passes; header options still run
+    let config = SortConfig { dedupe_only: true, fix_typos: true, rule_count: true, ..test_config() };
+    assert_eq!(optional_passes(&config), vec!["dedupe-only", "rule-count"]);
+
+    let hosts = SortConfig { localhost: true, no_selector_tidy: true, ..Default::default() };
+    assert_eq!(
+        file_decisions(std::path::Path::new("hosts"), "", "0.0.0.0 a.example\n", &hosts),
+        "File: hosts -> localhost (0 rules before, 1 after; passes: none)"
+    );
+}
+
+#[test]
+fn test_quarantine_invalid() {
+    use crate::fop_sort::write_quarantine;
+    let config = SortConfig { quarantine_invalid: true, ..test_config() };
+    let output = sort_string("! Title\n||intranet^\nab\n||.com^\n||ads.example^\n", &config).unwrap();
+    assert_eq!(output, "! Title\n||ads.example^\n");
+
+    let dir = TestDir::new("quarantine");
+    let path = dir.join("quarantine.txt");
+    assert_eq!(write_quarantine(&path).unwrap(), 3);
+    let written = std::fs::read_to_string(&path).unwrap();
+    let lines: Vec<&str> = written.lines().collect();
+    assert_eq!(lines.len(), 6);
+    assert!(lines[0].starts_with("! no dot in domain (") && lines[0].ends_with("<string>:2)"), "{}", lines[0]);
+    assert_eq!(lines[1], "||intranet^");
+    assert!(lines[2].starts_with("! too short (") && lines[2].ends_with(":3)"));
+    assert_eq!(lines[3], "ab");
+    assert!(lines[4].starts_with("! TLD-only rule ("));
+    assert_eq!(lines[5], "||.com^");
+}
+
+#[test]
+fn test_contradictory_options() {
+    use crate::fop_sort::contradictory_options;
+    let options = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+    assert_eq!(
+        contradictory_options(&options(&["third-party", "~third-party"])),
+        Some(("third-party", "~third-party"))
+    );
+    assert_eq!(contradictory_options(&options(&["3p", "~3p"])), Some(("3p", "~3p")));
+    // uBO aliases compare by meaning: 1p is ~third-party
+    assert_eq!(contradictory_options(&options(&["1p", "3p"])), Some(("3p", "1p")));
+    assert_eq!(contradictory_options(&options(&["script", "~xhr", "xmlhttprequest"])), Some(("xmlhttprequest", "~xhr")));
+    assert_eq!(contradictory_options(&options(&["script", "~image", "third-party"])), None);
+    assert_eq!(contradictory_options(&options(&["redirect=noop.js", "~redirect=noop.js"])), None);
+}
+
+#[test]
+fn test_redundant_options() {
+    use crate::fop_sort::redundant_options;
+    let options = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+    assert_eq!(redundant_options(&options(&["3p", "third-party"])), Some(("3p", "third-party")));
+    assert_eq!(redundant_options(&options(&["script", "1p", "~third-party"])), Some(("1p", "~third-party")));
+    assert_eq!(redundant_options(&options(&["~1p", "3p"])), Some(("~1p", "3p")));
+    // Exact duplicates are collapsed silently; opposite meanings are contradictions, not redundancy
+    assert_eq!(redundant_options(&options(&["script", "script"])), None);
+    assert_eq!(redundant_options(&options(&["1p", "3p"])), None);
+
+    // After conversion both spellings collapse into one option
+    assert_eq!(filter_tidy("||ads.example^$3p,third-party", ubo_tidy()), "||ads.example^$third-party");
+    assert_eq!(filter_tidy("||ads.example^$script,1p,~third-party", ubo_tidy()), "||ads.example^$script,~third-party");
+    // Without conversion the aliases are kept (and warned about)
+    assert_eq!(filter_tidy("||ads.example^$3p,third-party", TidyOptions::default()), "||ads.example^$3p,third-party");
+}
+
+#[test]
+fn test_broad_option_reason() {
+    use crate::fop_sort::broad_option_reason;
+    assert!(broad_option_reason("||ads.example^$all").is_some());
+    assert!(broad_option_reason("||ads.example^$document").is_some());
+    assert!(broad_option_reason("/popunder/$popup").is_some());
+    assert_eq!(broad_option_reason("||ads.example^$document,domain=a.com"), None);
+    assert_eq!(broad_option_reason("||ads.example^$popup,domain=a.com|b.com"), None);
+    assert_eq!(broad_option_reason("@@||ads.example^$document"), None);
+    assert_eq!(broad_option_reason("||ads.example^$script"), None);
+    assert_eq!(broad_option_reason("||ads.example^"), None);
+}
+
+#[test]
+fn test_warn_combine_leaves_output_unchanged() {
+    // --warn-combine only logs merges; the combined result must match a normal run
+    let input = "a.com##.ad\nb.com##.ad\n! Network\n||ads.example^$domain=a.com\n||ads.example^$domain=b.com\n";
+    let plain = test_config();
+    let warned = SortConfig { warn_combine: true, ..test_config() };
+    let expected = "a.com,b.com##.ad\n! Network\n||ads.example^$domain=a.com|b.com\n";
+    assert_eq!(sort_string(input, &plain).unwrap(), expected);
+    assert_eq!(sort_string(input, &warned).unwrap(), expected);
+}
+
 #[test]
 fn test_filter_tidy_jsonprune_no_commas() {
     // jsonprune with dot-separated path — dots preserved, spaces preserved
     let result = filter_tidy(
         "||assets.msn.com/service/news/feed/pages/$jsonprune=\\$.sections..subSections..cards..[?(key-substr 'type' 'nativead')]",
-        true,
+        ubo_tidy(),
     );
     assert!(result.contains("jsonprune=\\$.sections..subSections..cards.."), "jsonprune value broken: {}", result);
     assert!(result.contains("key-substr 'type' 'nativead'"), "jsonprune spaces stripped: {}", result);
@@ -191,7 +741,7 @@ fn test_filter_tidy_jsonprune_with_domain() {
     // jsonprune followed by domain= option — comma should separate them
     let result = filter_tidy(
         ".com/playlist?list=$jsonprune=\\$.playerConfig.ssapConfig,domain=youtubekids.com|youtube-nocookie.com|youtube.com",
-        true,
+        ubo_tidy(),
     );
     assert!(result.contains("jsonprune=\\$.playerConfig.ssapConfig"), "jsonprune value broken: {}", result);
     assert!(result.contains("domain=youtube-nocookie.com|youtube.com|youtubekids.com"), "domain missing or unsorted: {}", result);
@@ -202,7 +752,7 @@ fn test_filter_tidy_jsonprune_escaped_commas() {
     // jsonprune with escaped commas in value, followed by domain=
     let result = filter_tidy(
         ".com/watch?$xmlhttprequest,jsonprune=\\$..[adPlacements\\, adSlots\\, playerAds],domain=youtubekids.com|youtube-nocookie.com|youtube.com",
-        true,
+        ubo_tidy(),
     );
     assert!(result.contains("jsonprune=\\$..[adPlacements\\, adSlots\\, playerAds]"), "jsonprune escaped commas broken: {}", result);
     assert!(result.contains("domain="), "domain option lost: {}", result);
@@ -214,7 +764,7 @@ fn test_filter_tidy_jsonprune_complex_path() {
     // jsonprune with complex JSON path, no other options
     let result = filter_tidy(
         "||msn.com/resolver/api/resolve/$jsonprune=\\$.configs[\"ConsumptionPage/gallery_default\"].properties.componentConfigs.slideshowConfigs..interstitialNativeAds",
-        true,
+        ubo_tidy(),
     );
     assert!(result.contains("jsonprune=\\$.configs[\"ConsumptionPage/gallery_default\"]"), "jsonprune complex path broken: {}", result);
 }
@@ -246,7 +796,7 @@ fn test_sort_domains_with_ancestor_marker() {
 #[test]
 fn test_filter_tidy_ancestor_marker() {
     // Rule with >> suffix should be preserved
-    let result = filter_tidy("tomsguide.com>>##+js(trusted-click-element, button)", true);
+    let result = filter_tidy("tomsguide.com>>##+js(trusted-click-element, button)", ubo_tidy());
     assert!(result.contains("tomsguide.com>>"), "Ancestor marker lost: {}", result);
 }
 
@@ -259,7 +809,7 @@ fn test_attribute_selector_tilde_equals_preserved() {
     use crate::fop_sort::element_tidy;
     
     // ~= means "attribute contains word" - should NOT add spaces
-    let result = element_tidy("lowendtalk.com", "##", "#Panel a[rel~=\"sponsored\"]");
+    let result = element_tidy("lowendtalk.com", "##", "#Panel a[rel~=\"sponsored\"]", false, &[]);
     assert!(result.contains("[rel~=\"sponsored\"]"), "~= should be preserved, got: {}", result);
 }
 
@@ -267,10 +817,28 @@ fn test_attribute_selector_tilde_equals_preserved() {
 fn test_attribute_selector_alt_tilde() {
     use crate::fop_sort::element_tidy;
     
-    let result = element_tidy("example.com", "##", "div[alt~=\"Ad\"]");
+    let result = element_tidy("example.com", "##", "div[alt~=\"Ad\"]", false, &[]);
     assert!(result.contains("[alt~=\"Ad\"]"), "~= should be preserved, got: {}", result);
 }
 
+#[test]
+fn test_normalize_attribute_quotes() {
+    use crate::fop_sort::element_tidy;
+
+    let result = element_tidy("example.com", "##", "[href='ad']", true, &[]);
+    assert_eq!(result, "example.com##[href=\"ad\"]");
+
+    // Embedded double quotes are escaped, escaped single quotes unescaped
+    let result = element_tidy("example.com", "##", "[title='say \"hi\"'] > a[alt='it\\'s']", true, &[]);
+    assert_eq!(result, "example.com##[title=\"say \\\"hi\\\"\"] > a[alt=\"it's\"]");
+
+    // Already double-quoted values and disabled option are untouched
+    let result = element_tidy("example.com", "##", "[href=\"ad\"]", true, &[]);
+    assert_eq!(result, "example.com##[href=\"ad\"]");
+    let result = element_tidy("example.com", "##", "[href='ad']", false, &[]);
+    assert_eq!(result, "example.com##[href='ad']");
+}
+
 // =============================================================================
 // *:not() and *:has() Preservation Tests
 // =============================================================================
@@ -279,7 +847,7 @@ fn test_attribute_selector_alt_tilde() {
 fn test_star_not_preserved() {
     use crate::fop_sort::element_tidy;
     
-    let result = element_tidy("em.com.br", "##", "div > * > *:not(.comment-header)");
+    let result = element_tidy("em.com.br", "##", "div > * > *:not(.comment-header)", false, &[]);
     assert!(result.contains("*:not("), "* before :not() should be preserved, got: {}", result);
 }
 
@@ -287,7 +855,7 @@ fn test_star_not_preserved() {
 fn test_star_has_preserved() {
     use crate::fop_sort::element_tidy;
     
-    let result = element_tidy("example.com", "##", "div > *:has(.ad)");
+    let result = element_tidy("example.com", "##", "div > *:has(.ad)", false, &[]);
     assert!(result.contains("*:has("), "* before :has() should be preserved, got: {}", result);
 }
 
@@ -300,7 +868,7 @@ fn test_has_with_attribute_selectors() {
     use crate::fop_sort::element_tidy;
     
     // :has() with attribute selectors inside - should be preserved exactly
-    let result = element_tidy("tripadvisor.com", "##", "div:has(> div[class=\"ui_columns is-multiline \"])");
+    let result = element_tidy("tripadvisor.com", "##", "div:has(> div[class=\"ui_columns is-multiline \"])", false, &[]);
     assert!(result.contains(":has("), "Extended :has() should be preserved, got: {}", result);
     assert!(result.contains("[class=\"ui_columns is-multiline \"]"), "Attribute value should be preserved, got: {}", result);
 }
@@ -310,7 +878,7 @@ fn test_abp_extended_selectors() {
     use crate::fop_sort::element_tidy;
     
     // :-abp-contains should be preserved
-    let result = element_tidy("kijiji.ca", "#?#", "[data-testid^=\"listing-card-list-item-\"]:-abp-contains(TOP AD)");
+    let result = element_tidy("kijiji.ca", "#?#", "[data-testid^=\"listing-card-list-item-\"]:-abp-contains(TOP AD)", false, &[]);
     assert!(result.contains(":-abp-contains("), ":-abp-contains should be preserved, got: {}", result);
 }
 
@@ -319,7 +887,7 @@ fn test_escaped_tailwind_classes() {
     use crate::fop_sort::element_tidy;
     
     // Escaped brackets and colons in Tailwind-style classes
-    let result = element_tidy("theepochtimes.com", "##", ".bg-\\[\\#f8f8f8\\]");
+    let result = element_tidy("theepochtimes.com", "##", ".bg-\\[\\#f8f8f8\\]", false, &[]);
     assert!(result.contains("\\["), "Escaped brackets should be preserved, got: {}", result);
 }
 
@@ -332,7 +900,7 @@ fn test_adjacent_sibling_with_universal() {
     use crate::fop_sort::element_tidy;
     
     // + * should be preserved (adjacent sibling with universal selector)
-    let result = element_tidy("filecrypt.cc,filecrypt.co", "##", ".hghspd + *");
+    let result = element_tidy("filecrypt.cc,filecrypt.co", "##", ".hghspd + *", false, &[]);
     assert!(result.contains("+ *"), "Adjacent sibling + * should be preserved, got: {}", result);
 }
 
@@ -341,10 +909,10 @@ fn test_attribute_selectors() {
     use crate::fop_sort::element_tidy;
     
     // Various attribute selector types
-    let result = element_tidy("example.com", "##", "[class$=\"-ad\"]");
+    let result = element_tidy("example.com", "##", "[class$=\"-ad\"]", false, &[]);
     assert!(result.contains("[class$=\"-ad\"]"), "Attribute ends-with should be preserved, got: {}", result);
     
-    let result = element_tidy("example.com", "##", "[class*=\"-ad-\"]");
+    let result = element_tidy("example.com", "##", "[class*=\"-ad-\"]", false, &[]);
     assert!(result.contains("[class*=\"-ad-\"]"), "Attribute contains should be preserved, got: {}", result);
 }
 
@@ -353,7 +921,7 @@ fn test_complex_has_selectors() {
     use crate::fop_sort::element_tidy;
     
     // Complex :has() with nested attribute selectors
-    let result = element_tidy("twitter.com,x.com", "##", "div[data-testid=\"cellInnerDiv\"] > div > div[class] > div[class][data-testid=\"placementTracking\"]");
+    let result = element_tidy("twitter.com,x.com", "##", "div[data-testid=\"cellInnerDiv\"] > div > div[class] > div[class][data-testid=\"placementTracking\"]", false, &[]);
     assert!(result.contains("[data-testid=\"placementTracking\"]"), "Complex attribute selector should be preserved, got: {}", result);
 }
 
@@ -362,7 +930,7 @@ fn test_has_with_href_contains() {
     use crate::fop_sort::element_tidy;
     
     // :has() with href contains
-    let result = element_tidy("wayfair.com", "##", "div[data-hb-id=\"Grid.Item\"]:has(a[href*=\"&sponsoredid=\"])");
+    let result = element_tidy("wayfair.com", "##", "div[data-hb-id=\"Grid.Item\"]:has(a[href*=\"&sponsoredid=\"])", false, &[]);
     assert!(result.contains(":has("), ":has() should be preserved, got: {}", result);
     assert!(result.contains("[href*=\"&sponsoredid=\"]"), "href contains should be preserved, got: {}", result);
 }
@@ -376,7 +944,7 @@ fn test_adguard_js_injection_preserved() {
     use crate::fop_sort::element_tidy;
 
     // #%# JS injection - selector preserved, domains sorted
-    let result = element_tidy("z.com,a.com", "#%#", "//scriptlet('prevent-window-open')");
+    let result = element_tidy("z.com,a.com", "#%#", "//scriptlet('prevent-window-open')", false, &[]);
     assert!(result.starts_with("a.com,z.com#%#"), "Domains should be sorted, got: {}", result);
     assert!(result.contains("//scriptlet('prevent-window-open')"), "Selector should be preserved, got: {}", result);
 }
@@ -386,7 +954,7 @@ fn test_adguard_js_injection_braces_preserved() {
     use crate::fop_sort::element_tidy;
 
     // #%# with JS braces - should be preserved exactly
-    let result = element_tidy("example.com", "#%#", "(()=>{ window.test = true; })();");
+    let result = element_tidy("example.com", "#%#", "(()=>{ window.test = true; })();", false, &[]);
     assert!(result.contains("(()=>{ window.test = true; })();"), "JS braces should be preserved, got: {}", result);
 }
 
@@ -395,7 +963,7 @@ fn test_adguard_css_injection_preserved() {
     use crate::fop_sort::element_tidy;
 
     // #$# CSS injection - selector preserved
-    let result = element_tidy("example.com", "#$#", ".ad { display: none !important; }");
+    let result = element_tidy("example.com", "#$#", ".ad { display: none !important; }", false, &[]);
     assert!(result.contains("{ display: none !important; }"), "CSS injection should be preserved, got: {}", result);
 }
 
@@ -404,7 +972,7 @@ fn test_adguard_extended_css_preserved() {
     use crate::fop_sort::element_tidy;
 
     // #$?# extended CSS injection - selector preserved
-    let result = element_tidy("z.com,a.com", "#$?#", "div[style*=\"position: fixed\"] { remove: true; }");
+    let result = element_tidy("z.com,a.com", "#$?#", "div[style*=\"position: fixed\"] { remove: true; }", false, &[]);
     assert!(result.starts_with("a.com,z.com#$?#"), "Domains should be sorted, got: {}", result);
     assert!(result.contains("{ remove: true; }"), "Extended CSS should be preserved, got: {}", result);
 }
@@ -414,7 +982,7 @@ fn test_adguard_html_filtering_preserved() {
     use crate::fop_sort::element_tidy;
 
     // $$ HTML filtering - selector preserved, domains sorted
-    let result = element_tidy("z.com,a.com", "$$", "script[tag-content=\"adConfig\"]");
+    let result = element_tidy("z.com,a.com", "$$", "script[tag-content=\"adConfig\"]", false, &[]);
     assert!(result.starts_with("a.com,z.com$$"), "Domains should be sorted, got: {}", result);
     assert!(result.contains("script[tag-content=\"adConfig\"]"), "Selector should be preserved, got: {}", result);
 }
@@ -424,7 +992,7 @@ fn test_adguard_html_filtering_complex() {
     use crate::fop_sort::element_tidy;
 
     // $$ with wildcard and min/max-length
-    let result = element_tidy("site.com", "$$", "script[wildcard=\"*function*break;case*\"][min-length=\"25000\"][max-length=\"100000\"]");
+    let result = element_tidy("site.com", "$$", "script[wildcard=\"*function*break;case*\"][min-length=\"25000\"][max-length=\"100000\"]", false, &[]);
     assert!(result.contains("[wildcard="), "Wildcard attr should be preserved, got: {}", result);
     assert!(result.contains("[min-length="), "min-length should be preserved, got: {}", result);
 }
@@ -434,16 +1002,16 @@ fn test_adguard_exception_separators() {
     use crate::fop_sort::element_tidy;
 
     // Exception variants
-    let result = element_tidy("example.com", "#@$#", ".ad { display: none; }");
+    let result = element_tidy("example.com", "#@$#", ".ad { display: none; }", false, &[]);
     assert!(result.contains("#@$#"), "Exception separator should be preserved, got: {}", result);
 
-    let result = element_tidy("example.com", "#@%#", "//scriptlet('test')");
+    let result = element_tidy("example.com", "#@%#", "//scriptlet('test')", false, &[]);
     assert!(result.contains("#@%#"), "Exception separator should be preserved, got: {}", result);
 
-    let result = element_tidy("example.com", "#@$?#", ".ad { remove: true; }");
+    let result = element_tidy("example.com", "#@$?#", ".ad { remove: true; }", false, &[]);
     assert!(result.contains("#@$?#"), "Exception separator should be preserved, got: {}", result);
 
-    let result = element_tidy("example.com", "$@$", "script[tag-content=\"ad\"]");
+    let result = element_tidy("example.com", "$@$", "script[tag-content=\"ad\"]", false, &[]);
     assert!(result.contains("$@$"), "Exception separator should be preserved, got: {}", result);
 }
 
@@ -541,3 +1109,1107 @@ fn test_has_text_google_promo_example() {
     assert!(result[0].contains("Time for a new laptop|Gemini"));
     assert!(result[0].contains("Keep things dark"));
 }
+
+// =============================================================================
+// uBO Preprocessor Directive Tests
+// =============================================================================
+
+#[test]
+fn test_is_preprocessor_directive() {
+    assert!(is_preprocessor_directive("!#if env_firefox"));
+    assert!(is_preprocessor_directive("!#if !cap_html_filtering"));
+    assert!(is_preprocessor_directive("!#else"));
+    assert!(is_preprocessor_directive("!#endif"));
+    assert!(is_preprocessor_directive("!#include ublock-filters.txt"));
+    assert!(!is_preprocessor_directive("! comment"));
+    assert!(!is_preprocessor_directive("##.ad"));
+}
+
+#[test]
+fn test_preprocessor_nested_blocks_not_sorted_across() {
+    let config = SortConfig { quiet: true, ..test_config() };
+    let input = "\
+||z-outer.com^
+||a-outer.com^
+!#if env_firefox
+||y-firefox.com^
+||b-firefox.com^
+!#if !cap_html_filtering
+||x-nested.com^
+||c-nested.com^
+!#endif
+||w-firefox.com^
+!#else
+||v-other.com^
+||d-other.com^
+!#endif
+||u-after.com^
+||e-after.com^
+";
+    let expected = "\
+||a-outer.com^
+||z-outer.com^
+!#if env_firefox
+||b-firefox.com^
+||y-firefox.com^
+!#if !cap_html_filtering
+||c-nested.com^
+||x-nested.com^
+!#endif
+||w-firefox.com^
+!#else
+||d-other.com^
+||v-other.com^
+!#endif
+||e-after.com^
+||u-after.com^
+";
+    assert_eq!(sort_string(input, &config).unwrap(), expected);
+
+    // Directives stay boundaries even when ! is not a configured comment prefix
+    let comments = vec!["%".to_string()];
+    let config = SortConfig { comment_chars: &comments, quiet: true, ..test_config() };
+    assert_eq!(sort_string(input, &config).unwrap(), expected);
+}
+
+// =============================================================================
+// Section Classification Tests
+// =============================================================================
+
+#[test]
+fn test_section_classified_by_majority_not_first_lines() {
+    let config = SortConfig { quiet: true, ..test_config() };
+    // 11 cosmetic rules up front, but 15 network rules make up the majority
+    let letters: Vec<char> = ('a'..='k').collect();
+    let mut input = String::new();
+    for (i, d) in letters.iter().enumerate() {
+        input.push_str(&format!("{}.com##.{}\n", d, letters[letters.len() - 1 - i]));
+    }
+    for i in (10..25).rev() {
+        input.push_str(&format!("||ads{}.example.com^\n", i));
+    }
+
+    // Network ordering sorts by the full rule text, so cosmetic rules stay in domain order
+    let mut expected: Vec<String> = input.lines().map(String::from).collect();
+    expected.sort_by_key(|s| s.to_ascii_lowercase());
+    let expected = expected.join("\n") + "\n";
+
+    assert_eq!(sort_string(&input, &config).unwrap(), expected);
+}
+
+// =============================================================================
+// CSP Normalization Tests
+// =============================================================================
+
+#[test]
+fn test_normalize_csp_options() {
+    // Directives sorted, quoted keywords kept verbatim
+    assert_eq!(
+        normalize_csp_options("||example.com^$csp=script-src 'none'; img-src 'self'"),
+        "||example.com^$csp=img-src 'self'; script-src 'none'"
+    );
+    // Other options untouched
+    assert_eq!(
+        normalize_csp_options("||example.com^$csp=worker-src 'none';frame-src 'self',domain=a.com"),
+        "||example.com^$csp=frame-src 'self'; worker-src 'none',domain=a.com"
+    );
+    assert_eq!(
+        normalize_csp_options("@@||example.com^$permissions=geolocation=();camera=()"),
+        "@@||example.com^$permissions=camera=(); geolocation=()"
+    );
+    // Single directive and non-CSP rules are unchanged
+    assert_eq!(normalize_csp_options("||example.com^$csp=script-src 'none'"), "||example.com^$csp=script-src 'none'");
+    assert_eq!(normalize_csp_options("||example.com^$script"), "||example.com^$script");
+}
+
+// =============================================================================
+// UTF-8 Validation Tests
+// =============================================================================
+
+#[test]
+fn test_invalid_utf8_file_left_untouched() {
+    let config = SortConfig { quiet: true, ..test_config() };
+    let dir = TestDir::new("utf8");
+    let path = dir.join("list.txt");
+    let content: &[u8] = b"||z.com^\n||a.com^\n||bad\xff.com^\n";
+    std::fs::write(&path, content).unwrap();
+
+    let outcome = fop_sort(&path, &config).unwrap();
+    assert!(outcome.diff.is_none() && !outcome.sorted);
+    assert_eq!(std::fs::read(&path).unwrap(), content);
+    assert!(!path.with_extension("temp").exists());
+}
+
+#[test]
+fn test_latin1_list_round_trip() {
+    let dir = TestDir::new("latin1");
+    let path = dir.join("list.txt");
+    // "! Liste française" and "café.example##.pub" in Latin-1 (0xE7 = ç, 0xE9 = é)
+    let content: &[u8] = b"! Liste fran\xe7aise\nz.example##.pub\ncaf\xe9.example##.pub\n";
+    std::fs::write(&path, content).unwrap();
+
+    let latin1 = encoding_rs::Encoding::for_label(b"latin1");
+    let config = SortConfig { quiet: true, input_encoding: latin1, ..test_config() };
+    assert!(fop_sort(&path, &config).unwrap().sorted);
+    assert_eq!(std::fs::read(&path).unwrap(), b"! Liste fran\xe7aise\ncaf\xe9.example,z.example##.pub\n");
+    // Sorted again, nothing changes
+    assert!(!fop_sort(&path, &config).unwrap().sorted);
+
+    // --output-encoding=utf-8 converts the list
+    let config = SortConfig { output_encoding: Some(encoding_rs::UTF_8), ..config };
+    assert!(fop_sort(&path, &config).unwrap().sorted);
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "! Liste française\ncafé.example,z.example##.pub\n"
+    );
+}
+
+// =============================================================================
+// Library API Tests
+// =============================================================================
+
+#[test]
+fn test_sort_string_matches_sort_file() {
+    let config = SortConfig { convert_ubo: true, quiet: true, ..test_config() };
+    let input = "! Title\n||z.com^$script,image\n||a.com^\n! Cosmetic\nb.com##.ad\na.com##.ad\n";
+    let sorted = sort_string(input, &config).unwrap();
+    assert_eq!(sorted, "! Title\n||a.com^\n||z.com^$image,script\n! Cosmetic\na.com,b.com##.ad\n");
+
+    let dir = TestDir::new("sort-string");
+    let path = dir.join("list.txt");
+    std::fs::write(&path, input).unwrap();
+    crate::sort_file(&path, &config).unwrap();
+    assert_eq!(sorted, std::fs::read_to_string(&path).unwrap());
+}
+
+// =============================================================================
+// Rule Count Tests
+// =============================================================================
+
+#[test]
+fn test_update_rule_count() {
+    use crate::fop_datestamp::{is_rule_count_line, update_rule_count};
+    let comments = vec!["!".to_string()];
+
+    assert!(is_rule_count_line("! Rules: 12"));
+    assert!(is_rule_count_line("# rules: 3"));
+    assert!(!is_rule_count_line("! Rule list"));
+
+    // Inserted after the version/timestamp lines
+    let input = "[Adblock Plus 2.0]\n! Title: Test\n! Version: 202601010000\n! Comment\n||a.com^\n! Section\n##.ad\n";
+    assert_eq!(
+        update_rule_count(input, &comments, false, &[]).unwrap(),
+        "[Adblock Plus 2.0]\n! Title: Test\n! Version: 202601010000\n! Rules: 2\n! Comment\n||a.com^\n! Section\n##.ad\n"
+    );
+
+    // Updated in place, and left alone when already current
+    let input = "! Title: Test\n! Rules: 5\n||a.com^\n||b.com^\n";
+    assert_eq!(update_rule_count(input, &comments, false, &[]).unwrap(), "! Title: Test\n! Rules: 2\n||a.com^\n||b.com^\n");
+    assert!(update_rule_count("! Title: Test\n! Rules: 2\n||a.com^\n||b.com^\n", &comments, false, &[]).is_none());
+
+    // Localhost mode counts only host entries and uses #
+    let input = "# Title: Hosts\n0.0.0.0 a.com\n127.0.0.1 b.com\n";
+    assert_eq!(
+        update_rule_count(input, &comments, true, &[]).unwrap(),
+        "# Title: Hosts\n# Rules: 2\n0.0.0.0 a.com\n127.0.0.1 b.com\n"
+    );
+}
+
+#[test]
+fn test_rule_count_during_sort() {
+    let config = SortConfig { quiet: true, rule_count: true, ..test_config() };
+    let input = "! Title: Test\n||b.com^\n||a.com^\n||a.com^\n";
+    assert_eq!(sort_string(input, &config).unwrap(), "! Title: Test\n! Rules: 2\n||a.com^\n||b.com^\n");
+}
+
+// =============================================================================
+// Cosmetic/Network Mix Detection Tests
+// =============================================================================
+
+#[test]
+fn test_cosmetic_network_options() {
+    assert_eq!(cosmetic_network_options(".ad$third-party"), Some("third-party"));
+    assert_eq!(cosmetic_network_options(".ad$script,domain=a.com"), Some("script,domain=a.com"));
+    assert_eq!(cosmetic_network_options("div.banner$~third-party"), Some("~third-party"));
+    // Attribute selectors, quoted values and scriptlet arguments are not options
+    assert_eq!(cosmetic_network_options("[data-x$=\"y\"]"), None);
+    assert_eq!(cosmetic_network_options("a[href$=.js]"), None);
+    assert_eq!(cosmetic_network_options("+js(set-constant, $ads, true)"), None);
+    assert_eq!(cosmetic_network_options("div:has-text(/\\$\\d+/)"), None);
+    // Unknown suffix is left alone
+    assert_eq!(cosmetic_network_options(".price$value"), None);
+}
+
+#[test]
+fn test_cosmetic_network_mix_removed_during_sort() {
+    let config = SortConfig { quiet: true, ..test_config() };
+    let input = "example.com##.ad$third-party\nexample.com##[data-x$=\"y\"]\n";
+    assert_eq!(sort_string(input, &config).unwrap(), "example.com##[data-x$=\"y\"]\n");
+}
+
+// =============================================================================
+// Diff Stats Tests
+// =============================================================================
+
+#[test]
+fn test_diff_stats() {
+    let diff = "--- a/list.txt\n+++ b/list.txt\n@@ -1,3 +1,3 @@\n-||b.com^\n ||a.com^\n+||b.com^\n+||c.com^\n";
+    assert_eq!(diff_stats(diff), DiffStats { added: 2, removed: 1, reorder_only: false });
+
+    // Same lines moved around
+    let diff = "--- a/list.txt\n+++ b/list.txt\n@@ -1,2 +1,2 @@\n-||b.com^\n-||a.com^\n+||a.com^\n+||b.com^\n";
+    assert_eq!(diff_stats(diff), DiffStats { added: 2, removed: 2, reorder_only: true });
+
+    // Equal counts but different content is not a reorder
+    let diff = "@@ -1 +1 @@\n-||a.com^\n+||b.com^\n";
+    assert_eq!(diff_stats(diff), DiffStats { added: 1, removed: 1, reorder_only: false });
+}
+
+// =============================================================================
+// Temp Directory Tests
+// =============================================================================
+
+#[test]
+fn test_sort_with_temp_dir() {
+    let dir = TestDir::new("tempdir");
+    let (src, tmp) = (dir.join("src"), dir.join("tmp"));
+    std::fs::create_dir_all(&src).unwrap();
+    std::fs::create_dir_all(&tmp).unwrap();
+    let path = src.join("list.txt");
+    std::fs::write(&path, "||b.com^\n||a.com^\n").unwrap();
+
+    // Same file name in different directories gets a different temp file
+    assert_ne!(temp_path(&path, Some(&tmp)), temp_path(&dir.join("list.txt"), Some(&tmp)));
+    assert_eq!(temp_path(&path, None), src.join(format!("list.txt.fop-{}.temp", std::process::id())));
+
+    let config = SortConfig { quiet: true, temp_dir: Some(&tmp), ..test_config() };
+    fop_sort(&path, &config).unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "||a.com^\n||b.com^\n");
+    assert_eq!(std::fs::read_dir(&src).unwrap().count(), 1);
+    assert_eq!(std::fs::read_dir(&tmp).unwrap().count(), 0);
+}
+
+// =============================================================================
+// Byte Order Mark Tests
+// =============================================================================
+
+#[test]
+fn test_bom_stripped_before_header_detection() {
+    let config = SortConfig { quiet: true, ..test_config() };
+    // With the BOM attached, the header would be sorted as a rule after ||a.com^
+    let input = "\u{feff}[Adblock Plus 2.0]\n||b.com^\n||a.com^\n";
+    assert_eq!(sort_string(input, &config).unwrap(), "[Adblock Plus 2.0]\n||a.com^\n||b.com^\n");
+
+    let config = SortConfig { keep_bom: true, ..config };
+    assert_eq!(sort_string(input, &config).unwrap(), "\u{feff}[Adblock Plus 2.0]\n||a.com^\n||b.com^\n");
+}
+
+#[test]
+fn test_no_selector_tidy_preserves_selector() {
+    let config = test_config();
+    let input = "z.com,a.com##DIV>*.Ad:HOVER\n";
+    let tidied = sort_string(input, &config).unwrap();
+    assert_ne!(tidied, "a.com,z.com##DIV>*.Ad:HOVER\n");
+
+    let config = SortConfig { no_selector_tidy: true, ..config };
+    assert_eq!(sort_string(input, &config).unwrap(), "a.com,z.com##DIV>*.Ad:HOVER\n");
+}
+
+#[test]
+fn test_report_tidy_records_rewritten_rules() {
+    use crate::fop_sort::SORT_CHANGES;
+    let config = SortConfig { report_tidy: true, ..test_config() };
+    let input = "b.com,a.com##.ad\nx.com##.banner\nc.com##div>.ad\n";
+    assert_eq!(sort_string(input, &config).unwrap(), "a.com,b.com##.ad\nx.com##.banner\nc.com##div > .ad\n");
+    let changes = SORT_CHANGES.lock().unwrap();
+    let recorded: Vec<_> = changes
+        .selectors_tidied
+        .iter()
+        .filter(|(file, _, _)| file == Path::new("<string>"))
+        .map(|(_, before, after)| (before.as_str(), after.as_str()))
+        .collect();
+    // Reordering domains alone isn't a selector rewrite
+    assert_eq!(recorded, vec![("c.com##div>.ad", "c.com##div > .ad")]);
+}
+
+#[test]
+fn test_rules_diff_ignores_reorders_and_formatting() {
+    use crate::fop_sort::rules_diff;
+    let config = test_config();
+    let old = "! Title\n||b.example^$script,third-party\nb.com,a.com##.ad\n||gone.example^\n";
+    let new = "! Changed title\n\na.com,b.com##.ad\n  ||b.example^$third-party,script\n||new.example^\n";
+    assert_eq!(
+        rules_diff(std::path::Path::new("list.txt"), old, new, &config).as_deref(),
+        Some("Rules diff: list.txt (+1 -1)\n+||new.example^\n-||gone.example^\n")
+    );
+    assert_eq!(rules_diff(std::path::Path::new("list.txt"), old, old, &config), None);
+}
+
+#[test]
+fn test_detect_cross_file_dupes_records_sorted_rules() {
+    use crate::fop_sort::SORT_CHANGES;
+    let config = SortConfig { detect_cross_file_dupes: true, dry_run: true, ..test_config() };
+    let dir = TestDir::new("cross-dupes");
+    let (a, b) = (dir.join("a.txt"), dir.join("b.txt"));
+    std::fs::write(&a, "! Title\n||cross-dupe.example^\n||only-a.example^\n").unwrap();
+    std::fs::write(&b, "||CROSS-DUPE.example^$script,image\n||cross-dupe.example^\n").unwrap();
+    fop_sort(&a, &config).unwrap();
+    fop_sort(&b, &config).unwrap();
+    let changes = SORT_CHANGES.lock().unwrap();
+    let files: Vec<String> = changes.rule_locations["||cross-dupe.example^"]
+        .iter()
+        .map(|(file, line)| format!("{}:{}", file.display(), line))
+        .collect();
+    assert_eq!(files.len(), 2, "{:?}", files);
+    assert!(files.iter().any(|f| f.contains("a.txt") && f.ends_with(":2")));
+    assert!(files.iter().any(|f| f.contains("b.txt")));
+    assert!(!changes.rule_locations.contains_key("! Title"));
+}
+
+#[test]
+fn test_hosts_abp_overlap_records_blocked_domains() {
+    use crate::fop_sort::{blocked_hosts, SORT_CHANGES};
+    let abp = SortConfig { warn_hosts_abp_overlap: true, ..test_config() };
+    assert_eq!(blocked_hosts("||Ads.example^", &abp), vec!["ads.example"]);
+    assert!(blocked_hosts("||ads.example^$script", &abp).is_empty());
+    assert!(blocked_hosts("||ads.example/banner^", &abp).is_empty());
+    assert!(blocked_hosts("example.com##.ad", &abp).is_empty());
+
+    let hash = vec!["#".to_string()];
+    let hosts = SortConfig { comment_chars: &hash, localhost: true, ..abp };
+    assert_eq!(blocked_hosts("0.0.0.0 overlap-a.example overlap-b.example # ads", &hosts), vec!["overlap-a.example", "overlap-b.example"]);
+    assert!(blocked_hosts("||ads.example^", &hosts).is_empty());
+
+    let dir = TestDir::new("hosts-abp-overlap");
+    let (list, hosts_file) = (dir.join("list.txt"), dir.join("hosts.txt"));
+    std::fs::write(&list, "! Title\n||overlap-a.example^\n||overlap-c.example^\n").unwrap();
+    std::fs::write(&hosts_file, "# Hosts\n0.0.0.0 overlap-a.example\n0.0.0.0 overlap-d.example\n").unwrap();
+    fop_sort(&list, &abp).unwrap();
+    fop_sort(&hosts_file, &hosts).unwrap();
+    let changes = SORT_CHANGES.lock().unwrap();
+    assert!(changes.hosts_blocked["overlap-a.example"][0].0.ends_with("hosts.txt"));
+    assert_eq!(changes.abp_blocked["overlap-a.example"][0].1, 2);
+    assert!(!changes.abp_blocked.contains_key("overlap-d.example"));
+    assert!(!changes.hosts_blocked.contains_key("overlap-c.example"));
+}
+
+#[test]
+fn test_output_path_leaves_source_untouched() {
+    let dir = TestDir::new("output-path");
+    let source = dir.join("list.txt");
+    let unchanged = dir.join("sorted.txt");
+    std::fs::write(&source, "||b.com^\n||a.com^\n").unwrap();
+    std::fs::write(&unchanged, "||a.com^\n||b.com^\n").unwrap();
+    let output = dir.join("out/sub/list.txt");
+    let copied = dir.join("out/sub/sorted.txt");
+
+    let config = SortConfig { output_path: Some(&output), ..test_config() };
+    assert!(fop_sort(&source, &config).unwrap().sorted);
+    assert_eq!(std::fs::read_to_string(&source).unwrap(), "||b.com^\n||a.com^\n");
+    assert_eq!(std::fs::read_to_string(&output).unwrap(), "||a.com^\n||b.com^\n");
+
+    let config = SortConfig { output_path: Some(&copied), ..config };
+    fop_sort(&unchanged, &config).unwrap();
+    assert!(!copied.exists());
+    let config = SortConfig { copy_unchanged: true, ..config };
+    fop_sort(&unchanged, &config).unwrap();
+    assert_eq!(std::fs::read_to_string(&copied).unwrap(), "||a.com^\n||b.com^\n");
+}
+
+#[test]
+fn test_sort_locale_unicode() {
+    use crate::fop_sort::SortLocale;
+    let config = test_config();
+    let input = "||\u{e9}cole.example^\n||zoo.example^\n||ecole.example^\n";
+    assert_eq!(
+        sort_string(input, &config).unwrap(),
+        "||ecole.example^\n||zoo.example^\n||\u{e9}cole.example^\n"
+    );
+    let config = SortConfig { sort_locale: SortLocale::Unicode, ..config };
+    assert_eq!(
+        sort_string(input, &config).unwrap(),
+        "||ecole.example^\n||\u{e9}cole.example^\n||zoo.example^\n"
+    );
+    assert_eq!(
+        sort_string("zoo.example,\u{e9}cole.example,ecole.example##.ad\n", &config).unwrap(),
+        "ecole.example,\u{e9}cole.example,zoo.example##.ad\n"
+    );
+    // The collator only applies while sorting a file
+    let mut domains = vec!["\u{e9}.example".to_string(), "z.example".to_string()];
+    sort_domains(&mut domains);
+    assert_eq!(domains, vec!["z.example", "\u{e9}.example"]);
+}
+
+#[test]
+fn test_html_filter_rules_sort_as_cosmetic() {
+    let config = test_config();
+    // The {3} quantifier used to push this rule into network-rule handling
+    let input = "z.com##.ad\nb.com,a.com##^script:has-text(/ad\\d{3}/)\ny.com##.x\n";
+    assert_eq!(
+        sort_string(input, &config).unwrap(),
+        "z.com##.ad\ny.com##.x\na.com,b.com##^script:has-text(/ad\\d{3}/)\n"
+    );
+    // A section made only of HTML filters is still sorted by domain-stripped selector
+    let input = "b.com##^script:has-text(/x{2}/)\na.com##^div[id=\"ad\"]\nc.com#@#^script:has-text(y)\n";
+    assert_eq!(
+        sort_string(input, &config).unwrap(),
+        "a.com##^div[id=\"ad\"]\nb.com##^script:has-text(/x{2}/)\nc.com#@#^script:has-text(y)\n"
+    );
+}
+
+#[test]
+fn test_diff_context_lines() {
+    let dir = TestDir::new("diff-context");
+    let path = dir.join("list.txt");
+    std::fs::write(&path, "! Title\n! Note\n||b.com^\n||a.com^\n").unwrap();
+    let config = SortConfig { dry_run: true, ..test_config() };
+
+    let diff = fop_sort(&path, &config).unwrap().diff.unwrap();
+    assert!(diff.lines().any(|l| l == " ! Note"), "{}", diff);
+    let config = SortConfig { diff_context: Some(0), ..config };
+    let diff = fop_sort(&path, &config).unwrap().diff.unwrap();
+    assert!(!diff.lines().any(|l| l.starts_with(' ')), "{}", diff);
+    assert!(diff.contains("+||a.com^") && diff.contains("-||a.com^"), "{}", diff);
+}
+
+#[test]
+fn test_checksum_and_timestamp_keep_adblock_header_first() {
+    use crate::fop_checksum::{add_checksum, verify_checksum, ChecksumResult};
+    use crate::fop_datestamp::add_timestamp;
+    let dir = TestDir::new("header-first");
+    let path = dir.join("list.txt");
+
+    std::fs::write(&path, "[Adblock Plus 2.0]\n! Title: Test\n||a.com^\n").unwrap();
+    add_timestamp(&path, false, true, true, &test_config()).unwrap();
+    add_checksum(&path, false, true, true, &test_config()).unwrap();
+    let content = std::fs::read_to_string(&path).unwrap();
+    let lines: Vec<&str> = content.lines().collect();
+    assert_eq!(lines[0], "[Adblock Plus 2.0]");
+    assert!(lines[1].starts_with("! Checksum: "), "{}", content);
+    assert!(lines[2].starts_with("! Last modified: "), "{}", content);
+    assert!(matches!(verify_checksum(&path, &test_config()).unwrap(), ChecksumResult::Valid));
+
+    // Header lines that ended up above the header are moved below it
+    std::fs::write(&path, "! Checksum: stale\n! Last modified: 01 Jan 2020 00:00 UTC\n[Adblock Plus 2.0]\n||a.com^\n").unwrap();
+    add_timestamp(&path, false, true, true, &test_config()).unwrap();
+    add_checksum(&path, false, true, true, &test_config()).unwrap();
+    let content = std::fs::read_to_string(&path).unwrap();
+    let lines: Vec<&str> = content.lines().collect();
+    assert_eq!(lines[0], "[Adblock Plus 2.0]", "{}", content);
+    assert!(lines[1].starts_with("! Checksum: "), "{}", content);
+    assert!(lines[2].starts_with("! Last modified: "), "{}", content);
+    assert_eq!(lines.len(), 4, "{}", content);
+}
+
+#[test]
+fn test_header_edits_keep_input_encoding() {
+    use crate::fop_checksum::{add_checksum, verify_checksum, ChecksumResult};
+    use crate::fop_datestamp::add_timestamp;
+    let dir = TestDir::new("header-encoding");
+    let path = dir.join("list.txt");
+    let config = SortConfig { input_encoding: encoding_rs::Encoding::for_label(b"latin1"), ..test_config() };
+
+    std::fs::write(&path, b"[Adblock Plus 2.0]\n! Title: Caf\xe9\n||a.com^\n").unwrap();
+    assert!(add_timestamp(&path, false, true, true, &config).unwrap());
+    assert!(add_checksum(&path, false, true, true, &config).unwrap().is_some());
+    assert!(matches!(verify_checksum(&path, &config).unwrap(), ChecksumResult::Valid));
+    let data = std::fs::read(&path).unwrap();
+    assert!(data.windows(5).any(|w| w == b"Caf\xe9\n"), "{}", String::from_utf8_lossy(&data));
+
+    // Without an input encoding the list still has to be UTF-8
+    assert!(add_checksum(&path, false, true, true, &test_config()).is_err());
+}
+
+#[test]
+fn test_timestamp_formats() {
+    use crate::fop_datestamp::{format_timestamp, unknown_timestamp_token, update_timestamp_line, ISO8601_FORMAT};
+    let secs = 1_769_761_865; // 30 Jan 2026 08:31:05 UTC
+    assert_eq!(format_timestamp(secs, None), "30 Jan 2026 08:31 UTC");
+    assert_eq!(format_timestamp(secs, Some(ISO8601_FORMAT)), "2026-01-30T08:31Z");
+    assert_eq!(format_timestamp(secs, Some("%d.%m.%y %H:%M:%S %Z")), "30.01.26 08:31:05 UTC");
+    assert_eq!(format_timestamp(secs, Some("%B %e, %Y (100%%)")), "January 30, 2026 (100%)");
+
+    assert_eq!(unknown_timestamp_token("%Y-%m-%d"), None);
+    assert_eq!(unknown_timestamp_token("%Y-%q").as_deref(), Some("%q"));
+    assert_eq!(unknown_timestamp_token("%Y%").as_deref(), Some("%"));
+
+    let updated = update_timestamp_line("! Last modified: 01 Jan 2020 00:00 UTC", Some(ISO8601_FORMAT)).unwrap();
+    let value = updated.strip_prefix("! Last modified: ").unwrap();
+    assert!(value.len() == 17 && value.as_bytes()[10] == b'T' && value.ends_with('Z'), "{}", updated);
+}
+
+#[test]
+fn test_explain_rule_steps() {
+    use crate::fop_sort::explain_rule;
+    let config = SortConfig { convert_ubo: true, ..test_config() };
+
+    let steps = explain_rule("||ex.com^$3p,image", &config);
+    let labels: Vec<&str> = steps.iter().map(|(label, _)| *label).collect();
+    assert_eq!(labels.first(), Some(&"Input"));
+    assert!(labels.contains(&"uBO option aliases converted"));
+    assert_eq!(steps.last().unwrap(), &("Final", "||ex.com^$image,third-party".to_string()));
+
+    let steps = explain_rule("b.com,a.com##.ad", &config);
+    assert!(steps[1].0.starts_with("Cosmetic rule"));
+    assert_eq!(steps.last().unwrap().1, "a.com,b.com##.ad");
+
+    // Removed rules end with an empty final form
+    assert_eq!(explain_rule("||.com^", &config).last().unwrap().1, "");
+}
+
+// =============================================================================
+// Rule Count Tally Tests
+// =============================================================================
+
+#[test]
+fn test_count_rules() {
+    use crate::fop_sort::{count_rules, RuleCounts};
+    let config = test_config();
+    let input = "[Adblock Plus 2.0]\n! Title\n||a.com^\n@@||b.com^$script\n\nexample.com##.ad\nexample.com#@#.ad\n/ads\\d/##.x\n!#if env_firefox\n##+js(nobab)\n!#endif\n";
+    assert_eq!(
+        count_rules(input, &config),
+        RuleCounts { total: 6, cosmetic: 4, network: 2, exception: 2 }
+    );
+
+    let config = SortConfig { localhost: true, ..config };
+    let input = "# Hosts\n0.0.0.0 a.com\n127.0.0.1 b.com\nbogus line\n";
+    assert_eq!(count_rules(input, &config), RuleCounts { total: 2, cosmetic: 0, network: 2, exception: 0 });
+}
+
+#[test]
+fn test_files_changed_since() {
+    let dir = TestDir::new("since");
+    if dir.git_repo(&[("a.txt", "||a.com^\n"), ("b.txt", "||a.com^\n"), ("sub/c.txt", "||a.com^\n")]).is_none() {
+        return; // git not installed
+    }
+
+    std::fs::write(dir.join("a.txt"), "||b.com^\n").unwrap();
+    std::fs::write(dir.join("sub/c.txt"), "||c.com^\n").unwrap();
+    std::fs::remove_file(dir.join("b.txt")).unwrap();
+
+    let mut changed = files_changed_since(dir.path(), "HEAD", None).unwrap();
+    changed.sort();
+    assert_eq!(changed, vec![dir.join("a.txt"), dir.join("sub/c.txt")]);
+    // Only paths under the given location are returned
+    assert_eq!(files_changed_since(&dir.join("sub"), "HEAD", None).unwrap(), vec![dir.join("sub").join("c.txt")]);
+    assert!(matches!(files_changed_since(dir.path(), "no-such-ref", None), Err(crate::FopError::GitFailed(_))));
+}
+
+#[test]
+fn test_file_at_ref_gzip_and_encoding() {
+    use crate::fop_git::file_at_ref;
+    use crate::fop_sort::{encode_list, list_text, rules_diff};
+    let dir = TestDir::new("file-at-ref");
+    let Some(git) = dir.git_repo(&[("a.txt", "||a.com^\n")]) else {
+        return; // git not installed
+    };
+    let gz = dir.join("list.txt.gz");
+    let latin1 = dir.join("latin1.txt");
+    std::fs::write(&gz, encode_list(&gz, b"||b.com^\n||a.com^\n").unwrap()).unwrap();
+    std::fs::write(&latin1, b"caf\xe9.example##.ad\n").unwrap();
+    assert!(git(&["add", "."]));
+    assert!(git(&["commit", "-q", "-m", "lists"]));
+
+    // The old side is decompressed and decoded like the list on disk
+    std::fs::write(&gz, encode_list(&gz, b"||a.com^\n||b.com^\n||c.com^\n").unwrap()).unwrap();
+    let config = test_config();
+    let old = list_text(&file_at_ref(&gz, "HEAD", None).unwrap().unwrap(), &gz, &config).unwrap();
+    let new = list_text(&std::fs::read(&gz).unwrap(), &gz, &config).unwrap();
+    assert_eq!(old, "||b.com^\n||a.com^\n");
+    assert_eq!(rules_diff(&gz, &old, &new, &config).unwrap(), format!("Rules diff: {} (+1 -0)\n+||c.com^\n", gz.display()));
+
+    let latin1_config = SortConfig { input_encoding: encoding_rs::Encoding::for_label(b"latin1"), ..test_config() };
+    let old = list_text(&file_at_ref(&latin1, "HEAD", None).unwrap().unwrap(), &latin1, &latin1_config).unwrap();
+    assert_eq!(old, "café.example##.ad\n");
+
+    // A file added since the ref has no old side; an unknown ref is an error
+    assert_eq!(file_at_ref(&dir.join("new.txt"), "HEAD", None).unwrap(), None);
+    assert!(matches!(file_at_ref(&gz, "no-such-ref", None), Err(crate::FopError::GitFailed(_))));
+}
+
+#[test]
+fn test_retry_push_attempts() {
+    use crate::fop_git::{retry_push, PushAttempt};
+    use std::time::Duration;
+    let run = |retries, outcomes: &[PushAttempt]| {
+        let mut attempts = 0;
+        let pushed = retry_push(retries, true, Duration::ZERO, || {
+            attempts += 1;
+            outcomes.get(attempts - 1).copied().unwrap_or(PushAttempt::Retry)
+        });
+        (pushed, attempts)
+    };
+    // Stops after push_retries rejected attempts
+    assert_eq!(run(3, &[]), (false, 3));
+    assert_eq!(run(0, &[]), (false, 0));
+    // ... as soon as a push lands, or when retrying can't help
+    assert_eq!(run(3, &[PushAttempt::Retry, PushAttempt::Pushed]), (true, 2));
+    assert_eq!(run(3, &[PushAttempt::Failed]), (false, 1));
+}
+
+#[test]
+fn test_uncommitted_changes() {
+    use crate::fop_git::{build_base_command, uncommitted_changes, GIT};
+    let dir = TestDir::new("clean-tree");
+    let Some(git) = dir.git_repo(&[("a.txt", "||a.com^\n"), ("b.txt", "||a.com^\n")]) else {
+        return; // git not installed
+    };
+    let base_cmd = build_base_command(&GIT, dir.path(), None);
+    // Untracked files don't count
+    std::fs::write(dir.join("new.txt"), "||n.com^\n").unwrap();
+    assert_eq!(uncommitted_changes(&base_cmd, &GIT).unwrap(), Vec::<String>::new());
+
+    // Staged and unstaged changes to tracked files both do
+    std::fs::write(dir.join("a.txt"), "||b.com^\n").unwrap();
+    std::fs::write(dir.join("b.txt"), "||c.com^\n").unwrap();
+    assert!(git(&["add", "b.txt"]));
+    assert_eq!(uncommitted_changes(&base_cmd, &GIT).unwrap(), vec![" M a.txt", "M  b.txt"]);
+}
+
+#[test]
+fn test_commit_pathspec_filter() {
+    use crate::fop_git::sorted_files_matching;
+    let root = Path::new("/repo");
+    let matching = |glob: &str, sorted: &[&Path]| {
+        sorted_files_matching(sorted, root, &globset::Glob::new(glob).unwrap().compile_matcher())
+    };
+    // Only files sorted in this run are candidates, never other uncommitted edits
+    let sorted = [Path::new("/repo/filters/b.txt"), Path::new("/repo/a.txt"), Path::new("/repo/filters/c.txt")];
+    assert_eq!(matching("filters/*.txt", &sorted), vec!["filters/b.txt", "filters/c.txt"]);
+    assert_eq!(matching("*.txt", &sorted), vec!["a.txt", "filters/b.txt", "filters/c.txt"]);
+    assert_eq!(matching("filters/b.txt", &sorted[1..]), Vec::<String>::new());
+    assert_eq!(matching("*.md", &sorted), Vec::<String>::new());
+}
+
+#[test]
+fn test_user_temp_files_untouched() {
+    use crate::fop_sort::cleanup_temp_files;
+    let dir = TestDir::new("user-temp");
+    let path = dir.join("list.txt");
+    std::fs::write(&path, "||b.com^\n||a.com^\n").unwrap();
+    // A user's own .temp file next to the list must survive sorting and cleanup
+    std::fs::write(dir.join("list.temp"), "keep me\n").unwrap();
+
+    let config = SortConfig { quiet: true, ..test_config() };
+    fop_sort(&path, &config).unwrap();
+    cleanup_temp_files();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "||a.com^\n||b.com^\n");
+    assert_eq!(std::fs::read_to_string(dir.join("list.temp")).unwrap(), "keep me\n");
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 2);
+}
+
+#[test]
+fn test_allow_dotless_domains() {
+    let config = test_config();
+    let input = "||intranet^\n||localbox/ads^\n||other^\n||a.com^\n";
+    assert_eq!(sort_string(input, &config).unwrap(), "||a.com^\n");
+
+    let allowed = vec!["intranet".to_string(), "LocalBox".to_string()];
+    let config = SortConfig { allow_dotless_domains: &allowed, ..config };
+    assert_eq!(
+        sort_string(input, &config).unwrap(),
+        "||a.com^\n||intranet^\n||localbox/ads^\n"
+    );
+}
+
+#[test]
+fn test_mixed_comment_styles() {
+    use crate::fop_sort::is_comment;
+    let comments = vec!["!".to_string(), "#".to_string()];
+    assert!(is_comment("! Title: Hybrid", &comments, false));
+    assert!(is_comment("# hosts section", &comments, false));
+    assert!(is_comment("#div", &comments, false));
+    // Generic cosmetic rules are not comments even with # configured
+    assert!(!is_comment("##.ad", &comments, false));
+    assert!(!is_comment("#@#.ad", &comments, false));
+    assert!(!is_comment("#$#body { overflow: auto; }", &comments, false));
+    // Prefix-anchored: a # later in the line doesn't matter
+    assert!(!is_comment("example.com##.ad", &comments, false));
+    // Localhost mode: every # line is a comment
+    assert!(is_comment("## Ads", &[], true));
+
+    let config = SortConfig { comment_chars: &comments, ..test_config() };
+    let input = "! Title: Hybrid\n||b.com^\n||a.com^\n# Cosmetic\n##.b\n##.a\n";
+    assert_eq!(
+        sort_string(input, &config).unwrap(),
+        "! Title: Hybrid\n||a.com^\n||b.com^\n# Cosmetic\n##.a\n##.b\n"
+    );
+}
+
+#[test]
+fn test_cosmetic_sort_stable_for_equal_selectors() {
+    let config = test_config();
+    let forward = "~b.com##.ad:style(display:none!important)\nc.com##.ad:style(display:none!important)\na.com##.ad:style(display:none!important)\nx.com##.banner\n";
+    let backward = "x.com##.banner\na.com##.ad:style(display:none!important)\nc.com##.ad:style(display:none!important)\n~b.com##.ad:style(display:none!important)\n";
+    let sorted = sort_string(forward, &config).unwrap();
+    assert_eq!(sorted, sort_string(backward, &config).unwrap());
+    assert!(sorted.ends_with("x.com##.banner\n"), "{}", sorted);
+}
+
+#[test]
+fn test_gzip_list_round_trip() {
+    use crate::fop_checksum::{add_checksum, verify_checksum, ChecksumResult};
+    use crate::fop_sort::{encode_list, list_extension, read_list_to_string};
+    let dir = TestDir::new("gzip");
+    let path = dir.join("list.txt.gz");
+    assert_eq!(list_extension(&path), "txt");
+    assert_eq!(list_extension(&dir.join("list.txt")), "txt");
+    let compressed = encode_list(&path, b"! Title\n||b.com^\n||a.com^\n").unwrap();
+    assert!(compressed.starts_with(&[0x1f, 0x8b]));
+    std::fs::write(&path, &compressed).unwrap();
+
+    let config = test_config();
+    assert!(fop_sort(&path, &config).unwrap().sorted);
+    assert!(std::fs::read(&path).unwrap().starts_with(&[0x1f, 0x8b]));
+    assert_eq!(read_list_to_string(&path).unwrap(), "! Title\n||a.com^\n||b.com^\n");
+
+    // Checksums are calculated over the decompressed content
+    add_checksum(&path, false, true, true, &test_config()).unwrap();
+    assert!(matches!(verify_checksum(&path, &test_config()).unwrap(), ChecksumResult::Valid));
+    assert!(read_list_to_string(&path).unwrap().contains("! Checksum: "));
+}
+
+#[test]
+fn test_pr_url_body_and_labels() {
+    let labels = vec!["filters".to_string(), "auto update".to_string()];
+    let url = generate_pr_url("git@github.com:user/list.git", "master", "fop-update-1", Some("Routine & sort"), &labels).unwrap();
+    assert_eq!(
+        url,
+        "https://github.com/user/list/compare/master...fop-update-1?expand=1&body=Routine%20%26%20sort&labels=filters,auto%20update"
+    );
+    let url = generate_pr_url("https://github.com/user/list", "main", "b", None, &[]).unwrap();
+    assert_eq!(url, "https://github.com/user/list/compare/main...b?expand=1");
+    assert!(generate_pr_url("file:///srv/list", "main", "b", None, &labels).is_none());
+}
+
+#[test]
+fn test_extended_markers_from_config() {
+    use crate::fop_sort::element_tidy;
+
+    // An unknown procedural pseudo-class gets its selector rewritten...
+    let result = element_tidy("example.com", "##", "div:matches-attr(data-x=/a>b/)", false, &[]);
+    assert_ne!(result, "example.com##div:matches-attr(data-x=/a>b/)");
+    // ...unless it is added to the extended markers, which passes it through untouched
+    let markers = vec![":matches-attr(".to_string()];
+    let result = element_tidy("example.com", "##", "div:matches-attr(data-x=/a>b/)", false, &markers);
+    assert_eq!(result, "example.com##div:matches-attr(data-x=/a>b/)");
+    // Built-in markers still apply alongside the configured ones
+    let result = element_tidy("example.com", "##", "div:upward(2)", false, &markers);
+    assert_eq!(result, "example.com##div:upward(2)");
+}
+
+#[test]
+fn test_dedupe_only_keeps_lines_verbatim() {
+    let config = SortConfig { dedupe_only: true, fix_typos: true, convert_ubo: true, ..test_config() };
+    let input = "! Title\n||b.com^$3p\n||a.com^\n||b.com^$3p\n||.com^\n\nb.com,a.com##div >  .ad\nb.com,a.com##div >  .ad\n! Next\n||b.com^$3p\n";
+    assert_eq!(
+        sort_string(input, &config).unwrap(),
+        "! Title\n||b.com^$3p\n||a.com^\n||.com^\n\nb.com,a.com##div >  .ad\n! Next\n||b.com^$3p\n"
+    );
+}
+
+#[test]
+fn test_tld_cosmetic_domains() {
+    use crate::fop_sort::is_tld_cosmetic;
+    assert!(is_tld_cosmetic("com"));
+    assert!(is_tld_cosmetic("net,org"));
+    assert!(is_tld_cosmetic("com,~example.com"));
+    assert!(!is_tld_cosmetic("com,example.org"));
+    assert!(!is_tld_cosmetic("example.com"));
+    assert!(!is_tld_cosmetic("google.*"));
+    assert!(!is_tld_cosmetic("~com"));
+    assert!(!is_tld_cosmetic(""));
+}
+
+#[test]
+fn test_js_rules() {
+    use crate::fop_sort::is_js_rule;
+    assert!(is_js_rule("example.com#%#window.x = function() { return 1; };"));
+    assert!(is_js_rule("#%#//scriptlet('abort-on-property-read', 'x')"));
+    assert!(is_js_rule("example.com#@%#window.y = 1;"));
+    assert!(is_js_rule("[$path=/page]example.com#%#window.z = 1;"));
+    assert!(!is_js_rule("example.com##.ad"));
+    assert!(!is_js_rule("example.com#$#body { overflow: auto; }"));
+    assert!(!is_js_rule("||example.com/#%#^"));
+}
+
+#[test]
+fn test_from_domains_sorted_without_ubo_convert() {
+    assert_eq!(
+        filter_tidy("||ads.com^$script,from=z.com|~b.com|a.com|z.com", TidyOptions::default()),
+        "||ads.com^$from=a.com|~b.com|z.com,script"
+    );
+    // With conversion, from= becomes domain= and is sorted as before
+    assert_eq!(
+        filter_tidy("||ads.com^$script,from=z.com|~b.com|a.com", ubo_tidy()),
+        "||ads.com^$script,domain=a.com|~b.com|z.com"
+    );
+}
+
+#[test]
+fn test_rules_per_file_limit_leaves_output_unchanged() {
+    let input = "! Title\n||b.com^\n||a.com^\n||c.com^\n";
+    let config = SortConfig { rules_per_file_limit: Some(2), ..test_config() };
+    let dir = TestDir::new("rules-per-file-limit");
+    let path = dir.join("list.txt");
+    std::fs::write(&path, input).unwrap();
+    fop_sort(&path, &config).unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "! Title\n||a.com^\n||b.com^\n||c.com^\n");
+}
+
+#[test]
+fn test_would_change_writes_nothing() {
+    use crate::fop_sort::would_change;
+    let dir = TestDir::new("would-change");
+    let path = dir.join("list.txt");
+    let config = test_config();
+
+    std::fs::write(&path, "! Title\n||b.com^\n||a.com^\n").unwrap();
+    assert!(would_change(&path, &config).unwrap());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "! Title\n||b.com^\n||a.com^\n");
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+    std::fs::write(&path, "! Title\n||a.com^\n||b.com^\n").unwrap();
+    assert!(!would_change(&path, &config).unwrap());
+}
+
+#[test]
+fn test_dialect_unsupported_options() {
+    use crate::fop_sort::{dialect_unsupported_options, Dialect};
+    let rule = "||ads.com^$jsinject,~third-party,domain=a.com";
+    assert_eq!(dialect_unsupported_options(rule, Dialect::Ubo), vec!["jsinject"]);
+    assert!(dialect_unsupported_options(rule, Dialect::Adguard).is_empty());
+    assert!(dialect_unsupported_options(rule, Dialect::All).is_empty());
+    assert_eq!(
+        dialect_unsupported_options("||ads.com^$popunder,redirect=noopjs", Dialect::Abp),
+        vec!["popunder", "redirect"]
+    );
+    assert!(dialect_unsupported_options("||ads.com^$script,_____", Dialect::Ubo).is_empty());
+    // Unknown options are left to the "not recognised" warning
+    assert!(dialect_unsupported_options("||ads.com^$bogus", Dialect::Abp).is_empty());
+}
+
+#[test]
+fn test_fop_sort_reports_invalid_utf8() {
+    use crate::FopError;
+    let dir = TestDir::new("fop-error");
+    let path = dir.join("list.txt");
+    let content = b"||b.com^\n||a.com^\n\xff\n";
+    std::fs::write(&path, content).unwrap();
+
+    let mut config = SortConfig { quiet: true, ..test_config() };
+    assert!(fop_sort(&path, &config).unwrap().diff.is_none());
+    config.strict_utf8 = true;
+    match fop_sort(&path, &config) {
+        Err(FopError::Utf8 { line, .. }) => assert_eq!(line, 3),
+        other => panic!("expected a UTF-8 error, got {:?}", other.map(|o| o.sorted)),
+    }
+    assert_eq!(std::fs::read(&path).unwrap(), content);
+    assert!(matches!(fop_sort(&dir.join("missing.txt"), &config), Err(FopError::Io(_))));
+}
+
+#[test]
+fn test_sort_key_modes() {
+    use crate::fop_sort::SortKey;
+    let content = "b.com##.zed\na.com#?#.mid\nc.com##.ad\n";
+    let sorted = |sort_key, alt_sort| {
+        let config = SortConfig { sort_key, alt_sort, ..test_config() };
+        sort_string(content, &config).unwrap()
+    };
+    // domain (default): #?# keeps its domains in the key
+    assert_eq!(sorted(None, false), "c.com##.ad\nb.com##.zed\na.com#?#.mid\n");
+    assert_eq!(sorted(Some(SortKey::Full), false), "a.com#?#.mid\nb.com##.zed\nc.com##.ad\n");
+    // selector: every separator's domains are ignored; --alt-sort is an alias
+    let by_selector = "c.com##.ad\na.com#?#.mid\nb.com##.zed\n";
+    assert_eq!(sorted(Some(SortKey::Selector), false), by_selector);
+    assert_eq!(sorted(None, true), by_selector);
+    assert_eq!(sorted(Some(SortKey::Domain), true), "c.com##.ad\nb.com##.zed\na.com#?#.mid\n");
+}
+
+#[test]
+fn test_group_exceptions() {
+    let content = "||b.com^\n@@||a.com^$image\n/ads/banner.\nad-frame.\n@@||c.com^$script,domain=y.com\n@@||c.com^$script,domain=x.com\n";
+    let mut config = test_config();
+    assert_eq!(
+        sort_string(content, &config).unwrap(),
+        "/ads/banner.\n@@||a.com^$image\n@@||c.com^$script,domain=x.com|y.com\nad-frame.\n||b.com^\n"
+    );
+    config.group_exceptions = true;
+    assert_eq!(
+        sort_string(content, &config).unwrap(),
+        "/ads/banner.\nad-frame.\n||b.com^\n@@||a.com^$image\n@@||c.com^$script,domain=x.com|y.com\n"
+    );
+    config.exceptions_first = true;
+    assert_eq!(
+        sort_string(content, &config).unwrap(),
+        "@@||a.com^$image\n@@||c.com^$script,domain=x.com|y.com\n/ads/banner.\nad-frame.\n||b.com^\n"
+    );
+}
+
+/// Turns colors off crate-wide for one test at a time, and back on when dropped,
+/// even if the test panics
+struct NoColorGuard {
+    _lock: std::sync::MutexGuard<'static, ()>,
+}
+
+impl NoColorGuard {
+    fn set() -> Self {
+        static LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+        let lock = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        crate::set_no_color(true);
+        NoColorGuard { _lock: lock }
+    }
+}
+
+impl Drop for NoColorGuard {
+    fn drop(&mut self) {
+        crate::set_no_color(false);
+    }
+}
+
+#[test]
+fn test_color_disabled_crate_wide() {
+    use crate::fop_datestamp::header_change_line;
+    let path = std::path::Path::new("list.txt");
+    assert_eq!(header_change_line("Checksum:", None, "new", path, true), "Checksum: new list.txt");
+
+    // The global switch wins over a per-call `no_color = false`
+    {
+        let _no_color = NoColorGuard::set();
+        assert!(crate::colors_off(false));
+        assert_eq!(header_change_line("Checksum:", Some("old"), "new", path, false), "Checksum: old -> new list.txt");
+    }
+    assert!(!crate::colors_off(false) && crate::colors_off(true));
+    assert!(header_change_line("Checksum:", Some("old"), "new", path, false).contains('\x1b'));
+}
+
+#[test]
+fn test_checksum_details() {
+    use crate::fop_checksum::{add_checksum, checksum_details};
+    let dir = TestDir::new("checksum-details");
+    let good = dir.join("good.txt");
+    let drifted = dir.join("drifted.txt");
+    std::fs::write(&good, "[Adblock Plus 2.0]\n! Title: T\n||a.com^\n||b.com^\n||c.com^\n").unwrap();
+    add_checksum(&good, false, true, true, &test_config()).unwrap();
+    let content = std::fs::read_to_string(&good).unwrap();
+    std::fs::write(&drifted, content.replace("||c.com^", "||c.com^\r\r\n")).unwrap();
+
+    let good = checksum_details(&good, &test_config()).unwrap();
+    let drifted = checksum_details(&drifted, &test_config()).unwrap();
+    assert_eq!((good.lines, good.carriage_returns, good.blank_lines), (5, 0, 0));
+    assert_eq!(good.normalized_len, "[Adblock Plus 2.0]\n! Title: T\n||a.com^\n||b.com^\n||c.com^\n".len());
+    assert_eq!((drifted.lines, drifted.carriage_returns, drifted.blank_lines), (5, 1, 1));
+    // Normalization hides the stray CR, so every prefix still matches
+    assert_eq!(good.prefix_checksums, drifted.prefix_checksums);
+    assert_eq!(good.prefix_checksums.last().unwrap().0, 5);
+}
+
+#[test]
+fn test_hide_interactions() {
+    use crate::fop_sort::hide_interactions;
+    let config = test_config();
+    let content = "##.ad\n~example.com##.banner\nexample.com##.promo\nnews.example.com##.sidebar\nother.com##.promo\n\
+                   @@||example.com^$specifichide\n@@||site.org^$generichide\n@@||plain.com^$document\n";
+    assert_eq!(
+        hide_interactions(content, &config),
+        vec![
+            "Hide interaction: @@||example.com^$specifichide disables example.com##.promo",
+            "Hide interaction: @@||example.com^$specifichide disables news.example.com##.sidebar",
+            "Hide interaction: @@||site.org^$generichide disables 2 generic cosmetic rule(s) on site.org (e.g. ##.ad)",
+        ]
+    );
+    assert!(hide_interactions("example.com##.promo\n@@||other.com^$shide\n", &config).is_empty());
+}
+
+#[test]
+fn test_file_cache_round_trip() {
+    use crate::fop_cache::FileCache;
+    let dir = TestDir::new("cache");
+    let list = dir.join("list.txt");
+    let cache_path = dir.join(".fopcache");
+    std::fs::write(&list, "||a.com^\n").unwrap();
+
+    let mut cache = FileCache::load(&cache_path, "v1");
+    assert!(!cache.is_fresh(&list));
+    cache.record(&list);
+    assert!(cache.is_fresh(&list));
+    cache.save(&cache_path).unwrap();
+
+    assert!(FileCache::load(&cache_path, "v1").is_fresh(&list));
+    // Different options start an empty cache
+    assert!(!FileCache::load(&cache_path, "v2").is_fresh(&list));
+    // A newer mtime with the same content is still fresh; same size, new content isn't
+    let touch = |time| std::fs::File::options().write(true).open(&list).unwrap().set_modified(time).unwrap();
+    touch(std::time::SystemTime::now() + std::time::Duration::from_secs(60));
+    assert!(FileCache::load(&cache_path, "v1").is_fresh(&list));
+    std::fs::write(&list, "||b.com^\n").unwrap();
+    assert!(!FileCache::load(&cache_path, "v1").is_fresh(&list));
+    // A changed size invalidates the entry
+    std::fs::write(&list, "||a.com^\n||b.com^\n").unwrap();
+    assert!(!FileCache::load(&cache_path, "v1").is_fresh(&list));
+}
+
+#[test]
+fn test_warning_json_lines() {
+    use crate::{clear_warning_line, set_warning_line, warning_json, WarningFileGuard};
+    assert_eq!(
+        warning_json("Removed invalid domain"),
+        r#"{"severity":"warning","message":"Removed invalid domain","file":null,"line":null,"rule":null}"#
+    );
+    {
+        let _file = WarningFileGuard::set(std::path::Path::new("lists/ads.txt"));
+        set_warning_line(12, r#"||example.com^$scrpt,domain=a\b"#);
+        assert_eq!(
+            warning_json("Warning: The option \"scrpt\" used on the filter\tis not recognised"),
+            r#"{"severity":"warning","message":"Warning: The option \"scrpt\" used on the filter\tis not recognised","file":"lists/ads.txt","line":12,"rule":"||example.com^$scrpt,domain=a\\b"}"#
+        );
+        clear_warning_line();
+        assert_eq!(
+            warning_json("Fixed typo: a ? b"),
+            r#"{"severity":"info","message":"Fixed typo: a ? b","file":"lists/ads.txt","line":null,"rule":null}"#
+        );
+    }
+    // The guard restores the previous (empty) context
+    assert!(warning_json("Error: x").starts_with(r#"{"severity":"error","message":"Error: x","file":null"#));
+}
+
+#[test]
+fn test_max_warnings_per_file() {
+    use crate::{within_file_limit, WarningFileGuard, WARNING_CONTEXT};
+    let suppressed = || WARNING_CONTEXT.with(|c| c.borrow().suppressed);
+    {
+        let _file = WarningFileGuard::set(std::path::Path::new("a.txt"));
+        // A nested guard for the same file shares its count
+        let _nested = WarningFileGuard::set(std::path::Path::new("a.txt"));
+        assert_eq!((0..4).map(|_| within_file_limit(2)).collect::<Vec<_>>(), [true, true, false, false]);
+        assert_eq!(suppressed(), 2);
+        {
+            // Each file has its own cap
+            let _other = WarningFileGuard::set(std::path::Path::new("b.txt"));
+            assert!(within_file_limit(2));
+            assert_eq!(suppressed(), 0);
+        }
+        assert_eq!(suppressed(), 2);
+    }
+    // Warnings that aren't about a file are never held back
+    assert!((0..4).all(|_| within_file_limit(2)));
+    // Unlimited never counts
+    let _file = WarningFileGuard::set(std::path::Path::new("a.txt"));
+    assert!((0..4).all(|_| within_file_limit(usize::MAX)));
+    assert_eq!(suppressed(), 0);
+}
+
+#[test]
+fn test_confusable_domains() {
+    use crate::fop_sort::confusable_reason;
+    // Cyrillic а in an otherwise Latin label
+    assert_eq!(confusable_reason("\u{430}pple.com").as_deref(), Some("mixes scripts, looks like apple.com"));
+    assert_eq!(confusable_reason("~\u{430}pple.com").as_deref(), Some("mixes scripts, looks like apple.com"));
+    // All-Cyrillic label that reads as Latin
+    assert_eq!(confusable_reason("\u{440}\u{430}\u{443}.com").as_deref(), Some("looks like pay.com"));
+    // Mixed scripts without an ASCII lookalike
+    assert_eq!(confusable_reason("ex\u{436}.com").as_deref(), Some("mixes scripts in \"ex\u{436}\""));
+    for domain in ["example.com", "~example.com", "b\u{fc}cher.de", "\u{43f}\u{440}\u{438}\u{43c}\u{435}\u{440}.\u{440}\u{444}"] {
+        assert_eq!(confusable_reason(domain), None, "{}", domain);
+    }
+}